@@ -1,31 +1,257 @@
-//! This build script copies the `memory.x` file from the crate root into
-//! a directory where the linker can always find it at build time.
-//! For many projects this is optional, as the linker always searches the
-//! project root directory -- wherever `Cargo.toml` is. However, if you
-//! are using a workspace or have a more complicated build setup, this
-//! build script becomes required. Additionally, by requesting that
-//! Cargo re-run the build script whenever `memory.x` is changed,
-//! updating `memory.x` ensures a rebuild of the application with the
-//! new memory settings.
+//! This build script copies the MCU's memory layout, from `src/board/`,
+//! into a directory where the linker can always find it at build time,
+//! as `memory.x`. Cargo re-runs the build script whenever a layout is
+//! changed, so updating one ensures a rebuild of the application with
+//! the new memory settings.
+//!
+//! It also generates the artwork's tables from the 1-bpp XBM images in
+//! `art/` (see src/artwork.rs), so the scenes are drawn rather than typed
+//! out as coordinates, and the text renderer's font table from a BDF
+//! font (see src/oled/text.rs): `art/font.bdf`, or the font at the path
+//! in the `FLUID_FONT` environment variable, e.g.
+//!
+//!   FLUID_FONT=/usr/share/fonts/misc/6x8.bdf cargo build --release
+//!
+//! The font's glyphs are drawn in a cell its bounding box's size, as wide
+//! as it likes, but no taller than a display page, 8 pixels, as they're
+//! copied straight into the display's pages. Fonts in other formats can
+//! be exported as BDF, e.g. by FontForge.
 
 use std::env;
-use std::fs::File;
+use std::fmt::Write as _;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn main() {
-    // Put `memory.x` in our output directory and ensure it's
-    // on the linker search path.
+    // Put the MCU's memory layout in our output directory as `memory.x`,
+    // and ensure it's on the linker search path. The layouts are kept
+    // with the boards, rather than as `memory.x` in the crate root, which
+    // the linker would find first.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let mut memory = match env::var_os("CARGO_FEATURE_STM32F072") {
+        Some(_) => include_str!("src/board/stm32f072.x"),
+        None => include_str!("src/board/stm32f030x6.x"),
+    }.to_owned();
+    // The brownout feature keeps the last page of flash for its snapshot
+    // of the particles (see src/brownout.rs)
+    if env::var_os("CARGO_FEATURE_BROWNOUT").is_some() {
+        memory = memory.replace("LENGTH = 32K", "LENGTH = 31K");
+    }
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(memory.as_bytes())
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
     // By default, Cargo will re-run a build script whenever
-    // any file in the project changes. By specifying `memory.x`
+    // any file in the project changes. By specifying the layouts
     // here, we ensure the build script is only re-run when
-    // `memory.x` is changed.
-    println!("cargo:rerun-if-changed=memory.x");
+    // they're changed.
+    println!("cargo:rerun-if-changed=src/board/stm32f030x6.x");
+    println!("cargo:rerun-if-changed=src/board/stm32f072.x");
+
+    generate_artwork(Path::new("art"), &out.join("artwork.rs"));
+    println!("cargo:rerun-if-changed=art");
+
+    let font = env::var_os("FLUID_FONT").map_or_else(|| PathBuf::from("art/font.bdf"), PathBuf::from);
+    generate_font(&font, &out.join("font.rs"));
+    println!("cargo:rerun-if-env-changed=FLUID_FONT");
+    println!("cargo:rerun-if-changed={}", font.display());
+}
+
+
+/// The particles the firmware simulates (PARTICLES in src/main.rs), placed
+/// by a layout
+const PARTICLES: usize = 60;
+
+/// The obstacles a fluid can have (fluid_core::MAX_OBSTACLES), taken by
+/// a mask
+const MAX_OBSTACLES: usize = 16;
+
+/// Generate a table for each image in the art directory, by its name: a
+/// `<name>_layout.xbm` gives a layout's positions, one particle at each
+/// pixel set, from the top left, row by row, and a `<name>_mask.xbm`
+/// gives obstacles covering the pixels set, as few rectangles as runs
+/// of pixels repeating down the rows take. A layout setting more pixels
+/// than there are particles is warned of, and a mask taking more
+/// rectangles than there can be obstacles fails the build
+fn generate_artwork(art: &Path, output: &Path) {
+    let mut paths: Vec<_> = fs::read_dir(art)
+        .expect("no art directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "xbm"))
+        .collect();
+    paths.sort();
+
+    let mut tables = String::from("// Generated by build.rs from the images in art/\n");
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let (width, height, pixels) = parse_xbm(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|| panic!("{} isn't a 1-bpp XBM image", path.display()));
+        assert!(width <= 128 && height <= 128, "{} is larger than the display", path.display());
+        let set = |x: usize, y: usize| pixels[y * width + x];
+
+        writeln!(tables, "\n/// From art/{}.xbm", name).unwrap();
+        let constant = name.to_uppercase();
+        if name.ends_with("_layout") {
+            let count = pixels.iter().filter(|&&pixel| pixel).count();
+            if count > PARTICLES {
+                println!("cargo:warning={} sets {} pixels, but there are only {} particles to place",
+                         path.display(), count, PARTICLES);
+            }
+            writeln!(tables, "pub const {}: &[(i8, i8)] = &[", constant).unwrap();
+            for y in 0..height {
+                for x in (0..width).filter(|&x| set(x, y)) {
+                    writeln!(tables, "    ({}, {}),", x, y).unwrap();
+                }
+            }
+        } else if name.ends_with("_mask") {
+            let rectangles = rectangles(width, height, set);
+            assert!(rectangles.len() <= MAX_OBSTACLES, "{} takes {} rectangles, more than the fluid's {} obstacles",
+                    path.display(), rectangles.len(), MAX_OBSTACLES);
+            writeln!(tables, "pub const {}: &[Rect] = &[", constant).unwrap();
+            for (x, y, run, rows) in rectangles {
+                writeln!(tables, "    Rect::new({}, {}, {}, {}),", x, y, run, rows).unwrap();
+            }
+        } else {
+            panic!("{} is neither a _layout nor a _mask", path.display());
+        }
+        tables.push_str("];\n");
+    }
+    fs::write(output, tables).unwrap();
+}
+
+/// The width, height and pixels, row by row, of an XBM image
+fn parse_xbm(text: &str) -> Option<(usize, usize, Vec<bool>)> {
+    let define = |suffix: &str| text.lines()
+        .filter_map(|line| line.strip_prefix("#define "))
+        .find_map(|line| {
+            let (name, value) = line.split_once(char::is_whitespace)?;
+            name.ends_with(suffix).then(|| value.trim().parse().ok())?
+        });
+    let (width, height): (usize, usize) = (define("_width")?, define("_height")?);
+
+    // each row is padded to whole bytes, the leftmost pixel in the low bit
+    let (_, bytes) = text.split_once('{')?;
+    let (bytes, _) = bytes.split_once('}')?;
+    let bytes: Vec<u8> = bytes.split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x").trim_start_matches("0X"), 16).ok())
+        .collect::<Option<_>>()?;
+    let row_bytes = width.div_ceil(8);
+    if bytes.len() != row_bytes * height {
+        return None;
+    }
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .map(|(y, x)| bytes[y * row_bytes + x / 8] & (1 << (x % 8)) != 0)
+        .collect();
+    Some((width, height, pixels))
+}
+
+/// The rectangles covering the pixels set, as (x, y, width, height): each
+/// row's runs of pixels, merged with the same run in the rows beneath
+fn rectangles(width: usize, height: usize, set: impl Fn(usize, usize) -> bool) -> Vec<(usize, usize, usize, usize)> {
+    let runs = |y: usize| {
+        let mut runs = Vec::new();
+        let mut x = 0;
+        while x < width {
+            let start = x;
+            while x < width && set(x, y) {
+                x += 1;
+            }
+            if x > start {
+                runs.push((start, x - start));
+            }
+            x += 1;
+        }
+        runs
+    };
+
+    let mut open: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let mut closed = Vec::new();
+    for y in 0..height {
+        let runs = runs(y);
+        // close the rectangles whose run doesn't carry on into this row
+        let (carried, ended): (Vec<_>, Vec<_>) = open.into_iter()
+            .partition(|&(x, _, run, _)| runs.contains(&(x, run)));
+        closed.extend(ended);
+        open = carried;
+        for (x, run) in runs {
+            match open.iter_mut().find(|rectangle| (rectangle.0, rectangle.2) == (x, run)) {
+                Some(rectangle) => rectangle.3 += 1,
+                None => open.push((x, y, run, 1)),
+            }
+        }
+    }
+    closed.extend(open);
+    closed.sort_by_key(|&(x, y, ..)| (y, x));
+    closed
+}
+
+
+/// Generate the font's table, of its printable ASCII glyphs, each a
+/// column of pixels to a byte, top in the low bit, as in a display page.
+/// Characters the font has no glyph for are left blank.
+fn generate_font(path: &Path, output: &Path) {
+    let bdf = fs::read_to_string(path).unwrap_or_else(|_| panic!("no font at {}", path.display()));
+    let fields = |line: &str| line.split_ascii_whitespace().skip(1).map(|field| field.parse::<i32>().unwrap()).collect::<Vec<_>>();
+    let bounds = bdf.lines()
+        .find(|line| line.starts_with("FONTBOUNDINGBOX "))
+        .map(fields)
+        .unwrap_or_else(|| panic!("{} isn't a BDF font", path.display()));
+    let (width, height, left, bottom) = (bounds[0], bounds[1], bounds[2], bounds[3]);
+    assert!(height <= 8, "{} is taller than a display page, 8 pixels", path.display());
+
+    let mut glyphs = vec![vec![0u8; width as usize]; 95];
+    let mut lines = bdf.lines();
+    while let Some(line) = lines.next() {
+        let Some(encoding) = line.strip_prefix("ENCODING ") else {
+            continue;
+        };
+        let code: i32 = encoding.trim().parse().unwrap();
+        let bbx = fields(lines.find(|line| line.starts_with("BBX ")).unwrap());
+        let (glyph_width, glyph_height, x_offset, y_offset) = (bbx[0], bbx[1], bbx[2], bbx[3]);
+        lines.find(|line| *line == "BITMAP");
+        let rows: Vec<u32> = lines.by_ref()
+            .take_while(|line| *line != "ENDCHAR")
+            .map(|row| u32::from_str_radix(row.trim(), 16).unwrap())
+            .collect();
+        let Some(glyph) = usize::try_from(code - 0x20).ok().and_then(|index| glyphs.get_mut(index)) else {
+            continue;
+        };
+
+        // each row's pixels run from its most significant bit, padded to
+        // whole bytes, and the glyph's box sits within the font's
+        let top = (height + bottom) - (glyph_height + y_offset);
+        for (row, bits) in rows.iter().enumerate() {
+            let row_bits = (glyph_width as u32).div_ceil(8) * 8;
+            for column in 0..glyph_width {
+                let (x, y) = (x_offset - left + column, top + row as i32);
+                if bits & (1 << (row_bits - 1 - column as u32)) != 0 && (0..width).contains(&x) && (0..height).contains(&y) {
+                    glyph[x as usize] |= 1 << y;
+                }
+            }
+        }
+    }
+
+    let mut table = format!("// Generated by build.rs from {}\n", path.display());
+    writeln!(table, "pub const FONT_WIDTH: i32 = {};", width).unwrap();
+    writeln!(table, "pub const FONT_HEIGHT: i32 = {};", height).unwrap();
+    writeln!(table, "static FONT: [[u8; FONT_WIDTH as usize]; 95] = [").unwrap();
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let columns: Vec<_> = glyph.iter().map(|column| format!("0x{:02X}", column)).collect();
+        let c = char::from(0x20 + index as u8);
+        let label = match c {
+            ' ' => "' '".to_string(),
+            '\\' => "\\\\".to_string(),
+            c => c.to_string(),
+        };
+        writeln!(table, "    [{}], // {}", columns.join(", "), label).unwrap();
+    }
+    table.push_str("];\n");
+    fs::write(output, table).unwrap();
 }
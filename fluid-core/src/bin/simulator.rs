@@ -0,0 +1,82 @@
+//! A desktop simulator, running the same fluid simulation as the firmware
+//! in a window scaled up from the display's 128x64 pixels, so the solver
+//! can be tuned and scenes developed without flashing hardware.
+//!
+//! cargo run -p fluid-core --features simulator --target x86_64-unknown-linux-gnu
+//!
+//! The arrow keys set the direction of gravity, space removes it,
+//! and escape quits.
+
+use fluid_core::Fluid;
+use minifb::{Key, Scale, Window, WindowOptions};
+
+
+/// The resolution of the OLED display
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+
+/// The colors of lit and unlit pixels, as 0RGB
+const PIXEL_ON: u32 = 0x00C8_F0FF;
+const PIXEL_OFF: u32 = 0x0000_0000;
+
+
+fn main() {
+    let mut window = Window::new("Fluid", WIDTH, HEIGHT, WindowOptions {
+        scale: Scale::X8,
+        ..WindowOptions::default()
+    }).expect("failed to open the simulator window");
+    window.set_target_fps(30);
+
+    // The same simulation as the firmware
+    let mut fluid_sim = Fluid::<60>::new(125, 61);
+    let mut frame = vec![PIXEL_OFF; WIDTH * HEIGHT];
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        // Steer gravity with the arrow keys
+        let gravity = [
+            (Key::Up, (0.0, -1.0)),
+            (Key::Down, (0.0, 1.0)),
+            (Key::Left, (-1.0, 0.0)),
+            (Key::Right, (1.0, 0.0)),
+            (Key::Space, (0.0, 0.0)),
+        ];
+        for (key, (gx, gy)) in gravity {
+            if window.is_key_down(key) {
+                fluid_sim.set_gravity(gx, gy);
+            }
+        }
+
+        // Step the simulation and draw the results
+        fluid_sim.step();
+        frame.fill(PIXEL_OFF);
+        draw_particles(&mut frame, &fluid_sim);
+        window.update_with_buffer(&frame, WIDTH, HEIGHT)
+              .expect("failed to update the simulator window");
+    }
+}
+
+
+/// Draw an individual particle at the given origin, as the firmware does
+fn draw_particle(frame: &mut [u32], x: usize, y: usize) {
+    const PIXELS: [(usize,usize); 12] = [
+                (1, 0), (2, 0),
+        (0, 1), (1, 1), (2, 1), (3, 1),
+        (0, 2), (1, 2), (2, 2), (3, 2),
+                (1, 3), (2, 3),
+    ];
+
+    for (dx, dy) in PIXELS {
+        let (x, y) = (x + dx, y + dy);
+        if x < WIDTH && y < HEIGHT {
+            frame[y * WIDTH + x] = PIXEL_ON;
+        }
+    }
+}
+
+/// Draw all fluid simulation particles
+fn draw_particles<const T: usize>(frame: &mut [u32], fluid_sim: &Fluid<T>) {
+    for particle in fluid_sim.get_particles() {
+        let (x, y) = particle.get_display_position();
+        draw_particle(frame, x as usize, y as usize);
+    }
+}
@@ -10,8 +10,7 @@ impl FixedPt {
     pub const HALF_BASE: u8 = Self::BASE / 2;
     pub const ZERO: FixedPt = FixedPt::from_i8(0);
 
-    // TODO: make const when floating point arithmetic is supported in const fn
-    pub fn from_f32(value: f32) -> FixedPt {
+    pub const fn from_f32(value: f32) -> FixedPt {
         FixedPt { 
             value: (value * (1 << Self::BASE) as f32) as i32,
         }
@@ -116,11 +115,7 @@ pub struct FixedPtVec2D {
 
 impl FixedPtVec2D {
     // The value (sqrt(2) - 1) is used to approximate the magnitude of a vector. 
-    // Note: Floating point arithmetic is not supported in const fn, but it is
-    //       allowed in the definition of const values, so this value must be 
-    //       calculated here manually.
-    // const SQRT_2_MINUS_1: FixedPt = FixedPt::from_f32(0.41421356);
-    const SQRT_2_MINUS_1: FixedPt = FixedPt{ value: (0.41421356 * (1 << FixedPt::BASE) as f64) as i32 };
+    const SQRT_2_MINUS_1: FixedPt = FixedPt::from_f32(0.41421356);
 
     pub const fn from_i8s(x: i8, y: i8) -> Self {
         Self { 
@@ -129,7 +124,7 @@ impl FixedPtVec2D {
         }
     }
 
-    pub fn from_f32s(x: f32, y: f32) -> Self {
+    pub const fn from_f32s(x: f32, y: f32) -> Self {
         Self { 
             x: FixedPt::from_f32(x), 
             y: FixedPt::from_f32(y)
@@ -243,7 +238,7 @@ impl FixedPtNearFar {
             far: FixedPt::from_i8(far)
         }
     }
-    pub fn from_f32s(near: f32, far: f32) -> Self {
+    pub const fn from_f32s(near: f32, far: f32) -> Self {
         Self { 
             near: FixedPt::from_f32(near), 
             far: FixedPt::from_f32(far)
@@ -273,10 +268,113 @@ impl FixedPtViscosity {
         }
     }
 
-    pub fn from_f32s(sigma: f32, beta: f32) -> Self {
+    pub const fn from_f32s(sigma: f32, beta: f32) -> Self {
         Self { 
             sigma: FixedPt::from_f32(sigma), 
             beta: FixedPt::from_f32(beta)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One unit in the last place
+    const ULP: i32 = 1;
+
+    #[test]
+    fn conversions_round_trip() {
+        for value in [i8::MIN, -64, -1, 0, 1, 64, i8::MAX] {
+            assert_eq!(FixedPt::from_i8(value).to_i8(), value);
+        }
+        assert_eq!(FixedPt::from_f32(1.5).value, 3 << (FixedPt::BASE - 1));
+        assert_eq!(FixedPt::from_f32(-0.25).value, -(1 << (FixedPt::BASE - 2)));
+    }
+
+    #[test]
+    fn to_i8_floors_toward_negative_infinity() {
+        assert_eq!(FixedPt::from_f32(2.75).to_i8(), 2);
+        assert_eq!(FixedPt::from_f32(-0.25).to_i8(), -1);
+        assert_eq!(FixedPt { value: -ULP }.to_i8(), -1);
+    }
+
+    #[test]
+    fn abs_of_negative_zero_and_positive() {
+        assert_eq!(FixedPt::from_f32(-3.5).abs(), FixedPt::from_f32(3.5));
+        assert_eq!(FixedPt::ZERO.abs(), FixedPt::ZERO);
+        assert_eq!(FixedPt::from_i8(7).abs(), FixedPt::from_i8(7));
+    }
+
+    #[test]
+    fn multiplication_drops_the_low_half_of_each_operand() {
+        let two = FixedPt::from_i8(2);
+        assert_eq!(two * FixedPt::from_f32(1.5), FixedPt::from_i8(3));
+        assert_eq!(FixedPt::from_i8(-4) * FixedPt::from_f32(0.5), FixedPt::from_i8(-2));
+        assert_eq!(two * 3, FixedPt::from_i8(6));
+
+        // values below 2^-HALF_BASE vanish entirely
+        let tiny = FixedPt { value: (1 << FixedPt::HALF_BASE) - 1 };
+        assert_eq!(tiny * FixedPt::from_i8(100), FixedPt::ZERO);
+    }
+
+    #[test]
+    fn multiplication_of_the_largest_operands_does_not_overflow() {
+        // the simulation keeps values within i8 range, whose square must fit
+        let max = FixedPt::from_i8(i8::MAX);
+        assert_eq!((max * max).value >> FixedPt::BASE, 127 * 127);
+        let min = FixedPt::from_i8(i8::MIN);
+        assert_eq!((min * min).value >> FixedPt::BASE, 128 * 128);
+    }
+
+    #[test]
+    fn division_keeps_half_the_fractional_bits() {
+        assert_eq!(FixedPt::from_i8(3) / FixedPt::from_i8(2), FixedPt::from_f32(1.5));
+        assert_eq!(FixedPt::from_i8(-9) / FixedPt::from_i8(3), FixedPt::from_i8(-3));
+        assert_eq!(FixedPt::from_i8(7) / 2, FixedPt::from_f32(3.5));
+
+        let third = FixedPt::from_i8(1) / FixedPt::from_i8(3);
+        let error = (third - FixedPt::from_f32(1.0 / 3.0)).abs();
+        assert!(error.value < 1 << FixedPt::HALF_BASE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn division_by_zero_panics() {
+        let _ = FixedPt::from_i8(1) / FixedPt::ZERO;
+    }
+
+    #[test]
+    fn magnitude_estimate_is_within_eight_percent() {
+        for (x, y) in [(3, 4), (1, 1), (10, 0), (0, -10), (-5, 12), (8, 15)] {
+            let estimate = FixedPtVec2D::from_i8s(x, y).magnitude();
+            let exact = ((x as f32).powi(2) + (y as f32).powi(2)).sqrt();
+            let estimate = estimate.value as f32 / (1 << FixedPt::BASE) as f32;
+            assert!(estimate >= exact * 0.999, "{} < {} for ({}, {})", estimate, exact, x, y);
+            assert!(estimate <= exact * 1.083, "{} > {} for ({}, {})", estimate, exact, x, y);
+        }
+    }
+
+    #[test]
+    fn magnitude_is_exact_along_the_axes() {
+        assert_eq!(FixedPtVec2D::from_i8s(-6, 0).magnitude(), FixedPt::from_i8(6));
+        assert_eq!(FixedPtVec2D::from_i8s(0, 9).magnitude(), FixedPt::from_i8(9));
+    }
+
+    #[test]
+    fn unit_vectors_have_unit_magnitude() {
+        let unit = FixedPtVec2D::from_i8s(3, -4).unit();
+        let error = (unit.magnitude() - FixedPt::from_i8(1)).abs();
+        assert!(error.value < 1 << FixedPt::HALF_BASE);
+        assert!(unit.x > FixedPt::ZERO && unit.y < FixedPt::ZERO);
+    }
+
+    #[test]
+    fn dot_and_vector_to() {
+        let a = FixedPtVec2D::from_i8s(1, 2);
+        let b = FixedPtVec2D::from_i8s(4, -2);
+        assert_eq!(a.dot(&b), FixedPt::ZERO);
+        let v = a.vector_to(&b);
+        assert_eq!((v.x, v.y), (FixedPt::from_i8(3), FixedPt::from_i8(-4)));
+    }
+}
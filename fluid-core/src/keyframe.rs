@@ -0,0 +1,106 @@
+//! Keyframe animation of a 2D value (e.g. gravity) over frames: a track
+//! of keyframes, each reached from the previous one along an easing
+//! curve, so the value can sweep smoothly rather than step.
+
+
+/// How a keyframe's value is reached from the previous keyframe's
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Easing {
+    /// Hold the previous value, then jump at the keyframe
+    Step,
+    /// Move at a constant rate
+    Linear,
+    /// Start and finish gently (smoothstep)
+    EaseInOut,
+}
+
+impl Easing {
+    /// The fraction of the way between the keyframes, given the
+    /// fraction t (0 to 1) of the time between them
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+
+/// A value at a frame of a track
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Keyframe {
+    /// The frame, counted from the start of the track
+    pub frame: u16,
+    /// The value, e.g. gravity as (x, y) with +y down
+    pub value: (f32, f32),
+    /// How the value is reached from the previous keyframe's
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    /// A keyframe jumping to its value
+    pub const fn new(frame: u16, x: f32, y: f32) -> Self {
+        Self::eased(frame, x, y, Easing::Step)
+    }
+
+    /// A keyframe reached along the given easing curve
+    pub const fn eased(frame: u16, x: f32, y: f32, easing: Easing) -> Self {
+        Self { frame, value: (x, y), easing }
+    }
+}
+
+
+/// The value of a track of keyframes, in order of frame, at the given
+/// frame. The value is zero before the first keyframe, unless it eases
+/// in from zero, and holds after the last.
+pub fn sample(track: &[Keyframe], frame: u16) -> (f32, f32) {
+    let next = track.iter().position(|keyframe| keyframe.frame > frame);
+    let (previous, next) = match next {
+        Some(0) => (None, &track[0]),
+        Some(i) => (Some(&track[i - 1]), &track[i]),
+        None => return track.last().map_or((0.0, 0.0), |keyframe| keyframe.value),
+    };
+    let (start_frame, (x0, y0)) = previous.map_or((0, (0.0, 0.0)), |keyframe| (keyframe.frame, keyframe.value));
+    let t = (frame - start_frame) as f32 / (next.frame - start_frame) as f32;
+    let s = next.easing.apply(t);
+    let (x1, y1) = next.value;
+    (x0 + (x1 - x0) * s, y0 + (y1 - y0) * s)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK: [Keyframe; 3] = [
+        Keyframe::new(10, 0.0, 1.0),
+        Keyframe::eased(20, 1.0, 0.0, Easing::Linear),
+        Keyframe::eased(30, 0.0, -1.0, Easing::EaseInOut),
+    ];
+
+    #[test]
+    fn steps_hold_until_their_frame() {
+        assert_eq!(sample(&TRACK, 0), (0.0, 0.0));
+        assert_eq!(sample(&TRACK, 9), (0.0, 0.0));
+        assert_eq!(sample(&TRACK, 10), (0.0, 1.0));
+        assert_eq!(sample(&TRACK, 40), (0.0, -1.0));
+        assert_eq!(sample(&[], 5), (0.0, 0.0));
+    }
+
+    #[test]
+    fn linear_keyframes_are_interpolated_evenly() {
+        assert_eq!(sample(&TRACK, 15), (0.5, 0.5));
+        assert_eq!(sample(&TRACK, 20), (1.0, 0.0));
+    }
+
+    #[test]
+    fn eased_keyframes_start_and_finish_gently() {
+        let (x, y) = sample(&TRACK, 25);
+        assert!((x - 0.5).abs() < 1e-6 && (y + 0.5).abs() < 1e-6);
+        let (_, early) = sample(&TRACK, 21);
+        assert!(early > -0.1, "eased {} rather than -0.1 linearly", early);
+        let (_, late) = sample(&TRACK, 29);
+        assert!(late < -0.9);
+    }
+}
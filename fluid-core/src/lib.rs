@@ -0,0 +1,1144 @@
+//! A coarse, two-dimensional, particle-based fluid simulation using fixed
+//! point arithmetic, independent of any particular hardware. This is 
+//! `no_std` unless the `std` feature is enabled (e.g. for host tools).
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod fixed;
+pub mod keyframe;
+pub mod obstacle;
+pub mod replay;
+pub mod rng;
+pub mod scene;
+pub mod text;
+use fixed::{FixedPt, FixedPtVec2D, FixedPtNearFar, FixedPtViscosity};
+use obstacle::Rect;
+use rng::Rng;
+
+
+// Mark the end of a stage of the solver: in the defmt log, so the log's
+// timestamps give each stage's duration (debug builds only), and to the
+// caller's on_stage
+macro_rules! end_stage {
+    ($on_stage:ident, $stage:expr) => {{
+        #[cfg(all(feature = "defmt", debug_assertions))]
+        defmt::trace!("step: {}", $stage);
+        $on_stage($stage);
+    }};
+}
+
+
+/// The stages of the solver, in the order they run in each step
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Stage {
+    /// Gravity accelerates each particle
+    Gravity,
+    /// Viscous impulses between neighboring particles
+    Viscosity,
+    /// Positions are predicted from the velocities
+    Velocity,
+    /// Pressure impulses from the density around each particle
+    Relaxation,
+    /// Particles are kept within the bounds
+    Collisions,
+    /// Velocities are revised from the change in positions
+    Revision,
+}
+
+impl Stage {
+    /// Every stage, in order
+    pub const ALL: [Stage; 6] = [
+        Stage::Gravity,
+        Stage::Viscosity,
+        Stage::Velocity,
+        Stage::Relaxation,
+        Stage::Collisions,
+        Stage::Revision,
+    ];
+}
+
+
+#[derive(Copy, Clone)]
+pub struct Particle {
+    position: FixedPtVec2D,
+    previous_position: FixedPtVec2D,
+    velocity: FixedPtVec2D,
+    pub density: FixedPtNearFar,
+}
+
+impl Particle {
+    pub const fn new(x: i8, y: i8) -> Self {
+        Self {
+            position: FixedPtVec2D::from_i8s(x, y),
+            previous_position: FixedPtVec2D::from_i8s(x, y),
+            velocity: FixedPtVec2D::from_i8s(0, 0),
+            density: FixedPtNearFar::from_i8s(0, 0),
+        }
+    }
+
+    pub fn distance_to(&self, particle: &Self) -> FixedPt {
+        self.position.distance_to(&particle.position)
+    }
+
+    pub fn vector_to(&self, particle: &Self) -> FixedPtVec2D {
+        self.position.vector_to(&particle.position)
+    }
+
+    /// The speed at which the particles are approaching one another is the
+    /// dot product of the difference in velocity between the two particles
+    /// and unit vector pointing from this particle to the other.
+    /// Otherwise referred to as the inward radial velocity.
+    pub fn approach_speed_of(&self, particle: &Self) -> FixedPt {
+        let direction = self.position.vector_to(&particle.position).unit();
+        let velocity_diff = particle.velocity.vector_to(&self.velocity);
+        velocity_diff.dot(&direction)
+    }
+
+    pub fn get_display_position(&self) -> (i8, i8) {
+        (self.position.x.to_i8(), self.position.y.to_i8())
+    }
+
+    pub const fn set_position(&mut self, x: i8, y: i8) {
+        self.position = FixedPtVec2D::from_i8s(x, y);
+    }
+}
+
+
+/// An arrangement of the particles, at rest, e.g. to start a scene from
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Layout {
+    /// The particles spell out "FLUID", as a fluid is created
+    Logo,
+    /// A block of particles, columns wide, with its top left at (x, y),
+    /// e.g. to collapse like a breaking dam
+    Block { x: i8, y: i8, columns: u8 },
+    /// The particles at the given positions, in order, e.g. drawn as an
+    /// image (see the firmware's artwork)
+    Positions(&'static [(i8, i8)]),
+}
+
+
+/// The most obstacles a fluid can have, e.g. a maze's walls
+pub const MAX_OBSTACLES: usize = 16;
+
+/// The most mean kinetic energy the fluid can have, when its invariants
+/// are checked, before it's thought to have blown up: as if every particle crossed the
+/// interaction radius in a step
+#[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+pub const MAX_KINETIC_ENERGY: FixedPt = FixedPt::from_f32(128.0);
+
+
+/// An invariant of the solver's broken by a step. With the `invariants`
+/// feature, debug builds check the fluid after each step and freeze it
+/// once one's broken, so a fixed point bug is caught at the step it
+/// strikes rather than seen frames later. They're always checked in the
+/// host tests.
+#[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Violation {
+    /// The particle is outside the fluid's area
+    Position(usize),
+    /// The particle's density is negative
+    Density(usize),
+    /// The mean kinetic energy is over MAX_KINETIC_ENERGY
+    Energy,
+}
+
+
+pub struct Fluid<const N: usize> {
+    particles: [Particle; N],
+    particle_interaction_radius: FixedPt,
+    stiffness: FixedPtNearFar,
+    pub target_density: FixedPt,
+    viscosity: FixedPtViscosity, 
+    gravity: FixedPtVec2D,
+    x_max: FixedPt,
+    y_max: FixedPt,
+    impact: FixedPt,
+    obstacles: [Rect; MAX_OBSTACLES],
+    obstacle_count: usize,
+    buoyant_from: usize,
+    lift: FixedPt,
+    // whether a step broke an invariant, a bool rather than the
+    // violation so the fluid stays all zeros while empty
+    #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+    frozen: bool,
+}
+
+impl<const N: usize> Fluid<N> {
+    /// Create a fluid of N particles in a width by height area. This is 
+    /// const, so a fluid can be placed in a static without being built 
+    /// on the stack first.
+    pub const fn new(width: i8, height: i8) -> Self {
+        let mut fluid = Self::empty();
+        fluid.reset(width, height);
+        fluid
+    }
+
+    /// Create a fluid of N particles in no area, all at the origin, to be
+    /// reset before it's stepped. It's all zeros, so unlike a fluid
+    /// created by new, in a static it's zeroed at boot rather than copied
+    /// from flash, which saves its size in flash.
+    pub const fn empty() -> Self {
+        Fluid {
+            particles: [Particle::new(0, 0); N],
+            particle_interaction_radius: FixedPt::ZERO,
+            stiffness: FixedPtNearFar::ZERO,
+            target_density: FixedPt::ZERO,
+            viscosity: FixedPtViscosity::from_f32s(0.0, 0.0),
+            gravity: FixedPtVec2D::from_i8s(0, 0),
+            x_max: FixedPt::ZERO,
+            y_max: FixedPt::ZERO,
+            impact: FixedPt::ZERO,
+            obstacles: [Rect::EMPTY; MAX_OBSTACLES],
+            obstacle_count: 0,
+            buoyant_from: 0,
+            lift: FixedPt::ZERO,
+            #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+            frozen: false,
+        }
+    }
+
+    /// Reset the fluid, in place, to a fluid created by new with the
+    /// given area: its particles spell out the logo, at rest, and its
+    /// parameters are back to their defaults, without obstacles or a
+    /// buoyant phase, and it's thawed if it was frozen (see Violation)
+    pub const fn reset(&mut self, width: i8, height: i8) {
+        self.particle_interaction_radius = FixedPt::from_f32(16.0);
+        self.stiffness = FixedPtNearFar::from_f32s(4.0, 1.5);
+        self.target_density = FixedPt::from_f32(2.5);
+        self.viscosity = FixedPtViscosity::from_f32s(0.0, 0.10);
+        self.gravity = FixedPtVec2D::from_i8s(0, 0);
+        self.x_max = FixedPt::from_i8(width - 1);
+        self.y_max = FixedPt::from_i8(height - 1);
+        self.impact = FixedPt::ZERO;
+        self.obstacle_count = 0;
+        self.buoyant_from = N;
+        self.lift = FixedPt::ZERO;
+        #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+        {
+            self.frozen = false;
+        }
+
+        // Initialize Particle Positions, placing any the logo has no
+        // place for at the origin
+        let mut i = 0;
+        while i < N {
+            self.particles[i] = match i < Self::PARTICLE_POSITIONS_INIT.len() {
+                true => Particle::new(Self::PARTICLE_POSITIONS_INIT[i].0, Self::PARTICLE_POSITIONS_INIT[i].1),
+                false => Particle::new(0, 0),
+            };
+            i += 1;
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.step_with(|_| {});
+    }
+
+    /// Step the simulation, calling on_stage as each stage of the 
+    /// solver ends, e.g. to time the stages
+    pub fn step_with(&mut self, mut on_stage: impl FnMut(Stage)) {
+        //todo: do something better with this timestep
+        const DT: FixedPt = FixedPt::from_f32(0.9);
+
+        #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+        if self.frozen {
+            return;
+        }
+
+        // apply gravity to each particle
+        self.apply_gravity(DT);
+        end_stage!(on_stage, Stage::Gravity);
+
+        // apply viscosity
+        self.apply_viscosity(DT);
+        end_stage!(on_stage, Stage::Viscosity);
+
+        // update positions based on current velocity
+        self.apply_velocity(DT);
+        end_stage!(on_stage, Stage::Velocity);
+
+        // double density relaxation
+        self.double_density_relaxation(DT);
+        end_stage!(on_stage, Stage::Relaxation);
+
+        // resolve collisions
+        self.resolve_collisions();
+        end_stage!(on_stage, Stage::Collisions);
+
+        // revise velocity based on final positions
+        self.revise_velocity(DT);
+        end_stage!(on_stage, Stage::Revision);
+
+        // and freeze at the first broken invariant
+        #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+        if let Some(_violation) = self.check() {
+            #[cfg(feature = "defmt")]
+            defmt::error!("step: {}, frozen", _violation);
+            self.frozen = true;
+        }
+    }
+
+    /// Determine if a step broke an invariant, freezing the fluid until
+    /// it's reset (see Violation)
+    #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// The first invariant the fluid breaks, if it breaks one (see
+    /// Violation)
+    #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+    pub fn check(&self) -> Option<Violation> {
+        let outside = |value: FixedPt, max: FixedPt| value < FixedPt::ZERO || value > max;
+        let violation = self.particles.iter().enumerate().find_map(|(i, particle)| {
+            let (position, density) = (particle.position, particle.density);
+            match () {
+                _ if outside(position.x, self.x_max) || outside(position.y, self.y_max) => Some(Violation::Position(i)),
+                _ if density.near < FixedPt::ZERO || density.far < FixedPt::ZERO => Some(Violation::Density(i)),
+                _ => None,
+            }
+        });
+        violation.or((self.kinetic_energy() > MAX_KINETIC_ENERGY).then_some(Violation::Energy))
+    }
+
+    pub fn set_gravity(&mut self, gx: f32, gy: f32) {
+        self.gravity = FixedPtVec2D::from_f32s(gx, gy);
+    }
+
+    /// Take another fluid's gravity, e.g. for fluids side by side under
+    /// the same tilt
+    pub fn copy_gravity<const M: usize>(&mut self, fluid: &Fluid<M>) {
+        self.gravity = fluid.gravity;
+    }
+
+    /// Change the fluid's area to width by height, keeping its particles
+    /// and parameters, e.g. to share the display with another fluid. Any
+    /// particles outside it are pushed in.
+    pub fn set_area(&mut self, width: i8, height: i8) {
+        self.x_max = FixedPt::from_i8(width - 1);
+        self.y_max = FixedPt::from_i8(height - 1);
+        self.resolve_collisions();
+    }
+
+    /// Make the particles from the given index on a second phase of the
+    /// fluid, e.g. a lava lamp's wax, lifted against gravity by lift times
+    /// gravity: with a positive lift they're lighter than the rest and
+    /// float up through it, over 1 rising against gravity altogether, and
+    /// with a negative lift they're heavier and sink through it. A lift
+    /// of 0 makes them one phase with the rest again.
+    pub fn set_buoyancy(&mut self, from: usize, lift: f32) {
+        self.buoyant_from = from.min(N);
+        self.lift = FixedPt::from_f32(lift);
+    }
+
+    /// Set the linear (sigma) and quadratic (beta) viscosity coefficients
+    pub fn set_viscosity(&mut self, sigma: f32, beta: f32) {
+        self.viscosity = FixedPtViscosity::from_f32s(sigma, beta);
+    }
+
+    /// Change the velocity of the particles within radius of (x, y) by 
+    /// the impulse (ix, iy), falling off linearly with their distance to 
+    /// nothing at the radius, e.g. to splash the fluid
+    pub fn apply_impulse_at(&mut self, x: i8, y: i8, radius: i8, ix: f32, iy: f32) {
+        let centre = FixedPtVec2D::from_i8s(x, y);
+        let radius = FixedPt::from_i8(radius);
+        let impulse = FixedPtVec2D::from_f32s(ix, iy);
+        for particle in self.particles.iter_mut() {
+            let distance = particle.position.distance_to(&centre);
+            if distance < radius {
+                particle.velocity += impulse * ((radius - distance) / radius);
+            }
+        }
+    }
+
+    /// Fling the particles within radius of (x, y) out from it, and up
+    /// against gravity, at up to speed, falling off linearly with their
+    /// distance to nothing at the radius, e.g. the spray of a drop
+    /// landing in a pool
+    pub fn spray(&mut self, x: i8, y: i8, radius: i8, speed: f32) {
+        let centre = FixedPtVec2D::from_i8s(x, y);
+        let radius = FixedPt::from_i8(radius);
+        let speed = FixedPt::from_f32(speed);
+        for particle in self.particles.iter_mut() {
+            let outward = centre.vector_to(&particle.position);
+            let distance = outward.magnitude();
+            if distance < radius && distance > FixedPt::ZERO {
+                particle.velocity += (outward / distance - self.gravity) * (speed * ((radius - distance) / radius));
+            }
+        }
+    }
+
+    /// Draw the particles within radius of (x, y) towards it, at up to
+    /// speed, falling off linearly with their distance to nothing at the
+    /// radius, e.g. to gather the fluid around a point
+    pub fn attract(&mut self, x: i8, y: i8, radius: i8, speed: f32) {
+        let centre = FixedPtVec2D::from_i8s(x, y);
+        let radius = FixedPt::from_i8(radius);
+        let speed = FixedPt::from_f32(speed);
+        for particle in self.particles.iter_mut() {
+            let inward = particle.position.vector_to(&centre);
+            let distance = inward.magnitude();
+            if distance < radius && distance > FixedPt::ZERO {
+                particle.velocity += inward / distance * (speed * ((radius - distance) / radius));
+            }
+        }
+    }
+
+    /// Nudge each particle by up to amount along each axis, e.g. so the
+    /// initial layout doesn't play out the same way every run
+    pub fn jitter(&mut self, rng: &mut Rng, amount: FixedPt) {
+        for particle in self.particles.iter_mut() {
+            particle.position.x += rng.next_fixed(amount);
+            particle.position.y += rng.next_fixed(amount);
+            particle.previous_position = particle.position;
+        }
+        self.resolve_collisions();
+    }
+
+    /// Rearrange the particles at rest in the given layout. Particles the
+    /// logo, or the positions, have no place for are placed at the origin.
+    pub fn arrange(&mut self, layout: Layout) {
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            let (x, y) = match layout {
+                Layout::Logo => Self::PARTICLE_POSITIONS_INIT.get(i).copied().unwrap_or((0, 0)),
+                Layout::Block { x, y, columns } => {
+                    let columns = columns.max(1) as usize;
+                    (x + ((i % columns) as i8) * Self::LAYOUT_SPACING, y + ((i / columns) as i8) * Self::LAYOUT_SPACING)
+                },
+                Layout::Positions(positions) => positions.get(i).copied().unwrap_or((0, 0)),
+            };
+            *particle = Particle::new(x, y);
+        }
+        self.resolve_collisions();
+    }
+
+    /// Rearrange the particles at rest at the given positions, e.g. of
+    /// text (see text.rs). Particles left over rest in rows along the
+    /// floor, and positions left over are ignored.
+    pub fn arrange_at(&mut self, positions: impl IntoIterator<Item = (i8, i8)>) {
+        let mut positions = positions.into_iter();
+        let per_row = (self.x_max.to_i8() / Self::LAYOUT_SPACING + 1) as usize;
+        let mut left_over = 0;
+        for particle in self.particles.iter_mut() {
+            let (x, y) = positions.next().unwrap_or_else(|| {
+                let (column, row) = (left_over % per_row, left_over / per_row);
+                left_over += 1;
+                ((column as i8) * Self::LAYOUT_SPACING, self.y_max.to_i8() - (row as i8) * Self::LAYOUT_SPACING)
+            });
+            *particle = Particle::new(x, y);
+        }
+        self.resolve_collisions();
+    }
+
+    /// Place obstacles the particles can't enter, e.g. a maze's walls,
+    /// replacing any before. Particles already within them are pushed
+    /// out in the next step. There can be up to MAX_OBSTACLES; returns
+    /// false, leaving the obstacles as they were, if there are more.
+    pub fn set_obstacles(&mut self, obstacles: &[Rect]) -> bool {
+        if obstacles.len() > MAX_OBSTACLES {
+            return false;
+        }
+        self.obstacles[..obstacles.len()].copy_from_slice(obstacles);
+        self.obstacle_count = obstacles.len();
+        true
+    }
+
+    /// Move one of the obstacles, e.g. a paddle, by its index in the
+    /// obstacles placed. The particles in its way are pushed aside in
+    /// the next step, taking on its speed, as long as it moves by less
+    /// than half its size at a time (or they're pushed out the back).
+    /// Returns false, moving nothing, if there's no obstacle at the index.
+    pub fn move_obstacle(&mut self, index: usize, obstacle: Rect) -> bool {
+        match self.obstacles[..self.obstacle_count].get_mut(index) {
+            Some(placed) => {
+                *placed = obstacle;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The number of particles within the rectangle, e.g. to tell when
+    /// the fluid has reached a goal
+    pub fn particles_in(&self, rect: Rect) -> usize {
+        self.particles.iter().filter(|particle| {
+            let (x, y) = particle.get_display_position();
+            rect.contains(x, y)
+        }).count()
+    }
+
+    /// The index of a particle within the rectangle, if any, e.g. to
+    /// take from a sink and emit elsewhere (see emit)
+    pub fn particle_in(&self, rect: Rect) -> Option<usize> {
+        self.particles.iter().position(|particle| {
+            let (x, y) = particle.get_display_position();
+            rect.contains(x, y)
+        })
+    }
+
+    /// Emit the particle at the index from (x, y), at the velocity
+    /// (vx, vy), taking it from wherever it was: as the particles are
+    /// fixed in number, an emitter, e.g. a rain cloud, is fed from a sink,
+    /// e.g. a drain (see particle_in)
+    pub fn emit(&mut self, index: usize, x: i8, y: i8, vx: f32, vy: f32) {
+        let particle = &mut self.particles[index];
+        *particle = Particle::new(x, y);
+        particle.velocity = FixedPtVec2D::from_f32s(vx, vy);
+    }
+
+    /// The speed, in pixels per step, of the fastest particle to hit a
+    /// wall in the last step, or zero if none did, e.g. to make a sound
+    /// as the fluid splashes against the walls
+    pub fn impact(&self) -> FixedPt {
+        self.impact
+    }
+
+    /// The particles' mean kinetic energy, half their squared speed, in
+    /// pixels squared per step squared, e.g. to show how lively the
+    /// fluid is
+    pub fn kinetic_energy(&self) -> FixedPt {
+        let total = self.particles.iter().fold(FixedPt::ZERO, |total, particle| total + particle.velocity.dot(&particle.velocity));
+        total / (2 * N.max(1) as i32)
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn get_particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Serialize the particles' state: each one's position, previous
+    /// position and velocity, as raw little-endian values, e.g. to compare
+    /// runs bit for bit (see replay.rs)
+    pub fn write_state(&self, mut out: impl FnMut(&[u8])) {
+        for particle in &self.particles {
+            let (position, previous, velocity) = (particle.position, particle.previous_position, particle.velocity);
+            for value in [position.x, position.y, previous.x, previous.y, velocity.x, velocity.y] {
+                out(&value.value.to_le_bytes());
+            }
+        }
+    }
+
+    fn apply_gravity(&mut self, dt: FixedPt) {
+        let delta_v = self.gravity * dt;
+        for particle in &mut self.particles {
+            particle.velocity += delta_v;
+        }
+        if self.lift != FixedPt::ZERO {
+            let lift_v = delta_v * self.lift;
+            for particle in &mut self.particles[self.buoyant_from..] {
+                particle.velocity -= lift_v;
+            }
+        }
+    }
+
+    fn apply_viscosity(&mut self, dt: FixedPt) {
+        for i in 0..self.particle_count() {
+            for j in (i + 1)..self.particle_count() {
+                let distance_vector = self.particles[i].vector_to(&self.particles[j]);
+                let distance = distance_vector.magnitude();
+                if distance < self.particle_interaction_radius && distance > FixedPt::ZERO {
+                    // get the unit vector pointing from this particle to the neighbor
+                    let direction = distance_vector / distance;
+                    // calculate the inward radial velocity
+                    let irv = self.particles[i].approach_speed_of(&self.particles[j]);
+                    if irv > FixedPt::ZERO {
+                        // apply the linear viscosity kernel and quadratic viscosity impulses
+                        let viscosity_kernel = FixedPt::from_i8(1) - distance / self.particle_interaction_radius;
+                        let viscosity_impulse = direction * viscosity_kernel * (self.viscosity.sigma * irv + self.viscosity.beta * irv * irv) * dt;
+                        self.particles[i].velocity -= viscosity_impulse / 2;
+                        self.particles[j].velocity += viscosity_impulse / 2;
+                    }
+                    
+                }
+            }
+        }
+    }
+
+    fn apply_velocity(&mut self, dt: FixedPt) {
+        for particle in self.particles.iter_mut() {
+            particle.previous_position = particle.position;
+            particle.position += particle.velocity * dt;
+        }
+    }
+
+    fn double_density_relaxation(&mut self, dt: FixedPt) {
+        for i in 0..self.particle_count() {
+            // reset density
+            self.particles[i].density = FixedPtNearFar::ZERO;
+            // compute density and near density
+            for j in 0..self.particle_count() {
+                if i == j { 
+                    continue;
+                }
+                let distance = self.particles[i].distance_to(&self.particles[j]);
+                if distance < self.particle_interaction_radius {
+                    let linear_kernel = (self.particle_interaction_radius - distance) / self.particle_interaction_radius;
+                    let quadratic_kernel = linear_kernel * linear_kernel;
+                    let cubic_kernel = quadratic_kernel * linear_kernel;
+                    let density_contibution = FixedPtNearFar {  
+                        near: cubic_kernel,
+                        far: quadratic_kernel,
+                    };
+                    self.particles[i].density += density_contibution;
+                }
+            }
+            // compute pressure and near pressure, which are only needed
+            // for this particle's impulses, so they aren't kept
+            let pfar = self.stiffness.far * (self.particles[i].density.far - self.target_density);
+            let pnear = self.stiffness.near * self.particles[i].density.near;
+            // apply pressure impulse between neighboring particles
+            for j in 0..self.particle_count() {
+                if i == j { 
+                    continue;
+                }
+                let distance_vector = self.particles[i].vector_to(&self.particles[j]);
+                let distance = distance_vector.magnitude();
+                if distance < self.particle_interaction_radius && distance > FixedPt::ZERO {
+                    let direction = distance_vector / distance;
+                    let linear_kernel = (self.particle_interaction_radius - distance) / self.particle_interaction_radius;
+                    let quadratic_kernel = linear_kernel * linear_kernel;
+                    let pressure_impulse = direction * (pfar * linear_kernel + pnear * quadratic_kernel) * dt * dt;
+                    self.particles[i].position -= pressure_impulse / 2;
+                    self.particles[j].position += pressure_impulse / 2;
+                }
+            }
+        }
+    }
+
+    fn resolve_collisions(&mut self) {
+        let mut impact = FixedPt::ZERO;
+        for particle in self.particles.iter_mut() {
+            // Ensure particles stay within defined boundaries, noting the
+            // speed of those hitting them
+            let velocity = particle.velocity;
+            particle.position.x = match particle.position.x {
+                x if x < FixedPt::ZERO => { impact = impact.max(velocity.x.abs()); FixedPt::ZERO },
+                x if x > self.x_max => { impact = impact.max(velocity.x.abs()); self.x_max },
+                x => x,
+            };
+            particle.position.y = match particle.position.y {
+                y if y < FixedPt::ZERO => { impact = impact.max(velocity.y.abs()); FixedPt::ZERO },
+                y if y > self.y_max => { impact = impact.max(velocity.y.abs()); self.y_max },
+                y => y,
+            };
+
+            // and out of the obstacles, which are within the boundaries
+            let velocity = [velocity.x, velocity.y];
+            let max = FixedPtVec2D { x: self.x_max, y: self.y_max };
+            for obstacle in &self.obstacles[..self.obstacle_count] {
+                if let Some(axis) = obstacle.push_out(&mut particle.position, particle.previous_position, max) {
+                    impact = impact.max(velocity[axis].abs());
+                }
+            }
+        }
+        self.impact = impact;
+    }
+
+    fn revise_velocity(&mut self, dt: FixedPt) {
+        for particle in self.particles.iter_mut() {
+            particle.velocity = (particle.position - particle.previous_position) / dt;
+        }
+    }
+
+    // The distance between neighbouring particles in a block layout,
+    // as in the logo
+    const LAYOUT_SPACING: i8 = 6;
+
+    const PARTICLE_POSITIONS_INIT: [(i8, i8); 86] = [
+        // F
+        ( 0, 17),
+        ( 0, 23),
+        ( 0, 29),
+        ( 0, 35),
+        ( 6, 23),
+        ( 6, 35),
+        (12, 35),
+        // L
+        (21, 17),
+        (21, 23),
+        (21, 29),
+        (21, 35),
+        (27, 17),
+        (33, 17),
+        // U
+        (42, 17),
+        (42, 23),
+        (42, 29),
+        (42, 35),
+        (48, 17),
+        (54, 17),
+        (54, 23),
+        (54, 29),
+        (54, 35),
+        // I
+        (63, 17),
+        (63, 35),
+        (69, 17),
+        (69, 23),
+        (69, 29),
+        (69, 35),
+        (75, 17),
+        (75, 35),
+        // D
+        (84, 17),
+        (84, 23),
+        (84, 29),
+        (84, 35),
+        (90, 17),
+        (90, 35),
+        (96, 23),
+        (96, 29),
+        // [Drop]
+	    (105, 14),
+	    (105, 20),
+	    (108,  8),
+	    (108, 26),
+	    (111, 32),
+	    (114,  5),
+	    (114, 38),
+	    (117, 32),
+	    (120,  8),
+	    (120, 26),
+	    (123, 14),
+	    (123, 20),
+        // overflow rows
+	    (  3, 5),
+	    ( 13, 5),
+	    ( 23, 5),
+	    ( 33, 5),
+	    ( 43, 5),
+	    ( 53, 5),
+	    ( 63, 5),
+	    ( 73, 5),
+	    ( 83, 5),
+	    ( 93, 5),
+	    (89, 44),
+	    (95, 44),
+	    (29, 50),
+	    (35, 50),
+	    (41, 50),
+	    (47, 50),
+	    (53, 50),
+	    (59, 50),
+	    (65, 50),
+	    (71, 50),
+	    (77, 50),
+	    (83, 50),
+	    (89, 50),
+	    (95, 50),
+	    (29, 56),
+	    (35, 56),
+	    (41, 56),
+	    (47, 56),
+	    (53, 56),
+	    (59, 56),
+	    (65, 56),
+	    (71, 56),
+	    (77, 56),
+	    (83, 56),
+	    (89, 56),
+	    (95, 56),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: i8 = 125;
+    const HEIGHT: i8 = 61;
+
+    // A fluid of N particles at the given positions, at rest
+    fn fluid_at<const N: usize>(positions: [(i8, i8); N]) -> Fluid<N> {
+        let mut fluid = Fluid::<N>::new(WIDTH, HEIGHT);
+        for (particle, (x, y)) in fluid.particles.iter_mut().zip(positions) {
+            *particle = Particle::new(x, y);
+        }
+        fluid
+    }
+
+    // The sum of the raw particle positions, which symmetric impulses preserve
+    fn position_sum<const N: usize>(fluid: &Fluid<N>) -> (i64, i64) {
+        fluid.particles.iter().fold((0, 0), |(x, y), particle| {
+            (x + particle.position.x.value as i64, y + particle.position.y.value as i64)
+        })
+    }
+
+    // A fingerprint of the complete simulation state
+    fn checksum<const N: usize>(fluid: &Fluid<N>) -> u32 {
+        fluid.particles.iter()
+            .flat_map(|p| [p.position.x, p.position.y, p.velocity.x, p.velocity.y])
+            .fold(0x811C_9DC5, |hash, value| (hash ^ value.value as u32).wrapping_mul(0x0100_0193))
+    }
+
+    #[test]
+    fn kernels_are_one_at_zero_distance_and_vanish_at_the_radius() {
+        let fluid = Fluid::<1>::new(WIDTH, HEIGHT);
+        let radius = fluid.particle_interaction_radius;
+        let kernel = |distance: FixedPt| (radius - distance) / radius;
+        assert_eq!(kernel(FixedPt::ZERO), FixedPt::from_i8(1));
+        assert_eq!(kernel(radius), FixedPt::ZERO);
+        assert_eq!(kernel(radius / 2), FixedPt::from_f32(0.5));
+    }
+
+    #[test]
+    fn density_of_a_pair_follows_the_kernels() {
+        // half the interaction radius apart: far density is 0.5^2, near is 0.5^3
+        // (only the first particle is checked, before the pair is pushed apart)
+        let mut fluid = fluid_at([(60, 30), (68, 30)]);
+        fluid.double_density_relaxation(FixedPt::from_i8(1));
+        assert_eq!(fluid.particles[0].density.far, FixedPt::from_f32(0.25));
+        assert_eq!(fluid.particles[0].density.near, FixedPt::from_f32(0.125));
+    }
+
+    #[test]
+    fn distant_particles_do_not_interact() {
+        let mut fluid = fluid_at([(10, 30), (60, 30)]);
+        for _ in 0..20 {
+            fluid.step();
+        }
+        assert_eq!(fluid.particles[0].get_display_position(), (10, 30));
+        assert_eq!(fluid.particles[1].get_display_position(), (60, 30));
+    }
+
+    #[test]
+    fn crowded_particles_push_apart() {
+        // denser than the target density, so the pressure is repulsive
+        let mut fluid = fluid_at([
+            (58, 28), (60, 28), (62, 28),
+            (58, 30), (60, 30), (62, 30),
+            (58, 32), (60, 32), (62, 32),
+        ]);
+        let spread = |fluid: &Fluid<9>| fluid.particles[0].distance_to(&fluid.particles[8]);
+        let before = spread(&fluid);
+        fluid.step();
+        assert!(spread(&fluid) > before);
+    }
+
+    #[test]
+    fn pressure_conserves_the_centre_of_mass() {
+        // without gravity or walls, impulses are equal and opposite
+        let mut fluid = fluid_at([(58, 28), (62, 30), (60, 33), (64, 26), (57, 32)]);
+        let before = position_sum(&fluid);
+        for _ in 0..10 {
+            fluid.double_density_relaxation(FixedPt::from_f32(0.9));
+        }
+        assert_eq!(position_sum(&fluid), before);
+    }
+
+    #[test]
+    fn viscosity_conserves_momentum() {
+        let mut fluid = fluid_at([(60, 30), (66, 30)]);
+        fluid.particles[0].velocity = FixedPtVec2D::from_i8s(2, 0);
+        fluid.particles[1].velocity = FixedPtVec2D::from_i8s(-1, 0);
+        let momentum = |fluid: &Fluid<2>| fluid.particles[0].velocity + fluid.particles[1].velocity;
+        let before = momentum(&fluid);
+        fluid.apply_viscosity(FixedPt::from_f32(0.9));
+        let after = momentum(&fluid);
+        assert_eq!((after.x, after.y), (before.x, before.y));
+        assert!(fluid.particles[0].velocity.x < FixedPt::from_i8(2));
+    }
+
+    #[test]
+    fn particles_stay_within_the_bounds() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        for (step, gravity) in [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)].iter().cycle().take(8).enumerate() {
+            fluid.set_gravity(gravity.0, gravity.1);
+            for _ in 0..50 {
+                fluid.step();
+            }
+            for particle in fluid.get_particles() {
+                let (x, y) = particle.get_display_position();
+                assert!((0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y), "({}, {}) after {} steps", x, y, step);
+            }
+        }
+    }
+
+    #[test]
+    fn a_smaller_area_pushes_the_particles_in() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.set_area(62, HEIGHT);
+        fluid.step();
+        assert!(fluid.get_particles().iter().all(|p| p.get_display_position().0 < 62));
+    }
+
+    #[test]
+    fn gravity_settles_the_fluid_at_the_bottom() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..300 {
+            fluid.step();
+        }
+        let lowest = fluid.get_particles().iter().map(|p| p.get_display_position().1).max();
+        let mean = fluid.get_particles().iter().map(|p| p.get_display_position().1 as i32).sum::<i32>() / 60;
+        assert_eq!(lowest, Some(HEIGHT - 1));
+        assert!(mean > HEIGHT as i32 / 2);
+    }
+
+    #[test]
+    fn golden_state_after_startup() {
+        // The state after the firmware's opening sequence: at rest, then
+        // falling. Update these deliberately when the solver changes.
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        assert_eq!(checksum(&fluid), GOLDEN[0], "at rest");
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        assert_eq!(checksum(&fluid), GOLDEN[1], "falling");
+    }
+
+    #[test]
+    fn step_with_reports_each_stage_in_order() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        let mut plain = Fluid::<60>::new(WIDTH, HEIGHT);
+        let mut stages = Vec::new();
+        fluid.step_with(|stage| stages.push(stage));
+        plain.step();
+        assert_eq!(stages, Stage::ALL);
+        assert_eq!(checksum(&fluid), checksum(&plain));
+    }
+
+    #[test]
+    fn impulses_move_only_nearby_particles() {
+        let mut fluid = fluid_at([(20, 30), (26, 30), (100, 30)]);
+        fluid.apply_impulse_at(20, 30, 12, 2.0, -1.0);
+        let [centre, edge, outside] = fluid.particles.map(|p| p.velocity);
+        assert_eq!((centre.x, centre.y), (FixedPt::from_i8(2), FixedPt::from_i8(-1)));
+        assert_eq!((edge.x, edge.y), (FixedPt::from_i8(1), FixedPt::from_f32(-0.5)));
+        assert_eq!((outside.x, outside.y), (FixedPt::ZERO, FixedPt::ZERO));
+        fluid.step();
+        assert_eq!(fluid.particles[2].get_display_position(), (100, 30));
+        assert!(fluid.particles[0].get_display_position().0 > 20);
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, any(test, feature = "invariants")))]
+    fn a_blown_up_step_freezes_the_fluid() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        assert!(!fluid.is_frozen());
+
+        let mut fluid = fluid_at([(20, 30), (100, 30)]);
+        fluid.particles[0].velocity = FixedPtVec2D::from_i8s(30, 0);
+        fluid.step();
+        assert!(fluid.is_frozen());
+        assert_eq!(fluid.check(), Some(Violation::Energy));
+        let frozen = checksum(&fluid);
+        fluid.step();
+        assert_eq!(checksum(&fluid), frozen);
+
+        fluid.reset(WIDTH, HEIGHT);
+        assert!(!fluid.is_frozen());
+    }
+
+    #[test]
+    fn jitter_varies_with_the_seed_within_the_bounds() {
+        let jittered = |seed| {
+            let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+            fluid.jitter(&mut Rng::new(seed), FixedPt::from_f32(0.5));
+            fluid
+        };
+        let (a, b, c) = (jittered(1), jittered(1), jittered(2));
+        assert_eq!(checksum(&a), checksum(&b));
+        assert_ne!(checksum(&a), checksum(&c));
+        assert_ne!(checksum(&a), checksum(&Fluid::<60>::new(WIDTH, HEIGHT)));
+        for particle in a.get_particles() {
+            let (x, y) = particle.get_display_position();
+            assert!((0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y));
+        }
+    }
+
+    #[test]
+    fn arranging_restarts_the_particles_at_rest_in_the_layout() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..10 {
+            fluid.step();
+        }
+        fluid.arrange(Layout::Logo);
+        assert_eq!(checksum(&fluid), checksum(&Fluid::<60>::new(WIDTH, HEIGHT)));
+
+        fluid.arrange(Layout::Block { x: 10, y: 4, columns: 8 });
+        let particles = fluid.get_particles();
+        assert_eq!(particles[0].get_display_position(), (10, 4));
+        assert_eq!(particles[9].get_display_position(), (16, 10));
+        assert!(particles.iter().all(|particle| particle.velocity.x == FixedPt::ZERO && particle.velocity.y == FixedPt::ZERO));
+
+        fluid.arrange(Layout::Positions(&[(20, 30), (26, 30)]));
+        let particles = fluid.get_particles();
+        assert_eq!(particles[1].get_display_position(), (26, 30));
+        assert_eq!(particles[2].get_display_position(), (0, 0));
+    }
+
+    #[test]
+    fn particles_without_a_position_rest_along_the_floor() {
+        let mut fluid = Fluid::<30>::new(WIDTH, HEIGHT);
+        fluid.arrange_at([(40, 10), (46, 10)]);
+        let particles = fluid.get_particles();
+        assert_eq!(particles[1].get_display_position(), (46, 10));
+        assert_eq!(particles[2].get_display_position(), (0, HEIGHT - 1));
+        assert_eq!(particles[3].get_display_position(), (6, HEIGHT - 1));
+        assert_eq!(particles[23].get_display_position(), (0, HEIGHT - 7));
+    }
+
+    #[test]
+    fn impacts_with_the_walls_are_reported() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.step();
+        assert_eq!(fluid.impact(), FixedPt::ZERO);
+
+        fluid.set_gravity(0.0, 1.0);
+        let impacts: Vec<FixedPt> = (0..40).map(|_| { fluid.step(); fluid.impact() }).collect();
+        assert!(impacts.iter().any(|&impact| impact > FixedPt::from_i8(2)));
+    }
+
+    #[test]
+    fn kinetic_energy_grows_as_the_fluid_falls() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        assert_eq!(fluid.kinetic_energy(), FixedPt::ZERO);
+
+        fluid.set_gravity(0.0, 1.0);
+        let energies: Vec<FixedPt> = (0..5).map(|_| { fluid.step(); fluid.kinetic_energy() }).collect();
+        assert!(energies.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+
+    #[test]
+    fn resetting_an_empty_fluid_matches_a_new_one() {
+        let mut fluid = Fluid::<60>::empty();
+        assert!(fluid.get_particles().iter().all(|particle| particle.get_display_position() == (0, 0)));
+        fluid.reset(WIDTH, HEIGHT);
+        assert_eq!(checksum(&fluid), checksum(&Fluid::<60>::new(WIDTH, HEIGHT)));
+        for _ in 0..100 {
+            fluid.step();
+        }
+        assert_eq!(checksum(&fluid), GOLDEN[0]);
+    }
+
+    #[test]
+    fn obstacles_hold_the_fluid_and_push_it_as_they_move() {
+        // a shelf across the display, with the fluid falling onto it
+        let shelf = Rect::new(0, 40, WIDTH, 6);
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.arrange(Layout::Block { x: 0, y: 0, columns: 20 });
+        fluid.set_obstacles(&[shelf]);
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        let below = Rect::new(0, 41, WIDTH, HEIGHT);
+        assert_eq!(fluid.particles_in(below), 0);
+        assert_eq!(fluid.particles_in(Rect::new(0, 0, WIDTH, 40)), 60);
+
+        // raising the shelf lifts the fluid with it
+        fluid.move_obstacle(0, Rect::new(0, 37, WIDTH, 9));
+        fluid.step();
+        assert_eq!(fluid.particles_in(Rect::new(0, 38, WIDTH, HEIGHT)), 0);
+        assert!(fluid.get_particles().iter().any(|particle| particle.velocity.y < FixedPt::ZERO));
+    }
+
+    #[test]
+    fn too_many_obstacles_are_refused_leaving_those_placed() {
+        let shelf = Rect::new(0, 40, WIDTH, 6);
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.arrange(Layout::Block { x: 0, y: 0, columns: 20 });
+        assert!(fluid.set_obstacles(&[shelf]));
+        assert!(!fluid.set_obstacles(&[Rect::new(0, 0, 1, 1); MAX_OBSTACLES + 1]));
+
+        // the shelf still holds the fluid
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        assert_eq!(fluid.particles_in(Rect::new(0, 41, WIDTH, HEIGHT)), 0);
+        assert!(fluid.set_obstacles(&[shelf; MAX_OBSTACLES]));
+    }
+
+    #[test]
+    fn moving_an_obstacle_not_placed_is_refused() {
+        let shelf = Rect::new(0, 40, WIDTH, 6);
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        assert!(!fluid.move_obstacle(0, shelf));
+        fluid.set_obstacles(&[shelf, shelf]);
+        assert!(fluid.move_obstacle(1, Rect::new(0, 37, WIDTH, 9)));
+        assert!(!fluid.move_obstacle(2, shelf));
+        assert!(!fluid.move_obstacle(MAX_OBSTACLES, shelf));
+    }
+
+    #[test]
+    fn a_buoyant_phase_rises_through_the_rest_and_sinks_again() {
+        // the bottom row of a block of fluid is the lighter phase
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.arrange(Layout::Block { x: 2, y: 31, columns: 20 });
+        fluid.set_gravity(0.0, 1.0);
+        let mean_y = |fluid: &Fluid<60>, particles: core::ops::Range<usize>| {
+            let count = particles.len() as i32;
+            fluid.particles[particles].iter().map(|particle| particle.position.y.to_i8() as i32).sum::<i32>() / count
+        };
+
+        fluid.set_buoyancy(40, 1.6);
+        for _ in 0..200 {
+            fluid.step();
+        }
+        assert!(mean_y(&fluid, 40..60) < mean_y(&fluid, 0..40));
+
+        fluid.set_buoyancy(40, -1.0);
+        for _ in 0..300 {
+            fluid.step();
+        }
+        assert!(mean_y(&fluid, 40..60) > mean_y(&fluid, 0..40));
+    }
+
+    #[test]
+    fn a_sink_feeds_an_emitter_and_spray_flies_up_from_a_pool() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.arrange(Layout::Block { x: 0, y: 43, columns: 21 });
+        fluid.set_gravity(0.0, 1.0);
+        for _ in 0..50 {
+            fluid.step();
+        }
+
+        // a particle drained from the bottom left corner falls from above
+        let index = fluid.particle_in(Rect::new(0, 52, 8, 9)).unwrap();
+        fluid.emit(index, 60, 0, 0.0, 2.0);
+        assert_eq!(fluid.get_particles()[index].get_display_position(), (60, 0));
+        fluid.step();
+        assert!(fluid.get_particles()[index].position.y > FixedPt::from_i8(2));
+
+        // spray from the pool's surface flies up out of it
+        let above = Rect::new(0, 0, WIDTH, 44);
+        let airborne = fluid.particles_in(above);
+        fluid.spray(60, 52, 16, 5.0);
+        for _ in 0..3 {
+            fluid.step();
+        }
+        assert!(fluid.particles_in(above) > airborne);
+    }
+
+    #[test]
+    fn an_attractor_gathers_the_fluid_around_it() {
+        let mut fluid = Fluid::<60>::new(WIDTH, HEIGHT);
+        fluid.arrange(Layout::Block { x: 0, y: 0, columns: 20 });
+        let around = Rect::new(40, 10, 40, 40);
+        let gathered = fluid.particles_in(around);
+        for _ in 0..100 {
+            fluid.attract(60, 30, 80, 0.5);
+            fluid.step();
+        }
+        assert!(fluid.particles_in(around) > gathered + 20);
+    }
+
+    const GOLDEN: [u32; 2] = [0xCE4C_E1C5, 0x65FC_74E8];
+}
@@ -0,0 +1,104 @@
+//! Rectangles: obstacles the particles can't enter, e.g. a maze's walls
+//! or a game's paddles (see Fluid::set_obstacles), and regions of the
+//! fluid to count the particles within (see Fluid::particles_in).
+
+use crate::fixed::{FixedPt, FixedPtVec2D};
+
+
+/// A rectangle, with its top left at (x, y), in pixels
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x: i8,
+    pub y: i8,
+    pub width: i8,
+    pub height: i8,
+}
+
+impl Rect {
+    pub const EMPTY: Rect = Rect::new(0, 0, 0, 0);
+
+    pub const fn new(x: i8, y: i8, width: i8, height: i8) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Determine if the point is within the rectangle, or on its edge
+    pub fn contains(&self, x: i8, y: i8) -> bool {
+        let (x, y) = (x as i16, y as i16);
+        (self.x as i16..=self.x as i16 + self.width as i16).contains(&x)
+            && (self.y as i16..=self.y as i16 + self.height as i16).contains(&y)
+    }
+
+    /// Push a point inside the rectangle, or that passed through it, out
+    /// to the edge it came in through, given where it was before, or if
+    /// the rectangle moved onto it, the edge it was nearest. It can't be
+    /// pushed through an edge on or past the boundaries, from the origin
+    /// to max. Returns the axis it was pushed along, if it was pushed:
+    /// x is 0, y is 1.
+    pub(crate) fn push_out(&self, position: &mut FixedPtVec2D, previous: FixedPtVec2D, max: FixedPtVec2D) -> Option<usize> {
+        let left = FixedPt::from_i8(self.x);
+        let top = FixedPt::from_i8(self.y);
+        let right = left + FixedPt::from_i8(self.width);
+        let bottom = top + FixedPt::from_i8(self.height);
+
+        // A point on the edge is within, e.g. against a boundary the
+        // rectangle meets, and one moving fast enough may have passed
+        // right through
+        let within = |value: FixedPt, previous: FixedPt, low: FixedPt, high: FixedPt| {
+            (low <= value && value <= high) || (previous <= low && value >= high) || (previous >= high && value <= low)
+        };
+        if !(within(position.x, previous.x, left, right) && within(position.y, previous.y, top, bottom)) {
+            return None;
+        }
+
+        let open = [left > FixedPt::ZERO, right < max.x, top > FixedPt::ZERO, bottom < max.y];
+        let depths = [previous.x - left, right - previous.x, previous.y - top, bottom - previous.y];
+        let side = match (previous.x, previous.y) {
+            (x, _) if open[0] && x <= left && position.x > left => 0,
+            (x, _) if open[1] && x >= right && position.x < right => 1,
+            (_, y) if open[2] && y <= top && position.y > top => 2,
+            (_, y) if open[3] && y >= bottom && position.y < bottom => 3,
+            _ => (0..4).filter(|&side| open[side]).min_by_key(|&side| depths[side])?,
+        };
+        match side {
+            0 => position.x = left,
+            1 => position.x = right,
+            2 => position.y = top,
+            _ => position.y = bottom,
+        }
+        Some(side / 2)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_are_pushed_out_the_way_they_came() {
+        let max = FixedPtVec2D::from_i8s(124, 60);
+        let wall = Rect::new(10, 10, 20, 4);
+        let mut point = FixedPtVec2D::from_i8s(12, 11);
+        assert_eq!(wall.push_out(&mut point, FixedPtVec2D::from_i8s(12, 8), max), Some(1));
+        assert_eq!((point.x, point.y), (FixedPt::from_i8(12), FixedPt::from_i8(10)));
+
+        // a point the wall moved onto leaves by the edge it was nearest
+        let previous = FixedPtVec2D::from_i8s(28, 12);
+        let mut point = FixedPtVec2D::from_i8s(26, 13);
+        assert_eq!(wall.push_out(&mut point, previous, max), Some(0));
+        assert_eq!(point.x, FixedPt::from_i8(30));
+
+        let mut point = FixedPtVec2D::from_i8s(40, 12);
+        assert_eq!(wall.push_out(&mut point, FixedPtVec2D::from_i8s(40, 12), max), None);
+        assert!(wall.contains(30, 14) && !wall.contains(31, 14));
+    }
+
+    #[test]
+    fn points_are_not_pushed_out_past_the_boundaries() {
+        let max = FixedPtVec2D::from_i8s(124, 60);
+        let shelf = Rect::new(0, 40, 125, 6);
+        let mut point = FixedPtVec2D::from_i8s(124, 42);
+        assert_eq!(shelf.push_out(&mut point, FixedPtVec2D::from_i8s(124, 41), max), Some(1));
+        assert_eq!((point.x, point.y), (FixedPt::from_i8(124), FixedPt::from_i8(40)));
+    }
+}
@@ -0,0 +1,228 @@
+//! Record and replay, for regression testing the solver bit for bit: a
+//! Recorder notes the inputs to a fluid as they're made, frame by frame,
+//! into a trace, and replaying the trace from a fresh fluid reproduces
+//! the run exactly, as the solver is fixed point. A run's end state is
+//! summarized by the CRC-32 of its serialized state (see
+//! Fluid::write_state), so the golden traces below, with the CRCs they
+//! end in, verify a change to the solver changes nothing it shouldn't,
+//! on the host (`cargo test -p fluid-core`) and on the STM32 (the
+//! target-tests binary). When a change to the solver is meant to change
+//! its results, the CRCs are updated deliberately, from the host tests'
+//! failures.
+
+use crate::Fluid;
+
+
+/// An input to the fluid, as the firmware makes them
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Input {
+    /// Set gravity, as (x, y) with +y down
+    Gravity(f32, f32),
+    /// Set the linear and quadratic viscosity
+    Viscosity(f32, f32),
+    /// An impulse at (x, y), within the radius (see Fluid::apply_impulse_at)
+    Impulse { x: i8, y: i8, radius: i8, ix: f32, iy: f32 },
+    /// Spray from (x, y), within the radius (see Fluid::spray)
+    Spray { x: i8, y: i8, radius: i8, speed: f32 },
+}
+
+impl Input {
+    /// Make the input to the fluid
+    pub fn apply<const N: usize>(&self, fluid: &mut Fluid<N>) {
+        match *self {
+            Input::Gravity(gx, gy) => fluid.set_gravity(gx, gy),
+            Input::Viscosity(sigma, beta) => fluid.set_viscosity(sigma, beta),
+            Input::Impulse { x, y, radius, ix, iy } => fluid.apply_impulse_at(x, y, radius, ix, iy),
+            Input::Spray { x, y, radius, speed } => fluid.spray(x, y, radius, speed),
+        }
+    }
+}
+
+/// An input, made before the given frame's step
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Event {
+    pub frame: u16,
+    pub input: Input,
+}
+
+
+/// Notes the inputs to a fluid as they're made, up to M of them
+pub struct Recorder<const M: usize> {
+    events: [Event; M],
+    len: usize,
+    frame: u16,
+}
+
+impl<const M: usize> Recorder<M> {
+    /// Create a recorder, from the first frame
+    pub const fn new() -> Self {
+        Self { events: [Event { frame: 0, input: Input::Gravity(0.0, 0.0) }; M], len: 0, frame: 0 }
+    }
+
+    /// Note an input, made before this frame's step, returning false if
+    /// the recorder is full
+    pub fn record(&mut self, input: Input) -> bool {
+        let Some(event) = self.events.get_mut(self.len) else {
+            return false;
+        };
+        *event = Event { frame: self.frame, input };
+        self.len += 1;
+        true
+    }
+
+    /// Count a step, moving on to the next frame
+    pub fn step(&mut self) {
+        self.frame += 1;
+    }
+
+    /// The frames recorded, and the inputs made, in order
+    pub fn trace(&self) -> (u16, &[Event]) {
+        (self.frame, &self.events[..self.len])
+    }
+}
+
+impl<const M: usize> Default for Recorder<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// A recorded run, and the CRC of the state it ends in
+#[derive(Copy, Clone, Debug)]
+pub struct Trace {
+    /// The trace's name, e.g. for test reports
+    pub name: &'static str,
+    /// The frames stepped
+    pub frames: u16,
+    /// The inputs made, in order of frame
+    pub events: &'static [Event],
+    /// The CRC-32 of the state it ends in, with TRACE_PARTICLES
+    pub crc: u32,
+}
+
+impl Trace {
+    /// Replay the trace from a fluid reset to the display's area,
+    /// returning the CRC of the state it ends in
+    pub fn replay<const N: usize>(&self, fluid: &mut Fluid<N>) -> u32 {
+        fluid.reset(TRACE_WIDTH, TRACE_HEIGHT);
+        replay(fluid, self.frames, self.events)
+    }
+}
+
+
+/// The particles the golden traces are replayed with, few enough for a
+/// fluid to fit on the STM32's stack beside the tests' buffers
+pub const TRACE_PARTICLES: usize = 24;
+
+// The area the golden traces are replayed in, the display's
+const TRACE_WIDTH: i8 = 125;
+const TRACE_HEIGHT: i8 = 61;
+
+/// The golden traces, covering each of the solver's inputs
+pub const GOLDEN: [Trace; 3] = [
+    Trace {
+        name: "dam_break",
+        frames: 150,
+        events: &[
+            Event { frame: 0, input: Input::Gravity(0.0, 1.0) },
+        ],
+        crc: 0x5493_3363,
+    },
+    Trace {
+        name: "slosh",
+        frames: 200,
+        events: &[
+            Event { frame: 0, input: Input::Gravity(0.0, 1.0) },
+            Event { frame: 60, input: Input::Gravity(0.5, 0.5) },
+            Event { frame: 120, input: Input::Gravity(-0.3, 0.7) },
+        ],
+        crc: 0xB4FD_E43D,
+    },
+    Trace {
+        name: "splash",
+        frames: 200,
+        events: &[
+            Event { frame: 0, input: Input::Viscosity(0.0, 0.3) },
+            Event { frame: 0, input: Input::Gravity(0.0, 1.0) },
+            Event { frame: 60, input: Input::Impulse { x: 60, y: 50, radius: 20, ix: 0.5, iy: -1.5 } },
+            Event { frame: 100, input: Input::Spray { x: 30, y: 55, radius: 16, speed: 1.0 } },
+        ],
+        crc: 0xE4AD_EE36,
+    },
+];
+
+
+/// Step a fluid through the given frames, making the inputs before the
+/// steps of their frames, returning the CRC of the state it ends in
+pub fn replay<const N: usize>(fluid: &mut Fluid<N>, frames: u16, events: &[Event]) -> u32 {
+    let mut events = events.iter().peekable();
+    for frame in 0..frames {
+        while let Some(event) = events.next_if(|event| event.frame == frame) {
+            event.input.apply(fluid);
+        }
+        fluid.step();
+    }
+    state_crc(fluid)
+}
+
+/// The CRC-32 (as in zlib) of a fluid's serialized state
+pub fn state_crc<const N: usize>(fluid: &Fluid<N>) -> u32 {
+    let mut crc = !0;
+    fluid.write_state(|bytes| crc = crc32_update(crc, bytes));
+    !crc
+}
+
+// Take bytes into a CRC-32, bit by bit, as a table would cost 1K of flash
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_traces_end_in_their_crcs() {
+        let mut fluid = Fluid::<TRACE_PARTICLES>::empty();
+        for trace in GOLDEN {
+            assert_eq!(trace.replay(&mut fluid), trace.crc, "{}", trace.name);
+        }
+    }
+
+    #[test]
+    fn a_recording_replays_the_run() {
+        let mut fluid = Fluid::<TRACE_PARTICLES>::new(TRACE_WIDTH, TRACE_HEIGHT);
+        let mut recorder = Recorder::<4>::new();
+        for frame in 0..80 {
+            let input = match frame {
+                0 => Some(Input::Gravity(0.0, 1.0)),
+                40 => Some(Input::Impulse { x: 60, y: 50, radius: 20, ix: 0.0, iy: -2.0 }),
+                _ => None,
+            };
+            if let Some(input) = input {
+                input.apply(&mut fluid);
+                assert!(recorder.record(input));
+            }
+            fluid.step();
+            recorder.step();
+        }
+
+        let (frames, events) = recorder.trace();
+        let mut replayed = Fluid::<TRACE_PARTICLES>::new(TRACE_WIDTH, TRACE_HEIGHT);
+        assert_eq!(replay(&mut replayed, frames, events), state_crc(&fluid));
+    }
+
+    #[test]
+    fn the_crc_matches_zlibs() {
+        // "123456789" is CRC-32's check value, 0xCBF43926
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::fixed::FixedPt;
+
+
+/// A small, fast pseudo-random number generator (xorshift32) producing
+/// fixed point values, for varying the simulation between runs, e.g.
+/// jittering the initial layout. It isn't suitable for cryptography.
+#[derive(Copy, Clone, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    // xorshift never leaves (or reaches) zero, so a zero seed is replaced
+    const ZERO_SEED: u32 = 0x2545_F491;
+
+    /// Create a generator from a seed. The same seed gives the same sequence.
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { Self::ZERO_SEED } else { seed },
+        }
+    }
+
+    /// The next 32 random bits
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// A random value in [-magnitude, magnitude)
+    pub fn next_fixed(&mut self, magnitude: FixedPt) -> FixedPt {
+        let span = (magnitude.value.unsigned_abs() as u64) * 2;
+        let offset = (self.next_u32() as u64 * span) >> 32;
+        FixedPt { value: offset as i32 - magnitude.value.abs() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_sequence() {
+        let (mut a, mut b, mut c) = (Rng::new(1234), Rng::new(1234), Rng::new(1235));
+        let sequence = |rng: &mut Rng| [rng.next_u32(), rng.next_u32(), rng.next_u32()];
+        assert_eq!(sequence(&mut a), sequence(&mut b));
+        assert_ne!(sequence(&mut a), sequence(&mut c));
+    }
+
+    #[test]
+    fn a_zero_seed_still_varies() {
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next_u32(), 0);
+        assert_ne!(rng.next_u32(), rng.next_u32());
+    }
+
+    #[test]
+    fn fixed_values_stay_within_the_magnitude() {
+        let mut rng = Rng::new(42);
+        let magnitude = FixedPt::from_f32(0.5);
+        let values: Vec<FixedPt> = (0..1000).map(|_| rng.next_fixed(magnitude)).collect();
+        assert!(values.iter().all(|&v| v >= FixedPt::from_f32(-0.5) && v < magnitude));
+        assert!(values.iter().any(|&v| v < FixedPt::ZERO) && values.iter().any(|&v| v > FixedPt::ZERO));
+    }
+}
@@ -0,0 +1,170 @@
+//! Scenes for demo programs: each arranges the fluid, sets its viscosity,
+//! and steers its gravity through keyframes (see keyframe.rs) for a
+//! number of frames. A
+//! SceneManager plays a table of scenes in turn, one frame at a time, so
+//! a new program is a new table rather than new control flow.
+
+use crate::{Fluid, Layout};
+use crate::keyframe::{self, Keyframe};
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct Scene {
+    /// The scene's name, e.g. for logs
+    pub name: &'static str,
+    /// The particles' layout as the scene starts, or None to carry on
+    /// from where the previous scene left them
+    pub layout: Option<Layout>,
+    /// The linear and quadratic viscosity, or None to leave it as is
+    pub viscosity: Option<(f32, f32)>,
+    /// Gravity through the scene, as (x, y) with +y down, in order of
+    /// frame. There's no gravity before the first keyframe.
+    pub gravity: &'static [Keyframe],
+    /// The scene's length, in frames
+    pub frames: u16,
+}
+
+impl Scene {
+    /// Gravity at the given frame of the scene
+    pub fn gravity_at(&self, frame: u16) -> (f32, f32) {
+        keyframe::sample(self.gravity, frame)
+    }
+}
+
+
+/// Plays a table of scenes in turn, returning to the first after the last
+pub struct SceneManager {
+    scenes: &'static [Scene],
+    index: usize,
+    frame: u16,
+}
+
+impl SceneManager {
+    /// Create a manager at the start of the first of the given scenes,
+    /// of which there must be at least one
+    pub const fn new(scenes: &'static [Scene]) -> Self {
+        assert!(!scenes.is_empty());
+        Self {
+            scenes,
+            index: 0,
+            frame: 0,
+        }
+    }
+
+    /// The current scene
+    pub fn scene(&self) -> &'static Scene {
+        &self.scenes[self.index]
+    }
+
+    /// The current scene's index in the table
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Start the scene at the given index (wrapping around the table)
+    /// from its first frame, arranging the fluid for it
+    pub fn select<const N: usize>(&mut self, index: usize, fluid: &mut Fluid<N>) {
+        self.index = index % self.scenes.len();
+        self.frame = 0;
+        let scene = self.scene();
+        if let Some(layout) = scene.layout {
+            fluid.arrange(layout);
+        }
+        if let Some((sigma, beta)) = scene.viscosity {
+            fluid.set_viscosity(sigma, beta);
+        }
+    }
+
+    /// Start playing another table of scenes, from the scene at the
+    /// given index, e.g. to switch between programs
+    pub fn play<const N: usize>(&mut self, scenes: &'static [Scene], index: usize, fluid: &mut Fluid<N>) {
+        assert!(!scenes.is_empty());
+        self.scenes = scenes;
+        self.select(index, fluid);
+    }
+
+    /// Set the fluid's gravity for the current frame, and move on to
+    /// the next frame, or the next scene once this one is over. Returns
+    /// true if the next scene started.
+    pub fn advance<const N: usize>(&mut self, fluid: &mut Fluid<N>) -> bool {
+        let (gx, gy) = self.scene().gravity_at(self.frame);
+        fluid.set_gravity(gx, gy);
+
+        self.frame += 1;
+        if self.frame < self.scene().frames {
+            return false;
+        }
+        self.select(self.index + 1, fluid);
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENES: [Scene; 2] = [
+        Scene {
+            name: "drop",
+            layout: Some(Layout::Block { x: 50, y: 0, columns: 2 }),
+            viscosity: None,
+            gravity: &[Keyframe::new(2, 0.0, 1.0), Keyframe::new(4, -1.0, 0.0)],
+            frames: 6,
+        },
+        Scene {
+            name: "drift",
+            layout: None,
+            viscosity: Some((0.5, 0.2)),
+            gravity: &[Keyframe::new(0, 1.0, 0.0)],
+            frames: 3,
+        },
+    ];
+
+    #[test]
+    fn gravity_holds_each_keyframe_until_the_next() {
+        let scene = &SCENES[0];
+        assert_eq!(scene.gravity_at(0), (0.0, 0.0));
+        assert_eq!(scene.gravity_at(2), (0.0, 1.0));
+        assert_eq!(scene.gravity_at(3), (0.0, 1.0));
+        assert_eq!(scene.gravity_at(5), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn scenes_play_in_turn_and_wrap_around() {
+        let mut fluid = Fluid::<4>::new(125, 61);
+        let mut scenes = SceneManager::new(&SCENES);
+        scenes.select(0, &mut fluid);
+        assert_eq!(fluid.get_particles()[3].get_display_position(), (56, 6));
+
+        let started: Vec<bool> = (0..9).map(|_| scenes.advance(&mut fluid)).collect();
+        assert_eq!(started, [false, false, false, false, false, true, false, false, true]);
+        assert_eq!(scenes.index(), 0);
+        assert_eq!(scenes.scene().name, "drop");
+    }
+
+    #[test]
+    fn another_table_plays_from_the_given_scene() {
+        let mut fluid = Fluid::<4>::new(125, 61);
+        let mut scenes = SceneManager::new(&SCENES);
+        scenes.select(0, &mut fluid);
+        scenes.play(&SCENES[1..], 0, &mut fluid);
+        assert_eq!(scenes.scene().name, "drift");
+        let started: Vec<bool> = (0..3).map(|_| scenes.advance(&mut fluid)).collect();
+        assert_eq!(started, [false, false, true]);
+        assert_eq!(scenes.scene().name, "drift");
+    }
+
+    #[test]
+    fn a_scene_without_a_layout_carries_on_from_the_last() {
+        let mut fluid = Fluid::<4>::new(125, 61);
+        let mut scenes = SceneManager::new(&SCENES);
+        scenes.select(0, &mut fluid);
+        for _ in 0..20 {
+            fluid.step();
+        }
+        let positions: Vec<(i8, i8)> = fluid.get_particles().iter().map(|p| p.get_display_position()).collect();
+        scenes.select(1, &mut fluid);
+        assert!(fluid.get_particles().iter().map(|p| p.get_display_position()).eq(positions));
+    }
+}
@@ -0,0 +1,111 @@
+//! Text as particle layouts, e.g. to spell out the time: each lit pixel
+//! of a small 3x5 font is a particle, spaced as in the logo, for
+//! Fluid::arrange_at. The font has the digits and a colon; any other
+//! character is a gap the width of a digit.
+
+
+/// The distance between neighbouring particles of a glyph
+pub const SPACING: i8 = 6;
+
+/// The height of a line of text, from its top row to its bottom row
+pub const HEIGHT: i8 = 4 * SPACING;
+
+// The gap between glyphs, in particles
+const GAP: i8 = 1;
+
+// Each glyph's rows, top first, with its leftmost column in bit 2
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const COLON: [u8; 5] = [0b000, 0b100, 0b000, 0b100, 0b000];
+const BLANK: [u8; 5] = [0; 5];
+
+
+// A character's glyph, and its width in particles
+fn glyph(c: u8) -> ([u8; 5], i8) {
+    match c {
+        b'0'..=b'9' => (DIGITS[(c - b'0') as usize], 3),
+        b':' => (COLON, 1),
+        _ => (BLANK, 3),
+    }
+}
+
+/// The width of the text, from the left of its first particle to the
+/// left of its last column, as laid out by positions
+pub fn width(text: &str) -> i8 {
+    let columns: i8 = text.bytes().map(|c| glyph(c).1 + GAP).sum();
+    (columns - GAP - 1).max(0) * SPACING
+}
+
+/// The positions of the text's particles, with its top left at (x, y),
+/// row by row within each glyph
+pub fn positions(text: &str, x: i8, y: i8) -> Positions<'_> {
+    Positions { text: text.as_bytes(), x, y, pixel: 0 }
+}
+
+
+/// An iterator over the positions of text's particles
+pub struct Positions<'a> {
+    // The text from the current glyph on, with the glyph's top left
+    text: &'a [u8],
+    x: i8,
+    y: i8,
+    // The next of the glyph's pixels, row by row
+    pixel: i8,
+}
+
+impl Iterator for Positions<'_> {
+    type Item = (i8, i8);
+
+    fn next(&mut self) -> Option<(i8, i8)> {
+        loop {
+            let (&c, rest) = self.text.split_first()?;
+            let (rows, width) = glyph(c);
+            let (row, column) = (self.pixel / width, self.pixel % width);
+            if row == 5 {
+                self.text = rest;
+                self.x += (width + GAP) * SPACING;
+                self.pixel = 0;
+                continue;
+            }
+            self.pixel += 1;
+            if rows[row as usize] & (0b100 >> column) != 0 {
+                return Some((self.x + column * SPACING, self.y + row * SPACING));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_lit_pixel_is_a_particle() {
+        assert_eq!(positions("8", 0, 0).count(), 13);
+        assert_eq!(positions("1", 0, 0).count(), 8);
+        assert_eq!(positions(":", 0, 0).count(), 2);
+        assert_eq!(positions("08:08", 0, 0).count(), 52);
+        let one: Vec<(i8, i8)> = positions("1", 10, 20).collect();
+        assert_eq!(&one[..3], [(16, 20), (10, 26), (16, 26)]);
+    }
+
+    #[test]
+    fn glyphs_follow_one_another_with_a_gap() {
+        assert_eq!(positions("7:", 0, 0).last(), Some((24, 18)));
+        assert_eq!(positions("12", 0, 0).filter(|&(_, y)| y == 0).last(), Some((36, 0)));
+        assert_eq!(width("12:34"), 96);
+        assert_eq!(width("1"), 12);
+        assert_eq!(width(""), 0);
+    }
+}
@@ -0,0 +1,264 @@
+//! An accelerometer on the display's I2C bus, measuring the board's tilt
+//! so gravity in the simulation follows the real thing. Either an
+//! LIS3DH or an MPU6050 is supported, detected by its ID register at
+//! either of its addresses. The sensor's expected flat behind the
+//! display, with its x axis to the display's right and its y axis to
+//! the display's top, unless a calibration (see calibrate.rs) found it
+//! mounted otherwise: its axes are then mapped onto the display's, and
+//! its offsets at rest taken out, before anything else (see Mounting).
+//!
+//! Sudden movements of the board, e.g. a knock on the case or a shake, 
+//! are detected from the samples' change from the filtered gravity, and
+//! on the LIS3DH, from its click detection, which catches taps too short
+//! to show in samples taken once a frame.
+
+use embedded_hal_1::i2c::I2c;
+use crate::log;
+
+
+// Both sensors measure +/-2g, as 16-bit samples
+const COUNTS_PER_G: f32 = 16384.0;
+
+// The fraction of each sample's change taken into the filtered gravity
+const SMOOTHING: f32 = 0.25;
+
+// A change from the filtered gravity greater than this is a jolt, in g
+const JOLT_THRESHOLD_G: f32 = 0.5;
+
+// The acceleration reported for a tap, in g
+const TAP_G: f32 = 1.0;
+
+// The LIS3DH's click source flags: a click, its sign, and its axes
+const CLICK_ACTIVE: u8 = 0x40;
+const CLICK_NEGATIVE: u8 = 0x08;
+const CLICK_AXES: u8 = 0x07;
+
+
+/// The supported sensors
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum Model {
+    Lis3dh,
+    Mpu6050,
+}
+
+impl Model {
+    const ALL: [Model; 2] = [Model::Lis3dh, Model::Mpu6050];
+
+    // The sensor's possible addresses, depending on its address pin
+    fn addresses(self) -> [u8; 2] {
+        match self {
+            Model::Lis3dh => [0x18, 0x19],
+            Model::Mpu6050 => [0x68, 0x69],
+        }
+    }
+
+    // The ID register, and the ID it holds
+    fn id(self) -> (u8, u8) {
+        match self {
+            Model::Lis3dh => (0x0F, 0x33),
+            Model::Mpu6050 => (0x75, 0x68),
+        }
+    }
+
+    // The register writes configuring continuous measurement
+    fn init_writes(self) -> &'static [[u8; 2]] {
+        match self {
+            // CTRL_REG1: 100Hz, x/y/z enabled; CTRL_REG2: clicks are
+            // high pass filtered; CTRL_REG4: block data update, +/-2g, 
+            // high resolution; CLICK_CFG: single clicks on x/y/z; 
+            // CLICK_THS: latched, 0.64g; TIME_LIMIT: 100ms; 
+            // TIME_LATENCY: 200ms
+            Model::Lis3dh => &[[0x20, 0x57], [0x21, 0x04], [0x23, 0x88], [0x38, 0x15],
+                               [0x3A, 0xA8], [0x3B, 0x0A], [0x3C, 0x14]],
+            // PWR_MGMT_1: awake, clocked by the x gyro; ACCEL_CONFIG: +/-2g
+            Model::Mpu6050 => &[[0x6B, 0x01], [0x1C, 0x00]],
+        }
+    }
+
+    // The first of the x, y and z sample registers, read in a burst
+    fn data_register(self) -> u8 {
+        match self {
+            // OUT_X_L, with the auto-increment bit
+            Model::Lis3dh => 0x28 | 0x80,
+            // ACCEL_XOUT_H
+            Model::Mpu6050 => 0x3B,
+        }
+    }
+
+    // The click source register, if the sensor detects clicks
+    fn click_source(self) -> Option<u8> {
+        match self {
+            Model::Lis3dh => Some(0x39),
+            Model::Mpu6050 => None,
+        }
+    }
+
+    // Decode a sample from its bytes in the order read
+    fn sample(self, bytes: [u8; 2]) -> i16 {
+        match self {
+            Model::Lis3dh => i16::from_le_bytes(bytes),
+            Model::Mpu6050 => i16::from_be_bytes(bytes),
+        }
+    }
+}
+
+
+/// How the sensor's mounted: the sensor axis along each of the display's
+/// axes, and the sensor's readings at zero g
+#[cfg(any(feature = "calibrate", feature = "eeprom"))]
+#[derive(Copy, Clone, PartialEq)]
+pub struct Mounting {
+    /// The sensor axis along the display's x (right), y (up) and z (out
+    /// of the face), as 1-3 for the sensor's x-z, negated when reversed
+    pub axes: [i8; 3],
+    /// The sensor's x, y and z readings at zero g, in 1/128ths of a g
+    pub offsets: [i8; 3],
+}
+
+#[cfg(any(feature = "calibrate", feature = "eeprom"))]
+impl Mounting {
+    /// The sensor flat behind the display, its axes the display's
+    pub const FLAT: Self = Self { axes: [1, 2, 3], offsets: [0; 3] };
+
+    /// Map a sample along the sensor's axes onto the display's, taking
+    /// out the offsets
+    #[cfg(feature = "calibrate")]
+    pub fn apply(&self, sample: [f32; 3]) -> [f32; 3] {
+        self.axes.map(|axis| {
+            let i = axis.unsigned_abs() as usize - 1;
+            let g = sample[i] - self.offsets[i] as f32 * (1.0 / 128.0);
+            if axis < 0 { -g } else { g }
+        })
+    }
+
+    /// Whether the mapping's valid, each sensor axis used once
+    #[cfg(feature = "eeprom")]
+    pub fn is_valid(&self) -> bool {
+        let mut used = 0u8;
+        for axis in self.axes {
+            let i = axis.unsigned_abs();
+            if !(1..=3).contains(&i) || used & (1 << i) != 0 {
+                return false;
+            }
+            used |= 1 << i;
+        }
+        true
+    }
+}
+
+
+/// The board's motion, as measured in a frame
+pub struct Motion {
+    /// The direction of gravity in the display's plane, as (x, y) with
+    /// +y down, scaled by how steeply the board is tilted
+    pub gravity: (f32, f32),
+    /// A sudden acceleration of the board, e.g. from a tap or a shake, 
+    /// along the display's x and y (+y down) and the sensor's z, in g
+    pub jolt: Option<[f32; 3]>,
+}
+
+
+pub struct Accelerometer<I> {
+    i2c: I,
+    model: Model,
+    address: u8,
+    #[cfg(feature = "calibrate")]
+    mounting: Mounting,
+    filtered: Option<[f32; 3]>,
+}
+
+impl<I: I2c> Accelerometer<I> {
+    /// Detect a supported accelerometer on the bus and start its
+    /// measurements, or None if there isn't one
+    pub fn detect(mut i2c: I) -> Option<Self> {
+        for model in Model::ALL {
+            let (register, expected) = model.id();
+            for address in model.addresses() {
+                let mut id = [0];
+                if i2c.write_read(address, &[register], &mut id).is_ok() && id[0] == expected {
+                    let mut accel = Self {
+                        i2c,
+                        model,
+                        address,
+                        #[cfg(feature = "calibrate")]
+                        mounting: Mounting::FLAT,
+                        filtered: None,
+                    };
+                    return match accel.init() {
+                        Ok(()) => {
+                            log::info!("accel: {} at {=u8:#x}", model, address);
+                            Some(accel)
+                        },
+                        Err(_) => None,
+                    };
+                }
+            }
+        }
+        None
+    }
+
+    // Configure the sensor for continuous measurement
+    fn init(&mut self) -> Result<(), I::Error> {
+        for write in self.model.init_writes() {
+            self.i2c.write(self.address, write)?;
+        }
+        Ok(())
+    }
+
+    /// How the sensor's mounted
+    #[cfg(feature = "calibrate")]
+    pub fn mounting(&self) -> Mounting {
+        self.mounting
+    }
+
+    /// Set how the sensor's mounted, e.g. once it's calibrated
+    #[cfg(feature = "calibrate")]
+    pub fn set_mounting(&mut self, mounting: Mounting) {
+        self.mounting = mounting;
+        self.filtered = None;
+    }
+
+    /// The acceleration, in g, along the sensor's x, y and z axes
+    pub fn read(&mut self) -> Result<[f32; 3], I::Error> {
+        let mut bytes = [0; 6];
+        self.i2c.write_read(self.address, &[self.model.data_register()], &mut bytes)?;
+        Ok([0, 2, 4].map(|i| self.model.sample([bytes[i], bytes[i + 1]]) as f32 / COUNTS_PER_G))
+    }
+
+    /// Measure the board's motion, once a frame
+    pub fn update(&mut self) -> Result<Motion, I::Error> {
+        let sample = self.read()?;
+        #[cfg(feature = "calibrate")]
+        let sample = self.mounting.apply(sample);
+
+        // Gravity is filtered from the samples, and a sample far from
+        // it is a jolt
+        let filtered = self.filtered.get_or_insert(sample);
+        let change: [f32; 3] = core::array::from_fn(|i| sample[i] - filtered[i]);
+        for (filtered, change) in filtered.iter_mut().zip(change) {
+            *filtered += change * SMOOTHING;
+        }
+        let squared: f32 = change.iter().map(|a| a * a).sum();
+        let mut jolt = (squared > JOLT_THRESHOLD_G * JOLT_THRESHOLD_G).then_some(change);
+
+        if let Some(register) = self.model.click_source() {
+            let mut source = [0];
+            self.i2c.write_read(self.address, &[register], &mut source)?;
+            if source[0] & CLICK_ACTIVE != 0 {
+                let g = if source[0] & CLICK_NEGATIVE != 0 { -TAP_G } else { TAP_G };
+                let tap = core::array::from_fn(|i| if source[0] & CLICK_AXES & (1 << i) != 0 { g } else { 0.0 });
+                #[cfg(feature = "calibrate")]
+                let tap = Mounting { offsets: [0; 3], ..self.mounting }.apply(tap);
+                jolt = Some(tap);
+            }
+        }
+
+        // The sensor measures the reaction to gravity, i.e. up, and the
+        // display's y axis is up
+        let [x, y, _] = *filtered;
+        Ok(Motion {
+            gravity: (-x, y),
+            jolt: jolt.map(|[x, y, z]| [x, -y, z]),
+        })
+    }
+}
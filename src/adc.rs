@@ -0,0 +1,34 @@
+//! The ADC's setup and conversions, shared by the analog inputs sampled
+//! each frame: the knobs and the temperature sensor. (The entropy source
+//! configures the ADC for itself before them, and disables it again.)
+
+use stm32f0xx_hal::pac::{adc::RegisterBlock, RCC};
+
+
+/// Calibrate and enable the ADC, unless it's enabled already. The long
+/// sample time gives a potentiometer's impedance time to settle, and the
+/// temperature sensor the 4us it needs.
+pub fn enable(adc: &RegisterBlock, rcc: &RCC) {
+    if adc.cr.read().aden().is_enabled() {
+        return;
+    }
+    rcc.apb2enr.modify(|_, w| w.adcen().enabled());
+
+    // the ADC is clocked at PCLK/4, 12MHz at most
+    adc.cfgr2.write(|w| w.ckmode().pclk_div4());
+    adc.smpr.write(|w| w.smp().cycles239_5());
+
+    adc.cr.modify(|_, w| w.adcal().start_calibration());
+    while adc.cr.read().adcal().is_calibrating() {}
+    adc.cr.modify(|_, w| w.aden().enabled());
+    while adc.isr.read().adrdy().is_not_ready() {}
+}
+
+/// Convert the given channel, waiting for the result
+pub fn convert(adc: &RegisterBlock, channel: u8) -> u16 {
+    // SAFETY: CHSELR has a bit for each of the 19 channels
+    adc.chselr.write(|w| unsafe { w.bits(1 << channel) });
+    adc.cr.modify(|_, w| w.adstart().start_conversion());
+    while adc.isr.read().eoc().is_not_complete() {}
+    adc.dr.read().data().bits()
+}
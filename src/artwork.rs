@@ -0,0 +1,20 @@
+//! The artwork: layouts and obstacles drawn as 1-bpp XBM images in art/
+//! rather than typed out as coordinates, turned into tables by build.rs.
+//! A `<name>_layout.xbm` image, the display's size, has a pixel set for
+//! each particle's place, read from the top left row by row, and gives
+//! `<NAME>_LAYOUT`, the positions for a `Layout::Positions`. A
+//! `<name>_mask.xbm` image has the pixels the particles can't enter set,
+//! and gives `<NAME>_MASK`, the rectangles covering them for
+//! `Fluid::set_obstacles`; there's room for 16, so masks are best drawn
+//! in blocks. Any image editor saves XBM, e.g. GIMP, or ImageMagick from
+//! a PNG:
+//!
+//!   convert drawing.png -monochrome art/drawing_layout.xbm
+
+// Each build uses the tables of the scenes and modes it has
+#![allow(dead_code)]
+
+use fluid_core::obstacle::Rect;
+
+
+include!(concat!(env!("OUT_DIR"), "/artwork.rs"));
@@ -0,0 +1,107 @@
+//! The attract mode, for leaving the board running unattended, e.g. on
+//! a display table: the demo's scenes are played in turn, some at more
+//! than one viscosity, with a caption describing each scrolling along
+//! the top of the display (see scenes::ATTRACT). It's started at boot,
+//! as AppConfig's attract sets, or by the shell's `attract` command, and
+//! keeps the display awake. Any input, a turn of the encoder or a knob,
+//! a press of the wake button or a command, pauses the scenes and hides
+//! the caption, handing the board over to tuning, until it's been left
+//! alone for half a minute.
+
+use fluid_core::{scene::SceneManager, Fluid};
+use crate::oled::{marquee::Marquee, OLEDDriver};
+use crate::scenes;
+
+
+// The milliseconds without input before the scenes play on, counted on
+// the monotonic so they don't stretch as the frame rate drops
+const RESUME_MS: u32 = 30_000;
+
+// The page the caption scrolls along, the display's top
+const CAPTION_PAGE: usize = 0;
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Off,
+    Playing,
+    // Paused by input, since the first frame after it
+    Paused { since: Option<u32> },
+}
+
+pub struct Attract {
+    state: State,
+    // The caption, and the scene it describes
+    caption: Marquee<'static>,
+    scene: usize,
+}
+
+impl Attract {
+    /// Create the attract mode, not running
+    pub const fn new() -> Self {
+        Self {
+            state: State::Off,
+            caption: Marquee::new("", CAPTION_PAGE),
+            scene: usize::MAX,
+        }
+    }
+
+    /// Start playing the attract mode's scenes, from the first
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::ATTRACT, 0, fluid);
+        self.state = State::Playing;
+        self.scene = usize::MAX;
+    }
+
+    /// Stop the attract mode, e.g. as another mode starts. Returns true
+    /// if it was running, and its scenes have set the viscosity.
+    pub fn stop(&mut self) -> bool {
+        let running = self.state != State::Off;
+        self.state = State::Off;
+        running
+    }
+
+    /// Pause the scenes, as there's input, until it's been left alone
+    pub fn pause(&mut self) {
+        if self.state != State::Off {
+            self.state = State::Paused { since: None };
+        }
+    }
+
+    /// Whether the scenes are paused, and shouldn't be advanced
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, State::Paused { .. })
+    }
+
+    /// Play on once the input's been left alone, and caption the scene
+    /// as it starts. Called once a frame, with the milliseconds of
+    /// uptime. Returns true while the scenes are playing, keeping the
+    /// display awake.
+    pub fn update(&mut self, scenes: &SceneManager, now_ms: u32) -> bool {
+        match self.state {
+            State::Off => false,
+            State::Paused { since } => {
+                let since = since.unwrap_or(now_ms);
+                self.state = match now_ms.wrapping_sub(since) >= RESUME_MS {
+                    true => State::Playing,
+                    false => State::Paused { since: Some(since) },
+                };
+                false
+            },
+            State::Playing => {
+                if scenes.index() != self.scene {
+                    self.scene = scenes.index();
+                    self.caption = Marquee::new(scenes.scene().name, CAPTION_PAGE);
+                }
+                true
+            },
+        }
+    }
+
+    /// Draw the scene's caption, scrolling, while the scenes play
+    pub fn draw(&mut self, display: &mut OLEDDriver) {
+        if self.state == State::Playing {
+            self.caption.draw(display);
+        }
+    }
+}
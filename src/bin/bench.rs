@@ -0,0 +1,171 @@
+//! Micro-benchmarks run on the STM32F0 itself, so a change to the solver
+//! or the drawing code comes with numbers: each stage of the solver, and
+//! each drawing primitive, runs a fixed number of times and its average
+//! cycles are reported as a table over semihosting, e.g. with a runner in
+//! .cargo/config and a debug probe attached
+//!
+//!   cargo run --release --features debug-host --bin bench
+//!
+//! (the release profile being the one tuned for speed). The Cortex-M0
+//! has no DWT cycle counter, so cycles are counted by SysTick, free
+//! running from the core clock, which wraps every 2^24 cycles, 349ms at
+//! 48MHz, far longer than anything timed. The OLED display is expected
+//! on I2C1 (PB6/PB7) at the primary address, as for the target tests.
+#![no_std]
+#![no_main]
+
+
+use core::fmt::Write;
+use core::ptr;
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hio, hprintln};
+
+// the firmware's display drivers, most of which aren't exercised here
+#[allow(dead_code)]
+#[path = "../oled/mod.rs"]
+mod oled;
+use oled::{DMAi2c, OLEDDriver, OLEDBuffer, PowerSource, OLED_ADDR_PRIMARY, OLED_FRAME_SIZE};
+
+// the firmware's MCU family support: the clocks and the display bus
+#[path = "../board/stm32f0.rs"]
+mod board;
+use board::pac::{interrupt, Interrupt, Peripherals as F0Peripherals};
+
+// the driver logs over RTT, alongside the semihosted results
+#[path = "../log.rs"]
+mod log;
+
+use fluid_core::{Fluid, Stage};
+
+
+// The times each benchmark runs, averaged over
+const ITERATIONS: u32 = 100;
+
+// The particles simulated, as many as the firmware's
+const PARTICLES: usize = 60;
+
+// SysTick's full count, as it's free running
+const SYST_MASK: u32 = 0x00FF_FFFF;
+
+// The fluid, in a static, as it's too big for the stack beside the
+// display's buffer
+static mut FLUID: Fluid<PARTICLES> = Fluid::empty();
+
+
+type Primitive = fn(&mut OLEDDriver);
+
+/// The drawing primitives, as the firmware uses them each frame
+const PRIMITIVES: [(&str, Primitive); 8] = [
+    ("clear", |display| display.clear()),
+    ("set_pixel", |display| display.set_pixel(64, 32, true)),
+    ("draw_line", |display| display.draw_line((0, 0), (127, 63), 1)),
+    ("draw_line_wide", |display| display.draw_line((0, 0), (127, 63), 3)),
+    ("draw_rect", |display| display.draw_rect(8, 8, 112, 48)),
+    ("fill_rect", |display| display.fill_rect(8, 8, 112, 48, true)),
+    ("draw_text", |display| { display.draw_text(0, 0, "fluid 12:34"); }),
+    ("tx_frame", |display| {
+        display.tx_frame();
+        DMAi2c::wait_idle().ok();
+    }),
+];
+
+
+#[entry]
+fn main() -> ! {
+    let mut p = F0Peripherals::take().unwrap();
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+
+    // the same clocks and bus as the firmware
+    let mut rcc = board::init_clocks(p.RCC, &mut p.FLASH);
+    board::init_display_bus(p.I2C1, &mut p.DMA1, p.GPIOB, &mut rcc);
+    cp.SYST.set_clock_source(SystClkSource::Core);
+    cp.SYST.set_reload(SYST_MASK);
+    cp.SYST.clear_current();
+    cp.SYST.enable_counter();
+    cortex_m::asm::delay(board::SYSCLK_HZ / 10);
+
+    // SAFETY: the fluid is only borrowed here, once
+    let fluid = unsafe { &mut *ptr::addr_of_mut!(FLUID) };
+    fluid.reset(125, 61);
+    fluid.set_gravity(0.0, 1.0);
+    let buffer = cortex_m::singleton!(: OLEDBuffer = [0; OLED_FRAME_SIZE]).unwrap();
+    let mut display = OLEDDriver::new(OLED_ADDR_PRIMARY, PowerSource::ChargePump, buffer);
+    DMAi2c::wait_idle().ok();
+
+    hprintln!("{:<16} {:>9} {:>7}", "benchmark", "cycles", "us");
+
+    // Each stage of the solver, as the water falls from the logo
+    let mut totals = [0; Stage::ALL.len()];
+    for _ in 0..ITERATIONS {
+        let mut last = SYST::get_current();
+        fluid.step_with(|stage| {
+            let now = SYST::get_current();
+            totals[stage as usize] += last.wrapping_sub(now) & SYST_MASK;
+            last = now;
+        });
+    }
+    for (stage, total) in Stage::ALL.iter().zip(totals) {
+        report(stage_name(*stage), total / ITERATIONS);
+    }
+    report("step", totals.iter().sum::<u32>() / ITERATIONS);
+
+    // Each drawing primitive
+    for (name, primitive) in PRIMITIVES {
+        let mut total = 0;
+        for _ in 0..ITERATIONS {
+            let start = SYST::get_current();
+            primitive(&mut display);
+            total += start.wrapping_sub(SYST::get_current()) & SYST_MASK;
+        }
+        report(name, total / ITERATIONS);
+    }
+
+    debug::exit(debug::EXIT_SUCCESS);
+    loop {
+        continue;
+    }
+}
+
+
+// Print a row of the table: the average cycles, and in microseconds
+fn report(name: &str, cycles: u32) {
+    hprintln!("{:<16} {:>9} {:>7}", name, cycles, cycles / (board::SYSCLK_HZ / 1_000_000));
+}
+
+// A stage's name, for the table
+fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::Gravity => "gravity",
+        Stage::Viscosity => "viscosity",
+        Stage::Velocity => "velocity",
+        Stage::Relaxation => "relaxation",
+        Stage::Collisions => "collisions",
+        Stage::Revision => "revision",
+    }
+}
+
+
+#[interrupt]
+fn DMA1_CH2_3() {
+    // DMA I2C interface, while transmitting on channel 2
+    static mut I2C_INTERFACE: Option<DMAi2c> = None;
+    DMAi2c::on_dma_interrupt(I2C_INTERFACE, Interrupt::DMA1_CH2_3);
+}
+
+#[interrupt]
+fn I2C1() {
+    DMAi2c::on_i2c_interrupt(Interrupt::I2C1);
+}
+
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    if let Ok(mut stdout) = hio::hstdout() {
+        writeln!(stdout, "bench panicked: {}", info).ok();
+    }
+    debug::exit(debug::EXIT_FAILURE);
+    loop {
+        continue;
+    }
+}
@@ -0,0 +1,177 @@
+//! The firmware as cooperative async tasks on the Embassy executor, an
+//! alternative to the RTIC app in main.rs. The simulation, the inputs and
+//! the display update are independent tasks, and waiting on the OLED's
+//! DMA transfers and the frame period are awaits, so the core sleeps
+//! whenever no task can make progress.
+//!
+//!   cargo build --features embassy --bin fluid-embassy
+#![no_std]
+#![no_main]
+
+
+use panic_halt as _;
+
+use core::cell::RefCell;
+use embassy_executor::Spawner;
+use embassy_sync::{blocking_mutex::{Mutex, raw::ThreadModeRawMutex}, signal::Signal};
+use embassy_time::{Duration, Ticker, Timer};
+use board::pac::{interrupt, Interrupt, Peripherals as F0Peripherals};
+
+// the firmware's display drivers, shared with the RTIC app
+#[allow(dead_code)]
+#[path = "../../oled/mod.rs"]
+mod oled;
+use oled::{DMAi2c, OLEDDriver, OLEDBuffer, PowerSource, OLED_ADDR_PRIMARY, OLED_FRAME_SIZE};
+
+mod time_driver;
+
+// the firmware's MCU family support: the clocks and the display bus
+#[path = "../../board/stm32f0.rs"]
+mod board;
+use board::SYSCLK_HZ;
+
+#[path = "../../log.rs"]
+mod log;
+
+// Log timestamps, from the executor's clock
+#[cfg(debug_assertions)]
+defmt::timestamp!("{=u64:us}", log::uptime_us(embassy_time::Instant::now().as_ticks()));
+
+use fluid_core::Fluid;
+
+
+// The simulation runs at up to 30 fps
+const FRAME_PERIOD: Duration = Duration::from_millis(33);
+
+// Fewer particles than the RTIC app, as the executor's task arena and 
+// the tasks' state need the RAM (see the task-arena-size feature)
+const PARTICLES: usize = 48;
+
+// The fluid simulation, stepped by the simulation task and drawn by the
+// display task. Tasks don't preempt one another, so neither holds the
+// lock across an await, and interrupts aren't masked while it's held.
+static FLUID: Mutex<ThreadModeRawMutex, RefCell<Fluid<PARTICLES>>> = Mutex::new(RefCell::new(Fluid::new(125, 61)));
+
+// Signaled by the simulation task after each step
+static STEPPED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+// The latest gravity input, as (x, y)
+static GRAVITY: Signal<ThreadModeRawMutex, (f32, f32)> = Signal::new();
+
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mut p = F0Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    // Configure the system clock, and the display bus's clocks
+    let mut rcc = board::init_clocks(p.RCC, &mut p.FLASH);
+
+    // Configure systick as the time base for timers
+    time_driver::init(cp.SYST, SYSCLK_HZ);
+
+    // Initialize the DMA I2C interface shared by all devices on the bus
+    board::init_display_bus(p.I2C1, &mut p.DMA1, p.GPIOB, &mut rcc);
+
+    let oled_buffer = cortex_m::singleton!(: OLEDBuffer = [0; OLED_FRAME_SIZE]).unwrap();
+    spawner.must_spawn(display(oled_buffer));
+    spawner.must_spawn(simulate());
+    spawner.must_spawn(gravity());
+}
+
+
+/// Bring up the display, then draw and transmit each step of the simulation
+#[embassy_executor::task]
+async fn display(oled_buffer: &'static mut OLEDBuffer) {
+    // Delay for 100ms to ensure display has time to boot
+    Timer::after_millis(100).await;
+    let mut display = OLEDDriver::new(OLED_ADDR_PRIMARY, PowerSource::ChargePump, oled_buffer);
+
+    loop {
+        // Draw once the previous frame is out, so drawing never blocks
+        display.frame_done().await;
+        display.clear();
+        FLUID.lock(|fluid_sim| draw_particles(&mut display, &fluid_sim.borrow()));
+        display.tx_frame();
+        STEPPED.wait().await;
+    }
+}
+
+/// Step the simulation once per frame period
+#[embassy_executor::task]
+async fn simulate() {
+    // Allow the user to appreciate the intial state, 
+    // once the display has booted and shown it
+    Timer::after_millis(3_100).await;
+
+    let mut ticker = Ticker::every(FRAME_PERIOD);
+    loop {
+        FLUID.lock(|fluid_sim| {
+            let mut fluid_sim = fluid_sim.borrow_mut();
+            if let Some((gx, gy)) = GRAVITY.try_take() {
+                fluid_sim.set_gravity(gx, gy);
+            }
+            fluid_sim.step();
+        });
+        STEPPED.signal(());
+        ticker.next().await;
+    }
+}
+
+/// Cycle through different gravity configurations to make the
+/// simulation more interesting, standing in for a tilt input
+#[embassy_executor::task]
+async fn gravity() {
+    // Each configuration and how many frames it lasts
+    const CYCLE: [((f32, f32), u32); 6] = [
+        ((0.0, 0.0), 300),
+        ((0.0, 1.0), 100),
+        ((1.0, 0.0), 200),
+        ((-1.0, 0.0), 300),
+        ((0.0, -1.0), 100),
+        ((0.0, 0.0), 300),
+    ];
+
+    loop {
+        for &(gravity, frames) in CYCLE.iter() {
+            GRAVITY.signal(gravity);
+            Timer::after(FRAME_PERIOD * frames).await;
+        }
+    }
+}
+
+
+/// Draw an individual particle at the given origin
+fn draw_particle(display: &mut OLEDDriver, x: usize, y: usize) {
+    const PIXELS: [(usize,usize); 12] = [
+                (1, 0), (2, 0),
+        (0, 1), (1, 1), (2, 1), (3, 1),
+        (0, 2), (1, 2), (2, 2), (3, 2),
+                (1, 3), (2, 3),
+    ];
+
+    for (dx, dy) in PIXELS {
+        display.set_pixel(x + dx, y + dy, true);
+    }
+}
+
+/// Draw all fluid simulation particles
+fn draw_particles<const T:usize>(display: &mut OLEDDriver, fluid_sim: &Fluid<T>) {
+    for particle in fluid_sim.get_particles() {
+        let (x, y) = particle.get_display_position();
+        draw_particle(display, x as usize, y as usize);
+    }
+}
+
+
+#[interrupt]
+fn DMA1_CH2_3() {
+    // DMA I2C interface, while transmitting on channel 2
+    static mut I2C_INTERFACE: Option<DMAi2c> = None;
+    DMAi2c::on_dma_interrupt(I2C_INTERFACE, Interrupt::DMA1_CH2_3);
+}
+
+#[interrupt]
+fn I2C1() {
+    DMAi2c::on_i2c_interrupt(Interrupt::I2C1);
+}
@@ -0,0 +1,51 @@
+use core::{cell::{Cell, RefCell}, task::Waker};
+use cortex_m::{interrupt::Mutex, peripheral::{syst::SystClkSource, SYST}};
+use cortex_m_rt::exception;
+use embassy_time_driver::Driver;
+use embassy_time_queue_utils::Queue;
+
+
+/// An embassy-time driver ticking at 1kHz (see the tick-hz-1_000 feature)
+/// on SysTick, which leaves the timers free. Each tick wakes the timers 
+/// that have expired.
+struct SysTickDriver {
+    ticks: Mutex<Cell<u64>>,
+    queue: Mutex<RefCell<Queue>>,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: SysTickDriver = SysTickDriver {
+    ticks: Mutex::new(Cell::new(0)),
+    queue: Mutex::new(RefCell::new(Queue::new())),
+});
+
+impl Driver for SysTickDriver {
+    fn now(&self) -> u64 {
+        cortex_m::interrupt::free(|cs| self.ticks.borrow(cs).get())
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        cortex_m::interrupt::free(|cs| {
+            self.queue.borrow(cs).borrow_mut().schedule_wake(at, waker);
+        });
+    }
+}
+
+
+/// Start ticking, with SysTick clocked by the core at the given frequency
+pub fn init(mut syst: SYST, sysclk_hz: u32) {
+    syst.set_clock_source(SystClkSource::Core);
+    syst.set_reload(sysclk_hz / embassy_time_driver::TICK_HZ as u32 - 1);
+    syst.clear_current();
+    syst.enable_interrupt();
+    syst.enable_counter();
+}
+
+
+#[exception]
+fn SysTick() {
+    cortex_m::interrupt::free(|cs| {
+        let ticks = DRIVER.ticks.borrow(cs);
+        ticks.set(ticks.get() + 1);
+        DRIVER.queue.borrow(cs).borrow_mut().next_expiration(ticks.get());
+    });
+}
@@ -0,0 +1,308 @@
+//! Tests run on the STM32F0 itself, to catch what the host tests can't:
+//! target-specific arithmetic (shifts, overflow and division on a core
+//! without a divider), and the display drivers against real hardware.
+//! Results are reported over semihosting, and the program exits with
+//! a failure status if any test fails, so it can be run from CI with
+//! a debug probe attached, e.g. with a runner in .cargo/config and
+//!
+//!   cargo run --features debug-host --bin target-tests
+//!
+//! The OLED display is expected on I2C1 (PB6/PB7) at the primary address.
+//! The overlay's tested too with the `overlay` feature enabled.
+#![no_std]
+#![no_main]
+
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hio, hprintln};
+use stm32f0xx_hal::{prelude::*, delay::Delay, pac::{interrupt, Interrupt, Peripherals as F0Peripherals}};
+
+// the firmware's display drivers, most of which aren't exercised here
+#[allow(dead_code)]
+#[path = "../oled/mod.rs"]
+mod oled;
+use oled::{DMAi2c, OLEDDriver, OLEDBuffer, PowerSource, OLED_ADDR_PRIMARY, OLED_FRAME_SIZE};
+#[cfg(feature = "overlay")]
+use oled::{overlay::Overlay, OLED_PXLS_X};
+use oled::dmai2c::DmaChannel;
+use oled::dmamem::DmaMem;
+
+// the firmware's MCU family support: the clocks and the display bus
+#[path = "../board/stm32f0.rs"]
+mod board;
+
+// the driver logs over RTT, alongside the semihosted results
+#[path = "../log.rs"]
+mod log;
+
+use fluid_core::Fluid;
+use fluid_core::replay::{GOLDEN, TRACE_PARTICLES};
+use fluid_core::fixed::{FixedPt, FixedPtVec2D};
+
+
+/// The peripherals available to tests
+struct Context {
+    dma: board::pac::DMA1,
+    display: Option<OLEDDriver>,
+}
+
+type TestResult = Result<(), &'static str>;
+type Test = fn(&mut Context) -> TestResult;
+
+/// Fail the current test, naming the condition, unless it holds
+macro_rules! check {
+    ($cond:expr) => {
+        if !$cond {
+            return Err(stringify!($cond));
+        }
+    };
+}
+
+/// The suite, in the order it runs. Later driver tests rely on
+/// the display brought up by display_acknowledges.
+const TESTS: &[(&str, Test)] = &[
+    ("fixed_conversions", fixed_conversions),
+    ("fixed_negative_shifts", fixed_negative_shifts),
+    ("fixed_mul_range", fixed_mul_range),
+    ("fixed_division", fixed_division),
+    ("vector_magnitude", vector_magnitude),
+    ("fluid_matches_host", fluid_matches_host),
+    ("golden_traces_replay", golden_traces_replay),
+    ("dma_mem_clear_and_copy", dma_mem_clear_and_copy),
+    ("display_acknowledges", display_acknowledges),
+    ("display_frame", display_frame),
+    ("display_diff_frame", display_diff_frame),
+    #[cfg(feature = "overlay")]
+    ("display_overlay_detaches", display_overlay_detaches),
+];
+
+
+#[entry]
+fn main() -> ! {
+    let mut p = F0Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    // the same clocks and bus as the firmware
+    let mut rcc = board::init_clocks(p.RCC, &mut p.FLASH);
+    let mut delay = Delay::new(cp.SYST, &rcc);
+    board::init_display_bus(p.I2C1, &mut p.DMA1, p.GPIOB, &mut rcc);
+    delay.delay_ms(100_u8);
+
+    let mut context = Context { dma: p.DMA1, display: None };
+    let mut failures = 0;
+    for (name, test) in TESTS.iter() {
+        match test(&mut context) {
+            Ok(()) => hprintln!("test {} ... ok", name),
+            Err(reason) => {
+                hprintln!("test {} ... FAILED: {}", name, reason);
+                failures += 1;
+            }
+        }
+    }
+
+    hprintln!("test result: {} passed; {} failed", TESTS.len() - failures, failures);
+    debug::exit(match failures {
+        0 => debug::EXIT_SUCCESS,
+        _ => debug::EXIT_FAILURE,
+    });
+
+    loop {
+        continue;
+    }
+}
+
+
+fn fixed_conversions(_: &mut Context) -> TestResult {
+    for value in [i8::MIN, -1, 0, 1, i8::MAX] {
+        check!(FixedPt::from_i8(value).to_i8() == value);
+    }
+    check!(FixedPt::from_f32(1.5).value == 3 << (FixedPt::BASE - 1));
+    check!(FixedPt::from_f32(-0.25).value == -(1 << (FixedPt::BASE - 2)));
+    Ok(())
+}
+
+fn fixed_negative_shifts(_: &mut Context) -> TestResult {
+    // right shifts of negative values must be arithmetic
+    check!(FixedPt::from_f32(-0.25).to_i8() == -1);
+    check!(FixedPt::from_i8(-4) * FixedPt::from_f32(0.5) == FixedPt::from_i8(-2));
+    check!(FixedPt::from_f32(-3.5).abs() == FixedPt::from_f32(3.5));
+    Ok(())
+}
+
+fn fixed_mul_range(_: &mut Context) -> TestResult {
+    // the squares of the largest display coordinates must not overflow
+    let max = FixedPt::from_i8(i8::MAX);
+    let min = FixedPt::from_i8(i8::MIN);
+    check!((max * max).value >> FixedPt::BASE == 127 * 127);
+    check!((min * min).value >> FixedPt::BASE == 128 * 128);
+    check!(FixedPt::from_i8(2) * 3 == FixedPt::from_i8(6));
+    Ok(())
+}
+
+fn fixed_division(_: &mut Context) -> TestResult {
+    // the M0 has no divide instruction, so these use the compiler's routines
+    check!(FixedPt::from_i8(3) / FixedPt::from_i8(2) == FixedPt::from_f32(1.5));
+    check!(FixedPt::from_i8(-9) / FixedPt::from_i8(3) == FixedPt::from_i8(-3));
+    check!(FixedPt::from_i8(7) / 2 == FixedPt::from_f32(3.5));
+    check!(FixedPt::from_i8(-7) / -2 == FixedPt::from_f32(3.5));
+    Ok(())
+}
+
+fn vector_magnitude(_: &mut Context) -> TestResult {
+    check!(FixedPtVec2D::from_i8s(-6, 0).magnitude() == FixedPt::from_i8(6));
+    let estimate = FixedPtVec2D::from_i8s(3, 4).magnitude();
+    check!(estimate >= FixedPt::from_i8(5) && estimate < FixedPt::from_f32(5.42));
+    Ok(())
+}
+
+fn fluid_matches_host(_: &mut Context) -> TestResult {
+    // The same fingerprint of the display positions as a host run of the
+    // firmware's opening sequence, at rest then falling, with fewer
+    // particles so the simulation fits on the stack beside the buffers
+    const HOST: [u32; 2] = [0xEF54_A207, 0x416A_9C2F];
+
+    let mut fluid = Fluid::<24>::new(125, 61);
+    let mut fingerprints = [0; 2];
+    for (i, gravity) in [0.0, 1.0].into_iter().enumerate() {
+        fluid.set_gravity(0.0, gravity);
+        for _ in 0..100 {
+            fluid.step();
+        }
+        fingerprints[i] = fluid.get_particles().iter()
+            .flat_map(|particle| {
+                let (x, y) = particle.get_display_position();
+                [x as u8, y as u8]
+            })
+            .fold(0x811C_9DC5, |hash: u32, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    }
+    check!(fingerprints == HOST);
+    Ok(())
+}
+
+fn golden_traces_replay(_: &mut Context) -> TestResult {
+    // The same end states, bit for bit, as the host's replays
+    let mut fluid = Fluid::<TRACE_PARTICLES>::empty();
+    for trace in GOLDEN {
+        if trace.replay(&mut fluid) != trace.crc {
+            hprintln!("  {} diverged from the host", trace.name);
+            return Err("trace.replay(&mut fluid) == trace.crc");
+        }
+    }
+    Ok(())
+}
+
+fn dma_mem_clear_and_copy(context: &mut Context) -> TestResult {
+    let src = cortex_m::singleton!(: [u8; 64] = [0; 64]).unwrap();
+    let dest = cortex_m::singleton!(: [u8; 64] = [0xFF; 64]).unwrap();
+    for (i, byte) in src.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    let mut dma_mem = DmaMem::new(&mut context.dma, DmaChannel::Channel3);
+    // SAFETY: neither buffer is accessed until after each wait
+    unsafe { dma_mem.start_copy(src, dest) };
+    dma_mem.wait();
+    check!(src == dest);
+
+    // an unaligned, odd length is transferred bytewise
+    unsafe { dma_mem.start_clear(&mut dest[1..60]) };
+    dma_mem.wait();
+    check!(dest[0] == 0 && dest[60] == 60);
+    check!(dest[1..60].iter().all(|byte| *byte == 0));
+    Ok(())
+}
+
+fn display_acknowledges(context: &mut Context) -> TestResult {
+    check!(DMAi2c::probe(OLED_ADDR_PRIMARY));
+    let buffer = cortex_m::singleton!(: OLEDBuffer = [0; OLED_FRAME_SIZE]).unwrap();
+    let mut display = OLEDDriver::new(OLED_ADDR_PRIMARY, PowerSource::ChargePump, buffer);
+    check!(display.self_check());
+    check!(DMAi2c::wait_idle().is_ok());
+    context.display = Some(display);
+    Ok(())
+}
+
+fn display_frame(context: &mut Context) -> TestResult {
+    let display = context.display.as_mut().ok_or("no display")?;
+    DMAi2c::reset_stats();
+    display.test_pattern(oled::Pattern::Checkerboard);
+    check!(display.lit_pixel_count() == (OLED_FRAME_SIZE * 4) as u32);
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    check!(DMAi2c::take_error().is_none());
+    check!(DMAi2c::stats().bytes as usize >= OLED_FRAME_SIZE);
+    Ok(())
+}
+
+fn display_diff_frame(context: &mut Context) -> TestResult {
+    let display = context.display.as_mut().ok_or("no display")?;
+    let shadow = cortex_m::singleton!(: oled::diff::DiffShadow = oled::diff::DiffShadow::new()).unwrap();
+    display.set_diff_shadow(Some(shadow));
+
+    // an unchanged frame transmits nothing after the first
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    DMAi2c::reset_stats();
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    check!(DMAi2c::stats().bytes == 0);
+
+    // a single pixel transmits a short run
+    display.set_pixel(64, 32, false);
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    check!(DMAi2c::stats().bytes > 0 && (DMAi2c::stats().bytes as usize) < OLED_FRAME_SIZE);
+    check!(DMAi2c::take_error().is_none());
+    Ok(())
+}
+
+#[cfg(feature = "overlay")]
+fn display_overlay_detaches(context: &mut Context) -> TestResult {
+    let display = context.display.as_mut().ok_or("no display")?;
+    let overlay = cortex_m::singleton!(: Overlay = Overlay::new(0)).unwrap();
+    overlay.columns_mut().fill(0xFF);
+    let mut page = [0; OLED_PXLS_X];
+    page.copy_from_slice(&display.bitmap()[..OLED_PXLS_X]);
+
+    // the overlay's composited as the frame is sent, leaving the frame untouched
+    display.set_overlay(Some(overlay));
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    check!(display.bitmap()[..OLED_PXLS_X] == page);
+
+    // detaching it restores the page by sending the frame in full
+    DMAi2c::reset_stats();
+    check!(display.set_overlay(None).is_some());
+    display.tx_frame();
+    check!(DMAi2c::wait_idle().is_ok());
+    check!(display.bitmap()[..OLED_PXLS_X] == page);
+    check!(DMAi2c::stats().bytes as usize >= OLED_FRAME_SIZE);
+    check!(DMAi2c::take_error().is_none());
+    Ok(())
+}
+
+
+#[interrupt]
+fn DMA1_CH2_3() {
+    // DMA I2C interface, while transmitting on channel 2
+    static mut I2C_INTERFACE: Option<DMAi2c> = None;
+    DMAi2c::on_dma_interrupt(I2C_INTERFACE, Interrupt::DMA1_CH2_3);
+}
+
+#[interrupt]
+fn I2C1() {
+    DMAi2c::on_i2c_interrupt(Interrupt::I2C1);
+}
+
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    if let Ok(mut stdout) = hio::hstdout() {
+        writeln!(stdout, "test runner panicked: {}", info).ok();
+    }
+    debug::exit(debug::EXIT_FAILURE);
+    loop {
+        continue;
+    }
+}
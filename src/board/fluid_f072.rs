@@ -0,0 +1,163 @@
+//! The Fluid board's layout on an STM32F072, e.g. the STM32F072CB, with
+//! the command shell on a USB serial port as well as the USART (see
+//! usb.rs). The pins are the original board's (see fluid_f030.rs), but
+//! for PA11 and PA12, USB's D- and D+, so the RGB status LED and the
+//! frame start probe, which use them there, aren't available. The
+//! brown-out snapshot and the bootloader's entry are laid out for the
+//! STM32F030K6's memory, so they aren't either.
+//!
+//! | pin       | function                                        |
+//! |-----------|-------------------------------------------------|
+//! | PA0       | the wake button, to ground                      |
+//! | PA1       | left floating, sampled as noise for the seed    |
+//! | PA2, PA3  | the left and right game buttons, to ground      |
+//! | PA4, PA5  | the gravity angle and viscosity knobs (ADC)     |
+//! | PA6, PA7  | the tuning encoder (TIM3)                       |
+//! | PA8       | the status LED (TIM1_CH1)                       |
+//! | PA9, PA10 | the shell's USART1 TX and RX                    |
+//! | PA11      | USB D-                                          |
+//! | PA12      | USB D+                                          |
+//! | PA15      | the SD card's chip select                       |
+//! | PB1       | the piezo buzzer (TIM14_CH1)                    |
+//! | PB3-PB5   | the SD card's SCK, MISO and MOSI (SPI1)         |
+//! | PB6, PB7  | the display bus's SCL and SDA                   |
+
+#[cfg(any(feature = "led-rgb", feature = "scope"))]
+compile_error!("the fluid-f072 board has USB on PA11 and PA12, so it can't have led-rgb or scope");
+
+#[cfg(any(feature = "brownout", feature = "dfu"))]
+compile_error!("brownout and dfu are laid out for the STM32F030K6's memory, so the fluid-f072 board can't have them");
+
+use stm32f0xx_hal::{prelude::*, serial::{Event, Serial}};
+use stm32f0xx_hal::gpio::{gpioa::{PA9, PA10}, Alternate, AF1};
+use stm32f0xx_hal::pac::{Peripherals, USART1};
+use crate::accel::Accelerometer;
+#[cfg(feature = "eeprom")]
+use crate::eeprom::Model;
+use crate::encoder::Encoder;
+use crate::knobs::Knobs;
+use crate::oled::{DMAi2c, PowerSource, OLED_ADDR_PRIMARY};
+use crate::{entropy, log, power};
+use crate::usb;
+#[cfg(feature = "buzzer")]
+use crate::buzzer;
+#[cfg(any(feature = "clock", feature = "hourglass"))]
+use crate::rtc;
+#[cfg(feature = "led")]
+use crate::led;
+#[cfg(any(feature = "pong", feature = "paint"))]
+use crate::buttons;
+#[cfg(feature = "sdlog")]
+use crate::sdlog;
+#[cfg(feature = "stream")]
+use crate::stream;
+#[cfg(feature = "temperature")]
+use crate::temperature;
+use super::Board;
+
+
+/// The display's address on the bus
+pub const DISPLAY_ADDRESS: u8 = OLED_ADDR_PRIMARY;
+
+/// The display's panel supply
+pub const DISPLAY_POWER: PowerSource = PowerSource::ChargePump;
+
+/// The settings EEPROM on the display's bus, when fitted
+#[cfg(feature = "eeprom")]
+pub const EEPROM_MODEL: Model = Model::C32;
+
+/// The command shell's baud rate
+pub const SHELL_BAUD: u32 = 115_200;
+
+/// The command shell's USART
+pub type ShellSerial = Serial<USART1, PA9<Alternate<AF1>>, PA10<Alternate<AF1>>>;
+
+
+impl Board {
+    /// Configure the clocks, the pins and the peripherals, and detect
+    /// the optional sensors
+    pub fn new(mut p: Peripherals) -> Self {
+        // Configure the wake button's interrupt, and Stop mode
+        power::init_wake_button(&p.RCC, &p.SYSCFG, &p.EXTI);
+
+        // Count the tuning encoder's steps
+        let encoder = Encoder::new(p.TIM3, &p.RCC);
+
+        // Seed the random number generator before the knobs take the ADC
+        let seed = entropy::seed(&p.ADC, &p.RCC);
+        log::debug!("entropy: seed {=u32:#x}", seed);
+
+        // Measure the ambient temperature, before the knobs take the ADC
+        #[cfg(feature = "temperature")]
+        temperature::init(&p.ADC, &p.RCC);
+
+        // Sample the analog knobs, when fitted
+        let knobs = cfg!(feature = "knobs").then(|| Knobs::new(p.ADC, &p.RCC));
+
+        // Keep the time of day, for the clock mode and the hourglass
+        #[cfg(any(feature = "clock", feature = "hourglass"))]
+        rtc::init(&p.RTC, &p.RCC, &p.PWR);
+
+        // Chirp as the water splashes, on a buzzer
+        #[cfg(feature = "buzzer")]
+        buzzer::init(&p.TIM14, &p.GPIOB, &p.RCC);
+
+        // Show how lively the fluid is, on a status LED
+        #[cfg(feature = "led")]
+        led::init(&p.TIM1, &p.GPIOA, &p.GPIOB, &p.RCC);
+
+        // Read the game buttons
+        #[cfg(any(feature = "pong", feature = "paint"))]
+        buttons::init(&p.GPIOA, &p.RCC);
+
+        // Log the simulation to an SD card
+        #[cfg(feature = "sdlog")]
+        sdlog::init(&p.SPI1, &p.GPIOA, &p.GPIOB, &p.RCC);
+
+        // Clock USB from the HSI48 oscillator
+        usb::init_clock(&p.RCC, p.CRS);
+
+        // Configure the system clock, and the display bus's clocks
+        let mut rcc = super::init_clocks(p.RCC, &mut p.FLASH);
+
+        // Configure the pins of the wake button, the encoder and the shell,
+        // leaving USB's to its peripheral
+        let gpioa = p.GPIOA.split(&mut rcc);
+        let (usb_dm, usb_dp) = (gpioa.pa11, gpioa.pa12);
+        let (shell_tx, shell_rx) = cortex_m::interrupt::free(move |cs| {
+            let _button = gpioa.pa0.into_pull_up_input(cs);
+            let _encoder_a = gpioa.pa6.into_alternate_af1(cs);
+            let _encoder_b = gpioa.pa7.into_alternate_af1(cs);
+            if cfg!(feature = "knobs") {
+                let _gravity_knob = gpioa.pa4.into_analog(cs);
+                let _viscosity_knob = gpioa.pa5.into_analog(cs);
+            }
+            (gpioa.pa9.into_alternate_af1(cs), gpioa.pa10.into_alternate_af1(cs))
+        });
+
+        // Listen for commands on the shell's USART
+        let mut serial = Serial::usart1(p.USART1, (shell_tx, shell_rx), SHELL_BAUD.bps(), &mut rcc);
+        serial.listen(Event::Rxne);
+
+        // Stream the particles' state over the shell's USART
+        #[cfg(feature = "stream")]
+        stream::init(&p.SYSCFG, &p.DMA1);
+
+        // Serve the shell on USB too, once the host enumerates the device
+        usb::init(p.USB, usb_dm, usb_dp);
+
+        // Initialize the DMA I2C interface shared by all devices on the bus
+        super::init_display_bus(p.I2C1, &mut p.DMA1, p.GPIOB, &mut rcc);
+
+        // Follow the board's tilt, if it has an accelerometer
+        let accel = Accelerometer::detect(DMAi2c::bus());
+
+        Self {
+            encoder,
+            knobs,
+            accel,
+            serial,
+            seed,
+        }
+    }
+}
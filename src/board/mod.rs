@@ -0,0 +1,84 @@
+//! The hardware the firmware runs on. A board, selected by a cargo
+//! feature naming it, maps the display, the inputs and the sensors to
+//! pins and peripherals, and brings them up as a Board. The MCU family's
+//! pieces (the clocks and the display bus) are shared by its boards,
+//! and selected by the board's feature.
+//!
+//! Supporting new hardware with an MCU of a supported family is a new
+//! board file implementing Board::new, and a feature for it.
+//!
+//! Only the STM32F0 family is supported. The `stm32f1` and `stm32l0`
+//! features name the ports asked for, the STM32F1 of the "blue pill" and
+//! the STM32L0, but fail to build with what they're missing. A port to
+//! another family adds a module here with the same items, its HAL as an
+//! optional dependency enabled by its feature, and a DMA I2C driver for
+//! its I2C peripheral, as the one in oled/dmai2c.rs programs the F0's
+//! registers. The drivers of the optional peripherals (the encoder, the
+//! knobs, the temperature sensor, the buzzer, the LED, the ADC entropy,
+//! the RTC, Stop mode, streaming and the SD card log) program the F0's
+//! registers too.
+//!
+//! The STM32F042 and STM32F072 have a USB device peripheral that runs
+//! without a crystal, so a board with one can serve the command shell
+//! over a USB serial port (CDC ACM), needing only a USB cable, with the
+//! `usb` feature (see usb.rs). Its board starts the USB clock before the
+//! RCC is frozen, and brings the device up on PA11 and PA12, for the USB
+//! interrupt's task in main.rs to take. The `fluid-f072` board does, on
+//! an STM32F072.
+//!
+//! The MCU is selected by the board's feature too, e.g. `stm32f030x6`,
+//! choosing the HAL's part and the memory layout in build.rs. Building
+//! for a board other than the default takes --no-default-features, as
+//! the HAL supports one part at a time.
+
+use crate::accel::Accelerometer;
+use crate::encoder::Encoder;
+use crate::knobs::Knobs;
+use crate::oled::dmai2c::bus::I2cBus;
+
+#[cfg(feature = "stm32f0")]
+mod stm32f0;
+#[cfg(feature = "stm32f0")]
+pub use stm32f0::*;
+
+#[cfg(feature = "stm32f1")]
+compile_error!("the STM32F1 family isn't supported yet: it needs a family module with stm32f1xx-hal, \
+                a DMA I2C driver for its I2C peripheral, and ports of the drivers programming the F0's registers");
+
+#[cfg(feature = "stm32l0")]
+compile_error!("the STM32L0 family isn't supported yet: it needs a family module with stm32l0xx-hal, \
+                a DMA I2C driver for its I2C peripheral, and ports of the drivers programming the F0's registers");
+
+#[cfg(not(any(feature = "stm32f0", feature = "stm32f1", feature = "stm32l0")))]
+compile_error!("select an MCU family with its feature, e.g. --features stm32f0");
+
+#[cfg(feature = "fluid-f030")]
+mod fluid_f030;
+#[cfg(feature = "fluid-f030")]
+pub use fluid_f030::*;
+
+#[cfg(feature = "fluid-f072")]
+mod fluid_f072;
+#[cfg(feature = "fluid-f072")]
+pub use fluid_f072::*;
+
+#[cfg(not(any(feature = "fluid-f030", feature = "fluid-f072")))]
+compile_error!("select a board with its feature, e.g. --features fluid-f030");
+
+#[cfg(all(feature = "usb", not(feature = "stm32f072")))]
+compile_error!("the usb feature needs a board with a USB capable MCU, e.g. --no-default-features --features fluid-f072");
+
+
+/// The board's peripherals, configured, for the application to take
+pub struct Board {
+    /// The tuning encoder
+    pub encoder: Encoder,
+    /// The analog knobs, if fitted
+    pub knobs: Option<Knobs>,
+    /// The accelerometer on the display's bus, if fitted
+    pub accel: Option<Accelerometer<I2cBus>>,
+    /// The command shell's USART
+    pub serial: ShellSerial,
+    /// A random number generator seed, from the board's noise
+    pub seed: u32,
+}
@@ -0,0 +1,64 @@
+//! The STM32F0 family, e.g. the STM32F030K6: a 48MHz system clock from
+//! the internal oscillator and the PLL, or the 8MHz oscillator alone when
+//! scaled down (see scaling.rs), and the display bus on I2C1, fed by DMA
+//! channel 2, on PB6 (SCL) and PB7 (SDA).
+
+use stm32f0xx_hal::{prelude::*, rcc::Rcc};
+use stm32f0xx_hal::pac::{DMA1, FLASH, GPIOB, I2C1, RCC};
+use crate::oled::{DMAi2c, Speed};
+
+pub use stm32f0xx_hal::pac;
+
+
+/// The system clock frequency
+pub const SYSCLK_HZ: u32 = 48_000_000;
+
+/// The internal oscillator's frequency, the system clock's without the PLL
+#[allow(dead_code)]
+pub const HSI_HZ: u32 = 8_000_000;
+
+
+/// Enable the display bus's DMA and I2C clocks, then configure the system
+/// clock. Drivers configuring their own clocks from the RCC peripheral
+/// must be created beforehand.
+pub fn init_clocks(rcc: RCC, flash: &mut FLASH) -> Rcc {
+    // TODO: Ideally this would be handled in the OLED driver by passing 
+    //       a reference to the configured rcc value, similar to the I2C 
+    //       pins. This may be possible once the DMA module is available 
+    //       in the stm32f0xx_hal crate.
+    rcc.ahbenr.modify(|_, w| w.dmaen().enabled());
+    rcc.cfgr3.modify(|_, w| w.i2c1sw().sysclk());
+    rcc.apb1enr.modify(|_, w| w.i2c1en().enabled());
+
+    rcc.configure()
+       .sysclk(SYSCLK_HZ.hz())
+       .freeze(flash)
+}
+
+/// The system clock's current frequency: SYSCLK_HZ from the PLL, or the
+/// internal oscillator's, as the clock is scaled down or before the PLL
+/// is restarted after Stop mode. Without the scaling feature, the clock's
+/// only switched within Stop mode, so it's always SYSCLK_HZ here.
+#[allow(dead_code)]
+pub fn sysclk_hz() -> u32 {
+    if !cfg!(feature = "scaling") {
+        return SYSCLK_HZ;
+    }
+    // SAFETY: a read of the clock switch's status
+    let rcc = unsafe { &*RCC::ptr() };
+    match rcc.cfgr.read().sws().is_pll() {
+        true => SYSCLK_HZ,
+        false => HSI_HZ,
+    }
+}
+
+/// Configure the display bus's pins, and initialize the DMA I2C interface
+/// shared by all devices on the bus
+pub fn init_display_bus(i2c: I2C1, dma: &mut DMA1, gpiob: GPIOB, rcc: &mut Rcc) {
+    let gpiob = gpiob.split(rcc);
+    cortex_m::interrupt::free(move |cs| {
+        let _sda = gpiob.pb7.into_alternate_af1(cs);
+        let _scl = gpiob.pb6.into_alternate_af1(cs);
+    });
+    DMAi2c::init(i2c, dma, rcc, Speed::Fast);
+}
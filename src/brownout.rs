@@ -0,0 +1,230 @@
+//! Brown-out and low battery handling, for battery powered builds. The
+//! STM32F030 has no programmable voltage detector, so the supply is
+//! measured instead: the ADC samples the internal reference (VREFINT,
+//! ADC_IN17), whose sample rises as VDDA falls, and its factory
+//! calibration at 3.3V gives the supply in mV. As the battery runs low,
+//! the display is dimmed to stretch it out, and a battery is shown in the
+//! top right corner. Before the supply falls far enough for the display
+//! and the flash to fail, the particles' positions are saved to the last
+//! page of flash, which build.rs keeps out of the firmware with this
+//! feature, and the device sleeps as it does when idle. The next boot,
+//! e.g. once the battery's charged, restores them, so the water picks up
+//! where it was left.
+//!
+//! The snapshot is a run of halfwords: a marker, the particle count, each
+//! particle's display position (x in the low byte), and the wrapping sum
+//! of those before it. Once restored, its marker is programmed to 0 (the
+//! one value flash takes over a programmed halfword) so it's restored
+//! once.
+
+use core::ptr;
+use fluid_core::Fluid;
+use stm32f0xx_hal::pac::{flash::RegisterBlock, ADC, FLASH, RCC};
+use crate::{adc, log};
+use crate::oled::{DMAi2c, OLEDDriver};
+
+
+// The internal reference's ADC channel
+const CHANNEL: u8 = 17;
+
+// The address of the reference's factory calibration: its sample with
+// VDDA at 3.3V
+const VREFINT_CAL_ADDRESS: usize = 0x1FFF_F7BA;
+const VREFINT_CAL_MV: u32 = 3_300;
+
+// The samples averaged into each measurement, one a frame
+const SAMPLES_AVERAGED: u16 = 16;
+
+// The supply the battery is low below, and recovers above, and the
+// supply to save the snapshot and sleep below, in mV, well above the
+// 2.4V the flash needs to be programmed
+const LOW_MV: u32 = 3_100;
+const RECOVERED_MV: u32 = 3_200;
+const CRITICAL_MV: u32 = 2_900;
+
+// The contrast the display is dimmed to, while the battery is low
+const DIM_CONTRAST: u8 = 0;
+
+// The last page of flash, kept for the snapshot (see build.rs), and the
+// keys unlocking the flash for programming
+const SNAPSHOT_ADDRESS: u32 = 0x0800_7C00;
+const SNAPSHOT_PAGE_SIZE: usize = 1024;
+const FLASH_KEYS: [u32; 2] = [0x4567_0123, 0xCDEF_89AB];
+
+// The snapshot's marker
+const MARKER: u16 = 0xB047;
+
+// The battery's outline, its top left corner and size, and its terminal
+const BATTERY: (i32, i32, i32, i32) = (115, 1, 10, 6);
+const TERMINAL: (i32, i32, i32, i32) = (125, 3, 1, 2);
+
+
+/// Enable the internal reference, and calibrate and enable the ADC
+/// (which the other analog inputs then share)
+pub fn init(adc: &ADC, rcc: &RCC) {
+    adc.ccr.modify(|_, w| w.vrefen().set_bit());
+    adc::enable(adc, rcc);
+}
+
+
+// The state of the supply
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+enum Supply {
+    Good,
+    /// Low: the display's dimmed and the battery shown
+    Low,
+    /// About to fail: the snapshot's saved and the device sleeps
+    Critical,
+}
+
+/// Averages the internal reference's samples into the supply's state
+pub struct SupplyMonitor {
+    sum: u32,
+    count: u16,
+    supply: Supply,
+}
+
+impl SupplyMonitor {
+    /// Create a monitor, with the supply good. The reference is enabled
+    /// by init.
+    pub const fn new() -> Self {
+        Self { sum: 0, count: 0, supply: Supply::Good }
+    }
+
+    /// Sample the internal reference, once a frame, and as the supply
+    /// changes, dim the display, or back to the given contrast once it
+    /// recovers, or save the snapshot as it fails. Returns true once a
+    /// measurement finds it failing, when the device should sleep.
+    pub fn update<const N: usize>(&mut self, fluid: &Fluid<N>, display: &mut OLEDDriver, contrast: u8) -> bool {
+        // SAFETY: the ADC converts only in the simulation task, where
+        //         the other inputs' conversions are finished by this one's
+        let adc = unsafe { &*ADC::ptr() };
+        self.sum += adc::convert(adc, CHANNEL) as u32;
+        self.count += 1;
+        if self.count < SAMPLES_AVERAGED {
+            return false;
+        }
+
+        let average = core::mem::take(&mut self.sum) / SAMPLES_AVERAGED as u32;
+        self.count = 0;
+        // SAFETY: the calibration is a halfword in the system memory
+        let calibration = unsafe { ptr::read_volatile(VREFINT_CAL_ADDRESS as *const u16) } as u32;
+        let millivolts = VREFINT_CAL_MV * calibration / average.max(1);
+
+        let supply = match self.supply {
+            _ if millivolts < CRITICAL_MV => Supply::Critical,
+            _ if millivolts < LOW_MV => Supply::Low,
+            Supply::Good => Supply::Good,
+            _ if millivolts > RECOVERED_MV => Supply::Good,
+            supply => supply,
+        };
+        if supply != self.supply {
+            log::warn!("brownout: {} at {=u32}mV", supply, millivolts);
+            match supply {
+                Supply::Good => display.set_contrast(contrast),
+                Supply::Low => display.set_contrast(DIM_CONTRAST),
+                Supply::Critical => {
+                    DMAi2c::wait_idle().ok();
+                    save(fluid);
+                },
+            }
+            self.supply = supply;
+        }
+        supply == Supply::Critical
+    }
+
+    /// Draw the battery, while it's low
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        if self.supply == Supply::Good {
+            return;
+        }
+        let (x, y, w, h) = BATTERY;
+        display.fill_rect(x, y, w, h, false);
+        display.draw_rect(x, y, w, h);
+        display.fill_rect(x + 2, y + 2, 2, h - 4, true);
+        let (x, y, w, h) = TERMINAL;
+        display.fill_rect(x, y, w, h, true);
+    }
+}
+
+
+// Save a snapshot of the particles' positions to flash, erasing the
+// previous one. This stalls the core for the page's erase, some 40ms.
+fn save<const N: usize>(fluid: &Fluid<N>) {
+    const { assert!((N + 3) * 2 <= SNAPSHOT_PAGE_SIZE, "too many particles to snapshot") };
+    // SAFETY: the flash is only programmed here, from the simulation task
+    let flash = unsafe { &*FLASH::ptr() };
+    unlock(flash);
+    flash.cr.modify(|_, w| w.per().set_bit());
+    flash.ar.write(|w| w.far().bits(SNAPSHOT_ADDRESS));
+    flash.cr.modify(|_, w| w.strt().set_bit());
+    wait(flash);
+    flash.cr.modify(|_, w| w.per().clear_bit().pg().set_bit());
+
+    let positions = fluid.get_particles().iter().map(|particle| {
+        let (x, y) = particle.get_display_position();
+        u16::from_le_bytes([x as u8, y as u8])
+    });
+    let mut sum: u16 = 0;
+    let mut address = SNAPSHOT_ADDRESS as *mut u16;
+    for halfword in [MARKER, N as u16].into_iter().chain(positions) {
+        sum = sum.wrapping_add(halfword);
+        program(flash, address, halfword);
+        address = address.wrapping_add(1);
+    }
+    program(flash, address, sum);
+    flash.cr.modify(|_, w| w.pg().clear_bit().lock().set_bit());
+    log::info!("brownout: snapshot saved");
+}
+
+/// Restore the particles' positions from a snapshot in flash, if there's
+/// one for as many particles, returning true if they were
+pub fn restore<const N: usize>(fluid: &mut Fluid<N>) -> bool {
+    let snapshot = SNAPSHOT_ADDRESS as *const u16;
+    // SAFETY: the snapshot's page is in flash, kept by build.rs
+    let read = |index: usize| unsafe { ptr::read_volatile(snapshot.add(index)) };
+    if read(0) != MARKER || read(1) != N as u16 {
+        return false;
+    }
+    let sum = (0..N + 2).fold(0u16, |sum, index| sum.wrapping_add(read(index)));
+    if sum != read(N + 2) {
+        return false;
+    }
+    fluid.arrange_at((0..N).map(|index| {
+        let [x, y] = read(index + 2).to_le_bytes();
+        (x as i8, y as i8)
+    }));
+
+    // SAFETY: as in save
+    let flash = unsafe { &*FLASH::ptr() };
+    unlock(flash);
+    flash.cr.modify(|_, w| w.pg().set_bit());
+    program(flash, SNAPSHOT_ADDRESS as *mut u16, 0);
+    flash.cr.modify(|_, w| w.pg().clear_bit().lock().set_bit());
+    log::info!("brownout: snapshot restored");
+    true
+}
+
+
+// Unlock the flash's control register, if it's locked
+fn unlock(flash: &RegisterBlock) {
+    if flash.cr.read().lock().bit_is_set() {
+        for key in FLASH_KEYS {
+            flash.keyr.write(|w| w.fkeyr().bits(key));
+        }
+    }
+}
+
+// Program a halfword, with programming enabled, waiting until it's done
+fn program(flash: &RegisterBlock, address: *mut u16, halfword: u16) {
+    // SAFETY: the address is in the snapshot's page, which the firmware
+    //         doesn't occupy
+    unsafe { ptr::write_volatile(address, halfword) };
+    wait(flash);
+}
+
+// Wait for the flash to finish an operation, and acknowledge its end
+fn wait(flash: &RegisterBlock) {
+    while flash.sr.read().bsy().bit_is_set() {}
+    flash.sr.write(|w| w.eop().set_bit());
+}
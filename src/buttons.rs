@@ -0,0 +1,20 @@
+//! The game buttons, on PA2 (left) and PA3 (right), to ground, for the
+//! modes played with them: Pong (see pong.rs) and painting (see
+//! paint.rs).
+
+use stm32f0xx_hal::pac::{GPIOA, RCC};
+
+
+/// Configure the buttons' pins, with pull ups
+pub fn init(gpioa: &GPIOA, rcc: &RCC) {
+    rcc.ahbenr.modify(|_, w| w.iopaen().enabled());
+    gpioa.pupdr.modify(|_, w| w.pupdr2().pull_up().pupdr3().pull_up());
+    gpioa.moder.modify(|_, w| w.moder2().input().moder3().input());
+}
+
+/// The buttons held, left then right
+pub fn held() -> [bool; 2] {
+    // SAFETY: a read of the buttons' pins, which are only read
+    let idr = unsafe { &*GPIOA::ptr() }.idr.read();
+    [idr.idr2().is_low(), idr.idr3().is_low()]
+}
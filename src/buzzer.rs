@@ -0,0 +1,80 @@
+//! A piezo buzzer chirping as the water splashes against the walls, its
+//! pitch rising with the speed of the impact (see Fluid::impact). The
+//! buzzer is driven by TIM14's PWM output on PB1 (TIM14_CH1, alternate
+//! function 0), at half duty, and sounds for a couple of frames per
+//! splash, so the frame loop only starts and stops it.
+
+use fluid_core::fixed::FixedPt;
+use stm32f0xx_hal::pac::{GPIOB, RCC, TIM14};
+use crate::board::{self, SYSCLK_HZ};
+
+
+// The timer counts at 1MHz
+const TIMER_HZ: u32 = 1_000_000;
+
+// An impact slower than this doesn't chirp, in pixels per step, so the
+// water resting on the floor under gravity stays quiet
+const THRESHOLD: FixedPt = FixedPt::from_f32(2.0);
+
+// The pitch of the slowest audible impact, and the rise per pixel per
+// step faster, up to the highest pitch
+const BASE_HZ: u32 = 600;
+const HZ_PER_SPEED: u32 = 400;
+const MAX_HZ: u32 = 4_000;
+
+// Each chirp's length
+const CHIRP_FRAMES: u8 = 2;
+
+
+/// Configure PB1 and TIM14 for the buzzer, silent
+pub fn init(tim: &TIM14, gpiob: &GPIOB, rcc: &RCC) {
+    rcc.ahbenr.modify(|_, w| w.iopben().enabled());
+    rcc.apb1enr.modify(|_, w| w.tim14en().enabled());
+    gpiob.afrl.modify(|_, w| w.afrl1().af0());
+    gpiob.moder.modify(|_, w| w.moder1().alternate());
+
+    // the timer is clocked at the system clock, as PCLK isn't divided
+    tim.psc.write(|w| w.psc().bits((SYSCLK_HZ / TIMER_HZ - 1) as u16));
+    tim.ccmr1_output().write(|w| w.oc1m().pwm_mode1().oc1pe().set_bit());
+    tim.ccer.write(|w| w.cc1e().set_bit());
+}
+
+
+/// Chirps as the water splashes
+pub struct Buzzer {
+    frames_left: u8,
+}
+
+impl Buzzer {
+    /// Create a buzzer, silent. The timer is configured by init.
+    pub const fn new() -> Self {
+        Self { frames_left: 0 }
+    }
+
+    /// Chirp for the fastest impact in the frame, if it's fast enough,
+    /// or end the last chirp once it has sounded long enough. Called
+    /// once a frame.
+    pub fn update(&mut self, impact: FixedPt) {
+        // SAFETY: only TIM14's registers are modified, which only this module uses
+        let tim = unsafe { &*TIM14::ptr() };
+
+        if impact >= THRESHOLD {
+            let speed = (impact - THRESHOLD).to_i8() as u32;
+            let hz = (BASE_HZ + speed * HZ_PER_SPEED).min(MAX_HZ);
+            let period = TIMER_HZ / hz;
+            // the timer's clock follows the system clock, which may be scaled
+            tim.psc.write(|w| w.psc().bits((board::sysclk_hz() / TIMER_HZ - 1) as u16));
+            tim.arr.write(|w| unsafe { w.bits(period - 1) });
+            tim.ccr1.write(|w| unsafe { w.bits(period / 2) });
+            tim.egr.write(|w| w.ug().update());
+            tim.cr1.modify(|_, w| w.cen().enabled());
+            self.frames_left = CHIRP_FRAMES;
+        } else if self.frames_left > 0 {
+            self.frames_left -= 1;
+            if self.frames_left == 0 {
+                // a zero duty holds the output low, from the next period
+                tim.ccr1.write(|w| unsafe { w.bits(0) });
+            }
+        }
+    }
+}
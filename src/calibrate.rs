@@ -0,0 +1,179 @@
+//! The accelerometer's calibration, a guided mode finding how the sensor
+//! board's mounted, so tilting works whichever way round it's fitted: the
+//! display asks for the board to be laid flat, face up, then stood
+//! upright, and once it's held still in each pose for a second, the
+//! samples are averaged. Lying flat, the axis reading the most is the
+//! one out of the display's face, and upright, the one up the display;
+//! the third is across it, its direction following from the other two
+//! as the sensor's axes are right handed. Each axis's offset is its
+//! reading in the pose where it's level. The result's applied to the
+//! accelerometer (see Mounting), and kept with the settings, when
+//! they're kept (see settings.rs).
+
+use embedded_hal_1::i2c::I2c;
+use crate::accel::{Accelerometer, Mounting};
+use crate::oled::{OLEDDriver, FONT_ADVANCE};
+
+
+// The change between samples, in g, under which the board's still, and
+// the still samples averaged in each pose
+const STILL_G: f32 = 0.03;
+const SAMPLES: u8 = 30;
+
+// The least reading, in g, along the axis gravity's found on, so a pose
+// held at an angle is waited out
+const ALIGNED_G: f32 = 0.8;
+
+// The frames the result's shown for
+const MESSAGE_FRAMES: u8 = 60;
+
+// The prompt's box, across the middle of the display, and its progress
+// bar, filling as the still samples are taken
+const PROMPT_BOX: (i32, i32, i32, i32) = (4, 20, 120, 22);
+const PROGRESS_Y: i32 = 34;
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum Pose {
+    Flat,
+    Upright,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Off,
+    Sampling { pose: Pose, still: u8 },
+    Message { text: &'static str, frames: u8 },
+}
+
+pub struct Calibration {
+    state: State,
+    // The last sample, the sum of the still samples in the pose, and the
+    // flat pose's average
+    last: [f32; 3],
+    sum: [f32; 3],
+    flat: [f32; 3],
+}
+
+impl Calibration {
+    /// Create the calibration, not running
+    pub const fn new() -> Self {
+        Self { state: State::Off, last: [0.0; 3], sum: [0.0; 3], flat: [0.0; 3] }
+    }
+
+    /// Start calibrating, from the flat pose, if there's an
+    /// accelerometer to calibrate
+    pub fn start(&mut self, accelerometer: bool) {
+        self.state = match accelerometer {
+            true => State::Sampling { pose: Pose::Flat, still: 0 },
+            false => State::Message { text: "no accelerometer", frames: MESSAGE_FRAMES },
+        };
+    }
+
+    /// Stop calibrating, e.g. to return to the demo
+    pub fn stop(&mut self) {
+        self.state = State::Off;
+    }
+
+    /// Sample the accelerometer in the pose asked for, while calibrating.
+    /// Returns how it's mounted once both poses have been sampled. Called
+    /// once a frame.
+    pub fn update<I: I2c>(&mut self, accel: &mut Accelerometer<I>) -> Option<Mounting> {
+        match self.state {
+            State::Off => None,
+            State::Message { text, frames } => {
+                self.state = match frames {
+                    0 => State::Off,
+                    _ => State::Message { text, frames: frames - 1 },
+                };
+                None
+            },
+            State::Sampling { pose, still } => {
+                let sample = accel.read().ok()?;
+                let moved: f32 = sample.iter().zip(self.last).map(|(a, b)| (a - b) * (a - b)).sum();
+                self.last = sample;
+                if moved > STILL_G * STILL_G || !self.is_posed(pose, sample) {
+                    self.sum = [0.0; 3];
+                    self.state = State::Sampling { pose, still: 0 };
+                    return None;
+                }
+                for (sum, g) in self.sum.iter_mut().zip(sample) {
+                    *sum += g;
+                }
+                if still + 1 < SAMPLES {
+                    self.state = State::Sampling { pose, still: still + 1 };
+                    return None;
+                }
+
+                let average = self.sum.map(|sum| sum / SAMPLES as f32);
+                self.sum = [0.0; 3];
+                match pose {
+                    Pose::Flat => {
+                        self.flat = average;
+                        self.state = State::Sampling { pose: Pose::Upright, still: 0 };
+                        None
+                    },
+                    Pose::Upright => {
+                        self.state = State::Message { text: "calibrated", frames: MESSAGE_FRAMES };
+                        Some(solve(self.flat, average))
+                    },
+                }
+            },
+        }
+    }
+
+    // Whether the sample's gravity is along an axis, and upright, along
+    // another axis than it was flat
+    fn is_posed(&self, pose: Pose, sample: [f32; 3]) -> bool {
+        let axis = dominant(sample);
+        sample[axis].abs() >= ALIGNED_G && (pose == Pose::Flat || axis != dominant(self.flat))
+    }
+
+    /// Draw the prompt, and the progress in the pose, while calibrating
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        let (text, progress) = match self.state {
+            State::Off => return,
+            State::Sampling { pose: Pose::Flat, still } => ("lay flat, face up", Some(still)),
+            State::Sampling { pose: Pose::Upright, still } => ("stand upright", Some(still)),
+            State::Message { text, .. } => (text, None),
+        };
+        let (x, y, w, h) = PROMPT_BOX;
+        display.fill_rect(x, y, w, h, false);
+        display.draw_rect(x, y, w, h);
+        let width = text.len() as i32 * FONT_ADVANCE;
+        display.draw_text(x + (w - width) / 2, y + 4, text);
+        if let Some(still) = progress {
+            let filled = still as i32 * (w - 8) / SAMPLES as i32;
+            display.fill_rect(x + 4, PROGRESS_Y, filled, 4, true);
+        }
+    }
+}
+
+
+// The axis reading the most
+fn dominant(sample: [f32; 3]) -> usize {
+    (0..3).fold(0, |best, i| if sample[i].abs() > sample[best].abs() { i } else { best })
+}
+
+// How the sensor's mounted, from its average readings flat and upright:
+// the sensor measures the reaction to gravity, out of the face when flat
+// and up the display when upright, and the display's x is y cross z
+fn solve(flat: [f32; 3], upright: [f32; 3]) -> Mounting {
+    let (z, y) = (dominant(flat), dominant(upright));
+    let x = 3 - z - y;
+    let sign = |g: f32| if g < 0.0 { -1 } else { 1 };
+    let (z_sign, y_sign) = (sign(flat[z]), sign(upright[y]));
+    // the sensor's y cross z axes is its x, and so on round
+    let cyclic = (z + 3 - y) % 3 == 1;
+    let x_sign = y_sign * z_sign * if cyclic { 1 } else { -1 };
+    let axis = |i: usize, sign: i8| sign * (i as i8 + 1);
+
+    let mut offsets = [0.0; 3];
+    offsets[x] = (flat[x] + upright[x]) / 2.0;
+    offsets[y] = flat[y];
+    offsets[z] = upright[z];
+    Mounting {
+        axes: [axis(x, x_sign), axis(y, y_sign), axis(z, z_sign)],
+        offsets: offsets.map(|g| (g * 128.0).clamp(-127.0, 127.0) as i8),
+    }
+}
@@ -0,0 +1,64 @@
+//! The clock mode, a desk clock: the time is spelled out in particles
+//! (see fluid_core::text) as each minute starts, floats for a while,
+//! then collapses to the floor until the time reforms for the next
+//! minute, following the clock scene (see scenes.rs). The time is kept
+//! by the RTC (see rtc.rs), and the clock keeps the display awake.
+
+use fluid_core::{scene::SceneManager, text, Fluid};
+use crate::rtc::{self, Time};
+use crate::scenes;
+
+
+// The time is centred on the display
+const DISPLAY_SIZE: (i8, i8) = (125, 61);
+
+
+pub struct Clock {
+    // The minute shown, or None when the clock isn't shown
+    shown: Option<u8>,
+}
+
+impl Clock {
+    /// Create a clock, not shown. The RTC is started by rtc::init.
+    pub const fn new() -> Self {
+        Self { shown: None }
+    }
+
+    /// Show the clock, from the next update
+    pub fn show(&mut self) {
+        self.shown = Some(u8::MAX);
+    }
+
+    /// Stop showing the clock, e.g. to return to the demo
+    pub fn hide(&mut self) {
+        self.shown = None;
+    }
+
+    /// Set the time of day
+    pub fn set(&mut self, time: Time) {
+        rtc::set(time);
+        if self.shown.is_some() {
+            self.show();
+        }
+    }
+
+    /// Reform the time when a minute starts, while the clock is shown,
+    /// starting the clock scene over. Returns true if it was reformed.
+    pub fn update<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) -> bool {
+        let now = rtc::now();
+        match self.shown {
+            Some(minute) if minute != now.minutes => {
+                self.shown = Some(now.minutes);
+                scenes.play(scenes::CLOCK, 0, fluid);
+
+                let time = now.text();
+                // SAFETY: the time's text is ASCII digits and a colon
+                let time = unsafe { core::str::from_utf8_unchecked(&time) };
+                let (width, height) = DISPLAY_SIZE;
+                fluid.arrange_at(text::positions(time, (width - text::width(time)) / 2, (height - text::HEIGHT) / 2));
+                true
+            },
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! The frame loop's behavior, gathered in one place so it's tweaked
+//! here rather than in main: the frame rate, the program of scenes
+//! steering gravity and the scene it starts from, the frame rate while
+//! the clock's scaled down, when it's scaled, how long the splash
+//! holds the logo, whether the HUD's shown from the start, whether the
+//! attract mode starts at boot, when it's built, and which probe
+//! outputs toggle for a logic analyzer, when fitted. The
+//! defaults are a const, and the settings kept across power cycles,
+//! when they're kept (see settings.rs), override the HUD's visibility.
+
+use fluid_core::scene::Scene;
+use crate::scenes;
+#[cfg(feature = "scope")]
+use crate::scope::Probes;
+#[cfg(feature = "eeprom")]
+use crate::settings::Settings;
+
+
+/// How the frame loop behaves
+#[derive(Copy, Clone)]
+pub struct AppConfig {
+    /// The frames per second
+    pub frame_rate: u8,
+    /// The frames per second in the low power clock mode (see scaling.rs)
+    #[cfg(feature = "scaling")]
+    pub low_power_frame_rate: u8,
+    /// The scenes played in turn, steering gravity, e.g. the demo
+    pub program: &'static [Scene],
+    /// The program's scene played first, whose layout the splash shows
+    pub initial_scene: u8,
+    /// The frames the splash holds the whole logo for, before it melts
+    pub splash_hold_frames: u16,
+    /// Whether the HUD's shown from the start (see hud.rs)
+    pub hud: bool,
+    /// Whether the attract mode's started at boot (see attract.rs)
+    #[cfg(feature = "attract")]
+    pub attract: bool,
+    /// The probe outputs toggled for a logic analyzer (see scope.rs)
+    #[cfg(feature = "scope")]
+    pub probes: Probes,
+}
+
+impl AppConfig {
+    /// The default behavior: the demo, at 30 fps, without the HUD
+    pub const DEFAULT: Self = Self {
+        frame_rate: 30,
+        #[cfg(feature = "scaling")]
+        low_power_frame_rate: 10,
+        program: scenes::DEMO,
+        initial_scene: 0,
+        splash_hold_frames: 30,
+        hud: false,
+        #[cfg(feature = "attract")]
+        attract: true,
+        #[cfg(feature = "scope")]
+        probes: Probes::ALL,
+    };
+
+    /// The period of a frame, in whole milliseconds
+    pub const fn frame_period_ms(&self) -> u32 {
+        1_000 / self.frame_rate as u32
+    }
+
+    /// The period of a frame in the low power clock mode
+    #[cfg(feature = "scaling")]
+    pub const fn low_power_frame_period_ms(&self) -> u32 {
+        1_000 / self.low_power_frame_rate as u32
+    }
+
+    /// The configuration, with the settings kept overriding it
+    #[cfg(feature = "eeprom")]
+    pub const fn with_settings(self, settings: &Settings) -> Self {
+        Self { hud: settings.hud, ..self }
+    }
+}
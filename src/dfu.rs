@@ -0,0 +1,86 @@
+//! Firmware updates without opening the case to reach the SWD pads: the
+//! STM32's system memory holds ST's bootloader, which takes new firmware
+//! over USART1 on the shell's pins, e.g. with `stm32flash -w fluid.bin
+//! -g 0x08000000 /dev/ttyUSB0` through the same USB-serial adapter.
+//!
+//! The bootloader is entered from the state the MCU resets to: the
+//! shell's `dfu` command requests it in RAM that survives a reset, and
+//! resets the core, de-initializing every peripheral, then init jumps to
+//! it before the board is brought up (see enter_if_requested). Holding
+//! the wake button, and both game buttons when they're fitted, through a
+//! reset or power-up enters it too, for firmware that doesn't run the
+//! shell: they're read at the first frame, scheduled on the monotonic
+//! once the board's pull ups have long settled, and held, they request
+//! the bootloader just as the command does.
+
+use core::mem::MaybeUninit;
+use core::ptr;
+use cortex_m::peripheral::{NVIC, SCB};
+use stm32f0xx_hal::pac::{GPIOA, RCC, SYSCFG};
+#[cfg(any(feature = "pong", feature = "paint"))]
+use crate::buttons;
+
+
+// The system memory, starting with the bootloader's vector table
+const SYSTEM_MEMORY: u32 = 0x1FFF_EC00;
+
+// The word requesting the bootloader
+const REQUEST: u32 = 0xDF00_B007;
+
+
+// The request, in RAM left alone by cortex-m-rt's startup, so it
+// survives the reset
+#[link_section = ".uninit.DFU_REQUEST"]
+static mut REQUESTED: MaybeUninit<u32> = MaybeUninit::uninit();
+
+
+/// Request the bootloader, and reset the core to enter it
+pub fn restart() -> ! {
+    // SAFETY: a write of the request, which is only read by init, after
+    //         the reset
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(REQUESTED).cast::<u32>(), REQUEST) };
+    SCB::sys_reset()
+}
+
+/// Jump to the bootloader if it was requested before the reset. Called
+/// first thing in init, with interrupts disabled, before the board is
+/// brought up.
+pub fn enter_if_requested(rcc: &RCC, syscfg: &SYSCFG) {
+    // SAFETY: the request is read and cleared before anything else runs
+    let requested = unsafe {
+        let request = ptr::addr_of_mut!(REQUESTED).cast::<u32>();
+        ptr::replace(request, 0) == REQUEST
+    };
+
+    // The bootloader's go command leaves its memory mapped at 0, where
+    // the core takes the vectors from, so the firmware's are mapped back
+    rcc.apb2enr.modify(|_, w| w.syscfgen().enabled());
+    syscfg.cfgr1.modify(|_, w| w.mem_mode().main_flash());
+    if !requested {
+        return;
+    }
+
+    syscfg.cfgr1.modify(|_, w| w.mem_mode().system_flash());
+    // SAFETY: the system memory starts with a valid vector table, and
+    //         the interrupts unmasked for the firmware's tasks before
+    //         init are masked again, and cleared, before the bootloader
+    //         runs with interrupts enabled
+    unsafe {
+        let nvic = &*NVIC::PTR;
+        nvic.icer[0].write(u32::MAX);
+        nvic.icpr[0].write(u32::MAX);
+        cortex_m::interrupt::enable();
+        cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32)
+    }
+}
+
+/// Determine if the wake button is held, and the game buttons too, when
+/// they're fitted. Their pins must have been brought up, with their pull
+/// ups, by the board.
+pub fn buttons_held() -> bool {
+    // SAFETY: a read of the wake button's pin, which is only read
+    let held = unsafe { &*GPIOA::ptr() }.idr.read().idr0().is_low();
+    #[cfg(any(feature = "pong", feature = "paint"))]
+    let held = held && buttons::held() == [true, true];
+    held
+}
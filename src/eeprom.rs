@@ -0,0 +1,118 @@
+//! A 24Cxx serial EEPROM on the display's I2C bus, shared through the
+//! bus handle (see DMAi2c::bus), keeping the settings (see settings.rs)
+//! on boards where programming the MCU's own flash is undesirable, e.g.
+//! as a write would stall the core while the display's DMA runs. The
+//! EEPROM is at 0x50, its address pins tied low, and is detected by
+//! reading from it. Writes are split at its pages, and each waits for
+//! the EEPROM to program it, polling until it acknowledges again.
+
+use embedded_hal_1::i2c::{I2c, Operation};
+use crate::log;
+use crate::settings::Backend;
+
+
+// The EEPROM's address, with A0-A2 low
+const ADDRESS: u8 = 0x50;
+
+// The settings' address within the EEPROM
+const SETTINGS_ADDRESS: u16 = 0;
+
+// The times a write is polled for, about 10ms at 400kHz, beyond the
+// 5ms the EEPROMs take to program a page
+const WRITE_POLLS: u16 = 130;
+
+
+/// The supported EEPROMs
+#[allow(dead_code)]
+#[derive(Copy, Clone, defmt::Format)]
+pub enum Model {
+    /// A 24C01 or 24C02: a byte of address, and 8 byte pages
+    C02,
+    /// A 24C32 to 24C512: two bytes of address, and 32 byte pages
+    C32,
+}
+
+impl Model {
+    // The bytes addressing the given byte of the EEPROM
+    fn address(self, address: u16) -> ([u8; 2], usize) {
+        match self {
+            Model::C02 => ([address as u8, 0], 1),
+            Model::C32 => (address.to_be_bytes(), 2),
+        }
+    }
+
+    // The size of a page, which a write must not cross
+    fn page_size(self) -> u16 {
+        match self {
+            Model::C02 => 8,
+            Model::C32 => 32,
+        }
+    }
+}
+
+
+/// An EEPROM, on an I2C bus
+pub struct Eeprom<I> {
+    i2c: I,
+    model: Model,
+}
+
+impl<I: I2c> Eeprom<I> {
+    /// Find an EEPROM of the given model on the bus
+    pub fn detect(i2c: I, model: Model) -> Option<Self> {
+        let mut eeprom = Self { i2c, model };
+        let mut byte = [0];
+        match eeprom.read(0, &mut byte) {
+            Ok(()) => {
+                log::info!("eeprom: {} at {=u8:#x}", model, ADDRESS);
+                Some(eeprom)
+            },
+            Err(_) => None,
+        }
+    }
+
+    /// Read bytes from the given address
+    pub fn read(&mut self, address: u16, bytes: &mut [u8]) -> Result<(), I::Error> {
+        let (header, len) = self.model.address(address);
+        self.i2c.write_read(ADDRESS, &header[..len], bytes)
+    }
+
+    /// Write bytes to the given address, a page at a time, waiting for
+    /// each page to be programmed
+    pub fn write(&mut self, mut address: u16, mut bytes: &[u8]) -> Result<(), I::Error> {
+        while !bytes.is_empty() {
+            let page_left = (self.model.page_size() - address % self.model.page_size()) as usize;
+            let (page, rest) = bytes.split_at(page_left.min(bytes.len()));
+            let (header, len) = self.model.address(address);
+            self.i2c.transaction(ADDRESS, &mut [Operation::Write(&header[..len]), Operation::Write(page)])?;
+            self.wait_programmed(address)?;
+            address += page.len() as u16;
+            bytes = rest;
+        }
+        Ok(())
+    }
+
+    // Wait for the EEPROM to program a page, during which it doesn't
+    // acknowledge its address, by writing the given address to it
+    fn wait_programmed(&mut self, address: u16) -> Result<(), I::Error> {
+        let (header, len) = self.model.address(address);
+        let mut result = Ok(());
+        for _ in 0..WRITE_POLLS {
+            result = self.i2c.write(ADDRESS, &header[..len]);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+impl<I: I2c> Backend for Eeprom<I> {
+    fn load(&mut self, bytes: &mut [u8]) -> bool {
+        self.read(SETTINGS_ADDRESS, bytes).is_ok()
+    }
+
+    fn save(&mut self, bytes: &[u8]) -> bool {
+        self.write(SETTINGS_ADDRESS, bytes).is_ok()
+    }
+}
@@ -0,0 +1,50 @@
+//! A quadrature rotary encoder counted by TIM3 in encoder mode, so no
+//! steps are missed between polls and no interrupts are needed. The
+//! encoder's A and B outputs go to PA6 and PA7 (TIM3_CH1 and TIM3_CH2,
+//! alternate function 1), with pull-ups.
+
+use stm32f0xx_hal::pac::{RCC, TIM3};
+
+
+// The timer counts every edge of both outputs: four per detent
+const COUNTS_PER_DETENT: i16 = 4;
+
+// The input filter, requiring 8 consecutive equal samples at the
+// timer clock before an edge is counted
+const INPUT_FILTER: u8 = 0b0011;
+
+
+pub struct Encoder {
+    tim: TIM3,
+    last: u16,
+}
+
+impl Encoder {
+    /// Start counting the encoder's steps on TIM3. The encoder's pins
+    /// must be configured for TIM3 beforehand.
+    pub fn new(tim: TIM3, rcc: &RCC) -> Self {
+        rcc.apb1enr.modify(|_, w| w.tim3en().enabled());
+
+        // both channels capture their own input, filtered to reject
+        // contact bounce, and count up or down on each edge
+        tim.ccmr1_input().write(|w| w.cc1s().ti1()
+                                     .cc2s().ti2()
+                                     .ic1f().bits(INPUT_FILTER)
+                                     .ic2f().bits(INPUT_FILTER));
+        tim.smcr.write(|w| w.sms().encoder_mode_3());
+        tim.arr.write(|w| w.arr().bits(u16::MAX));
+        tim.cr1.modify(|_, w| w.cen().enabled());
+
+        let last = tim.cnt.read().cnt().bits();
+        Self { tim, last }
+    }
+
+    /// The number of detents turned since the last call, positive
+    /// clockwise. Partial detents are kept for the next call.
+    pub fn detents(&mut self) -> i16 {
+        let count = self.tim.cnt.read().cnt().bits();
+        let detents = (count.wrapping_sub(self.last) as i16) / COUNTS_PER_DETENT;
+        self.last = self.last.wrapping_add((detents * COUNTS_PER_DETENT) as u16);
+        detents
+    }
+}
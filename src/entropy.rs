@@ -0,0 +1,54 @@
+//! A source of entropy for seeding the random number generator, so each
+//! boot's jitter differs between units and between runs: the noise in
+//! the low bits of the ADC sampling a floating pin, PA1 (ADC_IN1, left
+//! unconnected), mixed with the 96-bit unique device ID.
+
+use stm32f0xx_hal::pac::{ADC, RCC};
+
+
+// The address of the 96-bit unique device ID
+const UID_ADDRESS: usize = 0x1FFF_F7AC;
+
+// The ADC channel sampled for noise, and the number of samples taken
+const NOISE_CHANNEL: u8 = 1;
+const NOISE_SAMPLES: usize = 32;
+
+
+/// Gather a seed from ADC noise and the unique device ID. The ADC is
+/// disabled again afterward, so it can be calibrated for other uses.
+pub fn seed(adc: &ADC, rcc: &RCC) -> u32 {
+    // SAFETY: the unique ID is read-only, and always present
+    let uid: [u32; 3] = core::array::from_fn(|i| unsafe {
+        core::ptr::read_volatile((UID_ADDRESS as *const u32).add(i))
+    });
+
+    rcc.apb2enr.modify(|_, w| w.adcen().enabled());
+
+    // the shortest sample time leaves the most noise
+    adc.cfgr2.write(|w| w.ckmode().pclk_div4());
+    adc.smpr.write(|w| w.smp().cycles1_5());
+    adc.cr.modify(|_, w| w.aden().enabled());
+    while adc.isr.read().adrdy().is_not_ready() {}
+
+    // SAFETY: CHSELR has a bit for each of the 19 channels
+    adc.chselr.write(|w| unsafe { w.bits(1 << NOISE_CHANNEL) });
+    let mut hash = uid.into_iter().fold(FNV_OFFSET, mix);
+    for _ in 0..NOISE_SAMPLES {
+        adc.cr.modify(|_, w| w.adstart().start_conversion());
+        while adc.isr.read().eoc().is_not_complete() {}
+        hash = mix(hash, adc.dr.read().data().bits() as u32);
+    }
+
+    adc.cr.modify(|_, w| w.addis().disable());
+    while adc.cr.read().aden().is_enabled() {}
+    hash
+}
+
+
+// FNV-1a, a word at a time
+const FNV_OFFSET: u32 = 0x811C_9DC5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+fn mix(hash: u32, word: u32) -> u32 {
+    (hash ^ word).wrapping_mul(FNV_PRIME)
+}
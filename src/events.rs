@@ -0,0 +1,43 @@
+//! The events interrupt handlers pass to the frame loop: rather than a
+//! shared resource apiece, each holding the latest of its kind (so a
+//! second command sent within a frame replaced the first), they're queued
+//! in the order they happen, and the frame loop takes them at the start
+//! of each frame. The queue is heapless's single producer, single
+//! consumer queue, lock free between its ends: its producer is a shared
+//! resource, so handlers at any priority push to it under its lock, and
+//! its consumer is the frame loop's own. A new interrupt driven input
+//! adds an event, and pushes it from its handler.
+
+use heapless::spsc::{Consumer, Producer, Queue};
+use crate::log;
+use crate::shell::Command;
+
+
+// The queue's size, holding one fewer events
+const SIZE: usize = 8;
+
+/// The queue, split into its ends at init
+pub type EventQueue = Queue<Event, SIZE>;
+/// The end handlers push events to
+pub type EventProducer = Producer<'static, Event, SIZE>;
+/// The end the frame loop takes events from
+pub type EventConsumer = Consumer<'static, Event, SIZE>;
+
+
+/// An event, for the frame loop
+#[derive(Copy, Clone)]
+pub enum Event {
+    /// A command, from the shell or the remote
+    Command(Command),
+    /// A press of the wake button, while awake
+    WakeButton,
+}
+
+
+/// Push an event, logging it's dropped if the frame loop has fallen so
+/// far behind that the queue's full
+pub fn post(events: &mut EventProducer, event: Event) {
+    if events.enqueue(event).is_err() {
+        log::warn!("events: queue full, event dropped");
+    }
+}
@@ -0,0 +1,63 @@
+//! A HardFault handler reporting where the fault occurred on the display,
+//! so memory bugs (e.g. in the unsafe buffer and DMA code) can be located
+//! without a debugger attached. The Cortex-M0 has no fault status
+//! registers, so the report is the stacked registers: the PC of the
+//! faulting instruction, the LR and xPSR, the stack pointer and r0-r2.
+//!
+//! The DMA I2C interface can't be trusted after a fault, nor can the
+//! clocks be assumed, so the report is written with the clocks reset to
+//! the internal oscillator and the bus bit-banged over the I2C pins.
+
+use cortex_m_rt::{exception, ExceptionFrame};
+use stm32f0xx_hal::{prelude::*, delay::Delay, pac::{self, NVIC}};
+use crate::log;
+use crate::board::{DISPLAY_ADDRESS, DISPLAY_POWER};
+use crate::oled::console::Console;
+use crate::oled::soft_i2c::SoftI2c;
+
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    let sp = frame as *const ExceptionFrame as u32;
+    log::error!("hard fault: pc={=u32:#010x} lr={=u32:#010x} xpsr={=u32:#010x} sp={=u32:#010x}",
+                frame.pc(), frame.lr(), frame.xpsr(), sp);
+
+    // SAFETY: nothing else runs after a fault, so the peripherals are
+    //         taken over from their owners for good
+    let mut p = unsafe { pac::Peripherals::steal() };
+    let cp = unsafe { cortex_m::Peripherals::steal() };
+
+    // Silence the DMA I2C interface and take the bus
+    NVIC::mask(pac::Interrupt::DMA1_CH2_3);
+    NVIC::mask(pac::Interrupt::I2C1);
+    p.DMA1.ch2.cr.modify(|_, w| w.en().disabled());
+    p.I2C1.cr1.modify(|_, w| w.pe().disabled());
+
+    let mut rcc = p.RCC.configure().freeze(&mut p.FLASH);
+    let delay = Delay::new(cp.SYST, &rcc);
+    let gpiob = p.GPIOB.split(&mut rcc);
+    let (scl, sda) = cortex_m::interrupt::free(move |cs| {
+        (gpiob.pb6.into_open_drain_output(cs), gpiob.pb7.into_open_drain_output(cs))
+    });
+    let mut soft_i2c = SoftI2c::new(scl, sda, delay);
+    soft_i2c.recover();
+
+    let mut console = Console::new(soft_i2c, DISPLAY_ADDRESS, DISPLAY_POWER);
+    console.write_line(0, "HARD FAULT");
+    let registers = [
+        ("PC", frame.pc()),
+        ("LR", frame.lr()),
+        ("xPSR", frame.xpsr()),
+        ("SP", sp),
+        ("R0", frame.r0()),
+        ("R1", frame.r1()),
+        ("R2", frame.r2()),
+    ];
+    for (line, (label, value)) in registers.into_iter().enumerate() {
+        console.write_hex(line + 1, label, value);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,82 @@
+//! Fixed rate frame scheduling on the SysTick monotonic. Each frame's
+//! deadline is a whole number of periods after the first, rather than a
+//! period after the previous frame ran, so the rate doesn't drift with
+//! the solver's run time or the latency of starting each frame.
+
+use cortex_m::peripheral::SYST;
+use systick_monotonic::fugit;
+
+
+/// The monotonic's tick rate: SysTick counts milliseconds
+pub const TICK_HZ: u32 = 1_000;
+
+/// A point in time, in milliseconds since the monotonic started
+pub type Instant = fugit::TimerInstantU64<TICK_HZ>;
+
+/// A span of time, in milliseconds
+pub type Duration = fugit::TimerDurationU64<TICK_HZ>;
+
+
+/// Schedules frames at a fixed rate. When a frame overruns its period,
+/// the deadlines already missed are skipped rather than run back to back
+/// to catch up, and counted as dropped frames.
+pub struct FrameScheduler {
+    period: Duration,
+    next: Option<Instant>,
+    dropped: u32,
+}
+
+#[allow(dead_code)]
+impl FrameScheduler {
+    /// Create a scheduler for frames of the given period. The first
+    /// frame scheduled is one period after the time it's scheduled at.
+    pub const fn new(period: Duration) -> Self {
+        Self {
+            period,
+            next: None,
+            dropped: 0,
+        }
+    }
+
+    /// The deadline of the next frame, given the current time
+    pub fn next(&mut self, now: Instant) -> Instant {
+        let mut next = match self.next {
+            Some(previous) => previous + self.period,
+            None => now + self.period,
+        };
+        while next <= now {
+            next += self.period;
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        self.next = Some(next);
+        next
+    }
+
+    /// Change the period, from the next frame scheduled, e.g. as the
+    /// clock's scaled
+    pub fn set_period(&mut self, period: Duration) {
+        self.period = period;
+    }
+
+    /// Restart the schedule, e.g. after a pause, so the frames
+    /// that would have run in the meantime aren't counted as dropped
+    pub fn restart(&mut self) {
+        self.next = None;
+    }
+
+    /// The number of frames dropped after overruns
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+
+/// The cycle count, given the milliseconds counted by a 1kHz SysTick
+/// monotonic: the milliseconds, plus SysTick's count within the current
+/// millisecond, as the Cortex-M0 has no DWT cycle counter. This wraps
+/// every 89 seconds at 48MHz, so only the differences between counts 
+/// are meaningful.
+pub fn cycles(ms: u64) -> u32 {
+    let reload = SYST::get_reload();
+    (ms as u32).wrapping_mul(reload + 1).wrapping_add(reload - SYST::get_current())
+}
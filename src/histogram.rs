@@ -0,0 +1,68 @@
+//! Histograms of each frame's solver time and transmission wait, for
+//! the worst frames an average hides, e.g. a solver step slowed by a
+//! crowd of particles, or a frame held up by a retried transmission.
+//! The frame loop records both times each frame, in cycles, into
+//! power-of-two buckets, over the whole run. They're reported by the
+//! shell's `histogram` command, over the USART and in the log, and
+//! restarted by `histogram reset`.
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::Mutex;
+use crate::log;
+use crate::shell;
+
+
+// The buckets: frames under 4K cycles (85us at 48MHz), then under each
+// power of two up to 4M (87ms), then the rest
+const BUCKETS: usize = 12;
+const FIRST_BUCKET_BITS: u32 = 12;
+const LABELS: [&str; BUCKETS] = ["<4K", "<8K", "<16K", "<32K", "<64K", "<128K", "<256K", "<512K", "<1M", "<2M", "<4M", "4M+"];
+
+// The frames counted in each bucket, saturating, for the solver and
+// the transmission
+type Counts = [u16; BUCKETS];
+static SOLVER: Mutex<Cell<Counts>> = Mutex::new(Cell::new([0; BUCKETS]));
+static TRANSMIT: Mutex<Cell<Counts>> = Mutex::new(Cell::new([0; BUCKETS]));
+
+
+// The bucket counting the given cycles
+fn bucket(cycles: u32) -> usize {
+    let bits = u32::BITS - cycles.leading_zeros();
+    (bits.saturating_sub(FIRST_BUCKET_BITS) as usize).min(BUCKETS - 1)
+}
+
+/// Count a frame's solver cycles, and cycles waiting for the previous
+/// frame's transmission. Called once a frame.
+pub fn record(solver: u32, transmit: u32) {
+    cortex_m::interrupt::free(|cs| {
+        for (counts, cycles) in [(&SOLVER, solver), (&TRANSMIT, transmit)] {
+            let counts: &Cell<[u16]> = counts.borrow(cs);
+            let count = &counts.as_slice_of_cells()[bucket(cycles)];
+            count.set(count.get().saturating_add(1));
+        }
+    });
+}
+
+/// Restart the histograms
+pub fn reset() {
+    cortex_m::interrupt::free(|cs| {
+        SOLVER.borrow(cs).set([0; BUCKETS]);
+        TRANSMIT.borrow(cs).set([0; BUCKETS]);
+    });
+}
+
+/// Write the histograms, a line each, and log them
+pub fn report(out: &mut impl Write) -> fmt::Result {
+    let (solver, transmit) = cortex_m::interrupt::free(|cs| (SOLVER.borrow(cs).get(), TRANSMIT.borrow(cs).get()));
+    log::info!("histogram: solver={} transmit={} (frames per power of two cycles, from 4K)", solver, transmit);
+    for (name, counts) in [("solver (cycles): ", solver), ("transmit (cycles): ", transmit)] {
+        out.write_str(name)?;
+        let mut labelled = [("", 0); BUCKETS];
+        for ((entry, label), count) in labelled.iter_mut().zip(LABELS).zip(counts) {
+            *entry = (label, count as u32);
+        }
+        shell::write_counts(out, &labelled)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! The hourglass, a timer: the water is the sand, in a glass of two
+//! chambers joined by a narrow neck, masked out of the display by
+//! obstacles (see fluid_core::obstacle), and drains from the top chamber
+//! into the bottom. The time is kept by the RTC (see rtc.rs) rather than
+//! by counting frames, and the water drains in step with it: a valve in
+//! the neck, another obstacle, closes whenever the chamber drained into
+//! holds its share of the water for the time gone, and opens again once
+//! it falls behind. When the time's up the hourglass flips, inverting
+//! gravity so the water drains back the other way, for the same time.
+//! The particles in each chamber are counted, and shown beside it.
+
+use fluid_core::{obstacle::Rect, scene::SceneManager, Fluid};
+use crate::oled::OLEDDriver;
+use crate::{rtc, scenes};
+
+
+// The display's size
+const WIDTH: i8 = 125;
+const HEIGHT: i8 = 61;
+
+// The inside of the glass, between its sides, and its neck: the band
+// between the chambers, and the gap through it
+const GLASS_LEFT: i8 = 36;
+const GLASS_RIGHT: i8 = 88;
+const NECK_TOP: i8 = 27;
+const NECK_HEIGHT: i8 = 6;
+const NECK_LEFT: i8 = 58;
+const NECK_RIGHT: i8 = 66;
+
+// The obstacles masking the glass: the display either side of it, the
+// neck's band either side of the gap, and the valve, closing the gap
+// and reaching into the band either side, or open, out of the way
+const WALLS: [Rect; 5] = [
+    Rect::new(0, 0, GLASS_LEFT, HEIGHT),
+    Rect::new(GLASS_RIGHT, 0, WIDTH - GLASS_RIGHT, HEIGHT),
+    Rect::new(0, NECK_TOP, NECK_LEFT, NECK_HEIGHT),
+    Rect::new(NECK_RIGHT, NECK_TOP, WIDTH - NECK_RIGHT, NECK_HEIGHT),
+    VALVE_OPEN,
+];
+const VALVE: usize = 4;
+const VALVE_CLOSED: Rect = Rect::new(NECK_LEFT - 4, NECK_TOP + 1, NECK_RIGHT - NECK_LEFT + 8, NECK_HEIGHT - 3);
+const VALVE_OPEN: Rect = Rect::EMPTY;
+
+// The chambers, above and below the neck, for counting their particles
+const CHAMBERS: [Rect; 2] = [
+    Rect::new(GLASS_LEFT, 0, GLASS_RIGHT - GLASS_LEFT, NECK_TOP),
+    Rect::new(GLASS_LEFT, NECK_TOP + NECK_HEIGHT, GLASS_RIGHT - GLASS_LEFT, HEIGHT - NECK_TOP - NECK_HEIGHT),
+];
+
+// The spacing of the particles as they start, filling the top chamber,
+// and their inset from its sides
+const FILL_SPACING: usize = 4;
+const FILL_INSET: i8 = 2;
+
+// A particle's width as drawn, right of its position
+const PARTICLE_WIDTH: i32 = 4;
+
+// The counts' positions, beside their chambers, and the time left's
+const COUNT_POSITIONS: [(i32, i32); 2] = [(8, 10), (8, 44)];
+const TIME_POSITION: (i32, i32) = (100, 27);
+
+// The seconds in a day, as the RTC's time of day wraps around
+const DAY_SECONDS: u32 = 24 * 60 * 60;
+
+
+pub struct Hourglass {
+    // The time to drain, in seconds, and while the hourglass is running,
+    // the second it was started or last flipped, and the seconds left
+    seconds: u16,
+    started: Option<u32>,
+    left: u16,
+    flipped: bool,
+}
+
+impl Hourglass {
+    /// Create an hourglass, not running. The RTC is started by rtc::init.
+    pub const fn new() -> Self {
+        Self { seconds: 0, started: None, left: 0, flipped: false }
+    }
+
+    /// Start the hourglass, for the given time in seconds, with the
+    /// water all in the top chamber
+    pub fn start<const N: usize>(&mut self, seconds: u16, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::HOURGLASS, 0, fluid);
+        fluid.set_obstacles(&WALLS);
+
+        let [top, _] = CHAMBERS;
+        let columns = (top.x + FILL_INSET..top.x + top.width).step_by(FILL_SPACING);
+        let rows = (top.y..top.y + top.height).step_by(FILL_SPACING);
+        fluid.arrange_at(rows
+            .flat_map(|y| columns.clone().map(move |x| (x, y)))
+            .filter(|&(x, y)| !WALLS.iter().any(|wall| wall.contains(x, y))));
+
+        self.seconds = seconds;
+        self.started = Some(seconds_of_day());
+        self.left = seconds;
+        self.flipped = false;
+    }
+
+    /// Stop the hourglass, taking the glass away, e.g. to return to the
+    /// demo
+    pub fn stop<const N: usize>(&mut self, fluid: &mut Fluid<N>) {
+        if self.started.take().is_some() {
+            fluid.set_obstacles(&[]);
+        }
+    }
+
+    /// Time the hourglass, while it's running, working the valve to keep
+    /// the water draining in step, and flipping it when the time is up.
+    /// Returns true when it flips, to keep the display awake.
+    pub fn update<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) -> bool {
+        let Some(started) = self.started else {
+            return false;
+        };
+        let now = seconds_of_day();
+        let elapsed = (now + DAY_SECONDS - started) % DAY_SECONDS;
+        if elapsed >= self.seconds as u32 {
+            self.flipped = !self.flipped;
+            scenes.select(self.flipped as usize, fluid);
+            self.started = Some(now);
+            self.left = self.seconds;
+            return true;
+        }
+        self.left = self.seconds - elapsed as u16;
+
+        let drained = fluid.particles_in(CHAMBERS[!self.flipped as usize]);
+        let due = N as u32 * elapsed / self.seconds as u32;
+        fluid.move_obstacle(VALVE, match (drained as u32) < due {
+            true => VALVE_OPEN,
+            false => VALVE_CLOSED,
+        });
+        false
+    }
+
+    /// Draw the glass, the count of each chamber's particles and the time
+    /// left over the frame, while the hourglass is running
+    pub fn draw<const N: usize>(&self, display: &mut OLEDDriver, fluid: &Fluid<N>) {
+        if self.started.is_none() {
+            return;
+        }
+        // The particles are drawn right of their positions, so the right
+        // of the glass is drawn a particle's width further right
+        let (top, height) = (NECK_TOP as i32, NECK_HEIGHT as i32);
+        display.fill_rect(GLASS_LEFT as i32 - 1, 0, 1, HEIGHT as i32 + PARTICLE_WIDTH, true);
+        display.fill_rect(GLASS_RIGHT as i32 + PARTICLE_WIDTH, 0, 1, HEIGHT as i32 + PARTICLE_WIDTH, true);
+        display.fill_rect(GLASS_LEFT as i32, top, (NECK_LEFT - GLASS_LEFT) as i32, height, true);
+        display.fill_rect(NECK_RIGHT as i32 + PARTICLE_WIDTH, top, (GLASS_RIGHT - NECK_RIGHT) as i32, height, true);
+
+        for (chamber, (x, y)) in CHAMBERS.into_iter().zip(COUNT_POSITIONS) {
+            display.draw_number(x, y, fluid.particles_in(chamber) as i32);
+        }
+        let (x, y) = TIME_POSITION;
+        let x = display.draw_number(x, y, self.left as i32);
+        display.draw_text(x, y, "s");
+    }
+}
+
+
+// The RTC's time of day, in seconds since midnight
+fn seconds_of_day() -> u32 {
+    let time = rtc::now();
+    (time.hours as u32 * 60 + time.minutes as u32) * 60 + time.seconds as u32
+}
@@ -0,0 +1,132 @@
+//! A heads-up display of the frame loop's performance, for tuning in the
+//! field without a debugger: the frames per second, and the milliseconds
+//! per frame spent in the solver and waiting on the display's DMA, each
+//! averaged over a second, with a bar of the CPU load (see load.rs) at
+//! the right. It's drawn along the top of the display, and toggled with
+//! the shell's `hud` command. A mode may also show a reading on it, e.g.
+//! the parameter sweep's viscosity (see sweep.rs), beneath the
+//! performance's, or in its place while it's hidden.
+
+use fluid_core::fixed::FixedPt;
+use crate::board;
+use crate::load;
+use crate::oled::{OLEDDriver, OLED_PXLS_X};
+
+
+// The period the readings are averaged over, in milliseconds
+const WINDOW_MS: u64 = 1_000;
+
+// The height of the strip cleared behind the readings, a line of text
+const HEIGHT: i32 = 8;
+
+// The width of the load bar
+const LOAD_BAR_WIDTH: i32 = 20;
+
+
+pub struct Hud {
+    enabled: bool,
+    window_start: Option<u64>,
+    frames: u16,
+    solver_cycles: u32,
+    dma_cycles: u32,
+    fps: u16,
+    solver_ms: FixedPt,
+    dma_ms: FixedPt,
+    #[cfg(feature = "sweep")]
+    reading: Option<(&'static str, FixedPt)>,
+}
+
+impl Hud {
+    /// Create a hidden HUD
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            window_start: None,
+            frames: 0,
+            solver_cycles: 0,
+            dma_cycles: 0,
+            fps: 0,
+            solver_ms: FixedPt::ZERO,
+            dma_ms: FixedPt::ZERO,
+            #[cfg(feature = "sweep")]
+            reading: None,
+        }
+    }
+
+    /// Show or hide the HUD
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Determine if the HUD is shown
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Show a reading, its label and value, or stop showing it (None)
+    #[cfg(feature = "sweep")]
+    pub fn show_reading(&mut self, reading: Option<(&'static str, FixedPt)>) {
+        self.reading = reading;
+    }
+
+    /// Record a frame at the given time, in milliseconds, with the cycles
+    /// spent in the solver and waiting on the DMA
+    pub fn record(&mut self, now_ms: u64, solver_cycles: u32, dma_cycles: u32) {
+        let window_start = *self.window_start.get_or_insert(now_ms);
+        self.frames += 1;
+        self.solver_cycles = self.solver_cycles.saturating_add(solver_cycles);
+        self.dma_cycles = self.dma_cycles.saturating_add(dma_cycles);
+
+        let elapsed = now_ms - window_start;
+        if elapsed < WINDOW_MS {
+            return;
+        }
+        let frames = self.frames as u32;
+        self.fps = (frames * 1_000 / elapsed as u32) as u16;
+        self.solver_ms = milliseconds(self.solver_cycles / frames);
+        self.dma_ms = milliseconds(self.dma_cycles / frames);
+        self.window_start = Some(now_ms);
+        self.frames = 0;
+        self.solver_cycles = 0;
+        self.dma_cycles = 0;
+    }
+
+    /// Draw the readings over the top of the frame, if the HUD is shown,
+    /// e.g. "30fps S21.4 D3.2", and the load bar
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        // The reading's on its own strip, e.g. "viscosity x2.5"
+        #[cfg(feature = "sweep")]
+        if let Some((label, value)) = self.reading {
+            let y = if self.enabled { HEIGHT } else { 0 };
+            display.fill_rect(0, y, OLED_PXLS_X as i32, HEIGHT, false);
+            let x = display.draw_text(0, y, label);
+            display.draw_fixed(x, y, value, 1);
+        }
+        if !self.enabled {
+            return;
+        }
+        display.fill_rect(0, 0, OLED_PXLS_X as i32, HEIGHT, false);
+        let x = display.draw_number(0, 0, self.fps as i32);
+        let x = display.draw_text(x, 0, "fps S");
+        let x = display.draw_fixed(x, 0, self.solver_ms, 1);
+        let x = display.draw_text(x, 0, " D");
+        display.draw_fixed(x, 0, self.dma_ms, 1);
+
+        // The bar is outlined, and filled from the left with the load
+        let x = OLED_PXLS_X as i32 - LOAD_BAR_WIDTH;
+        let filled = load::busy_percent() as i32 * (LOAD_BAR_WIDTH - 2) / 100;
+        display.fill_rect(x, 0, LOAD_BAR_WIDTH, HEIGHT - 1, true);
+        display.fill_rect(x + 1 + filled, 1, LOAD_BAR_WIDTH - 2 - filled, HEIGHT - 3, false);
+    }
+}
+
+
+// The milliseconds taken by the given number of cycles. The whole
+// milliseconds and the fraction are divided separately, as 64 bit
+// division takes a lot of flash on the Cortex-M0.
+fn milliseconds(cycles: u32) -> FixedPt {
+    let cycles_per_ms = board::sysclk_hz() / 1_000;
+    let whole = (cycles / cycles_per_ms).min(i16::MAX as u32) << FixedPt::BASE;
+    let fraction = ((cycles % cycles_per_ms) << FixedPt::BASE) / cycles_per_ms;
+    FixedPt { value: (whole + fraction) as i32 }
+}
@@ -0,0 +1,62 @@
+//! Analog control knobs: potentiometers sampled by the ADC each frame,
+//! setting the gravity angle and the viscosity without reflashing. Each
+//! potentiometer's wiper goes to an analog input, with its ends across
+//! the supply: the gravity angle knob to PA4 (ADC_IN4), and the
+//! viscosity knob to PA5 (ADC_IN5).
+//!
+//! A knob only takes effect once turned, so it doesn't undo tuning with
+//! the encoder, and its small jitter between samples is ignored.
+
+use stm32f0xx_hal::pac::{ADC, RCC};
+use crate::adc;
+use crate::tuning::Parameter;
+
+
+/// The full scale of a (12-bit) sample
+pub const FULL_SCALE: u16 = 4095;
+
+// The change in a knob's sample that counts as turning it, well above
+// the noise of a potentiometer's wiper
+const HYSTERESIS: u16 = 48;
+
+
+/// The knobs, with the ADC channel sampling each and the parameter it sets
+const KNOBS: [(u8, Parameter); 2] = [
+    (4, Parameter::GravityAngle),
+    (5, Parameter::Viscosity),
+];
+
+
+pub struct Knobs {
+    adc: ADC,
+    levels: [Option<u16>; KNOBS.len()],
+}
+
+impl Knobs {
+    /// Calibrate and enable the ADC. The knobs' pins must be
+    /// configured as analog inputs beforehand.
+    pub fn new(adc: ADC, rcc: &RCC) -> Self {
+        adc::enable(&adc, rcc);
+        Self {
+            adc,
+            levels: [None; KNOBS.len()],
+        }
+    }
+
+    /// Sample each knob, calling set with its parameter and level
+    /// (0 to FULL_SCALE) if it has been turned since the last call
+    pub fn sample(&mut self, mut set: impl FnMut(Parameter, u16)) {
+        for ((channel, parameter), level) in KNOBS.into_iter().zip(&mut self.levels) {
+            let sample = adc::convert(&self.adc, channel);
+            let turned = match *level {
+                Some(level) => sample.abs_diff(level) > HYSTERESIS,
+                None => true,
+            };
+            if turned {
+                *level = Some(sample);
+                set(parameter, sample);
+            }
+        }
+    }
+}
+
@@ -0,0 +1,96 @@
+//! The lava lamp, an ambient mode to leave running for hours: the fluid
+//! is two phases, the water and the wax settled beneath it, which is
+//! lighter than the water while it's hot (see Fluid::set_buoyancy). The
+//! lamp's temperature cycles slowly, so the wax warms, floats up through
+//! the water to the top of the lamp, then cools and sinks back through
+//! it, and the phases are drawn in shades of grey by ordered dithering,
+//! the wax brightening as it warms.
+
+use fluid_core::{scene::SceneManager, Fluid};
+use crate::draw_particle_shade;
+use crate::oled::{OLEDDriver, OLED_SHADES};
+use crate::scenes;
+
+
+// The wax: the particles of the bottom row of the scene's layout
+const WAX_PARTICLES: usize = 20;
+
+// The frames the temperature takes to cycle, warming over the first half
+// and cooling over the second
+const CYCLE_FRAMES: u16 = 2700;
+
+// The wax's lift against gravity, as a share of it, when coldest and
+// hottest: it sinks below a lift of 0, and rises off the water above 1
+const COLD_LIFT: f32 = -1.0;
+const HOT_LIFT: f32 = 1.6;
+
+// The shades of the water, and of the wax when coldest; the hottest wax
+// is fully lit
+const WATER_SHADE: usize = 4;
+const COLD_WAX_SHADE: usize = 9;
+
+// The temperature's full scale
+const HOT: u16 = CYCLE_FRAMES / 2;
+
+
+pub struct Lava {
+    // The frame of the temperature's cycle, while the lamp's on
+    frame: Option<u16>,
+}
+
+impl Lava {
+    /// Create a lamp, switched off
+    pub const fn new() -> Self {
+        Self { frame: None }
+    }
+
+    /// Switch the lamp on, with the wax cold beneath the water
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::LAVA, 0, fluid);
+        self.frame = Some(0);
+    }
+
+    /// Switch the lamp off, making the wax water again, e.g. to return
+    /// to the demo
+    pub fn stop<const N: usize>(&mut self, fluid: &mut Fluid<N>) {
+        if self.frame.take().is_some() {
+            fluid.set_buoyancy(N, 0.0);
+        }
+    }
+
+    /// Warm or cool the wax, for the temperature's cycle, while the lamp's
+    /// on. Returns true as each cycle starts, to keep the display awake.
+    pub fn update<const N: usize>(&mut self, fluid: &mut Fluid<N>) -> bool {
+        let Some(frame) = self.frame else {
+            return false;
+        };
+        let heat = temperature(frame) as f32 / HOT as f32;
+        fluid.set_buoyancy(N.saturating_sub(WAX_PARTICLES), COLD_LIFT + (HOT_LIFT - COLD_LIFT) * heat);
+        self.frame = Some((frame + 1) % CYCLE_FRAMES);
+        frame == 0
+    }
+
+    /// Draw the water and the wax in their shades, while the lamp's on.
+    /// Returns false, drawing nothing, while it's off.
+    pub fn draw<const N: usize>(&self, display: &mut OLEDDriver, fluid: &Fluid<N>) -> bool {
+        let Some(frame) = self.frame else {
+            return false;
+        };
+        let wax_shade = COLD_WAX_SHADE + (OLED_SHADES - COLD_WAX_SHADE) * temperature(frame) as usize / HOT as usize;
+        for (i, particle) in fluid.get_particles().iter().enumerate() {
+            let shade = if i + WAX_PARTICLES >= N { wax_shade } else { WATER_SHADE };
+            let (x, y) = particle.get_display_position();
+            draw_particle_shade(display, x as usize, y as usize, shade);
+        }
+        true
+    }
+}
+
+
+// The temperature at the given frame of its cycle, from 0 to HOT
+fn temperature(frame: u16) -> u16 {
+    match frame < HOT {
+        true => frame,
+        false => CYCLE_FRAMES - frame,
+    }
+}
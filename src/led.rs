@@ -0,0 +1,95 @@
+//! A status LED, showing at a glance how lively the fluid is: it breathes
+//! with the particles' kinetic energy (see Fluid::kinetic_energy), and
+//! flashes as the water hits the walls. It's driven by TIM1's PWM, each
+//! output through a resistor to ground: a single LED on PA8 (TIM1_CH1,
+//! alternate function 2), or with the `led-rgb` feature, an RGB LED with
+//! its red on PA8, its green on PB0 (TIM1_CH2N) and its blue on PA11
+//! (TIM1_CH4), which shifts from blue to red as the fluid livens up, and
+//! flashes white.
+
+use fluid_core::fixed::FixedPt;
+use stm32f0xx_hal::pac::{GPIOA, GPIOB, RCC, TIM1};
+
+
+// The PWM's period, for 10-bit duty cycles at 47kHz
+const FULL: u16 = 1023;
+
+// The kinetic energy lighting the LED fully, and the fraction of each
+// frame's change in it taken into the brightness
+const ENERGY_FULL: FixedPt = FixedPt::from_f32(4.0);
+const SMOOTHING: f32 = 0.2;
+
+// An impact at least this fast flashes the LED, in pixels per step,
+// fading by this much each frame
+const FLASH_THRESHOLD: FixedPt = FixedPt::from_f32(2.0);
+const FLASH_DECAY: f32 = 0.7;
+
+
+/// Configure the LED's pins and TIM1's PWM, dark
+pub fn init(tim: &TIM1, gpioa: &GPIOA, gpiob: &GPIOB, rcc: &RCC) {
+    rcc.ahbenr.modify(|_, w| w.iopaen().enabled().iopben().enabled());
+    rcc.apb2enr.modify(|_, w| w.tim1en().enabled());
+    gpioa.afrh.modify(|_, w| w.afrh8().af2().afrh11().af2());
+    gpioa.moder.modify(|_, w| if cfg!(feature = "led-rgb") { w.moder8().alternate().moder11().alternate() } else { w.moder8().alternate() });
+    if cfg!(feature = "led-rgb") {
+        gpiob.afrl.modify(|_, w| w.afrl0().af2());
+        gpiob.moder.modify(|_, w| w.moder0().alternate());
+    }
+
+    tim.arr.write(|w| w.arr().bits(FULL));
+    tim.ccmr1_output().write(|w| w.oc1m().pwm_mode1().oc1pe().set_bit()
+                                  .oc2m().pwm_mode1().oc2pe().set_bit());
+    tim.ccmr2_output().write(|w| w.oc4m().pwm_mode1().oc4pe().set_bit());
+    // the complementary green output follows its reference, rather than
+    // inverting it, as the main output is disabled
+    tim.ccer.write(|w| w.cc1e().set_bit().cc2ne().set_bit().cc4e().set_bit());
+    tim.bdtr.modify(|_, w| w.moe().set_bit());
+    tim.cr1.modify(|_, w| w.cen().enabled());
+}
+
+
+/// Lights the LED from the fluid's state
+pub struct Led {
+    level: f32,
+    flash: f32,
+}
+
+impl Led {
+    /// Create an LED, dark. The timer is configured by init.
+    pub const fn new() -> Self {
+        Self { level: 0.0, flash: 0.0 }
+    }
+
+    /// Light the LED for the fluid's kinetic energy, and flash it for an
+    /// impact fast enough. Called once a frame.
+    pub fn update(&mut self, energy: FixedPt, impact: FixedPt) {
+        let target = (energy.value as f32 / ENERGY_FULL.value as f32).min(1.0);
+        self.level += (target - self.level) * SMOOTHING;
+        self.flash = match impact >= FLASH_THRESHOLD {
+            true => 1.0,
+            false => self.flash * FLASH_DECAY,
+        };
+
+        // The perceived brightness is roughly the square of the duty
+        let duty = |brightness: f32| (brightness * brightness * FULL as f32) as u16;
+
+        // SAFETY: only TIM1's compare registers are written, which only this module uses
+        let tim = unsafe { &*TIM1::ptr() };
+        if cfg!(feature = "led-rgb") {
+            tim.ccr1.write(|w| w.ccr().bits(duty(self.level.max(self.flash))));
+            tim.ccr2.write(|w| w.ccr().bits(duty(self.flash)));
+            tim.ccr4.write(|w| w.ccr().bits(duty((1.0 - self.level).max(self.flash))));
+        } else {
+            tim.ccr1.write(|w| w.ccr().bits(duty(self.level.max(self.flash))));
+        }
+    }
+}
+
+/// Light the LED fully, red on an RGB LED, or darken it, e.g. for the
+/// self test's blink codes (see post.rs)
+#[cfg(feature = "post")]
+pub fn light(on: bool) {
+    // SAFETY: only TIM1's compare register is written, which only this module uses
+    let tim = unsafe { &*TIM1::ptr() };
+    tim.ccr1.write(|w| w.ccr().bits(if on { FULL } else { 0 }));
+}
@@ -0,0 +1,86 @@
+//! The CPU load: the share of each second the core spends busy, rather
+//! than asleep waiting for an interrupt, to show the headroom left when
+//! scaling up the particle count. The idle task counts the cycles it
+//! sleeps for (see sleep), and the frame loop turns them into the load
+//! once a second (see LoadMeter). The load is reported by the shell's
+//! `stats` command, and drawn as a bar by the HUD.
+
+use core::cell::Cell;
+use cortex_m::interrupt::{CriticalSection, Mutex};
+use cortex_m::peripheral::{SCB, SYST};
+use crate::board;
+
+
+// The cycles slept, wrapping, and the load over the last window
+static SLEPT: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static BUSY_PERCENT: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+
+// The period the load is measured over, in milliseconds
+const WINDOW_MS: u64 = 1_000;
+
+
+/// Sleep until an interrupt is pending, counting the cycles slept. Called
+/// with interrupts disabled, so the SysTick interrupt waking the core
+/// hasn't been taken when the cycles are counted.
+pub fn sleep(cs: &CriticalSection) {
+    let reload = SYST::get_reload();
+    let before = SYST::get_current();
+    let wrap_pending = SCB::is_pendst_pending();
+    cortex_m::asm::wfi();
+    let after = SYST::get_current();
+
+    // SysTick counts down, and wakes the core as it wraps, so it wraps
+    // at most once while asleep
+    let slept = match !wrap_pending && SCB::is_pendst_pending() {
+        true => before + (reload + 1 - after),
+        false => before.saturating_sub(after),
+    };
+    let total = SLEPT.borrow(cs);
+    total.set(total.get().wrapping_add(slept));
+}
+
+/// The percentage of the last second the core was busy
+pub fn busy_percent() -> u8 {
+    cortex_m::interrupt::free(|cs| BUSY_PERCENT.borrow(cs).get())
+}
+
+
+/// Measures the load from the cycles slept, over each second
+pub struct LoadMeter {
+    window_start: Option<u64>,
+    slept_start: u32,
+}
+
+impl LoadMeter {
+    /// Create a meter, measuring from its first update
+    pub const fn new() -> Self {
+        Self {
+            window_start: None,
+            slept_start: 0,
+        }
+    }
+
+    /// Measure the load at the given time, in milliseconds, once each
+    /// window has passed. Called once a frame.
+    pub fn update(&mut self, now_ms: u64) {
+        let slept = cortex_m::interrupt::free(|cs| SLEPT.borrow(cs).get());
+        let window_start = match self.window_start {
+            Some(window_start) => window_start,
+            None => {
+                self.window_start = Some(now_ms);
+                self.slept_start = slept;
+                return;
+            },
+        };
+
+        let elapsed = now_ms - window_start;
+        if elapsed < WINDOW_MS {
+            return;
+        }
+        let cycles_per_percent = elapsed as u32 * (board::sysclk_hz() / 1_000 / 100);
+        let busy = 100 - (slept.wrapping_sub(self.slept_start) / cycles_per_percent).min(100);
+        cortex_m::interrupt::free(|cs| BUSY_PERCENT.borrow(cs).set(busy as u8));
+        self.window_start = Some(now_ms);
+        self.slept_start = slept;
+    }
+}
@@ -0,0 +1,341 @@
+//! Structured logging with defmt, compiled out of release builds so log
+//! points cost nothing in the shipped firmware. The levels logged in
+//! debug builds are selected by DEFMT_LOG (see .cargo/config.toml).
+//!
+//! The encoded log goes out over a transport implementing Log, selected
+//! by a cargo feature, and is decoded on the host by defmt's tools:
+//!
+//! - RTT (the default), read by the debugger (e.g. `probe-rs run`),
+//!   which doesn't halt the core when no debugger is attached, and
+//!   carries the display mirror on a second channel (see mirror.rs)
+//! - semihosting (`log-semihosting`), to the debugger's semihosting
+//!   output, which halts the core when no debugger is attached; it
+//!   enables `debug-host`, without which semihosting isn't linked at all
+//! - USART1 (`log-uart`), e.g. into `defmt-print` through a USB-serial
+//!   adapter; the shell's replies share the line, and are reported by
+//!   the decoder as malformed frames
+//! - nowhere (`log-null`), saving the RTT buffer's RAM
+//!
+//! Log with e.g. `log::warn!("i2c: {}", error)`, in defmt's format syntax.
+
+#[cfg(debug_assertions)]
+use core::{cell::UnsafeCell, sync::atomic::{AtomicBool, Ordering}};
+#[cfg(debug_assertions)]
+use cortex_m::peripheral::SYST;
+
+
+/// A transport for the encoded log
+#[cfg(debug_assertions)]
+pub trait Log {
+    /// Write encoded bytes. A transport unable to take them all without
+    /// waiting on the host may drop them.
+    fn write(&self, bytes: &[u8]);
+
+    /// Wait for the written bytes to be sent
+    fn flush(&self) {}
+}
+
+
+// The log transport
+#[cfg(all(debug_assertions, feature = "log-semihosting"))]
+const TRANSPORT: Semihosting = Semihosting;
+#[cfg(all(debug_assertions, feature = "log-uart"))]
+const TRANSPORT: Uart = Uart;
+#[cfg(all(debug_assertions, feature = "log-null"))]
+const TRANSPORT: Null = Null;
+#[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+const TRANSPORT: Rtt = Rtt;
+
+#[cfg(any(all(feature = "log-semihosting", feature = "log-uart"),
+          all(feature = "log-semihosting", feature = "log-null"),
+          all(feature = "log-uart", feature = "log-null")))]
+compile_error!("select at most one of the log-semihosting, log-uart and log-null features");
+
+
+#[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+pub use rtt::Rtt;
+
+#[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+pub mod rtt {
+    use core::{cell::UnsafeCell, sync::atomic::{AtomicU32, Ordering}};
+    use super::Log;
+
+
+    /// Logs to the debugger's RTT up channel 0, taking what fits in the
+    /// buffer, unless the debugger asks to block until it's been read
+    pub struct Rtt;
+
+    // The RTT buffer holding encoded log messages until the debugger
+    // reads them, kept small as RAM is scarce
+    const BUFFER_SIZE: usize = 32;
+
+    // The display mirror's buffer, on up channel 1, only passing the
+    // frame through to the viewer reading it (see mirror.rs)
+    #[cfg(feature = "mirror")]
+    const DISPLAY_BUFFER_SIZE: usize = 16;
+
+    // The up channels: the log's, and the display mirror's
+    #[cfg(not(feature = "mirror"))]
+    const UP_CHANNELS: usize = 1;
+    #[cfg(feature = "mirror")]
+    const UP_CHANNELS: usize = 2;
+
+    // The channel's mode flags, set by the debugger to block when it's full
+    const MODE_MASK: u32 = 0b11;
+    const MODE_BLOCK_IF_FULL: u32 = 2;
+
+    /// An RTT up channel, written by the firmware and read by the debugger
+    #[repr(C)]
+    pub struct Channel {
+        name: *const u8,
+        buffer: *mut u8,
+        size: u32,
+        write: AtomicU32,
+        read: AtomicU32,
+        flags: AtomicU32,
+    }
+
+    impl Channel {
+        const fn new(name: &'static core::ffi::CStr, buffer: *mut u8, size: usize) -> Self {
+            Self {
+                name: name.as_ptr(),
+                buffer,
+                size: size as u32,
+                write: AtomicU32::new(0),
+                read: AtomicU32::new(0),
+                flags: AtomicU32::new(0),
+            }
+        }
+
+        /// Whether the debugger has asked to block until it's read the
+        /// channel, rather than have what doesn't fit dropped
+        pub fn is_blocking(&self) -> bool {
+            self.flags.load(Ordering::Relaxed) & MODE_MASK == MODE_BLOCK_IF_FULL
+        }
+
+        /// Write the bytes, dropping what doesn't fit unless blocking
+        pub fn write(&self, bytes: &[u8]) {
+            let blocking = self.is_blocking();
+            let size = self.size as usize;
+            let mut write = self.write.load(Ordering::Relaxed) as usize;
+            for &byte in bytes {
+                let next = (write + 1) % size;
+                while next == self.read.load(Ordering::Acquire) as usize {
+                    if !blocking {
+                        return;
+                    }
+                }
+                // SAFETY: the debugger doesn't read between write and read
+                unsafe { self.buffer.add(write).write_volatile(byte) };
+                write = next;
+                self.write.store(write as u32, Ordering::Release);
+            }
+        }
+
+        /// Wait for the written bytes to be read, when blocking
+        pub fn flush(&self) {
+            if self.is_blocking() {
+                while self.read.load(Ordering::Acquire) != self.write.load(Ordering::Relaxed) {}
+            }
+        }
+    }
+
+    // The RTT control block the debugger searches RAM for
+    #[repr(C)]
+    struct ControlBlock {
+        id: [u8; 16],
+        max_up_channels: u32,
+        max_down_channels: u32,
+        up: [Channel; UP_CHANNELS],
+    }
+
+    struct Buffer<const N: usize>(UnsafeCell<[u8; N]>);
+
+    // SAFETY: only the logger writes the log's channel and buffer, within
+    //         a critical section, only the simulation task writes the
+    //         display's, and the debugger reads them
+    unsafe impl Sync for ControlBlock {}
+    unsafe impl<const N: usize> Sync for Buffer<N> {}
+
+    static BUFFER: Buffer<BUFFER_SIZE> = Buffer(UnsafeCell::new([0; BUFFER_SIZE]));
+    #[cfg(feature = "mirror")]
+    static DISPLAY_BUFFER: Buffer<DISPLAY_BUFFER_SIZE> = Buffer(UnsafeCell::new([0; DISPLAY_BUFFER_SIZE]));
+
+    #[no_mangle]
+    static _SEGGER_RTT: ControlBlock = ControlBlock {
+        id: *b"SEGGER RTT\0\0\0\0\0\0",
+        max_up_channels: UP_CHANNELS as u32,
+        max_down_channels: 0,
+        up: [
+            Channel::new(c"defmt", BUFFER.0.get() as *mut u8, BUFFER_SIZE),
+            #[cfg(feature = "mirror")]
+            Channel::new(c"display", DISPLAY_BUFFER.0.get() as *mut u8, DISPLAY_BUFFER_SIZE),
+        ],
+    };
+
+    /// The display mirror's channel
+    #[cfg(feature = "mirror")]
+    pub fn display() -> &'static Channel {
+        &_SEGGER_RTT.up[1]
+    }
+
+    impl Log for Rtt {
+        fn write(&self, bytes: &[u8]) {
+            _SEGGER_RTT.up[0].write(bytes);
+        }
+
+        fn flush(&self) {
+            _SEGGER_RTT.up[0].flush();
+        }
+    }
+}
+
+
+/// Logs to the debugger's semihosting output
+#[cfg(all(debug_assertions, feature = "log-semihosting"))]
+pub struct Semihosting;
+
+#[cfg(all(debug_assertions, feature = "log-semihosting"))]
+impl Log for Semihosting {
+    fn write(&self, bytes: &[u8]) {
+        if let Ok(mut stdout) = cortex_m_semihosting::hio::hstdout() {
+            stdout.write_all(bytes).ok();
+        }
+    }
+}
+
+
+/// Logs to USART1, once the board has configured it
+#[cfg(all(debug_assertions, feature = "log-uart"))]
+pub struct Uart;
+
+#[cfg(all(debug_assertions, feature = "log-uart"))]
+impl Log for Uart {
+    fn write(&self, bytes: &[u8]) {
+        // SAFETY: the transmit data register is written once it's empty,
+        //         as the shell does
+        let usart = unsafe { &*crate::board::pac::USART1::ptr() };
+        for &byte in bytes {
+            while usart.isr.read().txe().bit_is_clear() {}
+            usart.tdr.write(|w| w.tdr().bits(byte as u16));
+        }
+    }
+
+    fn flush(&self) {
+        // SAFETY: a read of the status register
+        let usart = unsafe { &*crate::board::pac::USART1::ptr() };
+        while usart.isr.read().tc().bit_is_clear() {}
+    }
+}
+
+
+/// Drops the log
+#[cfg(all(debug_assertions, feature = "log-null"))]
+pub struct Null;
+
+#[cfg(all(debug_assertions, feature = "log-null"))]
+impl Log for Null {
+    fn write(&self, _bytes: &[u8]) {}
+}
+
+
+// Encodes defmt's frames, within a critical section, onto the transport
+#[cfg(debug_assertions)]
+#[defmt::global_logger]
+struct Logger;
+
+#[cfg(debug_assertions)]
+struct Encoder(UnsafeCell<defmt::Encoder>);
+
+// SAFETY: the encoder is only used within the logger's critical section
+#[cfg(debug_assertions)]
+unsafe impl Sync for Encoder {}
+
+#[cfg(debug_assertions)]
+static ENCODER: Encoder = Encoder(UnsafeCell::new(defmt::Encoder::new()));
+
+// Whether interrupts were enabled when the logger was acquired
+#[cfg(debug_assertions)]
+static INTERRUPTS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(debug_assertions)]
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {
+        let enabled = cortex_m::register::primask::read().is_active();
+        cortex_m::interrupt::disable();
+        INTERRUPTS_ENABLED.store(enabled, Ordering::Relaxed);
+        // SAFETY: within the critical section
+        unsafe { (*ENCODER.0.get()).start_frame(|bytes| TRANSPORT.write(bytes)) };
+    }
+
+    unsafe fn flush() {
+        TRANSPORT.flush();
+    }
+
+    unsafe fn release() {
+        // SAFETY: within the critical section, until interrupts are enabled
+        unsafe { (*ENCODER.0.get()).end_frame(|bytes| TRANSPORT.write(bytes)) };
+        if INTERRUPTS_ENABLED.load(Ordering::Relaxed) {
+            // SAFETY: interrupts were enabled before acquire
+            unsafe { cortex_m::interrupt::enable() };
+        }
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        // SAFETY: within the critical section
+        unsafe { (*ENCODER.0.get()).write(bytes, |bytes| TRANSPORT.write(bytes)) };
+    }
+}
+
+
+/// Microseconds since boot for log timestamps, given the milliseconds
+/// counted by a 1kHz SysTick, refined by SysTick's count within the 
+/// current tick. Approximate: a tick ending between the two reads 
+/// makes the timestamp a millisecond early.
+#[cfg(debug_assertions)]
+#[allow(dead_code)]
+pub fn uptime_us(ms: u64) -> u64 {
+    let reload = SYST::get_reload();
+    let elapsed = reload - SYST::get_current();
+    ms * 1_000 + (elapsed * 1_000 / (reload + 1)) as u64
+}
+
+/// Log an error, in debug builds
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)+) => {{
+        #[cfg(debug_assertions)]
+        defmt::error!($($arg)+);
+    }};
+}
+
+/// Log a warning, in debug builds
+#[allow(unused_macros)]
+macro_rules! warning {
+    ($($arg:tt)+) => {{
+        #[cfg(debug_assertions)]
+        defmt::warn!($($arg)+);
+    }};
+}
+
+/// Log an informational message, in debug builds
+#[allow(unused_macros)]
+macro_rules! info {
+    ($($arg:tt)+) => {{
+        #[cfg(debug_assertions)]
+        defmt::info!($($arg)+);
+    }};
+}
+
+/// Log a debugging message, in debug builds
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)+) => {{
+        #[cfg(debug_assertions)]
+        defmt::debug!($($arg)+);
+    }};
+}
+
+#[allow(unused_imports)]
+// (warn is renamed here, as it clashes with the lint attribute)
+pub(crate) use {error, warning as warn, info, debug};
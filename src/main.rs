@@ -4,105 +4,982 @@
 
 use panic_halt as _;
 
-use cortex_m::Peripherals as CorePeripherals;
-use cortex_m_rt::{entry, exception};
-use cortex_m_semihosting::syscall;
-use stm32f0xx_hal::{prelude::*, delay::Delay, pac::Peripherals as F0Peripherals};
-
+mod accel;
+mod adc;
+mod artwork;
+#[cfg(feature = "attract")]
+mod attract;
+mod board;
+#[cfg(feature = "brownout")]
+mod brownout;
+#[cfg(any(feature = "pong", feature = "paint"))]
+mod buttons;
+#[cfg(feature = "buzzer")]
+mod buzzer;
+#[cfg(feature = "calibrate")]
+mod calibrate;
+#[cfg(feature = "clock")]
+mod clock;
+mod config;
+#[cfg(feature = "dfu")]
+mod dfu;
+#[cfg(feature = "eeprom")]
+mod eeprom;
+mod encoder;
+mod entropy;
+mod events;
+mod fault;
+mod frame;
+#[cfg(feature = "histogram")]
+mod histogram;
+#[cfg(feature = "hourglass")]
+mod hourglass;
+mod hud;
+mod knobs;
+#[cfg(feature = "lava")]
+mod lava;
+#[cfg(feature = "led")]
+mod led;
+mod load;
+mod log;
+#[cfg(feature = "maze")]
+mod maze;
+#[cfg(feature = "mirror")]
+mod mirror;
 mod oled;
+#[cfg(feature = "paint")]
+mod paint;
+#[cfg(feature = "pong")]
+mod pong;
+mod power;
+#[cfg(feature = "post")]
+mod post;
+#[cfg(feature = "rain")]
+mod rain;
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(feature = "profile")]
+mod profile;
+#[cfg(any(feature = "clock", feature = "hourglass"))]
+mod rtc;
+#[cfg(feature = "scaling")]
+mod scaling;
+mod scenes;
+#[cfg(feature = "scope")]
+mod scope;
+#[cfg(feature = "sdlog")]
+mod sdlog;
+#[cfg(feature = "eeprom")]
+mod settings;
+mod shell;
+mod splash;
+#[cfg(feature = "split")]
+mod split;
+mod stack;
+#[cfg(feature = "sweep")]
+mod sweep;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(feature = "temperature")]
+mod temperature;
+mod tuning;
+#[cfg(feature = "usb")]
+mod usb;
 use oled::OLEDDriver;
 
-mod fluid;
-use fluid::Fluid;
-
-
-#[entry]
-fn main() -> ! {
-    if let (Some(mut p), Some(cp)) = (F0Peripherals::take(), CorePeripherals::take()) {        
-        // Enable the DMA and I2C clocks before freezing the RCC peripheral
-        // TODO: Ideally this would be handled in the OLED driver by passing 
-        //       a reference to the configured rcc value, similar to the I2C 
-        //       pins below. This may be possible once the DMA module is 
-        //       available in the stm32f0xx_hal crate.
-        p.RCC.ahbenr.modify(|_, w| w.dmaen().enabled());
-        p.RCC.cfgr3.modify(|_, w| w.i2c1sw().sysclk());
-        p.RCC.apb1enr.modify(|_, w| w.i2c1en().enabled());
-
-        // configure the clock to a frequency of 48MHz using
-        // the internal oscillator multiplied by the PLL
-        let mut rcc = p.RCC.configure()
-                           .sysclk(48.mhz())
-                           .freeze(&mut p.FLASH);
-
-        // Configure systick as a delay source
-        let systick = cp.SYST;
-        let mut delay = Delay::new(systick, &rcc);
-
-        // Create the fluid simulation
-        let mut fluid_sim = Fluid::<60>::new(125, 61);
-
-        // Configure pins for I2C
-        let gpiob = p.GPIOB.split(&mut rcc);
-        cortex_m::interrupt::free(move |cs| {
-            let _sda = gpiob.pb7.into_alternate_af1(cs);
-            let _scl = gpiob.pb6.into_alternate_af1(cs);
+use fluid_core::Fluid;
+
+// Log timestamps, from the scheduler's clock
+#[cfg(debug_assertions)]
+defmt::timestamp!("{=u64:us}", log::uptime_us(app::monotonics::now().ticks()));
+
+
+#[rtic::app(device = crate::board::pac, dispatchers = [SPI1])]
+mod app {
+    use core::fmt::Write;
+    use cortex_m::peripheral::SCB;
+    use embedded_hal::serial::{Read, Write as SerialWrite};
+    use systick_monotonic::{ExtU64, Systick};
+    use crate::frame::{self, Duration, FrameScheduler, TICK_HZ};
+    use crate::accel::Accelerometer;
+    #[cfg(feature = "eeprom")]
+    use crate::accel::Mounting;
+    use crate::board::{pac::Interrupt, Board, ShellSerial, DISPLAY_ADDRESS, DISPLAY_POWER, SYSCLK_HZ};
+    use crate::encoder::Encoder;
+    use crate::hud::Hud;
+    use crate::load::{self, LoadMeter};
+    use crate::power::{self, IdleManager};
+    use crate::config::AppConfig;
+    use crate::log;
+    use crate::shell::{Command, Shell};
+    use crate::events::{self, Event, EventConsumer, EventProducer, EventQueue};
+    use crate::splash::Splash;
+    use crate::stack::{self, StackMonitor};
+    use crate::tuning::Tuner;
+    #[cfg(feature = "usb")]
+    use crate::usb::UsbSerial;
+    use crate::oled::{dmai2c::bus::I2cBus, DMAi2c, OLEDDriver, OLEDBuffer, OLED_FRAME_SIZE};
+    use fluid_core::{fixed::FixedPt, rng::Rng, scene::SceneManager, Fluid};
+    use super::draw_particles;
+    #[cfg(feature = "profile")]
+    use crate::profile::{Profiler, Section};
+    #[cfg(feature = "stream")]
+    use crate::stream::{self, Streamer};
+    use crate::knobs::{self, Knobs};
+    #[cfg(feature = "attract")]
+    use crate::attract::Attract;
+    #[cfg(feature = "brownout")]
+    use crate::brownout::{self, SupplyMonitor};
+    #[cfg(feature = "buzzer")]
+    use crate::buzzer::Buzzer;
+    #[cfg(feature = "calibrate")]
+    use crate::calibrate::Calibration;
+    #[cfg(feature = "clock")]
+    use crate::clock::Clock;
+    #[cfg(feature = "dfu")]
+    use crate::dfu;
+    #[cfg(feature = "histogram")]
+    use crate::histogram;
+    #[cfg(feature = "hourglass")]
+    use crate::hourglass::Hourglass;
+    #[cfg(feature = "lava")]
+    use crate::lava::Lava;
+    #[cfg(feature = "led")]
+    use crate::led::Led;
+    #[cfg(feature = "maze")]
+    use crate::maze::Maze;
+    #[cfg(feature = "mirror")]
+    use crate::mirror;
+    #[cfg(feature = "paint")]
+    use crate::paint::Paint;
+    #[cfg(feature = "pong")]
+    use crate::pong::Pong;
+    #[cfg(feature = "post")]
+    use crate::post::SelfTest;
+    #[cfg(feature = "rain")]
+    use crate::rain::Rain;
+    #[cfg(feature = "remote")]
+    use crate::remote::{Received, Telemetry, TelemetryTimer};
+    #[cfg(feature = "scaling")]
+    use crate::scaling::{ClockMode, ClockScaler};
+    #[cfg(feature = "scope")]
+    use crate::scope;
+    #[cfg(feature = "sdlog")]
+    use crate::sdlog::SdLog;
+    #[cfg(feature = "split")]
+    use crate::split::{self, SplitScreen};
+    #[cfg(feature = "sweep")]
+    use crate::sweep::Sweep;
+    #[cfg(feature = "eeprom")]
+    use crate::board::EEPROM_MODEL;
+    #[cfg(feature = "eeprom")]
+    use crate::eeprom::Eeprom;
+    #[cfg(feature = "eeprom")]
+    use crate::settings::SettingsKeeper;
+    #[cfg(feature = "temperature")]
+    use crate::temperature::Thermometer;
+
+    // The particles simulated, half as many in split builds, for the
+    // second fluid to fit beside the first (see split.rs)
+    #[cfg(not(feature = "split"))]
+    const PARTICLES: usize = 60;
+    #[cfg(feature = "split")]
+    const PARTICLES: usize = split::PARTICLES;
+
+    // The simulation runs at a steady frame rate (see config.rs)
+    const FRAME_PERIOD: Duration = Duration::millis(AppConfig::DEFAULT.frame_period_ms() as u64);
+    #[cfg(feature = "scaling")]
+    const LOW_POWER_FRAME_PERIOD: Duration = Duration::millis(AppConfig::DEFAULT.low_power_frame_period_ms() as u64);
+
+    // The display sleeps and the core stops after 5 minutes without input
+    const IDLE_TIMEOUT_MS: Option<u32> = Some(5 * 60 * 1000);
+
+    // Each scene's layout is jittered by up to half a pixel each way, so
+    // it plays out differently each run
+    const JITTER: FixedPt = FixedPt::from_f32(0.5);
+
+    // A jolt of the board splashes the water around the middle of the 
+    // display, changing its velocity by this much per g
+    const SPLASH_CENTRE: (i8, i8) = (62, 30);
+    const SPLASH_RADIUS: i8 = 96;
+    const SPLASH_IMPULSE: f32 = 3.0;
+
+    // The particles' state is streamed to the host with their densities
+    #[cfg(feature = "stream")]
+    const STREAM_DENSITIES: bool = true;
+    #[cfg(feature = "stream")]
+    const STREAM_CAPACITY: usize = stream::frame_size(60, STREAM_DENSITIES);
+
+    // The message shown while the board's updated, in the middle
+    #[cfg(feature = "dfu")]
+    const DFU_TEXT_POSITION: (i32, i32) = (40, 28);
+
+    // The profiler logs its averages once a second
+    #[cfg(feature = "profile")]
+    const PROFILE_FRAMES: u16 = 30;
+
+    #[monotonic(binds = SysTick, default = true)]
+    type Mono = Systick<TICK_HZ>;
+
+    #[shared]
+    struct Shared {
+        idle_manager: IdleManager,
+        tuner: Tuner,
+        frames: FrameScheduler,
+        events: EventProducer,
+    }
+
+    #[local]
+    struct Local {
+        oled_buffer: Option<&'static mut OLEDBuffer>,
+        scb: SCB,
+        encoder: Encoder,
+        knobs: Option<Knobs>,
+        rng: Rng,
+        accel: Option<Accelerometer<I2cBus>>,
+        serial: ShellSerial,
+        event_consumer: EventConsumer,
+    }
+
+    #[init(local = [frame_buffer: OLEDBuffer = [0; OLED_FRAME_SIZE], event_queue: EventQueue = EventQueue::new()])]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        // Enter the bootloader instead, if it's been requested
+        #[cfg(feature = "dfu")]
+        dfu::enter_if_requested(&cx.device.RCC, &cx.device.SYSCFG);
+
+        // Paint the stack, for its watermark
+        stack::paint();
+
+        // Bring up the board's display bus, inputs and sensors
+        let board = Board::new(cx.device);
+
+        // Configure systick as the time base for scheduling tasks
+        let mono = Systick::new(cx.core.SYST, SYSCLK_HZ);
+
+        // Start the simulation once the display has had 100ms to boot
+        simulate::spawn_after(100.millis()).ok();
+
+        // Queue the interrupts' events for the frame loop
+        let (events, event_consumer) = cx.local.event_queue.split();
+
+        let shared = Shared {
+            idle_manager: IdleManager::new(IDLE_TIMEOUT_MS),
+            tuner: Tuner::new(),
+            frames: FrameScheduler::new(FRAME_PERIOD),
+            events,
+        };
+        let local = Local {
+            oled_buffer: Some(cx.local.frame_buffer),
+            scb: cx.core.SCB,
+            encoder: board.encoder,
+            knobs: board.knobs,
+            rng: Rng::new(board.seed),
+            accel: board.accel,
+            serial: board.serial,
+            event_consumer,
+        };
+        (shared, local, init::Monotonics(mono))
+    }
+
+    #[idle(local = [scb], shared = [idle_manager])]
+    fn idle(mut cx: idle::Context) -> ! {
+        loop {
+            // Sleep until the next frame, or DMA transmission, or stop
+            // until the wake button is pressed once the device is asleep.
+            // Interrupts are disabled until the clocks are restored, and
+            // a press just before stopping still wakes the core.
+            cortex_m::interrupt::free(|cs| {
+                match cx.shared.idle_manager.lock(|idle_manager| idle_manager.is_asleep()) {
+                    true => power::stop(cx.local.scb),
+                    false => load::sleep(cs),
+                }
+            });
+        }
+    }
+
+    /// Step the simulation and transmit the results, once per frame period.
+    /// Frames are scheduled at a fixed rate, however long each takes.
+    #[task(shared = [idle_manager, tuner, frames], local = [
+        oled_buffer,
+        encoder,
+        knobs,
+        rng,
+        accel,
+        event_consumer,
+        display: Option<OLEDDriver> = None,
+        fluid_sim: Fluid<PARTICLES> = Fluid::empty(),
+        scenes: SceneManager = SceneManager::new(AppConfig::DEFAULT.program),
+        splash: Splash = Splash::new(AppConfig::DEFAULT.splash_hold_frames),
+        hud: Hud = Hud::new(),
+        load_meter: LoadMeter = LoadMeter::new(),
+        stack_monitor: StackMonitor = StackMonitor::new(),
+        #[cfg(feature = "brownout")]
+        supply: SupplyMonitor = SupplyMonitor::new(),
+        #[cfg(feature = "attract")]
+        attract: Attract = Attract::new(),
+        #[cfg(feature = "buzzer")]
+        buzzer: Buzzer = Buzzer::new(),
+        #[cfg(feature = "calibrate")]
+        calibration: Calibration = Calibration::new(),
+        #[cfg(feature = "clock")]
+        clock: Clock = Clock::new(),
+        #[cfg(feature = "hourglass")]
+        hourglass: Hourglass = Hourglass::new(),
+        #[cfg(feature = "lava")]
+        lava: Lava = Lava::new(),
+        #[cfg(feature = "led")]
+        led: Led = Led::new(),
+        #[cfg(feature = "maze")]
+        maze: Maze = Maze::new(),
+        #[cfg(feature = "paint")]
+        paint: Paint = Paint::new(),
+        #[cfg(feature = "pong")]
+        pong: Pong = Pong::new(),
+        #[cfg(feature = "post")]
+        self_test: SelfTest = SelfTest::new(),
+        #[cfg(feature = "rain")]
+        rain: Rain = Rain::new(),
+        #[cfg(feature = "remote")]
+        telemetry: TelemetryTimer = TelemetryTimer::new(),
+        #[cfg(feature = "scaling")]
+        scaler: ClockScaler = ClockScaler::new(),
+        #[cfg(feature = "sdlog")]
+        sd_log: SdLog = SdLog::new(),
+        #[cfg(feature = "split")]
+        split: SplitScreen = SplitScreen::new(),
+        #[cfg(feature = "sweep")]
+        sweep: Sweep = Sweep::new(),
+        #[cfg(feature = "eeprom")]
+        settings: SettingsKeeper<Eeprom<I2cBus>> = SettingsKeeper::new(),
+        #[cfg(feature = "temperature")]
+        thermometer: Thermometer = Thermometer::new(),
+        #[cfg(feature = "profile")]
+        profiler: Profiler = Profiler::new(PROFILE_FRAMES),
+        #[cfg(feature = "stream")]
+        streamer: Streamer<STREAM_CAPACITY> = Streamer::new(STREAM_DENSITIES),
+    ])]
+    fn simulate(mut cx: simulate::Context) {
+        let now = monotonics::now();
+        #[cfg(feature = "scope")]
+        scope::frame_start();
+        let fluid_sim = cx.local.fluid_sim;
+        let scenes = cx.local.scenes;
+
+        let display = match cx.local.display {
+            Some(display) => display,
+            None => {
+                // Enter the bootloader, if the buttons were held through
+                // the reset
+                #[cfg(feature = "dfu")]
+                if dfu::buttons_held() {
+                    dfu::restart();
+                }
+
+                // Initialize the OLED display driver, and the fluid (in
+                // place, as it's too big for the stack) in the initial
+                // scene's layout for the splash
+                // Note: the driver queues more transmissions than fit in 
+                //       the queue, so it is created here rather than in 
+                //       init, where the DMA interrupt can't drain it
+                let oled_buffer = cx.local.oled_buffer.take().unwrap();
+                let display = cx.local.display.insert(OLEDDriver::new(DISPLAY_ADDRESS, DISPLAY_POWER, oled_buffer));
+                // Test the display, sensors and math before anything runs
+                #[cfg(feature = "post")]
+                cx.local.self_test.run(display, cx.local.accel.as_mut(), now.ticks() as u32);
+                fluid_sim.reset(125, 61);
+                let config = AppConfig::DEFAULT;
+                scenes.select(config.initial_scene as usize, fluid_sim);
+                #[cfg(feature = "attract")]
+                if config.attract {
+                    cx.local.attract.start(scenes, fluid_sim);
+                }
+                fluid_sim.jitter(cx.local.rng, JITTER);
+                // Pick up where the water was left as the battery failed
+                #[cfg(feature = "brownout")]
+                brownout::restore(fluid_sim);
+                #[cfg(feature = "sdlog")]
+                cx.local.sd_log.start();
+                // Restore the settings kept in the EEPROM, if it's fitted
+                #[cfg(feature = "eeprom")]
+                let config = match Eeprom::detect(DMAi2c::bus(), EEPROM_MODEL).and_then(|eeprom| cx.local.settings.open(eeprom)) {
+                    Some(settings) => {
+                        cx.shared.tuner.lock(|tuner| tuner.restore(settings, fluid_sim, display));
+                        #[cfg(feature = "calibrate")]
+                        if let Some(accel) = cx.local.accel.as_mut() {
+                            accel.set_mounting(settings.mounting);
+                        }
+                        config.with_settings(&settings)
+                    },
+                    None => config,
+                };
+                if config.hud {
+                    cx.local.hud.toggle();
+                }
+                display
+            }
+        };
+
+        // Show the self test's patterns, and blink its code, if a check
+        // failed, as the frames go by
+        #[cfg(all(feature = "post", feature = "led"))]
+        cx.local.self_test.update(now.ticks() as u32);
+        #[cfg(feature = "post")]
+        if cx.local.self_test.draw(display, now.ticks() as u32) {
+            display.tx_frame();
+            simulate::spawn_after(FRAME_PERIOD).ok();
+            return;
+        }
+
+        // Play the boot splash before the first frame, which melts it
+        if cx.local.splash.draw(display, fluid_sim) {
+            display.tx_frame();
+            #[cfg(feature = "mirror")]
+            mirror::send(display);
+            simulate::spawn_after(FRAME_PERIOD).ok();
+            return;
+        }
+
+        // Schedule the next frame, timed from the start of this one
+        let next_frame = cx.shared.frames.lock(|frames| frames.next(now));
+
+        // Wake the display, if the wake button woke the device
+        display.wake();
+
+        // Step the simulation and draw the results, timing the solver
+        // for the HUD, and each stage of the solver when profiling
+        let hud = cx.local.hud;
+        let cycles = || frame::cycles(monotonics::now().ticks());
+        let solver_start = cycles();
+        #[cfg(feature = "profile")]
+        let profiler = cx.local.profiler;
+        #[cfg(feature = "profile")]
+        profiler.start(solver_start);
+        fluid_sim.step_with(|_stage| {
+            #[cfg(feature = "profile")]
+            profiler.mark(Section::Solver(_stage), cycles());
         });
+        let solver_end = cycles();
+
+        // Chirp as the water hits the walls, and light the status LED
+        #[cfg(feature = "buzzer")]
+        cx.local.buzzer.update(fluid_sim.impact());
+        #[cfg(all(feature = "led", not(feature = "post")))]
+        cx.local.led.update(fluid_sim.kinetic_energy(), fluid_sim.impact());
+        #[cfg(all(feature = "led", feature = "post"))]
+        if !cx.local.self_test.is_blinking() {
+            cx.local.led.update(fluid_sim.kinetic_energy(), fluid_sim.impact());
+        }
 
-        // Initialize and take the OLED display driver
-        // Note: Delay for 100ms to ensure display has time to boot
-        delay.delay_ms(100_u8);
-        let mut display = OLEDDriver::new(p.I2C1, p.DMA1);
+        // Dim the display as the battery runs low, and save the particles
+        // and sleep as it fails
+        #[cfg(feature = "brownout")]
+        {
+            let contrast = cx.shared.tuner.lock(|tuner| tuner.steps().2);
+            if cx.local.supply.update(fluid_sim, display, contrast) {
+                cx.shared.idle_manager.lock(|idle_manager| idle_manager.sleep());
+            }
+        }
+
+        // Wait for the previous frame's transmission, when it's timed
+        let dma_end = match cfg!(feature = "profile") || cfg!(feature = "histogram") || hud.is_enabled() {
+            true => {
+                DMAi2c::wait_idle().ok();
+                cycles()
+            },
+            false => solver_end,
+        };
+        #[cfg(feature = "profile")]
+        {
+            profiler.mark(Section::DmaWait, dma_end);
+            profiler.end_frame();
+        }
+        hud.record(now.ticks(), solver_end.wrapping_sub(solver_start), dma_end.wrapping_sub(solver_end));
+        #[cfg(feature = "histogram")]
+        histogram::record(solver_end.wrapping_sub(solver_start), dma_end.wrapping_sub(solver_end));
+        cx.local.load_meter.update(now.ticks());
+        cx.local.stack_monitor.check();
 
-        // Transmit the initial frame and delay some amount
-        // to allow the user to appreciate the intial state
-        draw_particles(&mut display, &fluid_sim);
+        #[cfg(feature = "maze")]
+        let maze = cx.local.maze;
+        #[cfg(feature = "pong")]
+        let pong = cx.local.pong;
+        #[cfg(feature = "paint")]
+        let paint = cx.local.paint;
+        #[cfg(feature = "lava")]
+        let lava = cx.local.lava;
+        #[cfg(feature = "hourglass")]
+        let hourglass = cx.local.hourglass;
+        #[cfg(feature = "split")]
+        let split = cx.local.split;
+        #[cfg(feature = "calibrate")]
+        let calibration = cx.local.calibration;
+        #[cfg(feature = "attract")]
+        let attract = cx.local.attract;
+        #[cfg(feature = "sweep")]
+        let sweep = cx.local.sweep;
+        display.clear();
+        #[cfg(feature = "lava")]
+        let drawn = lava.draw(display, fluid_sim);
+        #[cfg(not(feature = "lava"))]
+        let drawn = false;
+        if !drawn {
+            draw_particles(display, fluid_sim);
+        }
+        #[cfg(feature = "maze")]
+        maze.draw(display);
+        #[cfg(feature = "pong")]
+        pong.draw(display);
+        #[cfg(feature = "hourglass")]
+        hourglass.draw(display, fluid_sim);
+        #[cfg(feature = "paint")]
+        paint.draw(display);
+        #[cfg(feature = "split")]
+        split.draw(display);
+        #[cfg(feature = "calibrate")]
+        calibration.draw(display);
+        #[cfg(feature = "attract")]
+        attract.draw(display);
+        hud.draw(display);
+        #[cfg(feature = "brownout")]
+        cx.local.supply.draw(display);
         display.tx_frame();
-        delay.delay_ms(3_000_u16);
+        #[cfg(feature = "mirror")]
+        mirror::send(display);
+        #[cfg(feature = "stream")]
+        cx.local.streamer.send(fluid_sim);
 
-        let mut cnt = 0;
-        loop {
-            // Step the simulation and draw the results
-            fluid_sim.step();
+        // Scale the clock down while the water's calm, and back up as it
+        // splashes, once the frame's queued, and the frame rate with it
+        #[cfg(feature = "scaling")]
+        if let Some(mode) = cx.local.scaler.update(fluid_sim.kinetic_energy()) {
+            let period = match mode {
+                ClockMode::Performance => FRAME_PERIOD,
+                ClockMode::LowPower => LOW_POWER_FRAME_PERIOD,
+            };
+            cx.shared.frames.lock(|frames| frames.set_period(period));
+            log::info!("scaling: {=u32}Hz", mode.sysclk_hz());
+        }
+
+        // Reform the time as each minute starts, in the clock mode, which
+        // keeps the display awake
+        #[cfg(feature = "clock")]
+        let clock = cx.local.clock;
+        #[cfg(feature = "clock")]
+        if clock.update(scenes, fluid_sim) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Score the maze once the water reaches its goal
+        #[cfg(feature = "maze")]
+        if let Some(seconds) = maze.update(fluid_sim) {
+            log::info!("maze: solved in {=u16}s", seconds);
+        }
+
+        // Move Pong's paddles, which keep the display awake while held
+        #[cfg(feature = "pong")]
+        if pong.update(fluid_sim) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Paint with the water, which keeps the display awake while the
+        // buttons are held
+        #[cfg(feature = "paint")]
+        if paint.update(fluid_sim) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Warm and cool the lava lamp's wax, which keeps the display awake
+        #[cfg(feature = "lava")]
+        if lava.update(fluid_sim) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Time the hourglass, which keeps the display awake as it flips
+        #[cfg(feature = "hourglass")]
+        if hourglass.update(scenes, fluid_sim) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Step the split screen's second fluid, under the first's gravity
+        #[cfg(feature = "split")]
+        split.update(fluid_sim);
+
+        // Sweep the viscosity, shown on the HUD, which keeps the display
+        // awake
+        #[cfg(feature = "sweep")]
+        {
+            let reading = sweep.update(fluid_sim);
+            hud.show_reading(reading);
+            if reading.is_some() {
+                cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+            }
+        }
+
+        // Play the scenes, which steer gravity, unless the attract mode's
+        // been paused by input
+        let rng = cx.local.rng;
+        #[cfg(feature = "attract")]
+        let paused = attract.is_paused();
+        #[cfg(not(feature = "attract"))]
+        let paused = false;
+        if !paused && scenes.advance(fluid_sim) {
+            fluid_sim.jitter(rng, JITTER);
+            log::info!("scene: {=str}", scenes.scene().name);
+        }
+
+        // Caption the attract mode's scenes, which keep the display awake
+        // while they play
+        #[cfg(feature = "attract")]
+        if attract.update(scenes, now.ticks() as u32) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Let the rain fall, from the pool's drain, which keeps the
+        // display awake
+        #[cfg(feature = "rain")]
+        let rain = cx.local.rain;
+        #[cfg(feature = "rain")]
+        if rain.update(fluid_sim, rng) {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
+
+        // Find how the accelerometer's mounted, while calibrating, and
+        // apply it to the tilt
+        #[cfg(feature = "calibrate")]
+        if let Some(accel) = cx.local.accel.as_mut() {
+            if let Some(mounting) = calibration.update(accel) {
+                log::info!("calibrated: axes={} offsets={}", mounting.axes, mounting.offsets);
+                accel.set_mounting(mounting);
+            }
+        }
+
+        // Gravity follows the board's tilt instead, when measured, and
+        // a jolt splashes the water: it lags behind the case, so it's 
+        // pushed against the jolt, and a knock on the face throws it up
+        if let Some(Ok(motion)) = cx.local.accel.as_mut().map(|accel| accel.update()) {
+            let (gx, gy) = motion.gravity;
+            fluid_sim.set_gravity(gx, gy);
+            if let Some([jx, jy, jz]) = motion.jolt {
+                let (x, y) = SPLASH_CENTRE;
+                fluid_sim.apply_impulse_at(x, y, SPLASH_RADIUS, -jx * SPLASH_IMPULSE, (-jy - jz.abs()) * SPLASH_IMPULSE);
+            }
+        }
+
+        // Tune the selected parameter with the encoder, any parameters 
+        // whose knobs have been turned, and any set from the shell, which
+        // replaces the demo's gravity once gravity is tuned, and scale the
+        // viscosity for the ambient temperature
+        let detents = cx.local.encoder.detents();
+        let mut turned = detents != 0;
+        #[cfg(feature = "paint")]
+        let detents = paint.steer(detents);
+        // Take the wake button's presses, and the next command, leaving
+        // any after it for the next frames
+        let command = loop {
+            match cx.local.event_consumer.dequeue() {
+                Some(Event::Command(command)) => break Some(command),
+                Some(Event::WakeButton) => {
+                    cx.shared.tuner.lock(|tuner| tuner.select_next());
+                    #[cfg(feature = "attract")]
+                    attract.pause();
+                },
+                None => break None,
+            }
+        };
+        #[cfg(feature = "remote")]
+        let telemetry = cx.local.telemetry;
+        if command.is_some_and(|command| command.starts_mode()) {
+            // a mode ends the others
+            #[cfg(feature = "clock")]
+            clock.hide();
+            #[cfg(feature = "maze")]
+            maze.stop(fluid_sim);
+            #[cfg(feature = "pong")]
+            pong.stop(fluid_sim);
+            #[cfg(feature = "lava")]
+            lava.stop(fluid_sim);
+            #[cfg(feature = "hourglass")]
+            hourglass.stop(fluid_sim);
+            #[cfg(feature = "rain")]
+            rain.stop();
+            #[cfg(feature = "paint")]
+            paint.stop();
+            #[cfg(feature = "split")]
+            split.stop(fluid_sim);
+            #[cfg(feature = "calibrate")]
+            calibration.stop();
+            #[cfg(feature = "attract")]
+            if attract.stop() {
+                cx.shared.tuner.lock(|tuner| tuner.restore_viscosity(fluid_sim));
+            }
+            #[cfg(feature = "sweep")]
+            if sweep.stop() {
+                cx.shared.tuner.lock(|tuner| tuner.restore_viscosity(fluid_sim));
+            }
+        }
+        // Say the board is being updated, once the frame's sent, and reset
+        // into the bootloader
+        #[cfg(feature = "dfu")]
+        if let Some(Command::Dfu) = command {
             display.clear();
-            draw_particles(&mut display, &fluid_sim);
+            display.draw_text(DFU_TEXT_POSITION.0, DFU_TEXT_POSITION.1, "updating");
             display.tx_frame();
+            DMAi2c::wait_idle().ok();
+            dfu::restart();
+        }
+        let tuned_gravity = cx.shared.tuner.lock(|tuner| {
+            match command {
+                Some(Command::Set(parameter, step)) => tuner.set_step(parameter, step, fluid_sim, display),
+                Some(Command::GravityCycle) => tuner.release_gravity(),
+                Some(Command::Scene(index)) => {
+                    scenes.play(AppConfig::DEFAULT.program, index as usize, fluid_sim);
+                    fluid_sim.jitter(rng, JITTER);
+                },
+                #[cfg(feature = "clock")]
+                Some(Command::Clock) => clock.show(),
+                #[cfg(feature = "clock")]
+                Some(Command::Time(time)) => clock.set(time),
+                #[cfg(feature = "maze")]
+                Some(Command::Maze) => {
+                    maze.start(scenes, fluid_sim);
+                    fluid_sim.jitter(rng, JITTER);
+                },
+                #[cfg(feature = "pong")]
+                Some(Command::Pong) => pong.start(scenes, fluid_sim),
+                #[cfg(feature = "lava")]
+                Some(Command::Lava) => {
+                    lava.start(scenes, fluid_sim);
+                    fluid_sim.jitter(rng, JITTER);
+                },
+                #[cfg(feature = "hourglass")]
+                Some(Command::Hourglass(seconds)) => hourglass.start(seconds, scenes, fluid_sim),
+                #[cfg(feature = "rain")]
+                Some(Command::Rain) => rain.start(scenes, fluid_sim),
+                #[cfg(feature = "paint")]
+                Some(Command::Paint) => paint.start(scenes, fluid_sim),
+                #[cfg(feature = "split")]
+                Some(Command::Split(viscosity)) => split.start(viscosity, scenes, fluid_sim),
+                #[cfg(feature = "attract")]
+                Some(Command::Attract) => {
+                    attract.start(scenes, fluid_sim);
+                    fluid_sim.jitter(rng, JITTER);
+                },
+                #[cfg(feature = "sweep")]
+                Some(Command::Sweep) => sweep.start(scenes, fluid_sim),
+                #[cfg(feature = "calibrate")]
+                Some(Command::Calibrate) => calibration.start(cx.local.accel.is_some()),
+                #[cfg(feature = "remote")]
+                Some(Command::Telemetry(seconds)) => telemetry.set_period(seconds),
+                #[cfg(feature = "scaling")]
+                Some(Command::Speed(mode)) => cx.local.scaler.set(mode),
+                Some(Command::Hud) => hud.toggle(),
+                _ => (),
+            }
+            if detents != 0 {
+                tuner.adjust(detents, fluid_sim, display);
+            }
+            if let Some(knobs) = cx.local.knobs {
+                knobs.sample(|parameter, level| {
+                    tuner.set(parameter, level, knobs::FULL_SCALE, fluid_sim, display);
+                    turned = true;
+                });
+            }
+            #[cfg(feature = "temperature")]
+            if let Some(celsius) = cx.local.thermometer.sample() {
+                tuner.set_temperature(celsius, fluid_sim);
+            }
+            tuner.gravity()
+        });
+        if let Some((gx, gy)) = tuned_gravity {
+            fluid_sim.set_gravity(gx, gy);
+        }
+        #[cfg(feature = "eeprom")]
+        {
+            #[cfg(feature = "calibrate")]
+            let mounting = cx.local.accel.as_ref().map_or(Mounting::FLAT, |accel| accel.mounting());
+            #[cfg(not(feature = "calibrate"))]
+            let mounting = Mounting::FLAT;
+            cx.local.settings.update(cx.shared.tuner.lock(|tuner| tuner.settings(hud.is_enabled(), mounting)));
+        }
+        // Any input pauses the attract mode, handing the board over to
+        // tuning
+        #[cfg(feature = "attract")]
+        if turned || command.is_some_and(|command| !command.starts_mode()) {
+            attract.pause();
+        }
+        if turned {
+            cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity());
+        }
 
-            // Cycle through different gravity configurations 
-            // to make the simulation more interesting
-            match cnt {
-                0..=299 => fluid_sim.set_gravity(0.0, 0.0),
-                300..=399 => fluid_sim.set_gravity(0.0, 1.0),
-                400..=599 => fluid_sim.set_gravity(1.0, 0.0),
-                600..=899 => fluid_sim.set_gravity(-1.0, 0.0),
-                900..=999 => fluid_sim.set_gravity(0.0, -1.0),
-                1000..=1299 => fluid_sim.set_gravity(0.0, 0.0),
-                _ => cnt = 0,
+        // Stream the telemetry to the remote, when it's due, between
+        // streamed frames
+        #[cfg(feature = "remote")]
+        if telemetry.tick() {
+            // milliseconds / 8 / 125, in 32 bits for over a year
+            let uptime = (now.ticks() >> 3) as u32 / 125;
+            let dropped = cx.shared.frames.lock(|frames| frames.dropped());
+            let steps = cx.shared.tuner.lock(|tuner| tuner.steps());
+            #[cfg(feature = "stream")]
+            stream::wait_idle();
+            Telemetry::gather(uptime, dropped, steps, fluid_sim).reply().send();
+        }
+
+        // Log the stats and snapshots of the particles to the SD card
+        #[cfg(feature = "sdlog")]
+        {
+            let uptime = (now.ticks() >> 3) as u32 / 125;
+            let dropped = cx.shared.frames.lock(|frames| frames.dropped());
+            cx.local.sd_log.update(uptime, dropped, fluid_sim);
+        }
+
+        // Once idle for long enough, put the display to sleep and stop 
+        // scheduling frames: the idle task then stops the core, and the
+        // wake button spawns the next frame
+        if cx.shared.idle_manager.lock(|idle_manager| idle_manager.tick(now.ticks() as u32)) {
+            display.sleep();
+            DMAi2c::wait_idle().ok();
+            cx.shared.frames.lock(|frames| frames.restart());
+            return;
+        }
+
+        simulate::spawn_at(next_frame).ok();
+    }
+
+    /// Service the DMA I2C interface's transmissions
+    #[task(binds = DMA1_CH2_3, priority = 2, local = [i2c_interface: Option<DMAi2c> = None])]
+    fn dma_complete(cx: dma_complete::Context) {
+        DMAi2c::on_dma_interrupt(cx.local.i2c_interface, Interrupt::DMA1_CH2_3);
+    }
+
+    /// Wake the device with the wake button, or once awake,
+    /// select the next parameter to tune
+    #[task(binds = EXTI0_1, priority = 2, shared = [idle_manager, events])]
+    fn wake_button(mut cx: wake_button::Context) {
+        power::clear_wake_button();
+        if cx.shared.idle_manager.lock(|idle_manager| idle_manager.activity()) {
+            simulate::spawn().ok();
+        } else {
+            cx.shared.events.lock(|events| events::post(events, Event::WakeButton));
+        }
+    }
+
+    /// Run the command shell on the USART
+    #[task(binds = USART1, shared = [frames, events], local = [serial, shell: Shell = Shell::new()])]
+    fn serial_shell(cx: serial_shell::Context) {
+        serve_shell(cx.local.serial, cx.local.shell, cx.shared.frames, cx.shared.events);
+    }
+
+    /// Run the command shell on the USB serial port, as its device is
+    /// serviced
+    #[cfg(feature = "usb")]
+    #[task(binds = USB, shared = [frames, events], local = [#[cfg(feature = "usb")] usb: Option<UsbSerial> = None, shell: Shell = Shell::new()])]
+    fn usb_shell(cx: usb_shell::Context) {
+        if let Some(usb) = UsbSerial::on_interrupt(cx.local.usb) {
+            serve_shell(usb, cx.local.shell, cx.shared.frames, cx.shared.events);
+        }
+    }
+
+    /// Serve the command shell over a serial port: echo each byte
+    /// received, and carry out each command line. Settings are queued for
+    /// the simulation task (see events.rs). Any port can serve the shell, e.g. the USART,
+    /// or a USB serial port on parts with USB (see usb.rs).
+    fn serve_shell<P>(port: &mut P, shell: &mut Shell, mut frames: impl rtic::Mutex<T = FrameScheduler>, mut events: impl rtic::Mutex<T = EventProducer>)
+    where
+        P: Read<u8> + SerialWrite<u8> + Write,
+    {
+        while let Ok(byte) = port.read() {
+            // Replies are written between streamed frames
+            #[cfg(feature = "stream")]
+            stream::wait_idle();
+
+            // The remote protocol's frames aren't echoed, and are
+            // acknowledged with a frame
+            #[cfg(feature = "remote")]
+            match shell.receive_frame(byte) {
+                Received::Text => (),
+                Received::Partial => continue,
+                Received::Frame(received, ack) => {
+                    if let Some(received) = received {
+                        events.lock(|events| events::post(events, Event::Command(received)));
+                    }
+                    ack.write(port);
+                    continue;
+                },
+            }
+
+            port.write(byte).ok();
+            let result = match shell.receive(byte) {
+                Some(result) => result,
+                None => continue,
+            };
+
+            port.write_str("\r\n").ok();
+            match result {
+                Ok(Command::Stats) => {
+                    // milliseconds / 8 / 125, in 32 bits for over a year
+                    let uptime = (monotonics::now().ticks() >> 3) as u32 / 125;
+                    let dropped = frames.lock(|frames| frames.dropped());
+                    let i2c = DMAi2c::stats();
+                    crate::shell::write_counts(port, &[("uptime (s)", uptime), ("dropped frames", dropped), ("cpu load (%)", load::busy_percent() as u32),
+                                                ("stack headroom (B)", stack::headroom() as u32)]).ok();
+                    crate::shell::write_counts(port, &[("i2c transmissions", i2c.transmissions), ("nacks", i2c.nacks), ("retries", i2c.retries),
+                                                ("bus errors", i2c.bus_errors), ("timeouts", i2c.timeouts)]).ok();
+                },
+                #[cfg(feature = "histogram")]
+                Ok(Command::Histogram(reset)) => {
+                    histogram::report(port).ok();
+                    if reset {
+                        histogram::reset();
+                    }
+                },
+                Ok(Command::Help) => {
+                    port.write_str(crate::shell::HELP).ok();
+                },
+                Ok(received) => {
+                    events.lock(|events| events::post(events, Event::Command(received)));
+                    port.write_str("ok\r\n").ok();
+                },
+                Err(error) => {
+                    port.write_str("error: ").ok();
+                    port.write_str(error).ok();
+                    port.write_str("\r\n").ok();
+                },
             }
-            cnt += 1;
         }
     }
 
-    loop {
-        // the loop of shame
-        continue;
+    /// Hand I2C errors and NACKs to the DMA interrupt
+    #[task(binds = I2C1, priority = 2)]
+    fn i2c_event(_: i2c_event::Context) {
+        DMAi2c::on_i2c_interrupt(Interrupt::I2C1);
     }
 }
 
 
+// The pixels of a particle, from its origin
+const PARTICLE_PIXELS: [(usize,usize); 12] = [
+            (1, 0), (2, 0),
+    (0, 1), (1, 1), (2, 1), (3, 1),
+    (0, 2), (1, 2), (2, 2), (3, 2),
+            (1, 3), (2, 3),
+];
+
 /// Draw an individual particle at the given origin
 fn draw_particle(display: &mut OLEDDriver, x: usize, y: usize) {
-    const PIXELS: [(usize,usize); 12] = [
-                (1, 0), (2, 0),
-        (0, 1), (1, 1), (2, 1), (3, 1),
-        (0, 2), (1, 2), (2, 2), (3, 2),
-                (1, 3), (2, 3),
-    ];
-
-    for (dx, dy) in PIXELS {
+    for (dx, dy) in PARTICLE_PIXELS {
         display.set_pixel(x + dx, y + dy, true);
     }
 }
 
+/// Draw an individual particle at the given origin, in a shade of grey
+/// (see OLEDDriver::set_pixel_shade)
+#[cfg(feature = "lava")]
+fn draw_particle_shade(display: &mut OLEDDriver, x: usize, y: usize, shade: usize) {
+    for (dx, dy) in PARTICLE_PIXELS {
+        display.set_pixel_shade(x + dx, y + dy, shade);
+    }
+}
+
 /// Draw all fluid simulation particles
 fn draw_particles<const T:usize>(display: &mut OLEDDriver, fluid_sim: &Fluid<T>) {
     for particle in fluid_sim.get_particles() {
@@ -111,24 +988,3 @@ fn draw_particles<const T:usize>(display: &mut OLEDDriver, fluid_sim: &Fluid<T>)
     }
 }
 
-
-/// Print ASCII string over Semihost
-pub fn print(msg: &[u8]) {
-    // The file descriptor of stdout on the host
-    // Note: STDOUT is not guaranteed to be FD 1
-    //       SysCall ISTTY can be used to check
-    const STDOUT: usize = 1;
-
-    // The write syscall does not return until complete, 
-    // so the lifetime of the reference in msg does not 
-    // have to be greater than this function.
-    unsafe { 
-        syscall!(WRITE, STDOUT, msg.as_ptr(), msg.len());
-    };
-}
-
-/// Handle the SysTick interrupt
-#[exception]
-fn SysTick() {
-    // Do some stuff
-}
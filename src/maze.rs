@@ -0,0 +1,99 @@
+//! The tilt maze, a game: the water starts in the top left, and the
+//! player tilts the board (see accel.rs), or turns the gravity by hand,
+//! to guide it through the maze's walls into the basin in the bottom
+//! right. The maze is solved once most of the water is in the basin, and
+//! the time it took is shown as the score.
+
+use fluid_core::{obstacle::Rect, scene::SceneManager, Fluid, MAX_OBSTACLES};
+use crate::oled::{NumberText, OLEDDriver, FONT_ADVANCE};
+use crate::{artwork, scenes};
+
+
+// The walls, the particles' obstacles, drawn in art/maze_mask.xbm:
+// three corridors, joined at alternate ends, with a baffle in the middle
+// one and a lip holding the water in the basin at the end of the last
+const WALLS: &[Rect] = artwork::MAZE_MASK;
+const _: () = assert!(WALLS.len() <= MAX_OBSTACLES, "the maze has more walls than the fluid has obstacles");
+
+// The basin, and the share of the water in it solving the maze
+const GOAL: Rect = Rect::new(102, 45, 23, 16);
+const SOLVED_SHARE: (usize, usize) = (2, 3);
+
+// The frames per second, for the score
+const FRAME_RATE: u16 = 30;
+
+// The score's box, in the middle of the display
+const SCORE_BOX: (i32, i32, i32, i32) = (22, 22, 84, 17);
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Off,
+    Playing { frames: u16 },
+    Solved { seconds: u16 },
+}
+
+pub struct Maze {
+    state: State,
+}
+
+impl Maze {
+    /// Create a maze, not being played
+    pub const fn new() -> Self {
+        Self { state: State::Off }
+    }
+
+    /// Start a game, with the water back at the start
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::MAZE, 0, fluid);
+        let placed = fluid.set_obstacles(WALLS);
+        debug_assert!(placed, "the maze's walls weren't placed");
+        self.state = State::Playing { frames: 0 };
+    }
+
+    /// End the game, taking the walls away, e.g. to return to the demo
+    pub fn stop<const N: usize>(&mut self, fluid: &mut Fluid<N>) {
+        if self.state != State::Off {
+            fluid.set_obstacles(&[]);
+            self.state = State::Off;
+        }
+    }
+
+    /// Time the game, while it's played, and check whether the maze has
+    /// been solved. Returns the score, in seconds, once it's solved.
+    pub fn update<const N: usize>(&mut self, fluid: &Fluid<N>) -> Option<u16> {
+        let State::Playing { frames } = self.state else {
+            return None;
+        };
+        let (numerator, denominator) = SOLVED_SHARE;
+        if fluid.particles_in(GOAL) * denominator < N * numerator {
+            self.state = State::Playing { frames: frames.saturating_add(1) };
+            return None;
+        }
+        let seconds = frames / FRAME_RATE;
+        self.state = State::Solved { seconds };
+        Some(seconds)
+    }
+
+    /// Draw the walls over the frame, while the maze is played, and the
+    /// score once it's solved
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        if self.state == State::Off {
+            return;
+        }
+        for wall in WALLS {
+            display.fill_rect(wall.x as i32, wall.y as i32, wall.width as i32, wall.height as i32, true);
+        }
+
+        if let State::Solved { seconds } = self.state {
+            let (x, y, w, h) = SCORE_BOX;
+            display.fill_rect(x, y, w, h, false);
+            display.draw_rect(x, y, w, h);
+            let seconds = NumberText::from_i32(seconds as i32);
+            let width = (10 + seconds.as_str().len() as i32 + 1) * FONT_ADVANCE;
+            let text_x = display.draw_text(x + (w - width) / 2, y + 5, "solved in ");
+            let text_x = display.draw_text(text_x, y + 5, seconds.as_str());
+            display.draw_text(text_x, y + 5, "s");
+        }
+    }
+}
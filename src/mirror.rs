@@ -0,0 +1,36 @@
+//! A mirror of the display over RTT, for a viewer on the host to show or
+//! record each frame at full quality, or for a headless run to capture
+//! what was drawn. Each frame is sent on RTT up channel 1 ("display"),
+//! after the log's, as a header and the frame's 1024 bytes in the panel's
+//! layout (see OLEDBuffer): 8 pages of 128 columns, each column byte's
+//! least significant bit the top pixel of its page.
+//!
+//! There's no RAM for a copy of the frame, so it's passed through the
+//! channel's small buffer, waiting on the viewer as it's read. Frames are
+//! only sent once the viewer has set the channel to block when full
+//! (e.g. probe-rs's `BlockIfFull` mode), and are skipped otherwise, so
+//! the frame rate is kept without a viewer attached. The mirror needs the
+//! log's RTT control block, so it's sent in debug builds logging over RTT.
+
+use crate::oled::OLEDDriver;
+#[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+use crate::log::rtt;
+
+
+// The header starting each frame, for the viewer to find its start
+#[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+const HEADER: &[u8; 4] = b"OLED";
+
+
+/// Send the frame drawn to the viewer, if one is reading the channel.
+/// Called once the frame's been transmitted to the panel.
+pub fn send(_display: &OLEDDriver) {
+    #[cfg(all(debug_assertions, not(any(feature = "log-semihosting", feature = "log-uart", feature = "log-null"))))]
+    {
+        let channel = rtt::display();
+        if channel.is_blocking() {
+            channel.write(HEADER);
+            channel.write(_display.bitmap());
+        }
+    }
+}
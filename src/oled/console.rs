@@ -0,0 +1,88 @@
+use super::{PowerSource, OLED_COLS, OLED_DISPLAY_ON_CMD, OLED_INIT_CMDS, OLED_PAGES, OLED_PAGE_HEADERS, OLED_PAGE_HEADER_SIZE};
+use super::transport::Transport;
+use super::text::{glyph, FONT_WIDTH};
+
+
+// The control byte preceding pixel data that continues a page
+static DATA_CONTROL: [u8; 1] = [0x40];
+
+// Blank columns, for clearing the display
+static BLANK: [u8; OLED_COLS] = [0; OLED_COLS];
+
+
+/// A text console writing lines straight to the display's RAM, one line
+/// per page, without a frame buffer. This is for reporting when there's
+/// no RAM to spare or the driver can't be trusted, e.g. from a fault
+/// handler, over a transport that completes each transmission before
+/// returning (such as SoftI2c). Each line holds up to 21 characters.
+pub struct Console<T> {
+    transport: T,
+    address: u8,
+}
+
+#[allow(dead_code)]
+impl<T: Transport> Console<T> {
+    /// Initialize the display at the given 7-bit I2C address over the
+    /// given transport, and clear it. The display may already have been
+    /// initialized, e.g. by an OLEDDriver.
+    pub fn new(mut transport: T, address: u8, power: PowerSource) -> Self {
+        let power_cmds = [power.charge_pump_cmd(), OLED_DISPLAY_ON_CMD];
+        for cmd in OLED_INIT_CMDS.iter().chain(power_cmds.iter()) {
+            transport.tx(address, cmd, None);
+        }
+
+        let mut console = Self { transport, address };
+        for page in 0..OLED_PAGES {
+            console.write_line(page, "");
+        }
+        console
+    }
+
+    /// Replace the given line (page) with the given ASCII text. Characters
+    /// beyond the width of the display are dropped.
+    pub fn write_line(&mut self, page: usize, text: &str) {
+        self.write_bytes(page, text.as_bytes());
+    }
+
+    /// Write a labelled value as 8 hexadecimal digits, e.g. a register
+    pub fn write_hex(&mut self, page: usize, label: &str, value: u32) {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+        let mut line = [b' '; 13];
+        for (byte, c) in line.iter_mut().zip(label.bytes().take(4)) {
+            *byte = c;
+        }
+        for (i, byte) in line[5..].iter_mut().enumerate() {
+            *byte = DIGITS[(value >> (28 - i * 4)) as usize & 0xF];
+        }
+        self.write_bytes(page, &line);
+    }
+
+    // Replace the given line with ASCII text
+    #[inline(never)]
+    fn write_bytes(&mut self, page: usize, text: &[u8]) {
+        let header = &OLED_PAGE_HEADERS[0][(page * OLED_PAGE_HEADER_SIZE)..((page + 1) * OLED_PAGE_HEADER_SIZE)];
+        self.tx_columns(header, &BLANK[..1]);
+        let mut column = 1;
+
+        for &c in text {
+            if column + FONT_WIDTH as usize + 1 > OLED_COLS {
+                break;
+            }
+            self.tx_columns(&DATA_CONTROL, glyph(c as char));
+            self.tx_columns(&DATA_CONTROL, &BLANK[..1]);
+            column += FONT_WIDTH as usize + 1;
+        }
+
+        // blank the rest of the page
+        self.tx_columns(&DATA_CONTROL, &BLANK[column..]);
+    }
+
+    // Transmit columns of pixel data following the given header, in one
+    // transfer. This and write_bytes are kept out of line, as the bit-banged
+    // transfers are otherwise unrolled into every caller.
+    #[inline(never)]
+    fn tx_columns(&mut self, header: &'static [u8], columns: &'static [u8]) {
+        self.transport.tx_gather(self.address, header, header.len(), columns, columns.len());
+    }
+}
@@ -0,0 +1,113 @@
+use super::{page_header, OLEDBuffer, OLEDDriver, OLED_COLS, OLED_FRAME_SIZE, OLED_PAGES, OLED_PAGE_HEADER_SIZE};
+use super::transport::Transport;
+
+
+/// A copy of the pixel data the panel currently holds, laid out like 
+/// the frame buffer, along with the command header of each page's run
+pub struct DiffShadow {
+    panel: OLEDBuffer,
+    headers: [u8; OLED_PAGES * OLED_PAGE_HEADER_SIZE],
+}
+
+impl DiffShadow {
+    #[allow(dead_code)]
+    pub const fn new() -> Self {
+        Self {
+            panel: [0; OLED_FRAME_SIZE],
+            headers: [0; OLED_PAGES * OLED_PAGE_HEADER_SIZE],
+        }
+    }
+}
+
+
+/// Frame diff encoding. Rather than transmitting the whole frame, each page
+/// is compared against a shadow copy of what the panel currently holds, and
+/// only the run of columns between the first and last changed column of the
+/// page is transmitted. The run is sent directly from the frame buffer,
+/// preceded by a page header addressing the first changed column, which
+/// the DMA gathers from the shadow.
+impl<T: Transport> OLEDDriver<T> {
+    /// Enable frame diff encoding using the given shadow buffer, or disable
+    /// it with None. The previously used shadow buffer is returned.
+    /// The first frame transmitted after enabling is sent in full.
+    #[allow(dead_code)]
+    pub fn set_diff_shadow(&mut self, shadow: Option<&'static mut DiffShadow>) -> Option<&'static mut DiffShadow> {
+        self.wait_buffer_idle();
+        self.shadow_synced = false;
+        core::mem::replace(&mut self.shadow, shadow)
+    }
+
+    // Transmit the changed column runs of each page.
+    // Note: the shadow buffer must be present and in sync with the panel.
+    pub(super) fn tx_frame_diff(&mut self) {
+        // the run headers of the previous frame may still be in use
+        self.wait_buffer_idle();
+        for page in 0..OLED_PAGES {
+            let run = match &mut self.shadow {
+                Some(shadow) => {
+                    let start = page * OLED_COLS;
+                    let frame = &self.frame.buffer()[start..(start + OLED_COLS)];
+                    let panel = &mut shadow.panel[start..(start + OLED_COLS)];
+                    let changed = |(x, (new, old)): (usize, (&u8, &u8))| (new != old).then_some(x);
+                    let first = frame.iter().zip(panel.iter()).enumerate().find_map(changed);
+                    let last = frame.iter().zip(panel.iter()).enumerate().rev().find_map(changed);
+                    match (first, last) {
+                        (Some(first), Some(last)) => {
+                            panel[first..=last].copy_from_slice(&frame[first..=last]);
+                            Some((first, last))
+                        },
+                        _ => None,
+                    }
+                },
+                None => None,
+            };
+            if let Some((first, last)) = run {
+                self.tx_run(page, first, last);
+            }
+        }
+    }
+
+    // Record the full frame as what the panel holds
+    pub(super) fn sync_shadow(&mut self) {
+        if let Some(shadow) = &mut self.shadow {
+            match &mut self.dma_mem {
+                // SAFETY: the frame and shadow are only modified after 
+                //         wait_buffer_idle, which waits for the copy to complete
+                Some(dma_mem) => unsafe { dma_mem.start_copy(&self.frame.buffer()[..], &mut shadow.panel) },
+                None => shadow.panel.copy_from_slice(&self.frame.buffer()[..]),
+            }
+            self.shadow_synced = true;
+        }
+    }
+
+    // Transmit the columns from first to last (inclusive) of a page
+    fn tx_run(&mut self, page: usize, first: usize, last: usize) {
+        // Drop any columns the burn-in shift has moved off the panel
+        let columns = self.burn_in_columns();
+        let last = last.min(OLED_COLS - 1 - columns);
+        if first > last {
+            return;
+        }
+        let shadow = match &mut self.shadow {
+            Some(shadow) => shadow,
+            None => return,
+        };
+
+        // Address the run with the page's header
+        let position = page * OLED_PAGE_HEADER_SIZE;
+        let header = &mut shadow.headers[position..(position + OLED_PAGE_HEADER_SIZE)];
+        header.copy_from_slice(&page_header(page, first + columns));
+
+        // Lend the header and run to the DMA interrupt for the duration of the transfer.
+        // SAFETY: the frame and shadow buffers are exclusively owned by this driver
+        //         and are 'static, and tx_frame_diff and get_buffer both wait for 
+        //         the transmission to complete before modifying either buffer again.
+        let start = page * OLED_COLS + first;
+        let (header, run): (&'static [u8], &'static [u8]) = unsafe {(
+            core::slice::from_raw_parts(header.as_ptr(), OLED_PAGE_HEADER_SIZE),
+            core::slice::from_raw_parts(self.frame.buffer().as_ptr().add(start), last + 1 - first),
+        )};
+        self.transport.tx_gather(self.address, header, OLED_PAGE_HEADER_SIZE, run, OLED_COLS);
+        self.is_transmitting = true;
+    }
+}
@@ -1,47 +1,562 @@
-use core::{cmp, mem, cell::RefCell};
-use cortex_m;
-use cortex_m::interrupt::Mutex;
-use stm32f0xx_hal::pac::{interrupt, Interrupt, I2C1, DMA1};
+pub mod async_tx;
+pub mod bus;
+pub mod transfer;
 
+use core::{cmp, cell::{Cell, UnsafeCell}, ops::Deref};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::{interrupt::{InterruptNumber, Mutex}, peripheral::{NVIC, SCB, SYST, scb::{SystemHandler, VectActive}}};
+use embedded_hal::{blocking::delay::DelayUs, digital::v2::{InputPin, OutputPin}};
+use stm32f0xx_hal::{rcc::{Clocks, Rcc}, pac::{dma1, i2c1, Interrupt, I2C1, I2C2, DMA1, RCC, SYSCFG}};
+use crate::{board, log};
 
-// Global variables for the DMA tx complete interrupt
-static DMA_I2C: Mutex<RefCell<Option<DMAi2c>>> = Mutex::new(RefCell::new(None));
-static DMA_I2C_BUFFER: Mutex<RefCell<Option<I2CBuffer>>> = Mutex::new(RefCell::new(None));
+
+// Global variables for the DMA tx complete interrupt.
+// The interface moves between the main thread and the DMA interrupt, 
+// while the state polled by the main thread is shared without locks.
+static DMA_I2C: Mutex<Cell<Option<DMAi2c>>> = Mutex::new(Cell::new(None));
+static DMA_I2C_ACTIVE: AtomicBool = AtomicBool::new(true);
+static DMA_I2C_QUEUE: TxQueue = TxQueue::new();
+static DMA_I2C_ABORT: AtomicBool = AtomicBool::new(false);
+static DMA_I2C_YIELD: AtomicBool = AtomicBool::new(false);
+static DMA_I2C_INTERRUPT: Mutex<Cell<Option<Interrupt>>> = Mutex::new(Cell::new(None));
+static DMA_I2C_ERROR: Mutex<Cell<Option<TxError>>> = Mutex::new(Cell::new(None));
+static DMA_I2C_STATS: Mutex<Cell<Stats>> = Mutex::new(Cell::new(Stats::new()));
+static DMA_I2C_COMPLETION_HOOK: Mutex<Cell<Option<CompletionHook>>> = Mutex::new(Cell::new(None));
+
+// The number of transmissions that may wait for the DMA interrupt
+const TX_QUEUE_CAPACITY: usize = 4;
+
+// The number of times a NACKed transfer is retried, unless configured
+const DEFAULT_RETRY_LIMIT: u8 = 3;
+
+// Wait timeouts, in microseconds, counted in CPU cycles at the system
+// clock's current frequency. A full frame takes ~27ms at 400kHz (~108ms
+// at 100kHz), and a single byte ~23us.
+const TX_TIMEOUT_US: u32 = 250_000;
+const BYTE_TIMEOUT_US: u32 = 1_000;
+const POLL_CYCLES: u32 = 48;
+
+// The I2C peripheral abandons a transfer if SCL is held low for longer
+// than this, e.g. by a stuck device, rather than leaving it to a waiter's
+// timeout
+const SCL_LOW_TIMEOUT_HZ: u32 = 40;         // 25ms
 
 
 /// A buffer for I2C transmissions. If the length of the buffer
 /// is greater than the tx_size, data will be transmitted in
-/// tx_size increments.
+/// tx_size increments. If headers are given, each increment is
+/// preceded in the same transfer by its own header_size bytes 
+/// of the headers, gathered from there by the DMA.
 #[derive(Copy, Clone)]
 pub struct I2CBuffer {
+    pub address: u8,
     pub data: &'static [u8],
     pub tx_size: u8,
+    pub headers: &'static [u8],
+    pub header_size: u8,
+}
+
+impl I2CBuffer {
+    // Get the header preceding the given block, if any
+    fn header(&self, block: usize) -> &'static [u8] {
+        let size = self.header_size as usize;
+        self.headers.get((block * size)..((block + 1) * size)).unwrap_or(&[])
+    }
+}
+
+
+// A fixed capacity FIFO of transmissions waiting for the DMA interrupt,
+// shared without locks between a single producer (the main thread) and 
+// a single consumer (the DMA interrupt). A slot is written before the
+// tail is advanced to publish it, and read before the head is advanced 
+// to release it. Only the producer writes the tail, and only the consumer
+// writes the head and the count of ended transmissions.
+// The wrapping tail numbers each transmission as it's queued, and the 
+// ended count lets callers tell when theirs has ended.
+struct TxQueue {
+    slots: [UnsafeCell<Option<I2CBuffer>>; TX_QUEUE_CAPACITY],
+    head: AtomicU32,
+    tail: AtomicU32,
+    ended: AtomicU32,
+}
+
+// SAFETY: each slot is only accessed by one side at a time, as above
+unsafe impl Sync for TxQueue {}
+
+impl TxQueue {
+    const fn new() -> Self {
+        TxQueue {
+            slots: [const { UnsafeCell::new(None) }; TX_QUEUE_CAPACITY],
+            head: AtomicU32::new(0),
+            tail: AtomicU32::new(0),
+            ended: AtomicU32::new(0),
+        }
+    }
+
+    fn slot(&self, position: u32) -> *mut Option<I2CBuffer> {
+        self.slots[position as usize % TX_QUEUE_CAPACITY].get()
+    }
+
+    fn is_full(&self) -> bool {
+        let len = self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire));
+        len as usize >= TX_QUEUE_CAPACITY
+    }
+
+    // Add a transmission to the back of the queue, if there's room,
+    // returning its number
+    // Note: only called from the main thread
+    fn push(&self, buffer: I2CBuffer) -> Option<u32> {
+        if self.is_full() {
+            return None;
+        }
+        let tail = self.tail.load(Ordering::Relaxed);
+        // SAFETY: the slot is outside of the published range, so 
+        //         the consumer doesn't access it
+        unsafe { *self.slot(tail) = Some(buffer) };
+        let tail = tail.wrapping_add(1);
+        self.tail.store(tail, Ordering::Release);
+        Some(tail)
+    }
+
+    // Determine if the transmission with the given number has ended
+    fn has_ended(&self, number: u32) -> bool {
+        self.ended.load(Ordering::Acquire).wrapping_sub(number) as i32 >= 0
+    }
+
+    // Count a transmission as ended
+    // Note: only called from DMA interrupt
+    fn end(&self) {
+        let ended = self.ended.load(Ordering::Relaxed);
+        self.ended.store(ended.wrapping_add(1), Ordering::Release);
+    }
+
+    // Remove the transmission at the front of the queue
+    // Note: only called from DMA interrupt
+    fn pop(&self) -> Option<I2CBuffer> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: the slot is published, so the producer doesn't access it
+        let buffer = unsafe { (*self.slot(head)).take() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        buffer
+    }
+
+    // Drop all queued transmissions, counting them (and the 
+    // transmission in progress) as ended
+    // Note: only called from DMA interrupt
+    fn clear(&self) {
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.store(tail, Ordering::Release);
+        self.ended.store(tail, Ordering::Release);
+    }
+}
+
+
+/// A function called from the DMA interrupt as each transmission ends,
+/// with the address of the device it was sent to. It should be short,
+/// e.g. setting a flag or pending another interrupt.
+pub type CompletionHook = fn(address: u8);
+
+
+/// Counters of the interface's DMA transmissions, for reporting link health
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub struct Stats {
+    /// Bytes transmitted, including retransmissions
+    pub bytes: u32,
+    /// Transmissions ended, whether or not they succeeded
+    pub transmissions: u32,
+    /// Blocks retransmitted after a NACK
+    pub retries: u32,
+    /// NACKs received
+    pub nacks: u32,
+    /// Bus errors and losses of arbitration
+    pub bus_errors: u32,
+    /// Transmissions aborted after the bus stopped responding
+    pub timeouts: u32,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            bytes: 0,
+            transmissions: 0,
+            retries: 0,
+            nacks: 0,
+            bus_errors: 0,
+            timeouts: 0,
+        }
+    }
+}
+
+
+/// Errors reported by the DMAi2c interface
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum TxError {
+    /// The device did not acknowledge a transfer, after all retries
+    Nack,
+    /// A misplaced START or STOP condition was detected on the bus
+    BusError,
+    /// Another controller took the bus during a transfer
+    ArbitrationLost,
+    /// The bus stopped responding, and the transfer was aborted
+    Timeout,
+}
+
+
+/// The DMA1 channels used by the interfaces here
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum DmaChannel {
+    /// Free for memory-to-memory operations
+    Channel1,
+    /// I2C1 transmit requests
+    Channel2,
+    /// Free for memory-to-memory operations
+    Channel3,
+    /// I2C2 transmit requests
+    Channel4,
+    /// Free for memory-to-memory operations
+    Channel5,
+    /// I2C1 transmit requests when remapped (STM32F07x only)
+    Channel6,
+}
+
+impl DmaChannel {
+    // The registers of this channel
+    pub(super) fn registers(self, dma: &dma1::RegisterBlock) -> &dma1::CH {
+        match self {
+            DmaChannel::Channel1 => &dma.ch1,
+            DmaChannel::Channel2 => &dma.ch2,
+            DmaChannel::Channel3 => &dma.ch3,
+            DmaChannel::Channel4 => &dma.ch4,
+            DmaChannel::Channel5 => &dma.ch5,
+            DmaChannel::Channel6 => &dma.ch6,
+        }
+    }
+
+    // The interrupt shared by this channel and its neighbors
+    // Note: on parts with channels 6 and 7, they share the 
+    //       channel 4 and 5 interrupt
+    fn interrupt(self) -> Interrupt {
+        match self {
+            DmaChannel::Channel1 => Interrupt::DMA1_CH1,
+            DmaChannel::Channel2 | DmaChannel::Channel3 => Interrupt::DMA1_CH2_3,
+            #[cfg(not(feature = "stm32f072"))]
+            DmaChannel::Channel4 | DmaChannel::Channel5 | DmaChannel::Channel6 => Interrupt::DMA1_CH4_5,
+            #[cfg(feature = "stm32f072")]
+            DmaChannel::Channel4 | DmaChannel::Channel5 | DmaChannel::Channel6 => Interrupt::DMA1_CH4_5_6_7,
+        }
+    }
+
+    // The bit offset of this channel's flags in the ISR and IFCR registers
+    fn flag_offset(self) -> u32 {
+        match self {
+            DmaChannel::Channel1 => 0,
+            DmaChannel::Channel2 => 4,
+            DmaChannel::Channel3 => 8,
+            DmaChannel::Channel4 => 12,
+            DmaChannel::Channel5 => 16,
+            DmaChannel::Channel6 => 20,
+        }
+    }
+
+    // Determine if this channel's transfer complete flag is set
+    pub(super) fn is_complete(self, dma: &dma1::RegisterBlock) -> bool {
+        const TCIF: u32 = 1 << 1;
+        dma.isr.read().bits() & (TCIF << self.flag_offset()) != 0
+    }
+
+    // Clear this channel's transfer complete flag.
+    // Note: IFCR is write 1 to clear, so other channels are not affected.
+    pub(super) fn clear_complete(self, dma: &dma1::RegisterBlock) {
+        const CTCIF: u32 = 1 << 1;
+        dma.ifcr.write(|w| unsafe { w.bits(CTCIF << self.flag_offset()) });
+    }
+}
+
+
+/// A device address on the I2C bus
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Address {
+    /// A 7-bit address, e.g. 0x3C for the OLED
+    SevenBit(u8),
+    /// A 10-bit address, for less common devices
+    TenBit(u16),
+}
+
+impl Address {
+    // Configure the address of a transfer in CR2.
+    // In 7-bit addressing mode SADD[7:1] holds the address,
+    // and in 10-bit addressing mode SADD[9:0] does.
+    fn configure<'w>(&self, w: &'w mut i2c1::cr2::W) -> &'w mut i2c1::cr2::W {
+        match *self {
+            Address::SevenBit(address) => w.sadd().bits((address as u16 & 0x7F) << 1)
+                                           .add10().clear_bit(),
+            Address::TenBit(address) => w.sadd().bits(address & 0x3FF)
+                                         .add10().set_bit()
+                                         .head10r().clear_bit(),
+        }
+    }
+}
+
+
+/// I2C bus speeds
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Speed {
+    /// Standard-mode, 100kHz
+    Standard,
+    /// Fast-mode, 400kHz
+    Fast,
+    /// Fast-mode Plus, 1MHz. Note: the pins' Fm+ drive must 
+    /// also be enabled through the SYSCFG peripheral.
+    FastPlus,
+}
+
+// The SCL low timeout field for the given I2C kernel clock, 
+// which counts periods of 2048 kernel clock cycles, less one
+fn scl_low_timeout(kernel_clock: u32) -> u16 {
+    let periods = (kernel_clock / 2048).div_ceil(SCL_LOW_TIMEOUT_HZ);
+    cmp::min(periods.saturating_sub(1), 0xFFF) as u16
+}
+
+// The fields of the I2C timing register
+struct Timing {
+    presc: u8,
+    scll: u8,
+    sclh: u8,
+    sdadel: u8,
+    scldel: u8,
+}
+
+impl Speed {
+    // The SCL low and high periods, and the data hold and setup times, 
+    // in ns. These exceed the minimums of the I2C specification, leaving
+    // margin for clock synchronization and rise times.
+    fn periods_ns(self) -> (u32, u32, u32, u32) {
+        match self {
+            Speed::Standard => (5000, 4500, 500, 1250),
+            Speed::Fast => (1300, 900, 0, 500),
+            Speed::FastPlus => (500, 300, 0, 125),
+        }
+    }
+
+    // Compute the timing register fields for the given I2C kernel clock,
+    // using the smallest prescaler that fits every field
+    fn timing(self, kernel_clock: u32) -> Timing {
+        const MAX_PRESC: u32 = 15;
+        let khz = kernel_clock / 1000;
+        let (low, high, hold, setup) = self.periods_ns();
+
+        // Each field counts prescaled clock periods, less one except for SDADEL
+        let periods = |ns: u32, prescale: u32| (ns * khz).div_ceil(1_000_000).div_ceil(prescale);
+        let mut timing = Timing { presc: 0, scll: 0, sclh: 0, sdadel: 0, scldel: 0 };
+        for presc in 0..=MAX_PRESC {
+            let prescale = presc + 1;
+            let scll = periods(low, prescale).saturating_sub(1);
+            let sclh = periods(high, prescale).saturating_sub(1);
+            let sdadel = periods(hold, prescale);
+            let scldel = periods(setup, prescale).saturating_sub(1);
+            timing = Timing {
+                presc: presc as u8,
+                scll: cmp::min(scll, 0xFF) as u8,
+                sclh: cmp::min(sclh, 0xFF) as u8,
+                sdadel: cmp::min(sdadel, 0xF) as u8,
+                scldel: cmp::min(scldel, 0xF) as u8,
+            };
+            if scll <= 0xFF && sclh <= 0xFF && sdadel <= 0xF && scldel <= 0xF {
+                break;
+            }
+        }
+        timing
+    }
+}
+
+
+/// An I2C peripheral that can be driven by the DMAi2c interface
+pub trait I2cInstance {
+    /// The DMA channel wired to this peripheral's transmit requests
+    const TX_CHANNEL: DmaChannel;
+
+    /// The peripheral's event interrupt
+    const INTERRUPT: Interrupt;
+
+    /// The frequency of the clock driving the peripheral, in Hz
+    fn kernel_clock(clocks: &Clocks) -> u32;
+
+    /// Consume the peripheral, returning a pointer to its registers
+    fn into_registers(self) -> *const i2c1::RegisterBlock;
+}
+
+macro_rules! i2c_instance {
+    ($($I2C:ident => $channel:ident, $kernel_clock:ident,)+) => {
+        $(
+            impl I2cInstance for $I2C {
+                const TX_CHANNEL: DmaChannel = DmaChannel::$channel;
+                const INTERRUPT: Interrupt = Interrupt::$I2C;
+
+                fn kernel_clock(clocks: &Clocks) -> u32 {
+                    $kernel_clock(clocks)
+                }
+
+                fn into_registers(self) -> *const i2c1::RegisterBlock {
+                    $I2C::ptr()
+                }
+            }
+        )+
+    }
+}
+
+i2c_instance! {
+    I2C1 => Channel2, i2c1_kernel_clock,
+    I2C2 => Channel4, apb_clock,
+}
+
+// I2C1 is clocked by either the HSI oscillator or SYSCLK
+fn i2c1_kernel_clock(clocks: &Clocks) -> u32 {
+    const HSI_FREQUENCY: u32 = 8_000_000;
+    // SAFETY: a read of the clock selection, which isn't modified here
+    let rcc = unsafe { &*RCC::ptr() };
+    match rcc.cfgr3.read().i2c1sw().is_sysclk() {
+        true => clocks.sysclk().0,
+        false => HSI_FREQUENCY,
+    }
+}
+
+// Other I2C peripherals are clocked by the APB
+fn apb_clock(clocks: &Clocks) -> u32 {
+    clocks.pclk().0
+}
+
+
+/// An I2C peripheral whose transmit requests can be remapped 
+/// to another DMA channel through the SYSCFG peripheral
+pub trait RemappableI2cInstance: I2cInstance {
+    /// The DMA channel servicing transmit requests when remapped
+    const REMAPPED_TX_CHANNEL: DmaChannel;
+
+    /// Route transmit requests to the remapped DMA channel
+    fn remap_tx(syscfg: &mut SYSCFG);
+}
+
+impl RemappableI2cInstance for I2C1 {
+    const REMAPPED_TX_CHANNEL: DmaChannel = DmaChannel::Channel6;
+
+    fn remap_tx(syscfg: &mut SYSCFG) {
+        // I2C1_DMA_RMP is only present on the STM32F07x, so it 
+        // isn't described by every device's register definitions
+        const I2C1_DMA_RMP: u32 = 1 << 27;
+        syscfg.cfgr1.modify(|r, w| unsafe { w.bits(r.bits() | I2C1_DMA_RMP) });
+    }
+}
+
+
+// A pointer to a peripheral's registers, which moves along with the 
+// DMAi2c interface between the main thread and the DMA interrupt
+pub(super) struct Registers<T>(pub(super) *const T);
+
+// SAFETY: the registers are only accessed by the current holder of the interface
+unsafe impl<T> Send for Registers<T> {}
+
+impl<T> Deref for Registers<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: the pointer is to a memory mapped peripheral
+        unsafe { &*self.0 }
+    }
 }
 
 
 /// An interface for DMA I2C transmissions
 pub struct DMAi2c {
-    i2c: I2C1,
-    dma: DMA1,
+    i2c: Registers<i2c1::RegisterBlock>,
+    dma: Registers<dma1::RegisterBlock>,
+    channel: DmaChannel,
+    event_interrupt: Interrupt,
     tx_data: Option<I2CBuffer>,
     tx_index: usize,
+    tx_length: u8,
+    tx_segment: Option<(u32, u8)>,
+    retry_limit: u8,
+    retries: u8,
+    paused: bool,
+    speed: Speed,
 }
 
 impl DMAi2c {
-    /// Initialize the DMAi2c interface.
-    /// TODO: consider generalizing beyond I2C1 and DMA1,
-    ///       or at least not taking all DMA channels.
-    pub fn init(mut i2c: I2C1, mut dma: DMA1) {
+    /// Initialize the DMAi2c interface on the given I2C peripheral,
+    /// using the DMA channel wired to its transmit requests. 
+    /// Only that DMA channel is used, so other DMA channels remain
+    /// available to the rest of the application. The bus timing is
+    /// computed for the given speed from the configured clocks.
+    pub fn init<I: I2cInstance>(i2c: I, dma: &mut DMA1, rcc: &Rcc, speed: Speed) {
+        DMAi2c::init_on_channel(i2c, dma, I::TX_CHANNEL, rcc, speed);
+    }
+
+    /// Initialize the DMAi2c interface on the given I2C peripheral, with
+    /// its transmit requests remapped to an alternate DMA channel. This 
+    /// frees the default channel for use by another peripheral.
+    /// Note: the SYSCFG peripheral clock must be enabled.
+    #[allow(dead_code)]
+    pub fn init_remapped<I: RemappableI2cInstance>(i2c: I, dma: &mut DMA1, syscfg: &mut SYSCFG, rcc: &Rcc, speed: Speed) {
+        I::remap_tx(syscfg);
+        DMAi2c::init_on_channel(i2c, dma, I::REMAPPED_TX_CHANNEL, rcc, speed);
+    }
+
+    /// The DMA channel servicing the interface's transmissions, 
+    /// or None if the interface is not initialized
+    #[allow(dead_code)]
+    pub fn channel() -> Option<DmaChannel> {
+        // The channel is only readable while the interface is idle
+        DMAi2c::wait_idle().ok()?;
+        cortex_m::interrupt::free(|cs| {
+            let intf = DMA_I2C.borrow(cs).take();
+            let channel = intf.as_ref().map(|intf| intf.channel);
+            DMA_I2C.borrow(cs).set(intf);
+            channel
+        })
+    }
+
+    // Initialize the interface with the given DMA channel
+    fn init_on_channel<I: I2cInstance>(i2c: I, dma: &mut DMA1, channel: DmaChannel, rcc: &Rcc, speed: Speed) {
+        // Note: only the registers of the selected DMA channel are modified,
+        //       and its flags are cleared without affecting other channels
+        let kernel_clock = I::kernel_clock(&rcc.clocks);
+        let timing = speed.timing(kernel_clock);
+        let i2c = Registers(i2c.into_registers());
+        let dma = Registers(&**dma as *const dma1::RegisterBlock);
+
         // configure the I2C and DMA peripherals
-        DMAi2c::init_i2c(&mut i2c);
-        DMAi2c::init_dma(&mut dma);
+        DMAi2c::init_i2c(&i2c, timing, scl_low_timeout(kernel_clock));
+        DMAi2c::init_dma(&dma, channel, &i2c);
+
+        // route NACKs and errors from the I2C interrupt to the channel's interrupt
+        cortex_m::interrupt::free(|cs| {
+            DMA_I2C_INTERRUPT.borrow(cs).set(Some(channel.interrupt()));
+        });
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(I::INTERRUPT);
+        }
 
         // Create the DMAi2c struct
         let dma_i2c = DMAi2c {
             i2c,
             dma,
+            channel,
+            event_interrupt: I::INTERRUPT,
             tx_data: None,
             tx_index: 0,
+            tx_length: 0,
+            tx_segment: None,
+            retry_limit: DEFAULT_RETRY_LIMIT,
+            retries: 0,
+            paused: false,
+            speed,
         };
 
         // move the DMAi2c struct to a global mutex
@@ -49,71 +564,435 @@ impl DMAi2c {
         DMAi2c::give_interface(dma_i2c);
     }
 
-    /// Transmit some data. This blocks until tx is possible
-    pub fn tx(data: &'static [u8], tx_size: Option<usize>) {
-        while DMAi2c::tx_in_progress() {
-            // Wait until pending buffer is available
+    /// Transmit some data to the device with the given 7-bit address.
+    /// Transmissions are queued and sent back-to-back in order, so this 
+    /// only blocks while the queue is full. Transmissions to different
+    /// devices sharing the bus are serialized.
+    /// The queue is shared with the DMA interrupt without locks, so 
+    /// transmissions must only be queued from the main thread.
+    pub fn tx(address: u8, data: &'static [u8], tx_size: Option<usize>) {
+        let tx_size = tx_size.unwrap_or(data.len()) as u8;
+        DMAi2c::tx_buffer(I2CBuffer { address, data, tx_size, headers: &[], header_size: 0 });
+    }
+
+    /// Transmit some data in blocks of tx_size bytes, each preceded in the
+    /// same transfer by its own header of header_size bytes from headers,
+    /// e.g. the commands addressing each page of a frame. The DMA gathers
+    /// each block from both buffers, so the data needn't leave room for 
+    /// the headers. Blocks, including their headers, are limited to 255 bytes.
+    #[allow(dead_code)]
+    pub fn tx_gather(address: u8, headers: &'static [u8], header_size: usize, data: &'static [u8], tx_size: usize) {
+        let tx_size = cmp::min(tx_size, (u8::MAX as usize).saturating_sub(header_size)) as u8;
+        DMAi2c::tx_buffer(I2CBuffer { address, data, tx_size, headers, header_size: header_size as u8 });
+    }
+
+    // Queue a transmission, blocking while the queue is full
+    fn tx_buffer(buffer: I2CBuffer) {
+        if sleep_for(TX_TIMEOUT_US, || !DMAi2c::tx_queue_full()).is_err() 
+            && DMAi2c::abort_stuck().is_err() {
+            // The interface is unavailable, so the data can't be sent
+            return;
         }
 
-        // Get the tx_size
-        let tx_size = match tx_size {
-            Some(x) => x,
-            None => data.len(),
-        } as u8;
+        DMAi2c::try_tx_buffer(buffer);
+    }
+
+    /// Set the number of times a transfer is retried when the device
+    /// doesn't acknowledge it, before it is abandoned and reported as 
+    /// a TxError::Nack. This blocks until any pending transmission is complete.
+    #[allow(dead_code)]
+    pub fn set_retry_limit(retries: u8) {
+        let mut intf = DMAi2c::acquire_interface();
+        if let Some(i2c) = &mut intf {
+            i2c.retry_limit = retries;
+        }
+        DMAi2c::swap_interface(&mut intf);
+    }
 
-        //Move data ref to global mutex for DMA interrupt
-        DMAi2c::set_tx_buffer(data, tx_size);
+    /// Recompute the bus timing for a new kernel clock, in Hz, e.g. as
+    /// the system clock is scaled, keeping the bus speed. This blocks
+    /// until any pending transmission is complete.
+    #[allow(dead_code)]
+    pub fn set_kernel_clock(kernel_clock: u32) {
+        let mut intf = DMAi2c::acquire_interface();
+        if let Some(i2c) = &mut intf {
+            DMAi2c::init_i2c(&i2c.i2c, i2c.speed.timing(kernel_clock), scl_low_timeout(kernel_clock));
+        }
+        DMAi2c::swap_interface(&mut intf);
+    }
 
-        // trigger the DMA interrupt to begin tx
-        cortex_m::peripheral::NVIC::pend(Interrupt::DMA1_CH2_3);
+    /// Set the priority of the interface's interrupts, lower values being
+    /// more urgent. The DMA channel and I2C event interrupts share the 
+    /// priority, so neither preempts the other. Only the top two bits are
+    /// implemented on the Cortex-M0. Note: the DMA channel's interrupt is
+    /// shared with its neighboring channel(s). This blocks until any pending 
+    /// transmission is complete.
+    #[allow(dead_code)]
+    pub fn set_priority(nvic: &mut NVIC, priority: u8) {
+        let mut intf = DMAi2c::acquire_interface();
+        if let Some(i2c) = &intf {
+            // SAFETY: the interface's shared state is only accessed in
+            //         critical sections, which don't rely on priorities
+            unsafe {
+                nvic.set_priority(i2c.channel.interrupt(), priority);
+                nvic.set_priority(i2c.event_interrupt, priority);
+            }
+        }
+        DMAi2c::swap_interface(&mut intf);
+    }
+
+    /// Recover a bus held by a device stuck mid-transfer with SDA low,
+    /// e.g. after a reset or glitch. The I2C peripheral is disabled while 
+    /// SCL is clocked until the device releases SDA, up to 9 times, and is
+    /// then reset. The caller must configure the pins as open drain GPIO 
+    /// beforehand, and return them to their I2C function afterward.
+    /// Returns whether SDA was released. This blocks until any pending 
+    /// transmission is complete.
+    #[allow(dead_code)]
+    pub fn recover_bus<SCL, SDA, D>(scl: &mut SCL, sda: &SDA, delay: &mut D) -> bool 
+    where SCL: OutputPin, SDA: InputPin, D: DelayUs<u16> {
+        // half of a 100kHz SCL period
+        const HALF_PERIOD_US: u16 = 5;
+        const MAX_CLOCKS: usize = 9;
+
+        let mut intf = DMAi2c::acquire_interface();
+
+        let mut released = false;
+        if let Some(i2c) = &mut intf {
+            i2c.i2c.cr1.modify(|_, w| w.pe().disabled());
+
+            // clock out the remainder of the byte the device is sending
+            for _ in 0..MAX_CLOCKS {
+                released = sda.is_high().unwrap_or(false);
+                if released {
+                    break;
+                }
+                scl.set_low().ok();
+                delay.delay_us(HALF_PERIOD_US);
+                scl.set_high().ok();
+                delay.delay_us(HALF_PERIOD_US);
+            }
+            released = sda.is_high().unwrap_or(false);
+
+            i2c.reset_i2c();
+        }
+
+        DMAi2c::swap_interface(&mut intf);
+        released
+    }
+
+    /// Set (or remove) the function called as each transmission ends, so
+    /// callers can be notified of completion without polling. A transmission
+    /// that failed after its retries still ends, and is reported by take_error;
+    /// aborted transmissions are not notified.
+    #[allow(dead_code)]
+    pub fn set_completion_hook(hook: Option<CompletionHook>) {
+        cortex_m::interrupt::free(|cs| DMA_I2C_COMPLETION_HOOK.borrow(cs).set(hook));
+    }
+
+    /// A snapshot of the interface's counters
+    #[allow(dead_code)]
+    pub fn stats() -> Stats {
+        cortex_m::interrupt::free(|cs| DMA_I2C_STATS.borrow(cs).get())
+    }
+
+    /// Reset the interface's counters to zero
+    #[allow(dead_code)]
+    pub fn reset_stats() {
+        cortex_m::interrupt::free(|cs| DMA_I2C_STATS.borrow(cs).set(Stats::new()));
+    }
+
+    /// Take the most recent error reported by the interface, if any
+    #[allow(dead_code)]
+    pub fn take_error() -> Option<TxError> {
+        cortex_m::interrupt::free(|cs| DMA_I2C_ERROR.borrow(cs).take())
+    }
+
+    /// Wait for all queued transmissions to complete. A transmission that
+    /// doesn't complete within the timeout is aborted along with the rest of
+    /// the queue, and reported as a TxError::Timeout by take_error. Returns 
+    /// an error if the interface remains unavailable, e.g. if it was never 
+    /// initialized.
+    pub fn wait_idle() -> Result<(), TxError> {
+        if sleep_for(TX_TIMEOUT_US, || !DMAi2c::tx_in_progress()).is_ok() {
+            return Ok(());
+        }
+        DMAi2c::abort_stuck()
+    }
+
+    /// Abandon the transmission in progress along with the rest of the
+    /// queue, e.g. when switching scenes or recovering from a stuck frame.
+    /// The DMA channel is disabled, the bus is released with a STOP
+    /// condition, and the interface is reset for the next transmission.
+    /// Aborted transmissions are not notified, and no error is reported.
+    /// Returns an error if the interface doesn't become available.
+    #[allow(dead_code)]
+    pub fn abort() -> Result<(), TxError> {
+        DMA_I2C_ABORT.store(true, Ordering::Release);
+        DMAi2c::pend_tx_interrupt();
+        wait_for(BYTE_TIMEOUT_US, || !DMAi2c::tx_in_progress())
+    }
+
+    // Have the DMA interrupt abandon a stuck transmission and the queue
+    fn abort_stuck() -> Result<(), TxError> {
+        report_error(TxError::Timeout);
+        DMAi2c::abort()
     }
 
     /// Determine if a transmission is in progress.
     /// The DMA Interrupt takes the DMAi2c interface while
-    /// transmitting, so if it resides in the global mutex,
-    /// no transmission is in progress.
+    /// transmitting, so if it resides in the global mutex
+    /// without a paused transmission, no transmission is in progress.
+    /// This is tracked as the interface moves, so polling is lock-free.
     pub fn tx_in_progress() -> bool {
-        let mut in_progress = true;
-        cortex_m::interrupt::free(|cs| {
-            if DMA_I2C.borrow(cs).borrow().is_some() {
-                in_progress = false;
+        DMA_I2C_ACTIVE.load(Ordering::Acquire)
+    }
+
+    /// Address the device with the given 7-bit address without sending
+    /// any data, and report whether it acknowledged. This blocks until 
+    /// any pending transmission is complete.
+    #[allow(dead_code)]
+    pub fn probe(address: u8) -> bool {
+        let mut intf = DMAi2c::acquire_interface();
+
+        let mut acknowledged = false;
+        if let Some(i2c) = &mut intf {
+            // The NACK is expected here, so it's not handed to the DMA interrupt
+            i2c.i2c.cr1.modify(|_, w| w.nackie().disabled());
+
+            // An address-only write: no bytes means no DMA requests,
+            // and the STOP condition is generated with or without an ACK
+            i2c.i2c.cr2.modify(|_, w| Address::SevenBit(address).configure(w)
+                                        .nbytes().bits(0)
+                                        .autoend().set_bit()
+                                        .rd_wrn().clear_bit()
+                                        .start().set_bit());
+            if wait_for(BYTE_TIMEOUT_US, || i2c.i2c.isr.read().stopf().is_stop()).is_ok() {
+                acknowledged = i2c.i2c.isr.read().nackf().is_no_nack();
+                i2c.i2c.icr.write(|w| w.stopcf().set_bit()
+                                       .nackcf().set_bit());
+                i2c.i2c.cr1.modify(|_, w| w.nackie().enabled());
+            } else {
+                report_error(TxError::Timeout);
+                i2c.reset_i2c();
             }
-        });
-        in_progress
+        }
+
+        DMAi2c::swap_interface(&mut intf);
+        acknowledged
     }
 
-    // Transmit a string of bytes of the given length, 
-    // starting at the given address. 
+    // Transmit a string of bytes of the given length, starting 
+    // at the given memory address, to the given 7-bit device address,
+    // preceded by the given header if it isn't empty. The header is
+    // transferred by the DMA first, and the data once it completes.
     // Note: only called from DMA interrupt
-    fn tx_data_addr_len(&mut self, address: u32, length: u8) {
-        // disable DMA peripheral while updating configuration
-        self.dma.ch2.cr.modify(|_, w| w.en().disabled());
-        while self.dma.ch2.cr.read().en().is_enabled() {
-            // wait for DMA to be disabled
+    fn tx_data_addr_len(&mut self, device: u8, header: &[u8], address: u32, length: u8) -> Result<(), TxError> {
+        if header.is_empty() {
+            self.tx_dma_addr_len(address, length)?;
+        } else {
+            self.tx_dma_addr_len(header.as_ptr() as u32, header.len() as u8)?;
+            self.tx_segment = Some((address, length));
+        }
+        let length = header.len() as u8 + length;
+
+        // ensure I2C is not mid transfer, failing straight away 
+        // on an error flagged since the last transfer
+        let mut isr = self.i2c.isr.read();
+        wait_for(BYTE_TIMEOUT_US, || {
+            isr = self.i2c.isr.read();
+            isr.txe().is_empty() || bus_error(&isr).is_some()
+        })?;
+        if let Some(error) = bus_error(&isr) {
+            return Err(error);
         }
 
+        // configure the I2C peripheral for the transfer and start
+        self.i2c.cr2.modify(|_, w| Address::SevenBit(device).configure(w)
+                                    .nbytes().bits(length)
+                                    .autoend().set_bit()
+                                    .rd_wrn().clear_bit()
+                                    .start().set_bit());
+        Ok(())
+    }
+
+    // Have the DMA channel feed the given bytes to the I2C peripheral
+    // Note: only called from DMA interrupt
+    fn tx_dma_addr_len(&mut self, address: u32, length: u8) -> Result<(), TxError> {
+        let ch = self.channel.registers(&self.dma);
+
+        // disable DMA peripheral while updating configuration
+        ch.cr.modify(|_, w| w.en().disabled());
+        wait_for(BYTE_TIMEOUT_US, || ch.cr.read().en().is_disabled())?;
+
         // set the start address for the DMA transfer
-        self.dma.ch2.mar.write(|w| unsafe { w.bits(address) });
+        ch.mar.write(|w| unsafe { w.bits(address) });
 
         // set the number of bytes to be transfered
-        self.dma.ch2.ndtr.write(|w| unsafe { w.bits(length as u32) });
+        ch.ndtr.write(|w| unsafe { w.bits(length as u32) });
 
         // enable the DMA peripheral
-        self.dma.ch2.cr.modify(|_, w| w.en().enabled());
+        ch.cr.modify(|_, w| w.en().enabled());
+        Ok(())
+    }
+
+    // Abort the current block after the device NACKed it, and rewind to 
+    // retransmit it, or skip the rest of the transfer if out of retries
+    // Note: only called from DMA interrupt
+    fn handle_nack(&mut self) {
+        // stop the DMA channel and flush any byte left in the I2C 
+        // transmit data register
+        let ch = self.channel.registers(&self.dma);
+        ch.cr.modify(|_, w| w.en().disabled());
+        self.i2c.isr.write(|w| w.txe().empty());
 
-        // ensure I2C is not mid transfer
-        while self.i2c.isr.read().txe().is_not_empty() {
-            // wait for I2C transmit data register to be empty
+        // the STOP condition follows a NACK in automatic end mode,
+        // then listen for the next NACK
+        if wait_for(BYTE_TIMEOUT_US, || self.i2c.isr.read().stopf().is_stop()).is_ok() {
+            self.i2c.icr.write(|w| w.stopcf().set_bit()
+                                   .nackcf().set_bit());
+            self.i2c.cr1.modify(|_, w| w.nackie().enabled());
+        } else {
+            self.reset_i2c();
         }
 
-        // configure the I2C peripheral for the transfer and start
-        // TODO: move "slave" address to be a tx parameter
-        self.i2c.cr2.modify(|_, w| w.sadd().bits(0b01111000)
-                                    .nbytes().bits(length as u8)
-                                    .autoend().set_bit()
-                                    .rd_wrn().clear_bit()
-                                    .start().set_bit());
+        self.tx_segment = None;
+        count(|stats| stats.nacks = stats.nacks.wrapping_add(1));
+        if self.retries < self.retry_limit {
+            self.retries += 1;
+            self.tx_index -= self.tx_length as usize;
+            log::debug!("i2c: NACK, retry {} of {}", self.retries, self.retry_limit);
+            count(|stats| stats.retries = stats.retries.wrapping_add(1));
+        } else if let Some(tx_data) = self.tx_data {
+            self.tx_index = tx_data.data.len();
+            report_error(TxError::Nack);
+        }
+    }
+
+    // Abort the transfer after a bus error or loss of arbitration, 
+    // and reset the I2C peripheral to release the bus
+    // Note: only called from DMA interrupt
+    fn handle_bus_error(&mut self) {
+        let error = bus_error(&self.i2c.isr.read()).unwrap_or(TxError::BusError);
+
+        let ch = self.channel.registers(&self.dma);
+        ch.cr.modify(|_, w| w.en().disabled());
+        self.reset_i2c();
+
+        self.tx_segment = None;
+        if let Some(tx_data) = self.tx_data {
+            self.tx_index = tx_data.data.len();
+        }
+        report_error(error);
+    }
+
+    // Abandon the current transfer and any queued behind it, stopping 
+    // the DMA channel and resetting the I2C peripheral to release the bus
+    // Note: only called from DMA interrupt
+    fn abort_transfer(&mut self) {
+        let ch = self.channel.registers(&self.dma);
+        ch.cr.modify(|_, w| w.en().disabled());
+        self.channel.clear_complete(&self.dma);
+
+        // end the transfer on the bus, so the device sees a STOP
+        // condition rather than the bus simply going idle
+        if self.i2c.isr.read().busy().bit_is_set() {
+            self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+            wait_for(BYTE_TIMEOUT_US, || self.i2c.isr.read().stopf().bit_is_set()).ok();
+        }
+        self.reset_i2c();
+        self.end_transfer();
+        DMA_I2C_QUEUE.clear();
+        async_tx::wake_waiter();
+    }
+
+    // Reset the transfer state once a transfer is complete or abandoned
+    fn end_transfer(&mut self) {
+        self.tx_data = None;
+        self.tx_index = 0;
+        self.tx_segment = None;
+        self.retries = 0;
+        self.paused = false;
+    }
 
+    // Reset the I2C peripheral, releasing the bus and clearing its flags.
+    // Note: a software reset leaves the configuration intact
+    fn reset_i2c(&mut self) {
+        self.i2c.cr1.modify(|_, w| w.pe().disabled());
+        wait_for(BYTE_TIMEOUT_US, || self.i2c.cr1.read().pe().is_disabled()).ok();
+
+        // the error flags aren't cleared by the reset
+        self.i2c.icr.write(|w| w.berrcf().set_bit()
+                               .arlocf().set_bit()
+                               .timoutcf().set_bit()
+                               .nackcf().set_bit()
+                               .stopcf().set_bit());
+
+        // restart, listening for the next NACK or error
+        self.i2c.cr1.modify(|_, w| w.nackie().enabled()
+                                    .errie().enabled()
+                                    .pe().enabled());
+    }
+
+    // Pend the interrupt of the DMA channel servicing the interface
+    fn pend_tx_interrupt() {
+        let interrupt = cortex_m::interrupt::free(|cs| DMA_I2C_INTERRUPT.borrow(cs).get());
+        if let Some(interrupt) = interrupt {
+            cortex_m::peripheral::NVIC::pend(interrupt);
+        }
+    }
+
+    // Queue some data for transmission if there's room, returning
+    // the number of the transmission
+    fn try_tx(address: u8, data: &'static [u8], tx_size: Option<usize>) -> Option<u32> {
+        // Get the tx_size
+        let tx_size = match tx_size {
+            Some(x) => x,
+            None => data.len(),
+        } as u8;
+
+        DMAi2c::try_tx_buffer(I2CBuffer { address, data, tx_size, headers: &[], header_size: 0 })
+    }
+
+    // Queue a transmission if there's room, returning its number
+    fn try_tx_buffer(buffer: I2CBuffer) -> Option<u32> {
+        // Queue the data ref for the DMA interrupt
+        let number = DMAi2c::queue_tx_buffer(buffer)?;
+
+        // trigger the DMA interrupt to begin tx
+        DMAi2c::pend_tx_interrupt();
+        Some(number)
+    }
+
+    // Determine if the transmission with the given number has ended
+    fn tx_ended(number: u32) -> bool {
+        DMA_I2C_QUEUE.has_ended(number)
+    }
+
+    // Take the interface for a bus transaction, pausing any transmission 
+    // in progress once its current block is complete
+    // Note: must be given back, and the DMA interrupt pended to resume!
+    fn acquire_between_blocks() -> Option<DMAi2c> {
+        let mut intf = DMAi2c::take_interface();
+        if intf.is_none() {
+            DMA_I2C_YIELD.store(true, Ordering::Release);
+            let acquired = wait_for(TX_TIMEOUT_US, || {
+                intf = DMAi2c::take_interface();
+                intf.is_some()
+            });
+            if acquired.is_err() && DMAi2c::abort_stuck().is_ok() {
+                intf = DMAi2c::take_interface();
+            }
+        }
+        DMA_I2C_YIELD.store(false, Ordering::Release);
+        intf
+    }
+
+    // Take the interface once any pending transmission is complete
+    // Note: must be given back!
+    fn acquire_interface() -> Option<DMAi2c> {
+        DMAi2c::wait_idle().ok()?;
+        DMAi2c::take_interface()
     }
 
     // Return the interface to the global mutex
@@ -126,7 +1005,11 @@ impl DMAi2c {
     //       it must be given back
     fn swap_interface(intf: &mut Option<DMAi2c>) {
         cortex_m::interrupt::free(|cs| {
-            mem::swap(intf, &mut DMA_I2C.borrow(cs).borrow_mut());
+            // Track whether a transmission is in progress for tx_in_progress
+            let incoming = intf.take();
+            let active = incoming.as_ref().is_none_or(|i2c| i2c.tx_data.is_some());
+            DMA_I2C_ACTIVE.store(active, Ordering::Release);
+            *intf = DMA_I2C.borrow(cs).replace(incoming);
         });
     }
 
@@ -138,22 +1021,27 @@ impl DMAi2c {
         intf
     }
 
-    // Set the tx buffer data in the global mutex
-    fn set_tx_buffer(data: &'static [u8], tx_size: u8) {
-        Self::swap_tx_buffer(&mut Some(I2CBuffer{data, tx_size}));
+    // Add a tx buffer to the global queue
+    fn queue_tx_buffer(buffer: I2CBuffer) -> Option<u32> {
+        DMA_I2C_QUEUE.push(buffer)
     }
 
-    // swap a tx buffer data with the global value
-    fn swap_tx_buffer(data: &mut Option<I2CBuffer>) {
-        cortex_m::interrupt::free(|cs| {
-            mem::swap(data, &mut DMA_I2C_BUFFER.borrow(cs).borrow_mut());
-        });
+    // Take the next tx buffer from the global queue
+    fn next_tx_buffer() -> Option<I2CBuffer> {
+        DMA_I2C_QUEUE.pop()
+    }
+
+    // Determine if the global queue has no room for another tx buffer
+    fn tx_queue_full() -> bool {
+        DMA_I2C_QUEUE.is_full()
     }
 
-    // Initialize the DMA peripheral for I2C transmissions
-    fn init_dma(dma: &mut DMA1) {
-        // configure DMA1 channel 2 for I2C transmissions
-        dma.ch2.cr.modify(|_, w| w.mem2mem().disabled()
+    // Initialize the DMA channel for I2C transmissions
+    fn init_dma(dma: &dma1::RegisterBlock, channel: DmaChannel, i2c: &i2c1::RegisterBlock) {
+        let ch = channel.registers(dma);
+
+        // configure the DMA channel for I2C transmissions
+        ch.cr.modify(|_, w| w.mem2mem().disabled()
                                   .pl().very_high()
                                   .msize().bits8()
                                   .psize().bits8()
@@ -165,80 +1053,371 @@ impl DMAi2c {
                                   .htie().disabled()
                                   .tcie().enabled());
 
-        // set peripheral address register to I2C1_TXDR
-        dma.ch2.par.write(|w| unsafe { w.bits(0x4000_5428) });
+        // set peripheral address register to the I2C TXDR register
+        let txdr = &i2c.txdr as *const _ as u32;
+        ch.par.write(|w| unsafe { w.bits(txdr) });
 
         // enable the dma peripheral
-        dma.ch2.cr.modify(|_, w| w.en().enabled());
+        ch.cr.modify(|_, w| w.en().enabled());
 
         // unmask the DMA transfer interrupt
         unsafe {
-            cortex_m::peripheral::NVIC::unmask(Interrupt::DMA1_CH2_3);
+            cortex_m::peripheral::NVIC::unmask(channel.interrupt());
         }
     }
 
     // Initialize the I2C peripheral for DMA transmissions
-    fn init_i2c(i2c: &mut I2C1) {
+    fn init_i2c(i2c: &i2c1::RegisterBlock, timing: Timing, timeout: u16) {
         // ensure i2c peripheral is disabled while changing configuration
         i2c.cr1.write(|w| w.pe().disabled());
         while i2c.cr1.read().pe().is_enabled() {
             // wait for i2c to be disabled
         }
 
-        // update the timing register for 400kHZ operation
-        i2c.timingr.write(|w| w.scll().bits(26)   // SCL low period
-                               .sclh().bits(20)   // SCL high period
-                               .sdadel().bits(0)  // SDA delay
-                               .scldel().bits(9)  // SCL delay
-                               .presc().bits(1)); // clock prescaler
+        // update the timing register for the bus speed
+        i2c.timingr.write(|w| w.scll().bits(timing.scll)     // SCL low period
+                               .sclh().bits(timing.sclh)     // SCL high period
+                               .sdadel().bits(timing.sdadel) // SDA delay
+                               .scldel().bits(timing.scldel) // SCL delay
+                               .presc().bits(timing.presc)); // clock prescaler
+
+        // time out a transfer when SCL is held low
+        // Note: the timeout can only be set while it's disabled
+        i2c.timeoutr.write(|w| w.timeouta().bits(timeout)
+                                .tidle().disabled());
+        i2c.timeoutr.modify(|_, w| w.timouten().enabled());
 
-        // enable DMA transmission requests and start the I2C peripheral
+        // enable DMA transmission requests, NACK and error interrupts, 
+        // and start the I2C peripheral
         i2c.cr1.write(|w| w.txdmaen().enabled()
+                           .nackie().enabled()
+                           .errie().enabled()
                            .pe().enabled());
     }
 }
 
 
-#[interrupt]
-fn DMA1_CH2_3() {
-    // DMA I2C interface
-    static mut I2C_INTERFACE: Option<DMAi2c> = None;
+impl DMAi2c {
+    /// Handle the interrupt of the DMA channel servicing the interface 
+    /// (DMA1_CH2_3 for I2C1, DMA1_CH4_5 for I2C2 or remapped I2C1). The
+    /// application binds this to the interrupt, e.g. as an RTIC hardware
+    /// task, along with a local interface slot, initially None, which holds
+    /// the interface for the duration of each transmission.
+    pub fn on_dma_interrupt(interface: &mut Option<DMAi2c>, interrupt: Interrupt) {
+        dma_tx_interrupt(interface, interrupt);
+    }
+
+    /// Handle the event interrupt of the I2C peripheral consumed by the 
+    /// interface (I2C1 or I2C2). The application binds this to the 
+    /// interrupt, at the same priority as the DMA channel interrupt.
+    #[allow(dead_code)]
+    pub fn on_i2c_interrupt(interrupt: Interrupt) {
+        match interrupt {
+            Interrupt::I2C1 => i2c_event_interrupt(I2C1::ptr()),
+            Interrupt::I2C2 => i2c_event_interrupt(I2C2::ptr()),
+            _ => (),
+        }
+    }
+}
 
+// Handle a DMA channel interrupt. The interface is held by the interrupt
+// handler of its channel for the duration of each transmission.
+fn dma_tx_interrupt(interface: &mut Option<DMAi2c>, interrupt: Interrupt) {
     // Take the DMA I2C interface if not already owned
-    if I2C_INTERFACE.is_none() {
-        *I2C_INTERFACE = DMAi2c::take_interface();
+    if interface.is_none() {
+        *interface = DMAi2c::take_interface();
+
+        // Give it back if it belongs to the other channel interrupt
+        if matches!(interface, Some(i2c) if i2c.channel.interrupt() != interrupt) {
+            DMAi2c::swap_interface(interface);
+            return;
+        }
+    }
+
+    // Abandon the transmission if it timed out
+    if DMA_I2C_ABORT.load(Ordering::Acquire) {
+        DMA_I2C_ABORT.store(false, Ordering::Release);
+        if let Some(i2c) = interface {
+            i2c.abort_transfer();
+            DMAi2c::swap_interface(interface);
+        }
+        return;
     }
 
     let mut tx_complete = false;
-    if let Some(i2c) = I2C_INTERFACE {
+    if let Some(i2c) = interface {
+        let isr = i2c.i2c.isr.read();
+        if bus_error(&isr).is_some() {
+            // The bus glitched, was taken by another controller, or was held low
+            i2c.handle_bus_error();
+        } else if i2c.tx_data.is_some() && isr.nackf().is_nack() {
+            // The device didn't acknowledge the current block
+            i2c.handle_nack();
+        } else if i2c.paused {
+            // Resume a transmission paused for a bus transaction
+            i2c.paused = false;
+        } else if i2c.tx_data.is_some() && !i2c.channel.is_complete(&i2c.dma) {
+            // Ignore interrupts from the channel sharing this interrupt
+            return;
+        }
+
         // clear interrupt flag
-        i2c.dma.ifcr.write(|w| w.ctcif2().set_bit());
+        i2c.channel.clear_complete(&i2c.dma);
 
-        // Get the data if not already acquired
-        if i2c.tx_data.is_none() {
-            DMAi2c::swap_tx_buffer(&mut i2c.tx_data);
+        // Follow a block's header with its data, within the same transfer
+        if let Some((address, length)) = i2c.tx_segment.take() {
+            if let Err(error) = i2c.tx_dma_addr_len(address, length) {
+                report_error(error);
+                i2c.abort_transfer();
+                DMAi2c::swap_interface(interface);
+            }
+            return;
         }
 
-        // TX any untransmitted data
-        match i2c.tx_data {
-            Some(tx_data) if i2c.tx_index < tx_data.data.len() => {
-                // TX next block of data
-                let transmission_address = tx_data.data.as_ptr() as u32 + i2c.tx_index as u32;
-                let transmission_length = cmp::min(tx_data.data.len() - i2c.tx_index, tx_data.tx_size as usize) as u8;
-                i2c.tx_data_addr_len(transmission_address, transmission_length);
-                i2c.tx_index += transmission_length as usize;
-            },
-            _ => { 
-                // TX complete, reset the tx data
-                tx_complete = true;
-                i2c.tx_data = None;
-                i2c.tx_index = 0;
-            },
+        // Pause between blocks for a waiting bus transaction
+        if DMA_I2C_YIELD.load(Ordering::Acquire) && i2c.tx_data.is_some() {
+            DMA_I2C_YIELD.store(false, Ordering::Release);
+            i2c.paused = true;
+            DMAi2c::swap_interface(interface);
+            return;
+        }
+
+        loop {
+            // Get the next queued data if not already acquired
+            if i2c.tx_data.is_none() {
+                i2c.tx_data = DMAi2c::next_tx_buffer();
+            }
+
+            // TX any untransmitted data
+            match i2c.tx_data {
+                Some(tx_data) if i2c.tx_index < tx_data.data.len() => {
+                    // TX next block of data
+                    let transmission_address = tx_data.data.as_ptr() as u32 + i2c.tx_index as u32;
+                    let transmission_length = cmp::min(tx_data.data.len() - i2c.tx_index, tx_data.tx_size as usize) as u8;
+                    let header = tx_data.header(i2c.tx_index / tx_data.tx_size as usize);
+                    match i2c.tx_data_addr_len(tx_data.address, header, transmission_address, transmission_length) {
+                        Ok(()) => {
+                            i2c.tx_index += transmission_length as usize;
+                            i2c.tx_length = transmission_length;
+                            let bytes = header.len() as u32 + transmission_length as u32;
+                            count(|stats| stats.bytes = stats.bytes.wrapping_add(bytes));
+                        },
+                        Err(error) => {
+                            // The bus is stuck, abandon the transmissions
+                            report_error(error);
+                            i2c.abort_transfer();
+                            tx_complete = true;
+                        },
+                    }
+                    break;
+                },
+                Some(tx_data) => {
+                    // TX complete, move on to the next queued transmission
+                    i2c.end_transfer();
+                    notify_complete(tx_data.address);
+                },
+                None => {
+                    // The queue is empty
+                    tx_complete = true;
+                    break;
+                },
+            }
         }
     }
 
-    // When the transmission is complete, return the DMA I2C interface
+    // When all transmissions are complete, return the DMA I2C interface
     if tx_complete {
-        DMAi2c::swap_interface(I2C_INTERFACE);
+        DMAi2c::swap_interface(interface);
+    }
+}
+
+
+// Handle an I2C event interrupt. NACKs and bus errors are handed to the
+// DMA channel interrupt, which holds the interface for the failed transfer.
+fn i2c_event_interrupt(i2c: *const i2c1::RegisterBlock) {
+    let i2c = Registers(i2c);
+    let isr = i2c.isr.read();
+    if bus_error(&isr).is_some() {
+        // Mask errors until handled, so this interrupt doesn't repeat
+        i2c.cr1.modify(|_, w| w.errie().disabled());
+        DMAi2c::pend_tx_interrupt();
+    } else if isr.nackf().is_nack() {
+        // Mask NACKs until handled, so this interrupt doesn't repeat
+        i2c.cr1.modify(|_, w| w.nackie().disabled());
+        DMAi2c::pend_tx_interrupt();
     }
 }
+
+// The error flagged by the I2C peripheral, other than a NACK, if any
+fn bus_error(isr: &i2c1::isr::R) -> Option<TxError> {
+    if isr.arlo().is_lost() {
+        Some(TxError::ArbitrationLost)
+    } else if isr.timeout().is_timeout() {
+        Some(TxError::Timeout)
+    } else if isr.berr().is_error() {
+        Some(TxError::BusError)
+    } else {
+        None
+    }
+}
+
+// Count a transmission as ended, and notify anything waiting on it
+fn notify_complete(address: u8) {
+    count(|stats| stats.transmissions = stats.transmissions.wrapping_add(1));
+    DMA_I2C_QUEUE.end();
+    async_tx::wake_waiter();
+
+    // Call the completion hook, if any
+    let hook = cortex_m::interrupt::free(|cs| DMA_I2C_COMPLETION_HOOK.borrow(cs).get());
+    if let Some(hook) = hook {
+        hook(address);
+    }
+}
+
+// Record an error for take_error, replacing any earlier error
+fn report_error(error: TxError) {
+    log::warn!("i2c: transfer abandoned, {}", error);
+    cortex_m::interrupt::free(|cs| DMA_I2C_ERROR.borrow(cs).set(Some(error)));
+    match error {
+        TxError::BusError | TxError::ArbitrationLost => count(|stats| stats.bus_errors = stats.bus_errors.wrapping_add(1)),
+        TxError::Timeout => count(|stats| stats.timeouts = stats.timeouts.wrapping_add(1)),
+        TxError::Nack => {},
+    }
+}
+
+// Update the interface's counters
+fn count(update: impl FnOnce(&mut Stats)) {
+    cortex_m::interrupt::free(|cs| {
+        let mut stats = DMA_I2C_STATS.borrow(cs).get();
+        update(&mut stats);
+        DMA_I2C_STATS.borrow(cs).set(stats);
+    });
+}
+
+// Sleep until the condition holds, or the timeout expires, waking on each
+// interrupt to check it, rather than spinning at full power. The DMA and
+// I2C interrupts fire as each transmission ends or fails, and SysTick's,
+// the scheduler's time base, every tick, so the waiter's woken to count
+// down its deadline even if nothing else happens. Waits that can't rely
+// on those interrupts busy-wait instead: they're not set up until the
+// interface is initialized, they can only wake a waiter they would
+// preempt, and SysTick only interrupts once it's counting with TICKINT
+// set, which it isn't while a HAL Delay or the bench polls it.
+fn sleep_for(timeout_us: u32, mut ready: impl FnMut() -> bool) -> Result<(), TxError> {
+    let interrupt = cortex_m::interrupt::free(|cs| DMA_I2C_INTERRUPT.borrow(cs).get());
+    let wakes = interrupt.is_some_and(|interrupt| can_preempt(NVIC::get_priority(interrupt)))
+        && can_preempt(SCB::get_priority(SystemHandler::SysTick))
+        && systick_interrupts();
+    if !wakes {
+        return wait_for(timeout_us, ready);
+    }
+
+    // Check the condition with interrupts disabled, so an interrupt that
+    // satisfies it can't slip in before the WFI. A pending interrupt
+    // still wakes the WFI, and is serviced once interrupts are enabled.
+    let mut deadline = Deadline::new(timeout_cycles(timeout_us));
+    while !cortex_m::interrupt::free(|_| {
+        let done = ready();
+        if !done {
+            cortex_m::asm::wfi();
+        }
+        done
+    }) {
+        if deadline.passed() {
+            return Err(TxError::Timeout);
+        }
+    }
+    Ok(())
+}
+
+// Whether SysTick's counting and interrupting each tick. Reading its
+// control register clears COUNTFLAG, which the monotonic reads in its
+// interrupt to count wraps, so it's only read when no wrap can be
+// waiting on it: the interrupt's not pending and the counter's well
+// clear of reloading. Otherwise it's assumed not to, and that wait
+// busy-waits.
+fn systick_interrupts() -> bool {
+    const ENABLE: u32 = 1 << 0;
+    const TICKINT: u32 = 1 << 1;
+    const MARGIN: u32 = 64;
+    cortex_m::interrupt::free(|_| {
+        let current = SYST::get_current();
+        if SCB::is_pendst_pending() || current < MARGIN || current + MARGIN > SYST::get_reload() {
+            return false;
+        }
+        // SAFETY: a read, with no wrap for COUNTFLAG to have recorded
+        let csr = unsafe { (*SYST::PTR).csr.read() };
+        csr & (ENABLE | TICKINT) == ENABLE | TICKINT
+    })
+}
+
+// A deadline in CPU cycles, counted down by SysTick's current value as
+// it counts down to each reload. It must be checked at least once a
+// tick, so no more than one reload passes between checks.
+struct Deadline {
+    last: u32,
+    remaining: u32,
+}
+
+impl Deadline {
+    fn new(timeout_cycles: u32) -> Self {
+        Self { last: SYST::get_current(), remaining: timeout_cycles }
+    }
+
+    // Count the cycles since the last check, returning true once the
+    // deadline's passed
+    fn passed(&mut self) -> bool {
+        let now = SYST::get_current();
+        let elapsed = match now <= self.last {
+            true => self.last - now,
+            false => self.last + SYST::get_reload() + 1 - now,
+        };
+        self.last = now;
+        self.remaining = self.remaining.saturating_sub(elapsed);
+        self.remaining == 0
+    }
+}
+
+// Determine if an interrupt of the given priority would preempt the
+// running code, and so wake it from WFI: always from thread mode, and
+// from an interrupt handler (e.g. an RTIC software task) of a lower
+// priority. Other exceptions are assumed not to be preempted.
+fn can_preempt(priority: u8) -> bool {
+    // An interrupt identified by its number, as reported by the SCB
+    #[derive(Copy, Clone)]
+    struct Irq(u16);
+
+    // SAFETY: the number is that of the active interrupt
+    unsafe impl InterruptNumber for Irq {
+        fn number(self) -> u16 {
+            self.0
+        }
+    }
+
+    match SCB::vect_active() {
+        VectActive::ThreadMode => true,
+        // lower values are higher priorities
+        VectActive::Interrupt { irqn } => priority < NVIC::get_priority(Irq(irqn as u16)),
+        VectActive::Exception(_) => false,
+    }
+}
+
+// Busy-wait until the condition holds, or the timeout expires, 
+// so a hung bus can't freeze the whole application
+fn wait_for(timeout_us: u32, mut ready: impl FnMut() -> bool) -> Result<(), TxError> {
+    let mut polls = timeout_cycles(timeout_us) / POLL_CYCLES;
+    while !ready() {
+        if polls == 0 {
+            return Err(TxError::Timeout);
+        }
+        polls -= 1;
+        cortex_m::asm::delay(POLL_CYCLES);
+    }
+    Ok(())
+}
+
+// Convert a timeout to CPU cycles at the system clock's frequency, which
+// changes as the clock's scaled
+fn timeout_cycles(timeout_us: u32) -> u32 {
+    timeout_us * (board::sysclk_hz() / 1_000_000)
+}
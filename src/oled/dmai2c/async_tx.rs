@@ -0,0 +1,110 @@
+use core::{cell::Cell, future::Future, pin::Pin, task::{Context, Poll, Waker}};
+use cortex_m::interrupt::Mutex;
+use super::DMAi2c;
+
+
+// The waker of the task awaiting a transmission, woken by the DMA interrupt
+static TX_WAKER: Mutex<Cell<Option<Waker>>> = Mutex::new(Cell::new(None));
+
+
+/// A future that queues a transmission once there's room in the queue,
+/// and resolves once the transmission has ended.
+#[must_use = "futures do nothing unless awaited"]
+pub struct TxFuture {
+    address: u8,
+    data: &'static [u8],
+    tx_size: Option<usize>,
+    number: Option<u32>,
+}
+
+impl Future for TxFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        // Register before checking, so a wake between the two isn't lost
+        register_waker(cx.waker());
+
+        if this.number.is_none() {
+            this.number = DMAi2c::try_tx(this.address, this.data, this.tx_size);
+        }
+        match this.number {
+            Some(number) if DMAi2c::tx_ended(number) => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+
+/// A future that resolves once all queued transmissions have ended
+#[must_use = "futures do nothing unless awaited"]
+pub struct IdleFuture {
+    _private: (),
+}
+
+impl Future for IdleFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Register before checking, so a wake between the two isn't lost
+        register_waker(cx.waker());
+
+        match DMAi2c::tx_in_progress() {
+            false => Poll::Ready(()),
+            true => Poll::Pending,
+        }
+    }
+}
+
+
+impl DMAi2c {
+    /// Transmit some data to the device with the given 7-bit address from
+    /// an async executor. The returned future resolves when the DMA interrupt
+    /// ends the transmission, so the CPU can sleep in the meantime. Failures
+    /// are reported by take_error, as with tx.
+    #[allow(dead_code)]
+    pub fn tx_async(address: u8, data: &'static [u8], tx_size: Option<usize>) -> TxFuture {
+        TxFuture {
+            address,
+            data,
+            tx_size,
+            number: None,
+        }
+    }
+
+    /// Wait from an async executor for all transmissions to end, e.g. 
+    /// for a frame to finish before drawing the next. The CPU can sleep
+    /// in the meantime, as with tx_async.
+    #[allow(dead_code)]
+    pub fn idle_async() -> IdleFuture {
+        IdleFuture { _private: () }
+    }
+}
+
+
+// Store the waker of the polling task. A different task's waker is 
+// woken as it's replaced, so that task polls again and re-registers.
+fn register_waker(waker: &Waker) {
+    let replaced = cortex_m::interrupt::free(|cs| {
+        let stored = TX_WAKER.borrow(cs).take();
+        match stored {
+            Some(current) if current.will_wake(waker) => {
+                TX_WAKER.borrow(cs).set(Some(current));
+                None
+            },
+            _ => TX_WAKER.borrow(cs).replace(Some(waker.clone())),
+        }
+    });
+    if let Some(replaced) = replaced {
+        replaced.wake();
+    }
+}
+
+// Wake the task awaiting a transmission, if any
+pub(super) fn wake_waiter() {
+    let waker = cortex_m::interrupt::free(|cs| TX_WAKER.borrow(cs).take());
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
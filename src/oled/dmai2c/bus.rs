@@ -0,0 +1,190 @@
+use core::cmp;
+use embedded_hal_1::i2c::{self, ErrorKind, ErrorType, NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress};
+use stm32f0xx_hal::pac::i2c1::isr;
+use super::{bus_error, wait_for, Address, DMAi2c, TxError, BYTE_TIMEOUT_US};
+
+
+// The largest number of bytes the I2C peripheral counts at once
+const MAX_NBYTES: usize = 255;
+
+
+/// A handle to the I2C bus of the DMAi2c interface, implementing the
+/// embedded-hal I2C trait so driver crates can share the display's bus.
+/// Transactions take priority over DMA transmissions: a transmission in 
+/// progress is paused at the end of its current block (e.g. a frame page),
+/// and resumed once the transaction's bytes are transferred by the CPU.
+/// Any number of handles may exist; their transactions are serialized.
+/// Both 7-bit and 10-bit device addresses are supported.
+pub struct I2cBus {
+    _private: (),
+}
+
+impl DMAi2c {
+    /// Create a handle to the I2C bus, for use with embedded-hal drivers
+    #[allow(dead_code)]
+    pub fn bus() -> I2cBus {
+        I2cBus { _private: () }
+    }
+
+    // Perform a transaction by polling, with the DMA requests and the
+    // NACK and error interrupts disabled for its duration
+    fn polled_transaction(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), TxError> {
+        self.i2c.cr1.modify(|_, w| w.txdmaen().disabled()
+                                    .nackie().disabled()
+                                    .errie().disabled());
+
+        let result = self.polled_operations(address, operations);
+
+        // End the transaction with a STOP condition (a NACK generates its own),
+        // or reset the peripheral to release the bus after any other error
+        let stopped = match result {
+            _ if operations.is_empty() => Ok(()),
+            Ok(()) | Err(TxError::Nack) => {
+                if result.is_ok() {
+                    self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+                }
+                wait_for(BYTE_TIMEOUT_US, || self.i2c.isr.read().stopf().is_stop())
+            },
+            Err(error) => Err(error),
+        };
+        match stopped {
+            Ok(()) => self.i2c.icr.write(|w| w.stopcf().set_bit()
+                                              .nackcf().set_bit()),
+            Err(_) => self.reset_i2c(),
+        }
+
+        self.i2c.cr1.modify(|_, w| w.txdmaen().enabled()
+                                    .nackie().enabled()
+                                    .errie().enabled());
+        result
+    }
+
+    // Transfer each run of operations in the same direction as a single 
+    // transfer, with a repeated START between runs
+    fn polled_operations(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), TxError> {
+        let mut start = 0;
+        while start < operations.len() {
+            let read = matches!(operations[start], Operation::Read(_));
+            let mut end = start + 1;
+            while end < operations.len() && matches!(operations[end], Operation::Read(_)) == read {
+                end += 1;
+            }
+            let run = &mut operations[start..end];
+
+            // Start (or restart) the transfer
+            // Note: a restart to read from a 10-bit address repeats the 
+            //       full address, as HEAD10R is cleared
+            let mut remaining: usize = run.iter().map(|op| match op {
+                Operation::Read(bytes) => bytes.len(),
+                Operation::Write(bytes) => bytes.len(),
+            }).sum();
+            let mut counted = cmp::min(remaining, MAX_NBYTES);
+            self.i2c.cr2.modify(|_, w| address.configure(w)
+                                        .nbytes().bits(counted as u8)
+                                        .reload().bit(remaining > MAX_NBYTES)
+                                        .autoend().clear_bit()
+                                        .rd_wrn().bit(read)
+                                        .start().set_bit());
+
+            for op in run.iter_mut() {
+                match op {
+                    Operation::Write(bytes) => for byte in bytes.iter() {
+                        if counted == 0 {
+                            counted = self.reload(remaining)?;
+                        }
+                        self.wait_isr(|isr| isr.txis().is_empty())?;
+                        self.i2c.txdr.write(|w| w.txdata().bits(*byte));
+                        counted -= 1;
+                        remaining -= 1;
+                    },
+                    Operation::Read(bytes) => for byte in bytes.iter_mut() {
+                        if counted == 0 {
+                            counted = self.reload(remaining)?;
+                        }
+                        self.wait_isr(|isr| isr.rxne().is_not_empty())?;
+                        *byte = self.i2c.rxdr.read().rxdata().bits();
+                        counted -= 1;
+                        remaining -= 1;
+                    },
+                }
+            }
+
+            // Wait for the transfer to complete before a restart or stop
+            self.wait_isr(|isr| isr.tc().is_complete())?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    // Count the next bytes of a transfer longer than NBYTES can hold, 
+    // returning how many were counted
+    fn reload(&mut self, remaining: usize) -> Result<usize, TxError> {
+        self.wait_isr(|isr| isr.tcr().is_complete())?;
+        let counted = cmp::min(remaining, MAX_NBYTES);
+        self.i2c.cr2.modify(|_, w| w.nbytes().bits(counted as u8)
+                                    .reload().bit(remaining > MAX_NBYTES));
+        Ok(counted)
+    }
+
+    // Wait for a flag during a polled transaction, stopping early 
+    // on a NACK, bus error, loss of arbitration or bus timeout
+    fn wait_isr(&self, flag: impl Fn(&isr::R) -> bool) -> Result<(), TxError> {
+        let mut isr = self.i2c.isr.read();
+        wait_for(BYTE_TIMEOUT_US, || {
+            isr = self.i2c.isr.read();
+            flag(&isr) || isr.nackf().is_nack() || bus_error(&isr).is_some()
+        })?;
+
+        if let Some(error) = bus_error(&isr) {
+            Err(error)
+        } else if isr.nackf().is_nack() {
+            Err(TxError::Nack)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+impl i2c::Error for TxError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            TxError::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            TxError::BusError => ErrorKind::Bus,
+            TxError::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            TxError::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for I2cBus {
+    type Error = TxError;
+}
+
+impl i2c::I2c<SevenBitAddress> for I2cBus {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), TxError> {
+        self.address_transaction(Address::SevenBit(address), operations)
+    }
+}
+
+impl i2c::I2c<TenBitAddress> for I2cBus {
+    fn transaction(&mut self, address: u16, operations: &mut [Operation<'_>]) -> Result<(), TxError> {
+        self.address_transaction(Address::TenBit(address), operations)
+    }
+}
+
+impl I2cBus {
+    // Perform a transaction with a device at the given address
+    fn address_transaction(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), TxError> {
+        let mut intf = DMAi2c::acquire_between_blocks();
+        let result = match &mut intf {
+            Some(i2c) => i2c.polled_transaction(address, operations),
+            None => Err(TxError::Timeout),
+        };
+        DMAi2c::swap_interface(&mut intf);
+
+        // Resume any paused transmission, or start any queued meanwhile
+        DMAi2c::pend_tx_interrupt();
+        result
+    }
+}
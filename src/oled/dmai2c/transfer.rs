@@ -0,0 +1,144 @@
+use core::ops::Range;
+use super::DMAi2c;
+
+
+/// A buffer that can be lent to the DMA interrupt for a transmission.
+/// # Safety
+/// The bytes must stay at the same address, and must not be modified
+/// other than through the buffer itself, for as long as it exists.
+pub unsafe trait TxBuffer: 'static {
+    fn as_bytes(&self) -> &[u8];
+}
+
+unsafe impl TxBuffer for &'static [u8] {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+unsafe impl TxBuffer for &'static mut [u8] {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+unsafe impl<const N: usize> TxBuffer for &'static [u8; N] {
+    fn as_bytes(&self) -> &[u8] {
+        *self
+    }
+}
+
+unsafe impl<const N: usize> TxBuffer for &'static mut [u8; N] {
+    fn as_bytes(&self) -> &[u8] {
+        *self
+    }
+}
+
+
+/// An owned buffer lent for transmission. The buffer is handed back by
+/// wait once the transmission is complete, so a mutable buffer can be
+/// refilled and transmitted again without any static muts. A buffer
+/// that's transmitted over and over, e.g. a frame, can instead be kept
+/// in its Transfer, borrowed back with get_mut to refill, and lent again
+/// with DMAi2c::tx_gather_lent.
+#[must_use]
+pub struct Transfer<B: TxBuffer> {
+    buffer: B,
+    // Whether the buffer's been lent since it was last waited for
+    lent: bool,
+}
+
+#[allow(dead_code)]
+impl<B: TxBuffer> Transfer<B> {
+    /// Hold a buffer to lend for transmissions, not yet transmitting
+    pub fn new(buffer: B) -> Self {
+        Self { buffer, lent: false }
+    }
+
+    /// Determine if the interface has finished transmitting
+    pub fn is_done(&self) -> bool {
+        !self.lent || !DMAi2c::tx_in_progress()
+    }
+
+    /// Wait for the transmission to complete (or be aborted on timeout),
+    /// and take back the buffer
+    pub fn wait(mut self) -> B {
+        self.get_mut();
+        self.buffer
+    }
+
+    /// The buffer, to read, which it can be while it's transmitted
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Wait for the transmission, if any, to complete (or be aborted on
+    /// timeout), and borrow back the buffer, e.g. to refill it
+    pub fn get_mut(&mut self) -> &mut B {
+        if self.lent {
+            DMAi2c::wait_idle().ok();
+            self.lent = false;
+        }
+        &mut self.buffer
+    }
+
+    // Lend the buffer's bytes for a transmission, until it's waited for
+    fn lend(&mut self) -> &'static [u8] {
+        self.lent = true;
+        // SAFETY: TxBuffer guarantees the bytes are neither moved nor 
+        //         modified, other than through the buffer, while it exists,
+        //         and the Transfer only gives it back, or lends it mutably,
+        //         once the transmission is complete (or it's leaked).
+        let data = self.buffer.as_bytes();
+        unsafe { core::slice::from_raw_parts(data.as_ptr(), data.len()) }
+    }
+}
+
+
+// Waits for the interface to be idle when dropped, so a borrowed 
+// buffer outlives the transmission even if the scope is left early
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        DMAi2c::wait_idle().ok();
+    }
+}
+
+
+impl DMAi2c {
+    /// Transmit an owned buffer to the device with the given 7-bit 
+    /// address, returning a handle that gives the buffer back once
+    /// the transmission is complete.
+    #[allow(dead_code)]
+    pub fn tx_owned<B: TxBuffer>(address: u8, buffer: B, tx_size: Option<usize>) -> Transfer<B> {
+        let mut transfer = Transfer::new(buffer);
+        DMAi2c::tx(address, transfer.lend(), tx_size);
+        transfer
+    }
+
+    /// Transmit the given range of the buffer held by a Transfer as 
+    /// tx_gather does, lending it until the transmission is complete,
+    /// e.g. to send a frame buffer again once it's been redrawn
+    pub fn tx_gather_lent<B: TxBuffer>(address: u8, headers: &'static [u8], header_size: usize, transfer: &mut Transfer<B>, range: Range<usize>, tx_size: usize) {
+        DMAi2c::tx_gather(address, headers, header_size, &transfer.lend()[range], tx_size);
+    }
+
+    /// Transmit a buffer that isn't 'static, e.g. one on the stack, to the
+    /// device with the given 7-bit address while running `work`. This waits
+    /// for the transmission to complete (or be aborted) before returning, 
+    /// so the buffer is never released while it's being transmitted.
+    /// Note: must not be called from an interrupt that preempts the DMA
+    ///       interrupt, which could then never complete the transmission.
+    #[allow(dead_code)]
+    pub fn tx_scoped<R>(address: u8, data: &[u8], tx_size: Option<usize>, work: impl FnOnce() -> R) -> R {
+        // SAFETY: the guard waits for the transmission to end before 
+        //         the borrow of data ends, on every path out of this scope
+        let _guard = ScopeGuard;
+        let data: &'static [u8] = unsafe {
+            core::slice::from_raw_parts(data.as_ptr(), data.len())
+        };
+        DMAi2c::tx(address, data, tx_size);
+        work()
+    }
+}
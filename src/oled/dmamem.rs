@@ -0,0 +1,106 @@
+use stm32f0xx_hal::pac::{dma1, DMA1};
+use super::dmai2c::{DmaChannel, Registers};
+
+
+// The source of DMA clears, which must be readable by the DMA
+static ZERO: u32 = 0;
+
+
+/// A spare DMA channel for clearing and copying memory in the background,
+/// e.g. clearing the frame buffer while the CPU steps the simulation.
+/// Transfers are word sized where the buffers allow, and run at the lowest
+/// priority so they don't hold up I2C transmissions.
+pub struct DmaMem {
+    dma: Registers<dma1::RegisterBlock>,
+    channel: DmaChannel,
+}
+
+#[allow(dead_code)]
+impl DmaMem {
+    /// Use the given DMA1 channel for memory operations. This must not be
+    /// the channel servicing the DMAi2c interface (see DMAi2c::channel).
+    pub fn new(dma: &mut DMA1, channel: DmaChannel) -> Self {
+        let dma = Registers(&**dma as *const dma1::RegisterBlock);
+        channel.registers(&dma).cr.modify(|_, w| w.en().disabled());
+        channel.clear_complete(&dma);
+        DmaMem { dma, channel }
+    }
+
+    /// Start setting every byte of dest to zero.
+    /// # Safety
+    /// dest must not be accessed, or reused, until the operation is
+    /// complete (see is_busy and wait).
+    pub unsafe fn start_clear(&mut self, dest: &mut [u8]) {
+        self.start(&ZERO as *const u32 as u32, false, dest.as_mut_ptr() as u32, dest.len());
+    }
+
+    /// Start copying src into dest, which must be the same length.
+    /// # Safety
+    /// dest must not be accessed, and src must not be modified or reused,
+    /// until the operation is complete (see is_busy and wait).
+    pub unsafe fn start_copy(&mut self, src: &[u8], dest: &mut [u8]) {
+        let len = core::cmp::min(src.len(), dest.len());
+        self.start(src.as_ptr() as u32, true, dest.as_mut_ptr() as u32, len);
+    }
+
+    /// Determine if an operation is in progress
+    pub fn is_busy(&self) -> bool {
+        let ch = self.channel.registers(&self.dma);
+        ch.cr.read().en().is_enabled() && !self.channel.is_complete(&self.dma)
+    }
+
+    /// Wait for any operation in progress to complete
+    pub fn wait(&mut self) {
+        while self.is_busy() {
+            // the longest operation is a few hundred bus cycles
+        }
+        self.channel.registers(&self.dma).cr.modify(|_, w| w.en().disabled());
+        self.channel.clear_complete(&self.dma);
+    }
+
+    // Start a transfer of len bytes, reading from the source (incrementing
+    // or not) and writing to the destination
+    fn start(&mut self, source: u32, source_inc: bool, dest: u32, len: usize) {
+        self.wait();
+
+        // transfer words when everything is word aligned
+        let aligned = |value: u32| value.is_multiple_of(4);
+        let words = (!source_inc || aligned(source)) && aligned(dest) && aligned(len as u32);
+        let count = if words { len / 4 } else { len };
+        if count == 0 {
+            return;
+        }
+
+        // In memory-to-memory mode, the peripheral address is the source
+        let ch = self.channel.registers(&self.dma);
+        ch.par.write(|w| unsafe { w.bits(source) });
+        ch.mar.write(|w| unsafe { w.bits(dest) });
+        ch.ndtr.write(|w| unsafe { w.bits(count as u32) });
+        ch.cr.write(|w| {
+            let w = w.mem2mem().enabled()
+                     .pl().low()
+                     .pinc().bit(source_inc)
+                     .minc().enabled()
+                     .circ().disabled()
+                     .dir().from_peripheral()
+                     .teie().disabled()
+                     .htie().disabled()
+                     .tcie().disabled();
+            match words {
+                true => w.psize().bits32().msize().bits32(),
+                false => w.psize().bits8().msize().bits8(),
+            }
+        });
+
+        // the buffers must be written before the DMA reads them
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        ch.cr.modify(|_, w| w.en().enabled());
+    }
+}
+
+impl Drop for DmaMem {
+    fn drop(&mut self) {
+        // the buffers of an operation in progress are only safe to reuse once it completes
+        self.wait();
+    }
+}
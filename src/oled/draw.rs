@@ -0,0 +1,177 @@
+use super::{OLEDDriver, OLED_COLS, OLED_PXLS_X, OLED_PXLS_Y};
+use super::transport::Transport;
+
+
+/// A rectangular region of the display that drawing is limited to,
+/// described by its inclusive corner coordinates
+#[derive(Copy, Clone)]
+pub(super) struct ClipRect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl ClipRect {
+    pub(super) const FULL: ClipRect = ClipRect {
+        x0: 0,
+        y0: 0,
+        x1: OLED_PXLS_X - 1,
+        y1: OLED_PXLS_Y - 1,
+    };
+
+    pub(super) fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x0..=self.x1).contains(&x) && (self.y0..=self.y1).contains(&y)
+    }
+    // Get the part of the rectangle between the given inclusive 
+    // corners that lies within this one, if any
+    fn intersect(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Option<ClipRect> {
+        let x0 = core::cmp::max(x0, self.x0 as i32);
+        let y0 = core::cmp::max(y0, self.y0 as i32);
+        let x1 = core::cmp::min(x1, self.x1 as i32);
+        let y1 = core::cmp::min(y1, self.y1 as i32);
+        match x0 <= x1 && y0 <= y1 {
+            true => Some(ClipRect { 
+                x0: x0 as usize, 
+                y0: y0 as usize, 
+                x1: x1 as usize, 
+                y1: y1 as usize,
+            }),
+            false => None,
+        }
+    }
+}
+
+/// Drawing primitives built on top of the pixel interface. Coordinates are
+/// signed so that shapes may extend past the edges of the display; any 
+/// pixels that fall outside of the display are skipped.
+impl<T: Transport> OLEDDriver<T> {
+    /// Limit all drawing to the rectangle between the given inclusive
+    /// corners, until the clipping region is changed or reset. 
+    /// Note: whole-frame and page operations such as clear and
+    ///       scroll_page_left are not clipped.
+    #[allow(dead_code)]
+    pub fn set_clip_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let clamp_x = |x: i32| x.clamp(0, OLED_PXLS_X as i32 - 1) as usize;
+        let clamp_y = |y: i32| y.clamp(0, OLED_PXLS_Y as i32 - 1) as usize;
+        self.clip = ClipRect {
+            x0: clamp_x(core::cmp::min(x0, x1)),
+            y0: clamp_y(core::cmp::min(y0, y1)),
+            x1: clamp_x(core::cmp::max(x0, x1)),
+            y1: clamp_y(core::cmp::max(y0, y1)),
+        };
+    }
+
+    /// Allow drawing on the entire display
+    #[allow(dead_code)]
+    pub fn reset_clip(&mut self) {
+        self.clip = ClipRect::FULL;
+    }
+
+    /// Draw a straight line between two points, using a square
+    /// brush that is the given number of pixels wide
+    #[allow(dead_code)]
+    pub fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), width: u32) {
+        // Bresenham's line algorithm
+        let (mut x, mut y) = from;
+        let dx = (to.0 - x).abs();
+        let dy = -(to.1 - y).abs();
+        let sx = if x < to.0 { 1 } else { -1 };
+        let sy = if y < to.1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.draw_brush(x, y, width);
+            if x == to.0 && y == to.1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draw connected line segments through each of the given points
+    #[allow(dead_code)]
+    pub fn draw_polyline(&mut self, points: &[(i32, i32)], width: u32) {
+        match points {
+            [point] => self.draw_brush(point.0, point.1, width),
+            _ => {
+                for segment in points.windows(2) {
+                    self.draw_line(segment[0], segment[1], width);
+                }
+            }
+        }
+    }
+
+    /// Draw the one pixel outline of a rectangle with its top left corner at (x, y)
+    #[allow(dead_code)]
+    pub fn draw_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        if w > 0 && h > 0 {
+            let (x1, y1) = (x + w - 1, y + h - 1);
+            self.fill_span(x, x1, y, true);
+            self.fill_span(x, x1, y1, true);
+            self.fill_area(x, y, x, y1, true);
+            self.fill_area(x1, y, x1, y1, true);
+        }
+    }
+
+    /// Set every pixel of a rectangle with its top left corner at (x, y)
+    #[allow(dead_code)]
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, on: bool) {
+        self.fill_area(x, y, x + w - 1, y + h - 1, on);
+    }
+
+    /// Set every pixel of a row between the inclusive x coordinates
+    #[allow(dead_code)]
+    pub fn fill_span(&mut self, x0: i32, x1: i32, y: i32, on: bool) {
+        self.fill_area(x0, y, x1, y, on);
+    }
+
+    // Set every pixel between the inclusive corners of a rectangle. 
+    // Each page the rectangle covers is filled by masking whole bytes,
+    // rather than setting pixels one at a time.
+    fn fill_area(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, on: bool) {
+        let area = match self.clip.intersect(x0, y0, x1, y1) {
+            Some(area) => area,
+            None => return,
+        };
+        let buffer = self.get_buffer();
+        for page in (area.y0 / 8)..=(area.y1 / 8) {
+            // mask off the rows of the page that are outside of the area
+            let top = core::cmp::max(area.y0, page * 8) % 8;
+            let bottom = core::cmp::min(area.y1, page * 8 + 7) % 8;
+            let mask = (0xFF_u8 << top) & (0xFF_u8 >> (7 - bottom));
+
+            let start = page * OLED_COLS;
+            for byte in &mut buffer[(start + area.x0)..=(start + area.x1)] {
+                if on {
+                    *byte |= mask;
+                }
+                else {
+                    *byte &= !mask;
+                }
+            }
+        }
+    }
+
+    // Turn on a square of pixels of the given width centered on a point
+    fn draw_brush(&mut self, x: i32, y: i32, width: u32) {
+        let width = core::cmp::max(width, 1) as i32;
+        let start = -(width - 1) / 2;
+        let end = start + width - 1;
+        self.fill_area(x + start, y + start, x + end, y + end, true);
+    }
+
+    // Set a pixel if it falls on the display
+    pub(super) fn plot(&mut self, x: i32, y: i32, on: bool) {
+        if (0..OLED_PXLS_X as i32).contains(&x) && (0..OLED_PXLS_Y as i32).contains(&y) {
+            self.set_pixel(x as usize, y as usize, on);
+        }
+    }
+}
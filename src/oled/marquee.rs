@@ -0,0 +1,69 @@
+use super::{OLEDDriver, OLED_COLS, OLED_PXLS_X};
+use super::transport::Transport;
+use super::text::{glyph, FONT_ADVANCE, FONT_WIDTH};
+
+
+/// A line of text scrolling right to left across one page of the display.
+/// Rather than redrawing the text every frame, the page is shifted left
+/// one column and a single new column of the text is written in, so the 
+/// cost of a step doesn't depend on the length of the text.
+#[allow(dead_code)]
+pub struct Marquee<'a> {
+    text: &'a [u8],
+    page: usize,
+    position: usize,
+}
+
+#[allow(dead_code)]
+impl<'a> Marquee<'a> {
+    /// Create a marquee for an ASCII string, scrolling on the given
+    /// page (8 pixel row) of the display
+    pub const fn new(text: &'a str, page: usize) -> Self {
+        Self {
+            text: text.as_bytes(),
+            page,
+            position: 0,
+        }
+    }
+
+    /// Scroll the text left by one column. The text enters from the 
+    /// right edge of the display, and repeats once it has fully left 
+    /// the display on the left edge.
+    pub fn step<T: Transport>(&mut self, display: &mut OLEDDriver<T>) {
+        display.scroll_page_left(self.page, self.column(self.position));
+        self.position = (self.position + 1) % self.length();
+    }
+
+    /// Draw the text where it has scrolled to over its page, for frames
+    /// drawn from scratch rather than shifted, and scroll it left by one
+    /// column. The text enters and repeats as with step.
+    pub fn draw<T: Transport>(&mut self, display: &mut OLEDDriver<T>) {
+        let length = self.length();
+        let text_columns = length - OLED_PXLS_X;
+        let start = self.page * OLED_COLS;
+        for (x, byte) in display.get_buffer()[start..start + OLED_COLS].iter_mut().enumerate() {
+            *byte = self.column((self.position + x + text_columns) % length);
+        }
+        self.position = (self.position + 1) % length;
+    }
+
+    /// Restart the text from the right edge of the display
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    // The number of columns in one cycle of the marquee, 
+    // including a blank display width between repetitions
+    fn length(&self) -> usize {
+        self.text.len() * FONT_ADVANCE as usize + OLED_PXLS_X
+    }
+
+    // Get a column of the rendered text as page data
+    fn column(&self, position: usize) -> u8 {
+        let column = position % FONT_ADVANCE as usize;
+        match self.text.get(position / FONT_ADVANCE as usize) {
+            Some(c) if column < FONT_WIDTH as usize => glyph(*c as char)[column],
+            _ => 0,
+        }
+    }
+}
@@ -1,7 +1,26 @@
-use stm32f0xx_hal::pac::{I2C1, DMA1};
+pub mod dmai2c;
+pub use dmai2c::{DMAi2c, Speed};
 
-mod dmai2c;
-use dmai2c::DMAi2c;
+pub mod console;
+pub mod diff;
+pub mod dmamem;
+mod draw;
+pub mod marquee;
+#[cfg(feature = "overlay")]
+pub mod overlay;
+pub mod soft_i2c;
+mod text;
+#[allow(unused_imports)]
+pub use text::{NumberText, FONT_ADVANCE};
+pub mod transport;
+mod widgets;
+use diff::DiffShadow;
+use dmamem::DmaMem;
+use draw::ClipRect;
+#[cfg(feature = "overlay")]
+use overlay::Overlay;
+use dmai2c::transfer::Transfer;
+use transport::{DmaTransport, Transport};
 
 
 /// The OLED display used here is a 128 pixel wide by 64 pixel
@@ -10,17 +29,37 @@ pub const OLED_PXLS_X: usize = 128;
 pub const OLED_PXLS_Y: usize = 64;
 const OLED_COLS: usize = OLED_PXLS_X;
 
+/// SSD1306 modules select between two 7-bit I2C addresses with
+/// the D/C# pin (or an address jumper), so two displays can share a bus.
+pub const OLED_ADDR_PRIMARY: u8 = 0x3C;
+#[allow(dead_code)]
+pub const OLED_ADDR_SECONDARY: u8 = 0x3D;
+
 // An OLED page represents a row of the display 8 pixels tall.
 // Each column in this row is represented by a u8 value, where
 // a 1 in the LSB represents the top pixel in the on state.
 const OLED_PAGES: usize = OLED_PXLS_Y / 8;
 const OLED_PAGE_HEADER_SIZE: usize = 7;
-const OLED_PAGE_SIZE: usize = OLED_COLS + OLED_PAGE_HEADER_SIZE;
-const OLED_FRAME_SIZE: usize = OLED_PAGE_SIZE * OLED_PAGES;
+pub const OLED_FRAME_SIZE: usize = OLED_COLS * OLED_PAGES;
+
+/// Storage for a complete frame as a plain bitmap: one page after 
+/// another, top to bottom, with one byte per column, left to right.
+/// The command headers addressing each page are gathered from a 
+/// separate buffer as the frame is transmitted.
+pub type OLEDBuffer = [u8; OLED_FRAME_SIZE];
+
+// The command headers preceding each page of a transmitted frame, with
+// each page starting at the first column, or the second while the
+// burn-in shift has moved the frame a column over
+#[cfg(feature = "burn-in")]
+static OLED_PAGE_HEADERS: [[u8; OLED_PAGES * OLED_PAGE_HEADER_SIZE]; 2] = [page_headers(0), page_headers(1)];
+#[cfg(not(feature = "burn-in"))]
+static OLED_PAGE_HEADERS: [[u8; OLED_PAGES * OLED_PAGE_HEADER_SIZE]; 1] = [page_headers(0)];
 
 
 // A list of commands for initializing the OLED display.
-static OLED_INIT_CMDS: [&[u8]; 18] = [
+// The charge pump and display on commands follow, see PowerSource.
+static OLED_INIT_CMDS: [&[u8]; 16] = [
     &[0, 0xAE],             //Put OLED display into sleep mode
     &[0, 0x81, 120],        //Set Contrast Value (Command: 0x81) to an 8-bit value ( < 256 )
     &[0, 0xA4],             //Display RAM content (0xA5: all pixels on, regardless of RAM contents)
@@ -37,82 +76,260 @@ static OLED_INIT_CMDS: [&[u8]; 18] = [
     &[0, 0xD5, 0xC0],       //Set Display Clock Oscillator speed to 12/16, prescaled by 0.
     &[0, 0xD9, 0x22],       //Set pre-charge period to 2 clocks for phase 1, and 2 clocks for phase 2
     &[0, 0xDB, 0x20],       //Set Vcomh deselect level 0x20 ~ 0.77Vcc (0x00: 0.65Vcc; 0x30: 0.83Vcc)*/
-    &[0, 0x8D, 0x14],       //Enable Charge Pump when display is on
-    &[0, 0xAF],             //Turn on OLED Display
 ];
+static OLED_DISPLAY_ON_CMD: &[u8] = &[0, 0xAF];  //Turn on OLED Display
+static OLED_DISPLAY_OFF_CMD: &[u8] = &[0, 0xAE]; //Put OLED display into sleep mode
+
+// Contrast commands for each of the levels selectable with set_contrast,
+// evenly spaced from the lowest contrast to the highest
+const OLED_CONTRAST_LEVELS: usize = 16;
+static OLED_CONTRAST_CMDS: [[u8; 3]; OLED_CONTRAST_LEVELS] = {
+    let mut cmds = [[0; 3]; OLED_CONTRAST_LEVELS];
+    let mut level = 0;
+    while level < OLED_CONTRAST_LEVELS {
+        cmds[level] = [0, 0x81, (level * 17) as u8]; //Set Contrast Value
+        level += 1;
+    }
+    cmds
+};
 
-// Global mutable OLED buffers
-static mut OLED_BUFFER: [u8; OLED_FRAME_SIZE] = [0; OLED_FRAME_SIZE];
+/// The shades of grey dithered between off and on (see set_pixel_shade)
+pub const OLED_SHADES: usize = 16;
+
+// 4x4 ordered dithering thresholds, for the shades of grey
+const BAYER: [[usize; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
+// Display offset commands, for the rows moved over by. Rather than
+// wrapping ROW0 around to COM63, the shifted frame leaves COM63 unused.
+#[cfg(feature = "burn-in")]
+static OLED_DISPLAY_OFFSET_CMDS: [&[u8]; 2] = [
+    &[0, 0xD3, 0, 0xA8, 63], //Set display offset vertical shift to 0, and MUX ratio to 64
+    &[0, 0xD3, 1, 0xA8, 62], //Set display offset vertical shift to 1 (ROW1 on COM0), and MUX ratio to 63
+];
+
+// The first column of each page, left blank while the burn-in shift
+// has moved the frame a column over
+#[cfg(feature = "burn-in")]
+static OLED_BLANK_COLUMN: [u8; OLED_PAGES] = [0; OLED_PAGES];
+
+
+/// The supply for the panel drive voltage (VCC)
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum PowerSource {
+    /// VCC is generated from VDD by the SSD1306's internal charge pump
+    ChargePump,
+    /// VCC is supplied externally, and the charge pump must stay disabled
+    External,
+}
+
+impl PowerSource {
+    fn charge_pump_cmd(self) -> &'static [u8] {
+        match self {
+            PowerSource::ChargePump => &[0, 0x8D, 0x14], //Enable Charge Pump when display is on
+            PowerSource::External => &[0, 0x8D, 0x10],   //Disable Charge Pump
+        }
+    }
+}
 
 
-pub struct OLEDDriver {
+/// Test patterns for checking a panel during hardware bring-up
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum Pattern {
+    /// Alternating single pixels
+    Checkerboard,
+    /// Alternating single pixel rows
+    Stripes,
+    /// Every pixel on
+    AllOn,
+    /// A dithered left-to-right ramp from off to on
+    Gradient,
+}
+
+/// Burn-in protection state: the whole frame is periodically shifted
+/// a pixel across and up so no pixel is lit constantly
+#[cfg(feature = "burn-in")]
+#[derive(Copy, Clone)]
+struct BurnInShift {
+    period: u16,
+    frames: u16,
+    phase: usize,
+}
+
+#[cfg(feature = "burn-in")]
+impl BurnInShift {
+    // The frame steps through being moved over by no columns and rows,
+    // then a column, a column and a row, and a row. Moving by a pixel at
+    // most, the column and row moved off the panel's edges are dropped
+    // rather than wrapping around to the opposite ones.
+    fn columns(&self) -> usize {
+        ((self.phase + 1) >> 1) & 1
+    }
+
+    fn rows(&self) -> usize {
+        self.phase >> 1
+    }
+}
+
+/// Inactivity tracking: the display is put to sleep after a number
+/// of idle ticks, where transmitting an unchanged frame or calling 
+/// idle_tick each count as one tick
+#[derive(Copy, Clone)]
+struct IdleTimeout {
+    timeout: u16,
+    idle: u16,
+    checksum: u32,
+}
+
+pub struct OLEDDriver<T = DmaTransport> {
+    transport: T,
+    address: u8,
+    // The frame buffer, lent to the transport while it's transmitted
+    frame: Transfer<&'static mut OLEDBuffer>,
+    clip: ClipRect,
+    #[cfg(feature = "burn-in")]
+    burn_in: Option<BurnInShift>,
+    // The overlay, lent to the transport while its page is transmitted
+    #[cfg(feature = "overlay")]
+    overlay: Option<Transfer<&'static mut Overlay>>,
+    idle_timeout: Option<IdleTimeout>,
+    shadow: Option<&'static mut DiffShadow>,
+    shadow_synced: bool,
+    dma_mem: Option<DmaMem>,
+    is_asleep: bool,
     is_transmitting: bool,
 }
 
 impl OLEDDriver {
-    /// Create and initialize a new OLED buffer
-    pub fn new(i2c: I2C1, dma: DMA1) -> OLEDDriver {
-        // Initialize the DMA I2C interface
-        DMAi2c::init(i2c, dma);
+    /// Create and initialize a new OLED driver for the display at the given
+    /// 7-bit I2C address, powered by the given source. DMAi2c::init must 
+    /// have been called beforehand.
+    /// The frame buffer is owned by the driver for the rest of the program,
+    /// and is lent to the DMA interrupt only while a frame is transmitting.
+    /// Multiple drivers may share the bus; their transmissions are serialized.
+    pub fn new(address: u8, power: PowerSource, buffer: &'static mut OLEDBuffer) -> OLEDDriver {
+        OLEDDriver::with_transport(DMAi2c::transport(), address, power, buffer)
+    }
 
-        // initialize the OLED
-        for cmd in &OLED_INIT_CMDS {
-            DMAi2c::tx(cmd, None);
+    /// Wait from an async executor for the frame in progress, if any, to 
+    /// finish transmitting, so the next can be drawn without blocking
+    #[allow(dead_code)]
+    pub async fn frame_done(&mut self) {
+        if self.is_transmitting {
+            DMAi2c::idle_async().await;
+            self.is_transmitting = false;
         }
+    }
+}
 
-        // Initialize the OLED buffer
-        unsafe {
-            OLEDDriver::init_oled_buffer(&mut OLED_BUFFER);
+impl<T: Transport> OLEDDriver<T> {
+    /// Create and initialize a new OLED driver for the display at the given
+    /// 7-bit I2C address, communicating over the given transport, e.g. a 
+    /// SoftI2c when no I2C peripheral is available.
+    pub fn with_transport(mut transport: T, address: u8, power: PowerSource, buffer: &'static mut OLEDBuffer) -> Self {
+        // initialize the OLED
+        for cmd in &OLED_INIT_CMDS {
+            transport.tx(address, cmd, None);
         }
+        transport.tx(address, power.charge_pump_cmd(), None);
+        transport.tx(address, OLED_DISPLAY_ON_CMD, None);
 
         // Return the OLED driver
         OLEDDriver {
+            transport,
+            address,
+            frame: Transfer::new(buffer),
+            clip: ClipRect::FULL,
+            #[cfg(feature = "burn-in")]
+            burn_in: None,
+            #[cfg(feature = "overlay")]
+            overlay: None,
+            idle_timeout: None,
+            shadow: None,
+            shadow_synced: false,
+            dma_mem: None,
+            is_asleep: false,
             is_transmitting: false,
         }
     }
 
     /// Turn off every pixel
     pub fn clear(&mut self) {
-        while self.tx_active() {
-            // wait for frame transmission to complete before 
-            // modifying display data
-        }
-        let buffer = self.get_buffer();
-        for i in 0..OLED_PAGES {
-            let start = i * OLED_PAGE_SIZE + OLED_PAGE_HEADER_SIZE;
-            let end = start + OLED_PAGE_SIZE - OLED_PAGE_HEADER_SIZE;
-            for byte in &mut buffer[start..end] {
-                *byte = 0
-            }
+        self.wait_buffer_idle();
+        match &mut self.dma_mem {
+            // SAFETY: the buffer is only accessed after wait_buffer_idle 
+            //         (e.g. through get_buffer), which waits for the clear
+            Some(dma_mem) => unsafe { dma_mem.start_clear(&mut self.frame.get_mut()[..]) },
+            None => self.frame.get_mut().fill(0),
         }
     }
 
+    /// Use a spare DMA channel to clear the frame buffer (and copy it to 
+    /// the diff shadow) in the background, or stop with None. The clear
+    /// completes the next time the frame buffer is drawn to or transmitted.
+    /// The previously used channel is returned.
+    #[allow(dead_code)]
+    pub fn set_dma_mem(&mut self, dma_mem: Option<DmaMem>) -> Option<DmaMem> {
+        self.wait_buffer_idle();
+        core::mem::replace(&mut self.dma_mem, dma_mem)
+    }
+
     /// Invert the OLED buffer
     #[allow(dead_code)]
     pub fn invert(&mut self) {
-        while self.tx_active() {
-            // wait for frame transmission to complete before 
-            // modifying display data
+        for byte in self.get_buffer().iter_mut() {
+            *byte = !*byte;
         }
-        let buffer = self.get_buffer();
-        for i in 0..OLED_PAGES {
-            let start = i * OLED_PAGE_SIZE + OLED_PAGE_HEADER_SIZE;
-            let end = start + OLED_PAGE_SIZE - OLED_PAGE_HEADER_SIZE;
-            for byte in &mut buffer[start..end] {
-                *byte = !*byte;
+    }
+
+    /// Count the pixels that are on in the OLED buffer. 
+    /// A count of zero after drawing usually indicates a rendering bug.
+    #[allow(dead_code)]
+    pub fn lit_pixel_count(&mut self) -> u32 {
+        self.get_buffer().iter()
+                         .map(|byte| byte.count_ones())
+                         .sum()
+    }
+
+    /// Fill the OLED buffer with a test pattern
+    #[allow(dead_code)]
+    pub fn test_pattern(&mut self, pattern: Pattern) {
+        for y in 0..OLED_PXLS_Y {
+            for x in 0..OLED_PXLS_X {
+                let on = match pattern {
+                    Pattern::Checkerboard => (x + y) % 2 == 0,
+                    Pattern::Stripes => y % 2 == 0,
+                    Pattern::AllOn => true,
+                    Pattern::Gradient => x * (OLED_SHADES + 1) / OLED_PXLS_X > BAYER[y % 4][x % 4],
+                };
+                self.set_pixel(x, y, on);
             }
         }
     }
 
-    /// Set a given pixel to be on or off
+    /// Verify that the display acknowledges its address on the bus.
+    /// This is useful during bring-up to separate wiring problems
+    /// from initialization or drawing problems.
+    #[allow(dead_code)]
+    pub fn self_check(&mut self) -> bool {
+        self.transport.probe(self.address)
+    }
+
+    /// Set a given pixel to be on or off. 
+    /// Pixels outside of the clipping region are left unchanged.
     pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
-        while self.tx_active() {
-            // wait for frame transmission to complete before 
-            // modifying display data
+        if !self.clip.contains(x, y) {
+            return;
         }
         let row = y / 8;
         let bit = y % 8;
-        let idx = row * OLED_PAGE_SIZE + OLED_PAGE_HEADER_SIZE + x;
+        let idx = row * OLED_COLS + x;
         let buffer = self.get_buffer();
         if on {
             buffer[idx] |= 1 << bit;
@@ -122,40 +339,308 @@ impl OLEDDriver {
         }
     }
 
+    /// Set a given pixel to a shade of grey, from off at 0 to on at
+    /// OLED_SHADES, by ordered dithering: a shade lights that share of a
+    /// 4x4 block's pixels, spread evenly through it.
+    /// Pixels outside of the clipping region are left unchanged.
+    #[allow(dead_code)]
+    pub fn set_pixel_shade(&mut self, x: usize, y: usize, shade: usize) {
+        self.set_pixel(x, y, shade > BAYER[y % 4][x % 4]);
+    }
+
+    /// Shift one page (8 pixel row) of the display left by one column,
+    /// filling the rightmost column with the given page data
+    #[allow(dead_code)]
+    pub fn scroll_page_left(&mut self, page: usize, column: u8) {
+        let start = page * OLED_COLS;
+        let end = start + OLED_COLS;
+        let buffer = self.get_buffer();
+        buffer.copy_within((start + 1)..end, start);
+        buffer[end - 1] = column;
+    }
+
+    /// Enable burn-in protection, shifting the frame by a pixel every 
+    /// period_frames transmitted frames, or disable it with None.
+    /// The column shifted off the panel is dropped, and the first left
+    /// blank, rather than wrapping around. The frame buffer's untouched.
+    /// This is intended for installations that run continuously.
+    #[cfg(feature = "burn-in")]
+    #[allow(dead_code)]
+    pub fn set_burn_in_shift(&mut self, period_frames: Option<u16>) {
+        self.burn_in = period_frames.map(|period| BurnInShift {
+            period,
+            frames: 0,
+            phase: 0,
+        });
+        self.transport.tx(self.address, OLED_DISPLAY_OFFSET_CMDS[0], None);
+        self.shadow_synced = false;
+    }
+
+    // The columns the burn-in shift has moved the frame over by
+    #[cfg(feature = "burn-in")]
+    pub(super) fn burn_in_columns(&self) -> usize {
+        self.burn_in.map_or(0, |shift| shift.columns())
+    }
+
+    #[cfg(not(feature = "burn-in"))]
+    pub(super) fn burn_in_columns(&self) -> usize {
+        0
+    }
+
+    /// Attach an overlay to be composited onto every transmitted frame,
+    /// or detach it with None. The previously attached overlay is returned,
+    /// once it's no longer being transmitted. The next frame is sent in
+    /// full, so a detached overlay's page is restored to the frame's.
+    #[cfg(feature = "overlay")]
+    #[allow(dead_code)]
+    pub fn set_overlay(&mut self, overlay: Option<&'static mut Overlay>) -> Option<&'static mut Overlay> {
+        self.shadow_synced = false;
+        core::mem::replace(&mut self.overlay, overlay.map(Transfer::new)).map(Transfer::wait)
+    }
+
+    /// Get the attached overlay to update its content
+    #[cfg(feature = "overlay")]
+    #[allow(dead_code)]
+    pub fn overlay_mut(&mut self) -> Option<&mut Overlay> {
+        self.overlay.as_mut().map(|overlay| &mut **overlay.get_mut())
+    }
+
+    #[cfg(feature = "overlay")]
+    fn has_overlay(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    #[cfg(not(feature = "overlay"))]
+    fn has_overlay(&self) -> bool {
+        false
+    }
+
+    /// Put the display to sleep after the given number of idle ticks, 
+    /// or never with None. Transmitting a frame with unchanged content
+    /// counts as an idle tick, as does each call to idle_tick, so the 
+    /// display will also sleep if frames stop being transmitted entirely.
+    /// The display wakes when a frame with new content is transmitted.
+    #[allow(dead_code)]
+    pub fn set_idle_timeout(&mut self, ticks: Option<u16>) {
+        self.idle_timeout = ticks.map(|timeout| IdleTimeout {
+            timeout,
+            idle: 0,
+            checksum: 0,
+        });
+    }
+
+    /// Count an idle tick towards the idle timeout.
+    /// This should be called at a fixed rate, e.g. from a timer.
+    #[allow(dead_code)]
+    pub fn idle_tick(&mut self) {
+        if let Some(idle_timeout) = &mut self.idle_timeout {
+            idle_timeout.idle = idle_timeout.idle.saturating_add(1);
+            if idle_timeout.idle >= idle_timeout.timeout {
+                self.sleep();
+            }
+        }
+    }
+
+    /// Put the display to sleep, turning off every pixel.
+    /// The frame buffer and display RAM are retained.
+    #[allow(dead_code)]
+    pub fn sleep(&mut self) {
+        if !self.is_asleep {
+            self.transport.tx(self.address, OLED_DISPLAY_OFF_CMD, None);
+            self.is_asleep = true;
+        }
+    }
+
+    /// Wake the display from sleep
+    #[allow(dead_code)]
+    pub fn wake(&mut self) {
+        if self.is_asleep {
+            self.transport.tx(self.address, OLED_DISPLAY_ON_CMD, None);
+            self.is_asleep = false;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_asleep(&self) -> bool {
+        self.is_asleep
+    }
+
+    /// Set the contrast to one of 16 levels, from 0 (the lowest) to 15.
+    /// Higher levels are clamped to 15.
+    #[allow(dead_code)]
+    pub fn set_contrast(&mut self, level: u8) {
+        let level = (level as usize).min(OLED_CONTRAST_LEVELS - 1);
+        self.transport.tx(self.address, &OLED_CONTRAST_CMDS[level], None);
+    }
+
     /// Transmit the current draw buffer to the OLED.
     /// This also swaps the buffers and clears the new draw buffer.
     pub fn tx_frame(&mut self) {
-        DMAi2c::tx(self.get_buffer(), Some(OLED_PAGE_SIZE));
+        // Track content changes for the idle timeout, skipping
+        // transmission altogether while the display is asleep
+        if self.idle_timeout.is_some() {
+            let checksum = self.frame_checksum();
+            if let Some(idle_timeout) = &mut self.idle_timeout {
+                if checksum != idle_timeout.checksum {
+                    idle_timeout.checksum = checksum;
+                    idle_timeout.idle = 0;
+                    self.wake();
+                }
+                else {
+                    self.idle_tick();
+                }
+            }
+            if self.is_asleep {
+                return;
+            }
+        }
+
+        // Advance the burn-in shift ahead of the frame
+        #[cfg(feature = "burn-in")]
+        if let Some(shift) = &mut self.burn_in {
+            shift.frames += 1;
+            if shift.frames >= shift.period {
+                shift.frames = 0;
+                shift.phase = (shift.phase + 1) % 4;
+                self.transport.tx(self.address, OLED_DISPLAY_OFFSET_CMDS[shift.rows()], None);
+                // the whole frame's sent again, from its new column
+                self.shadow_synced = false;
+            }
+        }
+
+        // Only transmit what changed once the panel's content is known.
+        // The overlay's page isn't shadowed, so frames are sent in full
+        // while one's attached.
+        if self.shadow_synced && !self.has_overlay() {
+            self.tx_frame_diff();
+            return;
+        }
+        self.wait_buffer_idle();
+        self.sync_shadow();
+
+        // Lend the buffer to the transport for the duration of the transfer;
+        // it's only borrowed back once the transmission is complete.
+        // The overlay's page, if any, is sent from the overlay instead.
+        let columns = self.burn_in_columns();
+        let headers = &OLED_PAGE_HEADERS[columns];
+        let page = self.tx_overlay(headers).unwrap_or(OLED_PAGES);
+        if page > 0 {
+            self.transport.tx_gather_lent(self.address, headers, OLED_PAGE_HEADER_SIZE, &mut self.frame, 0..(page * OLED_COLS), OLED_COLS);
+        }
+        if page + 1 < OLED_PAGES {
+            self.transport.tx_gather_lent(self.address, &headers[((page + 1) * OLED_PAGE_HEADER_SIZE)..], OLED_PAGE_HEADER_SIZE, &mut self.frame, ((page + 1) * OLED_COLS)..OLED_FRAME_SIZE, OLED_COLS);
+        }
+
+        // Each page's last column, moved off the panel, wraps around to
+        // the next page's first, so blank the first columns again after
+        #[cfg(feature = "burn-in")]
+        if columns != 0 {
+            self.transport.tx_gather(self.address, &OLED_PAGE_HEADERS[0], OLED_PAGE_HEADER_SIZE, &OLED_BLANK_COLUMN, 1);
+        }
         self.is_transmitting = true;
     }
 
+    // Composite the overlay onto its page of the frame and transmit it
+    // with the page's header, returning the page, or None without an overlay
+    #[cfg(feature = "overlay")]
+    fn tx_overlay(&mut self, headers: &'static [u8]) -> Option<usize> {
+        let overlay = self.overlay.as_mut()?;
+        let page = overlay.buffer().page();
+        let start = page * OLED_COLS;
+        overlay.get_mut().composite(&self.frame.buffer()[start..(start + OLED_COLS)]);
+        self.transport.tx_gather_lent(self.address, &headers[(page * OLED_PAGE_HEADER_SIZE)..], OLED_PAGE_HEADER_SIZE, overlay, 0..OLED_COLS, OLED_COLS);
+        Some(page)
+    }
+
+    #[cfg(not(feature = "overlay"))]
+    fn tx_overlay(&mut self, _headers: &'static [u8]) -> Option<usize> {
+        None
+    }
+
+
+    // An FNV-1a hash of the frame's pixel data, for detecting changes
+    fn frame_checksum(&mut self) -> u32 {
+        self.get_buffer().iter().fold(0x811C_9DC5, |hash: u32, byte| {
+            (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+        })
+    }
+
     fn tx_active(&mut self) -> bool {
         match self.is_transmitting {
             false => false,
             true => {
-                self.is_transmitting = DMAi2c::tx_in_progress();
+                self.is_transmitting = self.transport.tx_in_progress();
                 self.is_transmitting
             }
         }
     }
 
-    /// Initialize an existing OLED buffer
-    fn init_oled_buffer(buf: &mut [u8]) {
-        // set up buffer page headers
-        for i in 0..OLED_PAGES {
-            buf[i * OLED_PAGE_SIZE + 0] = 0x80;             // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
-            buf[i * OLED_PAGE_SIZE + 1] = 0xB0 + i as u8;   // Command byte: set the page address to page i
-            buf[i * OLED_PAGE_SIZE + 2] = 0x80;             // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
-            buf[i * OLED_PAGE_SIZE + 3] = 0x10;             // Command byte: set the column address to 0, 1 of 2 (the 4 lsbs in this command correspond to the 4 msbs in the column address)
-            buf[i * OLED_PAGE_SIZE + 4] = 0x80;             // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
-            buf[i * OLED_PAGE_SIZE + 5] = 0x00;             // Command byte: set the column address to 0, 2 of 2 (the 4 lsbs in this command correspond to the 4 lsbs in the column address)
-            buf[i * OLED_PAGE_SIZE + 6] = 0x40;             // Control byte: specify that the remainder of the transmission will be pixel data
+    /// The frame as a plain bitmap (see OLEDBuffer), to read. The frame
+    /// is only read while it's transmitted, so this doesn't wait.
+    #[allow(dead_code)]
+    pub fn bitmap(&self) -> &OLEDBuffer {
+        self.frame.buffer()
+    }
+
+    /// Direct access to the frame as a plain bitmap (see OLEDBuffer),
+    /// e.g. for drawing libraries. This waits for any frame transmission
+    /// to complete first.
+    #[allow(dead_code)]
+    pub fn bitmap_mut(&mut self) -> &mut OLEDBuffer {
+        self.get_buffer()
+    }
+
+    // Wait for the frame's transmission to complete (or be aborted),
+    // along with any background operation on the frame or shadow
+    fn wait_buffer_idle(&mut self) {
+        if self.tx_active() {
+            self.transport.wait_idle().ok();
+            self.is_transmitting = false;
         }
+        if let Some(dma_mem) = &mut self.dma_mem {
+            dma_mem.wait();
+        }
+    }
+
+    /// Return a mutable reference to the display buffer,
+    /// waiting for any frame transmission to complete first
+    fn get_buffer(&mut self) -> &mut OLEDBuffer {
+        // wait for frame transmission to complete before 
+        // modifying display data
+        self.wait_buffer_idle();
+        self.frame.get_mut()
     }
 
-    /// Return a mutable reference to the display buffer
-    fn get_buffer(&self) -> &'static mut [u8] {
-        unsafe { &mut OLED_BUFFER }
+}
+
+
+// The command headers preceding each page's pixel data, each page
+// starting at the given column
+const fn page_headers(column: usize) -> [u8; OLED_PAGES * OLED_PAGE_HEADER_SIZE] {
+    let mut headers = [0; OLED_PAGES * OLED_PAGE_HEADER_SIZE];
+    let mut page = 0;
+    while page < OLED_PAGES {
+        let header = page_header(page, column);
+        let mut i = 0;
+        while i < OLED_PAGE_HEADER_SIZE {
+            headers[page * OLED_PAGE_HEADER_SIZE + i] = header[i];
+            i += 1;
+        }
+        page += 1;
     }
+    headers
+}
 
+// The command header preceding the pixel data of a page, 
+// addressing the given page and starting column
+const fn page_header(page: usize, column: usize) -> [u8; OLED_PAGE_HEADER_SIZE] {
+    [
+        0x80,                       // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
+        0xB0 + page as u8,          // Command byte: set the page address
+        0x80,                       // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
+        0x10 + (column >> 4) as u8, // Command byte: set the column address, 1 of 2 (the 4 lsbs in this command correspond to the 4 msbs in the column address)
+        0x80,                       // Control byte: specify that the next two bytes will be a command byte followed by another control byte.
+        (column & 0x0F) as u8,      // Command byte: set the column address, 2 of 2 (the 4 lsbs in this command correspond to the 4 lsbs in the column address)
+        0x40,                       // Control byte: specify that the remainder of the transmission will be pixel data
+    ]
 }
@@ -0,0 +1,91 @@
+use super::{OLED_COLS, OLED_PAGES};
+use super::dmai2c::transfer::TxBuffer;
+use super::text::{glyph, NumberText, FONT_ADVANCE};
+use fluid_core::fixed::FixedPt;
+
+
+/// A one page (8 pixel) tall layer that is OR-composited onto a page of
+/// the frame each time the frame is transmitted. Because the overlay is
+/// kept separately from the frame, its content persists when the frame
+/// is cleared, so a caption or counter only needs redrawing when it changes.
+/// The frame itself is left untouched: the page is composited into the
+/// overlay's own copy, which is transmitted in place of the frame's.
+/// Text positions are given in columns, and text is drawn top-aligned.
+#[allow(dead_code)]
+pub struct Overlay {
+    page: usize,
+    columns: [u8; OLED_COLS],
+    // The frame's page with the overlay ORed on, as last transmitted
+    composited: [u8; OLED_COLS],
+}
+
+// SAFETY: the composited page is only written by composite, through the
+//         overlay itself
+unsafe impl TxBuffer for &'static mut Overlay {
+    fn as_bytes(&self) -> &[u8] {
+        &self.composited
+    }
+}
+
+#[allow(dead_code)]
+impl Overlay {
+    /// Create an empty overlay covering the given page of the display
+    pub const fn new(page: usize) -> Self {
+        Self {
+            page: if page < OLED_PAGES { page } else { OLED_PAGES - 1 },
+            columns: [0; OLED_COLS],
+            composited: [0; OLED_COLS],
+        }
+    }
+
+    // OR the overlay onto the frame's page, for transmission
+    pub(super) fn composite(&mut self, page: &[u8]) {
+        for (byte, (pixels, column)) in self.composited.iter_mut().zip(page.iter().zip(self.columns.iter())) {
+            *byte = pixels | column;
+        }
+    }
+
+    /// The page of the display the overlay covers
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// The overlay's page data, one byte per column
+    pub fn columns(&self) -> &[u8; OLED_COLS] {
+        &self.columns
+    }
+
+    /// Mutable access to the overlay's page data, one byte per column,
+    /// where a 1 in the LSB represents the top pixel in the on state.
+    pub fn columns_mut(&mut self) -> &mut [u8; OLED_COLS] {
+        &mut self.columns
+    }
+
+    /// Turn off every pixel of the overlay
+    pub fn clear(&mut self) {
+        self.columns = [0; OLED_COLS];
+    }
+
+    /// Draw a string starting at the given column, returning the 
+    /// column following the last character
+    pub fn draw_text(&mut self, x: usize, text: &str) -> usize {
+        text.chars().fold(x, |x, c| {
+            for (dx, column) in glyph(c).iter().enumerate() {
+                if let Some(byte) = self.columns.get_mut(x + dx) {
+                    *byte = *column;
+                }
+            }
+            x + FONT_ADVANCE as usize
+        })
+    }
+
+    /// Draw a signed decimal integer starting at the given column
+    pub fn draw_number(&mut self, x: usize, value: i32) -> usize {
+        self.draw_text(x, NumberText::from_i32(value).as_str())
+    }
+
+    /// Draw a fixed point value with the given number of decimal places
+    pub fn draw_fixed(&mut self, x: usize, value: FixedPt, decimals: u8) -> usize {
+        self.draw_text(x, NumberText::from_fixed(value, decimals).as_str())
+    }
+}
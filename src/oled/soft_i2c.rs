@@ -0,0 +1,200 @@
+use core::ops::Range;
+use embedded_hal::{blocking::delay::DelayUs, digital::v2::{InputPin, OutputPin}};
+use super::dmai2c::{transfer::{Transfer, TxBuffer}, TxError};
+use super::transport::Transport;
+
+
+// half of a 100kHz SCL period
+const HALF_PERIOD_US: u16 = 5;
+
+// The longest a device may stretch the clock before a transfer times out
+const MAX_STRETCH_US: u16 = 1_000;
+
+
+/// A bit-banged I2C transport on GPIO pins, so the display can be brought
+/// up on pins without an I2C alternate function, or on parts where the I2C
+/// peripherals are occupied. Both pins must be configured as open-drain
+/// outputs, with pull-ups. The bus is clocked at roughly 100kHz, and each
+/// transmission is complete by the time tx returns.
+pub struct SoftI2c<SCL, SDA, D> {
+    scl: SCL,
+    sda: SDA,
+    delay: D,
+    error: Option<TxError>,
+}
+
+#[allow(dead_code)]
+impl<SCL, SDA, D> SoftI2c<SCL, SDA, D>
+where SCL: OutputPin + InputPin, SDA: OutputPin + InputPin, D: DelayUs<u16> {
+    /// Create a transport on the given pins, releasing both lines
+    pub fn new(mut scl: SCL, mut sda: SDA, delay: D) -> Self {
+        scl.set_high().ok();
+        sda.set_high().ok();
+        Self { scl, sda, delay, error: None }
+    }
+
+    /// Take the most recent error, if any
+    pub fn take_error(&mut self) -> Option<TxError> {
+        self.error.take()
+    }
+
+    /// Release a bus held by a device stuck mid-transfer with SDA low,
+    /// e.g. when taking the bus over from the I2C peripheral. SCL is 
+    /// clocked until the device releases SDA, up to 9 times, and then
+    /// a STOP condition is generated. Returns whether SDA was released.
+    pub fn recover(&mut self) -> bool {
+        const MAX_CLOCKS: usize = 9;
+
+        self.sda.set_high().ok();
+        for _ in 0..MAX_CLOCKS {
+            if self.sda.is_high().unwrap_or(false) {
+                break;
+            }
+            self.scl.set_low().ok();
+            self.delay.delay_us(HALF_PERIOD_US);
+            self.release_scl().ok();
+        }
+        let released = self.sda.is_high().unwrap_or(false);
+        self.scl.set_low().ok();
+        self.stop();
+        released
+    }
+
+    /// Release the pins and the delay source
+    pub fn free(self) -> (SCL, SDA, D) {
+        (self.scl, self.sda, self.delay)
+    }
+
+    // Transmit the given parts as a single transfer to the device
+    // with the given 7-bit address
+    fn transfer(&mut self, address: u8, parts: &[&[u8]]) -> Result<(), TxError> {
+        let result = self.write_parts(address, parts);
+        self.stop();
+        result
+    }
+
+    // Transmit each block as its own transfer, preceded by its header
+    // if any, abandoning the rest after an error
+    fn transfer_blocks<'a>(&mut self, address: u8, blocks: impl Iterator<Item = (&'a [u8], &'a [u8])>) {
+        for (header, block) in blocks {
+            if let Err(error) = self.transfer(address, &[header, block]) {
+                self.error = Some(error);
+                break;
+            }
+        }
+    }
+
+    // Transmit the data in blocks of tx_size bytes, each preceded by its
+    // own header_size bytes of the headers
+    fn gather_blocks(&mut self, address: u8, headers: &[u8], header_size: usize, data: &[u8], tx_size: usize) {
+        let blocks = data.chunks(tx_size.max(1)).enumerate().map(|(i, block)| {
+            let header = headers.get((i * header_size)..((i + 1) * header_size)).unwrap_or(&[]);
+            (header, block)
+        });
+        self.transfer_blocks(address, blocks);
+    }
+
+    // Address the device and clock out the parts
+    fn write_parts(&mut self, address: u8, parts: &[&[u8]]) -> Result<(), TxError> {
+        self.start()?;
+        self.write_byte(address << 1)?;
+        for byte in parts.iter().flat_map(|part| part.iter()) {
+            self.write_byte(*byte)?;
+        }
+        Ok(())
+    }
+
+    // Generate a START condition: SDA falls while SCL is high
+    fn start(&mut self) -> Result<(), TxError> {
+        self.sda.set_high().ok();
+        self.release_scl()?;
+        if self.sda.is_low().unwrap_or(true) {
+            // another controller or a stuck device holds the bus
+            return Err(TxError::BusError);
+        }
+        self.sda.set_low().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.scl.set_low().ok();
+        Ok(())
+    }
+
+    // Generate a STOP condition: SDA rises while SCL is high
+    fn stop(&mut self) {
+        self.sda.set_low().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.release_scl().ok();
+        self.sda.set_high().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+    }
+
+    // Clock out a byte, MSB first, and check the device's acknowledgement
+    fn write_byte(&mut self, byte: u8) -> Result<(), TxError> {
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                self.sda.set_high().ok();
+            } else {
+                self.sda.set_low().ok();
+            }
+            self.delay.delay_us(HALF_PERIOD_US);
+            self.release_scl()?;
+            self.scl.set_low().ok();
+        }
+
+        // the device pulls SDA low during the ninth clock to acknowledge
+        self.sda.set_high().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.release_scl()?;
+        let acknowledged = self.sda.is_low().unwrap_or(false);
+        self.scl.set_low().ok();
+        match acknowledged {
+            true => Ok(()),
+            false => Err(TxError::Nack),
+        }
+    }
+
+    // Release SCL for the high half of a clock period, waiting
+    // for any device stretching the clock to release it too
+    fn release_scl(&mut self) -> Result<(), TxError> {
+        self.scl.set_high().ok();
+        let mut waited = 0;
+        while self.scl.is_low().unwrap_or(false) {
+            if waited >= MAX_STRETCH_US {
+                return Err(TxError::Timeout);
+            }
+            self.delay.delay_us(1);
+            waited += 1;
+        }
+        self.delay.delay_us(HALF_PERIOD_US);
+        Ok(())
+    }
+}
+
+impl<SCL, SDA, D> Transport for SoftI2c<SCL, SDA, D>
+where SCL: OutputPin + InputPin, SDA: OutputPin + InputPin, D: DelayUs<u16> {
+    fn tx(&mut self, address: u8, data: &'static [u8], tx_size: Option<usize>) {
+        let tx_size = tx_size.unwrap_or(data.len()).max(1);
+        self.transfer_blocks(address, data.chunks(tx_size).map(|block| (&[][..], block)));
+    }
+
+    fn tx_gather(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &'static [u8], tx_size: usize) {
+        self.gather_blocks(address, headers, header_size, data, tx_size);
+    }
+
+    // The transmission's complete once this returns, so the buffer's
+    // never actually lent
+    fn tx_gather_lent<B: TxBuffer>(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &mut Transfer<B>, range: Range<usize>, tx_size: usize) {
+        self.gather_blocks(address, headers, header_size, &data.buffer().as_bytes()[range], tx_size);
+    }
+
+    fn tx_in_progress(&self) -> bool {
+        false
+    }
+
+    fn wait_idle(&mut self) -> Result<(), TxError> {
+        Ok(())
+    }
+
+    fn probe(&mut self, address: u8) -> bool {
+        self.transfer(address, &[]).is_ok()
+    }
+}
@@ -0,0 +1,151 @@
+use super::OLEDDriver;
+use super::transport::Transport;
+use fluid_core::fixed::FixedPt;
+
+
+// The font: FONT_WIDTH, FONT_HEIGHT and FONT, its printable ASCII
+// glyphs, generated by build.rs from a BDF font, art/font.bdf unless
+// another's given. Each byte is a column of the glyph, left to right,
+// where a 1 in the LSB represents the top pixel in the on state. This
+// matches the layout of an OLED page, so glyphs may be copied directly
+// into the frame buffer.
+include!(concat!(env!("OUT_DIR"), "/font.rs"));
+
+/// Glyphs have a blank column and row between neighboring characters.
+pub const FONT_ADVANCE: i32 = FONT_WIDTH + 1;
+
+// The first and last characters in the font table
+const FONT_FIRST: char = ' ';
+const FONT_LAST: char = '~';
+
+
+/// Text and number rendering. Positions are the top left corner of the
+/// first character, and each function returns the x position following
+/// the last character drawn so that calls may be chained.
+impl<T: Transport> OLEDDriver<T> {
+    /// Draw a single character. Characters outside of printable ASCII are
+    /// drawn as '?'.
+    #[allow(dead_code)]
+    pub fn draw_char(&mut self, x: i32, y: i32, c: char) -> i32 {
+        for (dx, column) in glyph(c).iter().enumerate() {
+            for dy in 0..FONT_HEIGHT {
+                if column & (1 << dy) != 0 {
+                    self.plot(x + dx as i32, y + dy, true);
+                }
+            }
+        }
+        x + FONT_ADVANCE
+    }
+
+    /// Draw a string on a single line
+    #[allow(dead_code)]
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str) -> i32 {
+        text.chars().fold(x, |x, c| self.draw_char(x, y, c))
+    }
+
+    /// Draw a signed decimal integer
+    #[allow(dead_code)]
+    pub fn draw_number(&mut self, x: i32, y: i32, value: i32) -> i32 {
+        self.draw_text(x, y, NumberText::from_i32(value).as_str())
+    }
+
+    /// Draw a fixed point value with the given number of decimal places.
+    /// Additional places are truncated rather than rounded.
+    #[allow(dead_code)]
+    pub fn draw_fixed(&mut self, x: i32, y: i32, value: FixedPt, decimals: u8) -> i32 {
+        self.draw_text(x, y, NumberText::from_fixed(value, decimals).as_str())
+    }
+}
+
+
+/// A number formatted as decimal text, without any formatting machinery
+pub struct NumberText {
+    text: [u8; NumberText::CAPACITY],
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl NumberText {
+    /// Enough for a sign, 10 integer digits, a decimal point and 12 decimals
+    const CAPACITY: usize = 24;
+    const MAX_DECIMALS: u8 = 12;
+
+    /// Format a signed decimal integer
+    pub fn from_i32(value: i32) -> Self {
+        let mut text = Self::new();
+        if value < 0 {
+            text.push(b'-');
+        }
+        text.push_digits(value.unsigned_abs());
+        text
+    }
+
+    /// Format a fixed point value with the given number of decimal places
+    /// (at most 12). Additional places are truncated rather than rounded.
+    pub fn from_fixed(value: FixedPt, decimals: u8) -> Self {
+        const FRACTION_MASK: u32 = (1 << FixedPt::BASE) - 1;
+
+        let mut text = Self::new();
+        if value.value < 0 {
+            text.push(b'-');
+        }
+        let magnitude = value.value.unsigned_abs();
+        text.push_digits(magnitude >> FixedPt::BASE);
+        if decimals > 0 {
+            text.push(b'.');
+            let mut fraction = magnitude & FRACTION_MASK;
+            for _ in 0..core::cmp::min(decimals, Self::MAX_DECIMALS) {
+                fraction *= 10;
+                text.push(digit(fraction >> FixedPt::BASE));
+                fraction &= FRACTION_MASK;
+            }
+        }
+        text
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: only ASCII digits, '-' and '.' are ever pushed
+        unsafe { core::str::from_utf8_unchecked(&self.text[..self.len]) }
+    }
+
+    fn new() -> Self {
+        Self {
+            text: [0; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, c: u8) {
+        self.text[self.len] = c;
+        self.len += 1;
+    }
+
+    // Append an unsigned decimal integer
+    fn push_digits(&mut self, mut value: u32) {
+        let start = self.len;
+        loop {
+            self.push(digit(value % 10));
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        // digits were pushed least significant first
+        self.text[start..self.len].reverse();
+    }
+}
+
+
+// Look up the glyph for a character
+pub(super) fn glyph(c: char) -> &'static [u8; FONT_WIDTH as usize] {
+    let c = match c {
+        FONT_FIRST..=FONT_LAST => c,
+        _ => '?',
+    };
+    &FONT[c as usize - FONT_FIRST as usize]
+}
+
+// Convert a value from 0 to 9 into its decimal character
+fn digit(value: u32) -> u8 {
+    b'0' + value as u8
+}
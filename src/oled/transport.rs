@@ -0,0 +1,70 @@
+use core::ops::Range;
+use super::dmai2c::{transfer::{Transfer, TxBuffer}, DMAi2c, TxError};
+
+
+/// The link the OLED driver sends its commands and frames over.
+/// Transmissions may complete in the background (as with DMA), so data
+/// is 'static and must not be modified until tx_in_progress is false.
+pub trait Transport {
+    /// Transmit some data to the device with the given 7-bit address,
+    /// in blocks of tx_size bytes if given
+    fn tx(&mut self, address: u8, data: &'static [u8], tx_size: Option<usize>);
+
+    /// Transmit some data in blocks of tx_size bytes, each preceded in
+    /// the same transfer by its own header of header_size bytes from headers
+    fn tx_gather(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &'static [u8], tx_size: usize);
+
+    /// Transmit the given range of the buffer held by a Transfer as 
+    /// tx_gather does, lending it until the transmission is complete
+    fn tx_gather_lent<B: TxBuffer>(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &mut Transfer<B>, range: Range<usize>, tx_size: usize);
+
+    /// Determine if a transmission is in progress
+    fn tx_in_progress(&self) -> bool;
+
+    /// Wait for all transmissions to complete
+    fn wait_idle(&mut self) -> Result<(), TxError>;
+
+    /// Address the device with the given 7-bit address without sending
+    /// any data, and report whether it acknowledged
+    fn probe(&mut self, address: u8) -> bool;
+}
+
+
+/// The DMAi2c interface as a transport. The interface itself is global,
+/// so any number of these handles may exist.
+pub struct DmaTransport {
+    _private: (),
+}
+
+impl DMAi2c {
+    /// Create a handle to the interface, for use as an OLED transport
+    pub fn transport() -> DmaTransport {
+        DmaTransport { _private: () }
+    }
+}
+
+impl Transport for DmaTransport {
+    fn tx(&mut self, address: u8, data: &'static [u8], tx_size: Option<usize>) {
+        DMAi2c::tx(address, data, tx_size);
+    }
+
+    fn tx_gather(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &'static [u8], tx_size: usize) {
+        DMAi2c::tx_gather(address, headers, header_size, data, tx_size);
+    }
+
+    fn tx_gather_lent<B: TxBuffer>(&mut self, address: u8, headers: &'static [u8], header_size: usize, data: &mut Transfer<B>, range: Range<usize>, tx_size: usize) {
+        DMAi2c::tx_gather_lent(address, headers, header_size, data, range, tx_size);
+    }
+
+    fn tx_in_progress(&self) -> bool {
+        DMAi2c::tx_in_progress()
+    }
+
+    fn wait_idle(&mut self) -> Result<(), TxError> {
+        DMAi2c::wait_idle()
+    }
+
+    fn probe(&mut self, address: u8) -> bool {
+        DMAi2c::probe(address)
+    }
+}
@@ -0,0 +1,19 @@
+use super::OLEDDriver;
+use super::transport::Transport;
+
+
+/// Small composite widgets for on-screen meters and readouts
+impl<T: Transport> OLEDDriver<T> {
+    /// Draw an outlined horizontal bar with its top left corner at (x, y),
+    /// filled from the left in proportion to the given fraction (0.0 to 1.0).
+    #[allow(dead_code)]
+    pub fn draw_bar(&mut self, x: i32, y: i32, w: i32, h: i32, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let inner_w = w - 2;
+        let filled_w = (fraction * inner_w as f32) as i32;
+
+        self.draw_rect(x, y, w, h);
+        self.fill_rect(x + 1, y + 1, filled_w, h - 2, true);
+        self.fill_rect(x + 1 + filled_w, y + 1, inner_w - filled_w, h - 2, false);
+    }
+}
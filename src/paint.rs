@@ -0,0 +1,138 @@
+//! Painting with water: a cursor, steered by the tuning encoder (see
+//! encoder.rs), moves forward while the left game button is held (see
+//! buttons.rs), pouring water as it goes from the particles poured
+//! longest ago. The right game button switches an attractor at the
+//! cursor on and off, gathering the water around it. There's no gravity,
+//! so the water stays where it's poured, spreading only as it settles.
+
+use fluid_core::{scene::SceneManager, Fluid};
+use crate::buttons;
+use crate::oled::OLEDDriver;
+use crate::scenes;
+
+
+// The display's size
+const WIDTH: i16 = 125;
+const HEIGHT: i16 = 61;
+
+// The cursor's headings, clockwise from right, as steps of a pixel in
+// 16ths, and the steps it moves a frame
+const HEADINGS: [(i16, i16); 16] = [
+    (16, 0), (15, 6), (11, 11), (6, 15),
+    (0, 16), (-6, 15), (-11, 11), (-15, 6),
+    (-16, 0), (-15, -6), (-11, -11), (-6, -15),
+    (0, -16), (6, -15), (11, -11), (15, -6),
+];
+const STEPS_PER_FRAME: i16 = 2;
+
+// The frames between the particles poured
+const POUR_PERIOD: u8 = 2;
+
+// The attractor's reach, and the speed it draws the water in at, nearest
+// the cursor
+const ATTRACT_RADIUS: i8 = 24;
+const ATTRACT_SPEED: f32 = 0.3;
+
+// The length of the cursor's heading as drawn, and the attractor's size
+const HEADING_LENGTH: i16 = 5;
+const ATTRACTOR_SIZE: i32 = 9;
+
+
+pub struct Paint {
+    on: bool,
+    // The cursor's position, in 16ths of a pixel, and heading
+    position: (i16, i16),
+    heading: u8,
+    // The particle to pour next, and the frames until it's poured
+    next: usize,
+    wait: u8,
+    attracting: bool,
+    right_held: bool,
+}
+
+impl Paint {
+    /// Create the painting, not being painted
+    pub const fn new() -> Self {
+        Self {
+            on: false,
+            position: (WIDTH / 2 * 16, HEIGHT / 2 * 16),
+            heading: 0,
+            next: 0,
+            wait: 0,
+            attracting: false,
+            right_held: false,
+        }
+    }
+
+    /// Start painting, with the cursor in the middle and the water in a
+    /// pool along the bottom to pour from
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::PAINT, 0, fluid);
+        *self = Self { on: true, ..Self::new() };
+    }
+
+    /// Stop painting, e.g. to return to the demo
+    pub fn stop(&mut self) {
+        self.on = false;
+    }
+
+    /// Steer the cursor by the encoder's detents, while painting, so they
+    /// don't tune the parameters. Returns the detents left to tune with.
+    pub fn steer(&mut self, detents: i16) -> i16 {
+        if !self.on {
+            return detents;
+        }
+        self.heading = (self.heading as i16 + detents) as u8 % HEADINGS.len() as u8;
+        0
+    }
+
+    /// Move the cursor and pour the water while the left button's held,
+    /// and switch the attractor as the right button's pressed, while
+    /// painting. Returns true if either button is held.
+    pub fn update<const N: usize>(&mut self, fluid: &mut Fluid<N>) -> bool {
+        if !self.on {
+            return false;
+        }
+        let [left, right] = buttons::held();
+        if right && !self.right_held {
+            self.attracting = !self.attracting;
+        }
+        self.right_held = right;
+
+        self.wait = self.wait.saturating_sub(1);
+        let (x, y) = self.position;
+        if left {
+            let (dx, dy) = HEADINGS[self.heading as usize];
+            self.position = (
+                (x + dx * STEPS_PER_FRAME).clamp(0, (WIDTH - 1) * 16),
+                (y + dy * STEPS_PER_FRAME).clamp(0, (HEIGHT - 1) * 16),
+            );
+            if self.wait == 0 {
+                fluid.emit(self.next, (x / 16) as i8, (y / 16) as i8, 0.0, 0.0);
+                self.next = (self.next + 1) % N;
+                self.wait = POUR_PERIOD;
+            }
+        }
+        if self.attracting {
+            fluid.attract((x / 16) as i8, (y / 16) as i8, ATTRACT_RADIUS, ATTRACT_SPEED);
+        }
+        left || right
+    }
+
+    /// Draw the cursor, with its heading, and the attractor, while it's
+    /// on, over the frame, while painting
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        if !self.on {
+            return;
+        }
+        // The cursor's centred on the particles poured from it, which are
+        // drawn right of and below their positions
+        let (x, y) = (self.position.0 / 16 + 1, self.position.1 / 16 + 1);
+        let (dx, dy) = HEADINGS[self.heading as usize];
+        display.draw_rect(x as i32 - 1, y as i32 - 1, 3, 3);
+        display.draw_line((x as i32, y as i32), ((x + dx * HEADING_LENGTH / 16) as i32, (y + dy * HEADING_LENGTH / 16) as i32), 1);
+        if self.attracting {
+            display.draw_rect(x as i32 - ATTRACTOR_SIZE / 2, y as i32 - ATTRACTOR_SIZE / 2, ATTRACTOR_SIZE, ATTRACTOR_SIZE);
+        }
+    }
+}
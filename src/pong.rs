@@ -0,0 +1,151 @@
+//! Fluid Pong, a game for two: the ball is a blob of water, floating
+//! without gravity (unless the board's tilted, see accel.rs), and the
+//! paddles are obstacles (see fluid_core::obstacle) on either side of the
+//! display, each rising while its player holds their button and sinking
+//! while it's released. A paddle bats the water back as it reaches it,
+//! and a point is scored once enough of the water gets past a paddle,
+//! before it's served again from the middle. The players' buttons are
+//! the game buttons (see buttons.rs).
+
+use fluid_core::{obstacle::Rect, scene::SceneManager, Fluid, Layout};
+use crate::buttons;
+use crate::oled::OLEDDriver;
+use crate::scenes;
+
+
+// The display's size
+const WIDTH: i8 = 125;
+const HEIGHT: i8 = 61;
+
+// The paddles' size, their distance from the sides, and their speed, in
+// pixels per frame
+const PADDLE_WIDTH: i8 = 4;
+const PADDLE_HEIGHT: i8 = 20;
+const PADDLE_INSET: i8 = 4;
+const PADDLE_SPEED: i8 = 3;
+
+// The water within this distance in front of a paddle is batted back,
+// by an impulse around the paddle
+const BAT_REACH: i8 = 4;
+const BAT_RADIUS: i8 = 24;
+const BAT_SPEED: f32 = 4.0;
+
+// The water is served from the middle, at this velocity towards one side
+const SERVE_LAYOUT: Layout = Layout::Block { x: 35, y: 12, columns: 10 };
+const SERVE_VELOCITY: (f32, f32) = (3.0, 1.0);
+
+// The particles past a paddle scoring a point
+const POINT_PARTICLES: usize = 8;
+
+// The scores' positions, either side of the middle of the top
+const SCORE_X: [i32; 2] = [50, 70];
+
+
+pub struct Pong {
+    playing: bool,
+    // The left and right paddles' tops, and the players' scores
+    paddles: [i8; 2],
+    scores: [u8; 2],
+    // The side that last batted the water, which can't bat it again
+    // until it's been batted back, or served
+    batted: Option<usize>,
+}
+
+impl Pong {
+    /// Create a game, not being played. The buttons are configured by
+    /// buttons::init.
+    pub const fn new() -> Self {
+        Self {
+            playing: false,
+            paddles: [(HEIGHT - PADDLE_HEIGHT) / 2; 2],
+            scores: [0; 2],
+            batted: None,
+        }
+    }
+
+    /// Start a game, from nil all, serving to the right
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        *self = Self::new();
+        self.playing = true;
+        scenes.play(scenes::PONG, 0, fluid);
+        fluid.set_obstacles(&[paddle(0, self.paddles[0]), paddle(1, self.paddles[1])]);
+        self.serve(1, fluid);
+    }
+
+    /// End the game, taking the paddles away, e.g. to return to the demo
+    pub fn stop<const N: usize>(&mut self, fluid: &mut Fluid<N>) {
+        if self.playing {
+            fluid.set_obstacles(&[]);
+            self.playing = false;
+        }
+    }
+
+    /// Move the paddles for the buttons, bat the water back from them,
+    /// and score the points, while the game's played. Returns true if
+    /// either button is held.
+    pub fn update<const N: usize>(&mut self, fluid: &mut Fluid<N>) -> bool {
+        if !self.playing {
+            return false;
+        }
+        let held = buttons::held();
+
+        for (side, held) in held.into_iter().enumerate() {
+            let step = if held { -PADDLE_SPEED } else { PADDLE_SPEED };
+            let top = (self.paddles[side] + step).clamp(0, HEIGHT - PADDLE_HEIGHT);
+            self.paddles[side] = top;
+            fluid.move_obstacle(side, paddle(side, top));
+
+            let (direction, front) = match side {
+                0 => (1.0, PADDLE_INSET + PADDLE_WIDTH),
+                _ => (-1.0, WIDTH - PADDLE_INSET - PADDLE_WIDTH - BAT_REACH),
+            };
+            if self.batted != Some(side) && fluid.particles_in(Rect::new(front, top, BAT_REACH, PADDLE_HEIGHT)) > 0 {
+                fluid.apply_impulse_at(front, top + PADDLE_HEIGHT / 2, BAT_RADIUS, direction * BAT_SPEED, 0.0);
+                self.batted = Some(side);
+            }
+
+            let behind = match side {
+                0 => Rect::new(0, 0, PADDLE_INSET - 1, HEIGHT),
+                _ => Rect::new(WIDTH - PADDLE_INSET + 1, 0, PADDLE_INSET - 1, HEIGHT),
+            };
+            if fluid.particles_in(behind) >= POINT_PARTICLES {
+                let scorer = 1 - side;
+                self.scores[scorer] = self.scores[scorer].saturating_add(1);
+                self.serve(side, fluid);
+            }
+        }
+        held.contains(&true)
+    }
+
+    /// Draw the paddles and the scores over the frame, while the game's
+    /// played
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        if !self.playing {
+            return;
+        }
+        for (side, score_x) in SCORE_X.into_iter().enumerate() {
+            let rect = paddle(side, self.paddles[side]);
+            display.fill_rect(rect.x as i32, rect.y as i32, rect.width as i32, rect.height as i32, true);
+            display.draw_number(score_x, 0, self.scores[side] as i32);
+        }
+    }
+
+    // Serve the water from the middle towards the given side
+    fn serve<const N: usize>(&mut self, side: usize, fluid: &mut Fluid<N>) {
+        let (vx, vy) = SERVE_VELOCITY;
+        let vx = if side == 0 { -vx } else { vx };
+        fluid.arrange(SERVE_LAYOUT);
+        fluid.apply_impulse_at(WIDTH / 2, HEIGHT / 2, i8::MAX, vx, vy);
+        self.batted = None;
+    }
+}
+
+
+// A paddle, by side (the left is 0), with its top at the given height
+fn paddle(side: usize, top: i8) -> Rect {
+    let x = match side {
+        0 => PADDLE_INSET,
+        _ => WIDTH - PADDLE_INSET - PADDLE_WIDTH,
+    };
+    Rect::new(x, top, PADDLE_WIDTH, PADDLE_HEIGHT)
+}
@@ -0,0 +1,167 @@
+//! The power-on self test, run once as the display's driver comes up,
+//! before the splash: it checks the display acknowledges on the bus, the
+//! accelerometer (when one was detected) reads, and the fixed-point math
+//! gives the results it should, then flashes test patterns on the panel
+//! to show every pixel lights. A failed check is logged, and blinked on
+//! the status LED, when it's fitted, as a code: the check's number of
+//! short blinks (see Check), three times over. The simulation starts
+//! regardless, so a board with e.g. a loose sensor still runs.
+//!
+//! Nothing here waits: the checks run once, and the patterns and the
+//! blink code play out over the frames that follow, each step lasting
+//! until a deadline on the monotonic's milliseconds, checked once a
+//! frame (see SelfTest), so the shell and the scheduler are served
+//! throughout.
+
+use core::hint::black_box;
+use embedded_hal_1::i2c::I2c;
+use fluid_core::fixed::FixedPt;
+use crate::accel::Accelerometer;
+use crate::log;
+use crate::oled::{OLEDDriver, Pattern};
+
+
+// The patterns flashed, and how long each is shown, in ms
+const PATTERNS: [Pattern; 2] = [Pattern::AllOn, Pattern::Checkerboard];
+const PATTERN_MS: u32 = 300;
+
+// A blink code's timing: each blink's on and off time, the pause
+// between codes, in ms, and the times the code is repeated
+#[cfg(feature = "led")]
+const BLINK_MS: u32 = 200;
+#[cfg(feature = "led")]
+const CODE_PAUSE_MS: u32 = 1_000;
+#[cfg(feature = "led")]
+const CODE_REPEATS: u8 = 3;
+
+
+/// The checks, numbered by their blink codes
+#[derive(Copy, Clone, defmt::Format)]
+pub enum Check {
+    Display = 1,
+    Accelerometer = 2,
+    Math = 3,
+}
+
+
+/// The self test's progress through its patterns and its blink code
+pub struct SelfTest {
+    // The pattern shown, PATTERNS.len() once they've all been shown
+    pattern: u8,
+    // The blink code, or 0, and the step of it shown: each blink is two
+    // steps, on and off, then a pause ends the code
+    #[cfg(feature = "led")]
+    code: u8,
+    #[cfg(feature = "led")]
+    step: u8,
+    // When the pattern and the blink code's step end, in ms
+    pattern_until: u32,
+    #[cfg(feature = "led")]
+    step_until: u32,
+}
+
+impl SelfTest {
+    /// Create the self test, not yet run
+    pub const fn new() -> Self {
+        Self {
+            pattern: PATTERNS.len() as u8,
+            #[cfg(feature = "led")]
+            code: 0,
+            #[cfg(feature = "led")]
+            step: 0,
+            pattern_until: 0,
+            #[cfg(feature = "led")]
+            step_until: 0,
+        }
+    }
+
+    /// Run the checks, on the display and the accelerometer, if one was
+    /// detected, at the given time in ms, returning the first check
+    /// failed. The patterns follow, if the display passed, and the blink
+    /// code, if a check failed.
+    pub fn run<I: I2c>(&mut self, display: &mut OLEDDriver, accel: Option<&mut Accelerometer<I>>, now_ms: u32) -> Option<Check> {
+        let display_ok = display.self_check();
+        let failed = if !display_ok {
+            Some(Check::Display)
+        } else if accel.is_some_and(|accel| accel.read().is_err()) {
+            Some(Check::Accelerometer)
+        } else if !math_ok() {
+            Some(Check::Math)
+        } else {
+            None
+        };
+
+        if display_ok {
+            self.pattern = 0;
+            self.pattern_until = now_ms + PATTERN_MS;
+        }
+
+        match failed {
+            Some(check) => {
+                log::error!("post: {} failed", check);
+                #[cfg(feature = "led")]
+                {
+                    self.code = check as u8;
+                    self.step = 0;
+                    self.step_until = now_ms;
+                }
+            },
+            None => log::info!("post: passed"),
+        }
+        failed
+    }
+
+    /// Draw the test pattern due at the given time, in ms, returning false
+    /// once they've all been shown. Called once a frame, before the splash.
+    pub fn draw(&mut self, display: &mut OLEDDriver, now_ms: u32) -> bool {
+        if self.pattern as usize >= PATTERNS.len() {
+            return false;
+        }
+        if now_ms >= self.pattern_until {
+            self.pattern += 1;
+            self.pattern_until = now_ms + PATTERN_MS;
+            if self.pattern as usize >= PATTERNS.len() {
+                display.clear();
+                return false;
+            }
+        }
+        display.test_pattern(PATTERNS[self.pattern as usize]);
+        true
+    }
+
+    /// Blink the code's step due at the given time, in ms, on the status
+    /// LED. Called once a frame, with the LED left to it while blinking.
+    #[cfg(feature = "led")]
+    pub fn update(&mut self, now_ms: u32) {
+        if self.code == 0 || now_ms < self.step_until {
+            return;
+        }
+        let steps = self.code * 2 + 1;
+        let step = self.step % steps;
+        let blink = step < self.code * 2;
+        crate::led::light(blink && step.is_multiple_of(2));
+        self.step_until = now_ms + if blink { BLINK_MS } else { CODE_PAUSE_MS };
+        self.step += 1;
+        if self.step >= steps * CODE_REPEATS {
+            self.code = 0;
+        }
+    }
+
+    /// Determine if the blink code's still being blinked on the LED
+    #[cfg(feature = "led")]
+    pub fn is_blinking(&self) -> bool {
+        self.code != 0
+    }
+}
+
+
+// Check the fixed-point math the solver relies on, with the operands
+// hidden from the compiler so it's the core that works out the results
+fn math_ok() -> bool {
+    let [a, b, c] = black_box([FixedPt::from_f32(1.5), FixedPt::from_f32(-2.25), FixedPt::from_i8(7)]);
+    a.value == 3 << (FixedPt::BASE - 1)
+        && a * b == FixedPt::from_f32(-3.375)
+        && c / FixedPt::from_i8(2) == FixedPt::from_f32(3.5)
+        && b.abs() == FixedPt::from_f32(2.25)
+        && c * black_box(-3) == FixedPt::from_i8(-21)
+}
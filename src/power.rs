@@ -0,0 +1,125 @@
+//! Power management for battery operation: after a period without any
+//! input, the display is put to sleep and the core enters Stop mode,
+//! where every clock but the low speed oscillators is stopped. A press
+//! of the wake button (PA0, to ground) wakes the core through EXTI, and
+//! the simulation resumes where it left off, as RAM is retained.
+
+use cortex_m::peripheral::SCB;
+use stm32f0xx_hal::pac::{EXTI, PWR, RCC, SYSCFG};
+
+
+/// Tracks inactivity, in milliseconds of the monotonic's time so it
+/// doesn't stretch as the frame rate drops, and whether the device is
+/// asleep
+pub struct IdleManager {
+    timeout: Option<u32>,
+    // When the device was first found idle, since the last activity
+    since: Option<u32>,
+    asleep: bool,
+}
+
+#[allow(dead_code)]
+impl IdleManager {
+    /// Create a manager putting the device to sleep after the given
+    /// number of milliseconds without activity, or never with None
+    pub const fn new(timeout: Option<u32>) -> Self {
+        Self {
+            timeout,
+            since: None,
+            asleep: false,
+        }
+    }
+
+    /// Count the time to a frame, at the given milliseconds of uptime,
+    /// towards the timeout. Returns true once it expires, at which point
+    /// the device is considered asleep.
+    pub fn tick(&mut self, now_ms: u32) -> bool {
+        let Some(timeout) = self.timeout else {
+            return false;
+        };
+        let since = *self.since.get_or_insert(now_ms);
+        if now_ms.wrapping_sub(since) >= timeout {
+            self.asleep = true;
+        }
+        self.asleep
+    }
+
+    /// Record activity, e.g. an input, restarting the timeout.
+    /// Returns true if this woke the device.
+    pub fn activity(&mut self) -> bool {
+        let woke = self.asleep;
+        self.since = None;
+        self.asleep = false;
+        woke
+    }
+
+    /// Put the device to sleep now, as though the timeout expired, e.g.
+    /// as the battery fails
+    #[cfg(feature = "brownout")]
+    pub fn sleep(&mut self) {
+        self.asleep = true;
+    }
+
+    /// Determine if the device is asleep, and so should enter Stop mode
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+}
+
+
+/// Configure PA0 as the wake button, with its pull-up and an EXTI
+/// interrupt (EXTI0_1) on each press. The pin must be an input.
+pub fn init_wake_button(rcc: &RCC, syscfg: &SYSCFG, exti: &EXTI) {
+    rcc.apb2enr.modify(|_, w| w.syscfgen().enabled());
+    syscfg.exticr1.modify(|_, w| w.exti0().pa0());
+    exti.ftsr.modify(|_, w| w.tr0().enabled());
+    exti.imr.modify(|_, w| w.mr0().unmasked());
+
+    // Stop mode is configured once: the regulator runs in low power mode
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+    // SAFETY: PWR is only written here
+    let pwr = unsafe { &*PWR::ptr() };
+    pwr.cr.modify(|_, w| w.pdds().stop_mode().lpds().set_bit());
+}
+
+/// Acknowledge a press of the wake button, from its EXTI interrupt
+pub fn clear_wake_button() {
+    // SAFETY: a write-1-to-clear of the button's pending bit alone
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.pr.write(|w| unsafe { w.bits(1 << 0) });
+}
+
+/// Enter Stop mode until an interrupt, e.g. the wake button. This should
+/// be called with interrupts disabled, once the display's transmissions
+/// have ended, so no interrupt runs before the system clock is restored:
+/// the core wakes on the internal oscillator, and the PLL is restarted
+/// here with its previous configuration, if it was running.
+pub fn stop(scb: &mut SCB) {
+    // SAFETY: the RCC is configured at startup, and only the PLL's
+    //         enable and the clock switch are changed here
+    let rcc = unsafe { &*RCC::ptr() };
+    let pll = rcc.cfgr.read().sws().is_pll();
+
+    // USB's clock stops with the core, so the core only sleeps while a
+    // host has the device configured
+    #[cfg(feature = "usb")]
+    if crate::usb::is_configured() {
+        cortex_m::asm::wfi();
+        return;
+    }
+
+    scb.set_sleepdeep();
+    cortex_m::asm::wfi();
+    scb.clear_sleepdeep();
+
+    #[cfg(feature = "usb")]
+    crate::usb::restart_clock(rcc);
+
+    if !pll {
+        return;
+    }
+    rcc.cr.modify(|_, w| w.pllon().on());
+    while rcc.cr.read().pllrdy().is_not_ready() {}
+    rcc.cfgr.modify(|_, w| w.sw().pll());
+    while !rcc.cfgr.read().sws().is_pll() {}
+}
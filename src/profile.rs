@@ -0,0 +1,87 @@
+//! A lightweight profiler for the frame loop, measuring the cycles spent
+//! in each stage of the solver and waiting on the display's DMA, so
+//! optimizations can be quantified on the target. The averages are
+//! logged once per report period (see the log module for viewing them).
+//!
+//! The Cortex-M0 has no DWT cycle counter, so cycles are counted by
+//! SysTick (see frame::cycles).
+
+use fluid_core::Stage;
+#[cfg(debug_assertions)]
+use crate::log;
+
+
+/// The sections of a frame that are timed
+#[derive(Copy, Clone)]
+pub enum Section {
+    /// A stage of the solver
+    Solver(Stage),
+    /// Waiting for the previous frame's transmission to finish
+    DmaWait,
+}
+
+impl Section {
+    const COUNT: usize = Stage::ALL.len() + 1;
+
+    fn index(self) -> usize {
+        match self {
+            Section::Solver(stage) => stage as usize,
+            Section::DmaWait => Stage::ALL.len(),
+        }
+    }
+}
+
+
+/// Accumulates the cycles spent in each section over a number of frames
+pub struct Profiler {
+    totals: [u32; Section::COUNT],
+    frames: u16,
+    report_frames: u16,
+    last: u32,
+}
+
+impl Profiler {
+    /// Create a profiler reporting averages over every report_frames frames
+    pub const fn new(report_frames: u16) -> Self {
+        Self {
+            totals: [0; Section::COUNT],
+            frames: 0,
+            report_frames,
+            last: 0,
+        }
+    }
+
+    /// Start timing a frame at the given cycle count
+    pub fn start(&mut self, now: u32) {
+        self.last = now;
+    }
+
+    /// Attribute the cycles since the previous mark (or the start of
+    /// the frame) to the given section
+    pub fn mark(&mut self, section: Section, now: u32) {
+        let total = &mut self.totals[section.index()];
+        *total = total.wrapping_add(now.wrapping_sub(self.last));
+        self.last = now;
+    }
+
+    /// End a frame, logging the average cycles per section once enough
+    /// frames have been timed
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+        if self.frames < self.report_frames {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        {
+            let frames = self.frames as u32;
+            let [gravity, viscosity, velocity, relaxation, collisions, revision, dma_wait] = self.totals.map(|total| total / frames);
+            log::info!("profile: gravity={} viscosity={} velocity={} relaxation={} collisions={} revision={} dma_wait={} (cycles/frame)",
+                       gravity, viscosity, velocity, relaxation, collisions, revision, dma_wait);
+        }
+
+        self.totals = [0; Section::COUNT];
+        self.frames = 0;
+    }
+}
+
@@ -0,0 +1,99 @@
+//! The rain, an ambient mode: drops fall from random points along the
+//! top of the display into a pool, each throwing up spray as it lands
+//! (see Fluid::spray). The particles are fixed in number, so the rain is
+//! fed by a drain in the pool's floor, a sink: each drop is a particle
+//! taken from the drain and emitted at the top (see Fluid::emit), and as
+//! the rain refills the pool as fast as the drain empties it, the pool
+//! keeps its level.
+
+use fluid_core::{obstacle::Rect, rng::Rng, scene::SceneManager, Fluid};
+use crate::scenes;
+
+
+// The display's width
+const WIDTH: u32 = 125;
+
+// The drain, in the middle of the pool's floor
+const DRAIN: Rect = Rect::new(58, 56, 8, 5);
+
+// The most drops falling at once, and the frames between them
+const MAX_DROPS: usize = 6;
+const DROP_PERIOD: u8 = 4;
+
+// The drops' speed as they fall from the top, in pixels per step
+const DROP_SPEED: f32 = 1.0;
+
+// The spray thrown up as a drop lands: its radius, and the speed it's
+// thrown at, nearest the drop
+const SPRAY_RADIUS: i8 = 10;
+const SPRAY_SPEED: f32 = 2.5;
+
+
+// A falling drop: its particle, and the lowest it's fallen to
+#[derive(Copy, Clone)]
+struct Drop {
+    index: u8,
+    y: i8,
+}
+
+pub struct Rain {
+    on: bool,
+    drops: [Option<Drop>; MAX_DROPS],
+    wait: u8,
+}
+
+impl Rain {
+    /// Create the rain, stopped
+    pub const fn new() -> Self {
+        Self { on: false, drops: [None; MAX_DROPS], wait: 0 }
+    }
+
+    /// Start the rain, over a still pool
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::RAIN, 0, fluid);
+        *self = Self { on: true, ..Self::new() };
+    }
+
+    /// Stop the rain, e.g. to return to the demo
+    pub fn stop(&mut self) {
+        self.on = false;
+    }
+
+    /// Spray the drops that have landed, and let another fall from the
+    /// drain, while it's raining. Returns true as a drop falls, to keep
+    /// the display awake.
+    pub fn update<const N: usize>(&mut self, fluid: &mut Fluid<N>, rng: &mut Rng) -> bool {
+        if !self.on {
+            return false;
+        }
+        // A drop has landed once it stops falling
+        for slot in self.drops.iter_mut() {
+            let Some(drop) = slot else {
+                continue;
+            };
+            let (x, y) = fluid.get_particles()[drop.index as usize].get_display_position();
+            if y > drop.y {
+                drop.y = y;
+                continue;
+            }
+            fluid.spray(x, y, SPRAY_RADIUS, SPRAY_SPEED);
+            *slot = None;
+        }
+
+        self.wait = self.wait.saturating_sub(1);
+        if self.wait > 0 {
+            return false;
+        }
+        let Some(slot) = self.drops.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        let Some(index) = fluid.particle_in(DRAIN) else {
+            return false;
+        };
+        let x = ((rng.next_u32() >> 16) * WIDTH) >> 16;
+        fluid.emit(index, x as i8, 0, 0.0, DROP_SPEED);
+        *slot = Some(Drop { index: index as u8, y: 0 });
+        self.wait = DROP_PERIOD;
+        true
+    }
+}
@@ -0,0 +1,281 @@
+//! A compact remote control protocol for a Bluetooth serial module, an
+//! HC-05 (Bluetooth Classic) or HM-10 (BLE), wired to the shell's USART
+//! in place of a USB-serial adapter, so a phone app can tune and monitor
+//! the simulation wirelessly. The module must be set to the shell's
+//! 115200 baud beforehand, with `AT+UART=115200,0,0` (HC-05) or
+//! `AT+BAUD4` (HM-10).
+//!
+//! The protocol is binary, in short frames, each starting with a sync
+//! byte that's never text, so the shell picks them out of its input (see
+//! Shell::receive_frame) and still serves a terminal alongside. A frame
+//! isn't echoed; each is acknowledged with a frame. The phone sends
+//! commands of 4 bytes:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 1     | the sync byte, 0xA5                                         |
+//! | 1     | the command (see below)                                     |
+//! | 1     | its argument, or 0                                          |
+//! | 1     | the checksum: the wrapping sum of the command and argument  |
+//!
+//! The commands are 0x01 `gravity <0-15>`, 0x02 `gravity cycle`, 0x03
+//! `viscosity <0-25>`, 0x04 `contrast <0-15>`, 0x05 `scene <n>`, 0x06
+//! `hud` and 0x07 `telemetry <0-60>`, which streams the telemetry every
+//! given seconds, or stops it (0). The board sends replies:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 1     | the sync byte, 0xA5                                         |
+//! | 1     | the reply: 0x01 an acknowledgement, 0x02 the telemetry      |
+//! | 1     | the length of the payload, n                                |
+//! | n     | the payload                                                 |
+//! | 1     | the checksum: the wrapping sum of every byte but the sync   |
+//!
+//! An acknowledgement's payload is the command, and 0 if it was carried
+//! out or 1 if it was invalid. The telemetry's, little-endian, is:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 4     | the uptime, in seconds                                      |
+//! | 2     | the frames dropped, saturated                               |
+//! | 1     | the CPU load, in percent                                    |
+//! | 2     | the stack headroom, in bytes                                |
+//! | 2     | the fluid's kinetic energy, in 16ths, saturated             |
+//! | 1     | the viscosity's step                                        |
+//! | 1     | gravity's step, or 0xFF while the demo steers it            |
+//! | 1     | the contrast's step                                         |
+//!
+//! Every frame fits in one of the HM-10's 20 byte notifications. The
+//! telemetry is written by the frame loop, between the shell's replies,
+//! and takes 1.6ms to send.
+
+use embedded_hal::serial::Write;
+use fluid_core::{fixed::FixedPt, Fluid};
+use stm32f0xx_hal::pac::USART1;
+use crate::{load, stack};
+use crate::config::AppConfig;
+use crate::shell::Command;
+use crate::tuning::Parameter;
+
+
+/// The byte starting each frame
+pub const SYNC: u8 = 0xA5;
+
+// The commands
+const GRAVITY: u8 = 0x01;
+const GRAVITY_CYCLE: u8 = 0x02;
+const VISCOSITY: u8 = 0x03;
+const CONTRAST: u8 = 0x04;
+const SCENE: u8 = 0x05;
+const HUD: u8 = 0x06;
+const TELEMETRY: u8 = 0x07;
+
+// The replies
+const REPLY_ACK: u8 = 0x01;
+const REPLY_TELEMETRY: u8 = 0x02;
+
+// The bytes of a command frame after the sync byte
+const COMMAND_LENGTH: usize = 3;
+
+// The longest payload, the telemetry's, and the bytes of a reply besides
+// its payload: the sync byte, the reply and length, and the checksum
+const MAX_PAYLOAD: usize = 14;
+const OVERHEAD: usize = 4;
+
+/// The longest period of the telemetry, in seconds
+pub const MAX_TELEMETRY_SECONDS: u8 = 60;
+
+// The frames per second
+const FRAME_RATE: u16 = 30;
+
+// The kinetic energy's fractional bits sent
+const ENERGY_FRACTION_BITS: u8 = 4;
+
+
+/// A byte received by the shell, as the remote protocol sees it
+pub enum Received {
+    /// Text, for the shell
+    Text,
+    /// Part of a frame, which hasn't ended
+    Partial,
+    /// The end of a frame: the command, unless it's invalid, and the
+    /// acknowledgement to reply with
+    Frame(Option<Command>, Reply),
+}
+
+/// Assembles received bytes into command frames
+pub struct Decoder {
+    frame: [u8; COMMAND_LENGTH],
+    // The bytes of the frame received, while one is
+    len: Option<u8>,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { frame: [0; COMMAND_LENGTH], len: None }
+    }
+
+    /// Take a received byte, returning the command once a frame ends
+    pub fn receive(&mut self, byte: u8) -> Received {
+        let Some(len) = self.len else {
+            if byte == SYNC {
+                self.len = Some(0);
+                return Received::Partial;
+            }
+            return Received::Text;
+        };
+        self.frame[len as usize] = byte;
+        if (len as usize) < COMMAND_LENGTH - 1 {
+            self.len = Some(len + 1);
+            return Received::Partial;
+        }
+        self.len = None;
+
+        let [kind, argument, checksum] = self.frame;
+        let command = match checksum == kind.wrapping_add(argument) {
+            true => parse(kind, argument),
+            false => None,
+        };
+        Received::Frame(command, Reply::new(REPLY_ACK, &[kind, command.is_none() as u8]))
+    }
+}
+
+
+/// The state of the simulation reported by the telemetry
+pub struct Telemetry {
+    pub uptime: u32,
+    pub dropped: u32,
+    pub load: u8,
+    pub headroom: usize,
+    pub energy: FixedPt,
+    pub viscosity: u8,
+    pub gravity: Option<u8>,
+    pub contrast: u8,
+}
+
+impl Telemetry {
+    /// Gather the telemetry from the frame loop's state: the uptime in
+    /// seconds, the frames dropped, the tuner's steps (see Tuner::steps)
+    /// and the fluid
+    pub fn gather<const N: usize>(uptime: u32, dropped: u32, steps: (u8, Option<u8>, u8), fluid: &Fluid<N>) -> Self {
+        let (viscosity, gravity, contrast) = steps;
+        Self {
+            uptime,
+            dropped,
+            load: load::busy_percent(),
+            headroom: stack::headroom(),
+            energy: fluid.kinetic_energy(),
+            viscosity,
+            gravity,
+            contrast,
+        }
+    }
+
+    /// The telemetry's reply
+    pub fn reply(&self) -> Reply {
+        let [u0, u1, u2, u3] = self.uptime.to_le_bytes();
+        let [d0, d1] = (self.dropped.min(u16::MAX as u32) as u16).to_le_bytes();
+        let [h0, h1] = (self.headroom.min(u16::MAX as usize) as u16).to_le_bytes();
+        let energy = self.energy.value >> (FixedPt::BASE - ENERGY_FRACTION_BITS);
+        let [e0, e1] = (energy.clamp(0, u16::MAX as i32) as u16).to_le_bytes();
+        Reply::new(REPLY_TELEMETRY, &[
+            u0, u1, u2, u3, d0, d1, self.load, h0, h1, e0, e1,
+            self.viscosity, self.gravity.unwrap_or(u8::MAX), self.contrast,
+        ])
+    }
+}
+
+/// Times the telemetry, streamed every so many seconds once requested
+pub struct TelemetryTimer {
+    // The frames between reports, while streaming, and until the next
+    period: Option<u16>,
+    wait: u16,
+}
+
+impl TelemetryTimer {
+    /// Create a timer, not streaming
+    pub const fn new() -> Self {
+        Self { period: None, wait: 0 }
+    }
+
+    /// Stream the telemetry every given seconds, from the next frame,
+    /// or stop it (0)
+    pub fn set_period(&mut self, seconds: u8) {
+        self.period = (seconds > 0).then_some(seconds as u16 * FRAME_RATE);
+        self.wait = 0;
+    }
+
+    /// Count a frame, returning true when the telemetry is due
+    pub fn tick(&mut self) -> bool {
+        let Some(period) = self.period else {
+            return false;
+        };
+        match self.wait.checked_sub(1) {
+            Some(wait) => {
+                self.wait = wait;
+                false
+            },
+            None => {
+                self.wait = period - 1;
+                true
+            },
+        }
+    }
+}
+
+
+/// A reply frame
+pub struct Reply {
+    bytes: [u8; OVERHEAD + MAX_PAYLOAD],
+    len: u8,
+}
+
+impl Reply {
+    fn new(kind: u8, payload: &[u8]) -> Self {
+        let mut bytes = [0; OVERHEAD + MAX_PAYLOAD];
+        let len = OVERHEAD + payload.len();
+        bytes[..3].copy_from_slice(&[SYNC, kind, payload.len() as u8]);
+        bytes[3..len - 1].copy_from_slice(payload);
+        bytes[len - 1] = bytes[1..len - 1].iter().fold(0, |sum, byte| sum.wrapping_add(*byte));
+        Self { bytes, len: len as u8 }
+    }
+
+    /// Write the reply to a serial port, waiting for room as it's written
+    pub fn write(&self, port: &mut impl Write<u8>) {
+        for byte in &self.bytes[..self.len as usize] {
+            while port.write(*byte).is_err() {}
+        }
+    }
+
+    /// Send the reply over USART1, outside the shell, waiting for each
+    /// byte to be taken
+    pub fn send(&self) {
+        // SAFETY: only the USART's status is read and its data register
+        //         written, which the shell doesn't do while the frame loop
+        //         runs, at the same priority
+        let usart = unsafe { &*USART1::ptr() };
+        for byte in &self.bytes[..self.len as usize] {
+            while usart.isr.read().txe().bit_is_clear() {}
+            usart.tdr.write(|w| w.tdr().bits(*byte as u16));
+        }
+    }
+}
+
+
+// Parse a command frame's command and argument, if they're valid
+fn parse(kind: u8, argument: u8) -> Option<Command> {
+    let set = |parameter: Parameter| {
+        let step = argument as i16;
+        (step <= parameter.max_step()).then_some(Command::Set(parameter, step))
+    };
+    match kind {
+        GRAVITY => set(Parameter::GravityAngle),
+        GRAVITY_CYCLE => Some(Command::GravityCycle),
+        VISCOSITY => set(Parameter::Viscosity),
+        CONTRAST => set(Parameter::Contrast),
+        SCENE => ((argument as usize) < AppConfig::DEFAULT.program.len()).then_some(Command::Scene(argument)),
+        HUD => Some(Command::Hud),
+        TELEMETRY => (argument <= MAX_TELEMETRY_SECONDS).then_some(Command::Telemetry(argument)),
+        _ => None,
+    }
+}
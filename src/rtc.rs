@@ -0,0 +1,110 @@
+//! The real-time clock, keeping the time of day for the clock mode (see
+//! clock.rs) and timing the hourglass (see hourglass.rs). It's clocked
+//! by the LSI oscillator, as the board has no 32.768kHz crystal, so it's
+//! only as accurate as the LSI (which may be off by several percent) and
+//! is best set now and then with the shell's `time` command. The RTC is in the backup domain, so it keeps the time
+//! through a reset and in Stop mode, but not without power.
+
+use stm32f0xx_hal::pac::{rtc::RegisterBlock, PWR, RCC, RTC};
+
+
+// The LSI's nominal frequency is 40kHz, divided by the asynchronous and
+// synchronous prescalers (each less one) to 1Hz
+const PREDIV_A: u32 = 100 - 1;
+const PREDIV_S: u32 = 400 - 1;
+
+// The keys unlocking the RTC's registers, and any other value locking them
+const UNLOCK_KEYS: [u32; 2] = [0xCA, 0x53];
+const LOCK_KEY: u32 = 0xFF;
+
+
+/// A time of day, on the 24 hour clock
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub struct Time {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+#[cfg(feature = "clock")]
+impl Time {
+    /// Parse a time given as HH:MM, e.g. "07:45", on the minute
+    pub fn parse(text: &str) -> Option<Self> {
+        let (hours, minutes) = text.split_once(':')?;
+        let field = |text: &str, limit: u8| match *text.as_bytes() {
+            [tens @ b'0'..=b'9', units @ b'0'..=b'9'] => Some((tens - b'0') * 10 + (units - b'0')).filter(|&value| value < limit),
+            _ => None,
+        };
+        Some(Self { hours: field(hours, 24)?, minutes: field(minutes, 60)?, seconds: 0 })
+    }
+
+    /// The hours and minutes, as HH:MM
+    pub fn text(&self) -> [u8; 5] {
+        let digit = |value: u8| b'0' + value;
+        [digit(self.hours / 10), digit(self.hours % 10), b':', digit(self.minutes / 10), digit(self.minutes % 10)]
+    }
+}
+
+
+/// Start the RTC from the LSI at midnight, unless it's still running
+/// from before a reset, and allow its registers to be written
+pub fn init(rtc: &RTC, rcc: &RCC, pwr: &PWR) {
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+
+    // Unlike the RTC, the LSI stops with each reset
+    rcc.csr.modify(|_, w| w.lsion().on());
+    while rcc.csr.read().lsirdy().is_not_ready() {}
+
+    if rcc.bdcr.read().rtcen().is_disabled() {
+        rcc.bdcr.modify(|_, w| w.rtcsel().lsi().rtcen().enabled());
+        // SAFETY: both prescalers are within their fields
+        edit(rtc, |rtc| rtc.prer.write(|w| unsafe { w.bits((PREDIV_A << 16) | PREDIV_S) }));
+    }
+}
+
+/// The time of day
+pub fn now() -> Time {
+    // SAFETY: a read of the calendar, which only this module uses
+    let rtc = unsafe { &*RTC::ptr() };
+
+    // Reading the time locks the calendar's shadow registers until the
+    // date is read
+    let tr = rtc.tr.read();
+    let _ = rtc.dr.read();
+    Time {
+        hours: tr.ht().bits() * 10 + tr.hu().bits(),
+        minutes: tr.mnt().bits() * 10 + tr.mnu().bits(),
+        seconds: tr.st().bits() * 10 + tr.su().bits(),
+    }
+}
+
+/// Set the time of day
+#[cfg(feature = "clock")]
+pub fn set(time: Time) {
+    // SAFETY: only the calendar is modified, which only this module uses
+    let rtc = unsafe { &*RTC::ptr() };
+
+    let bcd = |value: u8| (((value / 10) << 4) | (value % 10)) as u32;
+    let bits = (bcd(time.hours) << 16) | (bcd(time.minutes) << 8) | bcd(time.seconds);
+    // SAFETY: each field is BCD within its range, and the time is AM/24 hour
+    edit(rtc, |rtc| rtc.tr.write(|w| unsafe { w.bits(bits) }));
+}
+
+// Unlock the RTC's registers and stop the calendar while they're edited,
+// then restart it and wait for the shadow registers to catch up
+fn edit(rtc: &RegisterBlock, edit: impl FnOnce(&RegisterBlock)) {
+    for key in UNLOCK_KEYS {
+        // SAFETY: any key is valid
+        rtc.wpr.write(|w| unsafe { w.bits(key) });
+    }
+    rtc.isr.modify(|_, w| w.init().set_bit());
+    while rtc.isr.read().initf().bit_is_clear() {}
+
+    edit(rtc);
+
+    rtc.isr.modify(|_, w| w.init().clear_bit().rsf().clear_bit());
+    // SAFETY: any key is valid
+    rtc.wpr.write(|w| unsafe { w.bits(LOCK_KEY) });
+    while rtc.isr.read().rsf().bit_is_clear() {}
+}
@@ -0,0 +1,164 @@
+//! Clock scaling, to save power while the water's calm: the system clock
+//! runs at 48MHz from the PLL in performance mode, or at 8MHz from the
+//! internal oscillator alone, with the PLL stopped, in low power mode,
+//! where frames are scheduled less often as the solver takes longer
+//! (see AppConfig). The mode's selected by the shell's `speed` command,
+//! or follows the fluid's kinetic energy: it drops to low power once the
+//! water's been calm for a couple of seconds, and ramps back up as soon
+//! as it splashes.
+//!
+//! What's clocked from the system clock follows it: SysTick's reload is
+//! rescaled, so the monotonic keeps counting milliseconds, as are the
+//! shell's baud rate and the display bus's timing (see
+//! DMAi2c::set_kernel_clock), and the buzzer's timer is rescaled as it
+//! chirps. The status LED's PWM slows down, still too fast to flicker.
+
+use cortex_m::peripheral::SYST;
+use fluid_core::fixed::FixedPt;
+use stm32f0xx_hal::pac::{FLASH, RCC, USART1};
+use crate::board::{self, HSI_HZ, SHELL_BAUD, SYSCLK_HZ};
+use crate::frame::TICK_HZ;
+use crate::oled::DMAi2c;
+#[cfg(feature = "stream")]
+use crate::stream;
+
+
+// The kinetic energy under which the water's calm, for long enough to
+// drop to low power, and over which it's splashing, ramping back up
+const CALM: FixedPt = FixedPt::from_f32(0.2);
+const SPLASHING: FixedPt = FixedPt::from_f32(0.5);
+const CALM_FRAMES: u8 = 20;
+
+// The bit times allowed for the shell's last byte to finish sending
+// before the switch, a byte's ten with a margin, should the USART stick
+const DRAIN_BITS: u32 = 20;
+
+
+/// The system clock's modes
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum ClockMode {
+    /// 48MHz, from the PLL
+    Performance,
+    /// 8MHz, from the internal oscillator
+    LowPower,
+}
+
+impl ClockMode {
+    /// The system clock frequency in the mode
+    pub const fn sysclk_hz(self) -> u32 {
+        match self {
+            ClockMode::Performance => SYSCLK_HZ,
+            ClockMode::LowPower => HSI_HZ,
+        }
+    }
+}
+
+
+/// Selects the clock mode, as set or following the fluid's energy
+pub struct ClockScaler {
+    mode: ClockMode,
+    // The mode set from the shell, or None to follow the energy
+    fixed: Option<ClockMode>,
+    calm_frames: u8,
+}
+
+impl ClockScaler {
+    /// Create a scaler in performance mode, following the fluid's energy
+    pub const fn new() -> Self {
+        Self {
+            mode: ClockMode::Performance,
+            fixed: None,
+            calm_frames: 0,
+        }
+    }
+
+    /// Fix the mode, or follow the fluid's energy (None)
+    pub fn set(&mut self, mode: Option<ClockMode>) {
+        self.fixed = mode;
+        self.calm_frames = 0;
+    }
+
+    /// Switch the mode as set, or for the fluid's kinetic energy, once a
+    /// frame. Returns the mode switched to, if it switched, for the frame
+    /// rate to follow. The frame's transmission must be queued first, as
+    /// the display bus is retimed once it's sent.
+    pub fn update(&mut self, kinetic_energy: FixedPt) -> Option<ClockMode> {
+        let mode = match self.fixed {
+            Some(mode) => mode,
+            None if kinetic_energy > SPLASHING => {
+                self.calm_frames = 0;
+                ClockMode::Performance
+            },
+            None if kinetic_energy < CALM => {
+                self.calm_frames = self.calm_frames.saturating_add(1);
+                match self.calm_frames >= CALM_FRAMES {
+                    true => ClockMode::LowPower,
+                    false => self.mode,
+                }
+            },
+            None => {
+                self.calm_frames = 0;
+                self.mode
+            },
+        };
+        if mode == self.mode {
+            return None;
+        }
+        switch(mode);
+        self.mode = mode;
+        Some(mode)
+    }
+}
+
+
+// Switch the system clock, rescaling what's clocked from it, once the
+// display bus, the stream and the shell's USART are idle
+fn switch(mode: ClockMode) {
+    DMAi2c::wait_idle().ok();
+    #[cfg(feature = "stream")]
+    stream::wait_idle();
+
+    // SAFETY: the clock switch, the PLL's enable, the flash's wait
+    //         states, SysTick's reload and the USART's baud rate are
+    //         only changed here and at startup, and the USART's only
+    //         written by the shell, at the simulation's priority
+    let rcc = unsafe { &*RCC::ptr() };
+    let flash = unsafe { &*FLASH::ptr() };
+    let usart = unsafe { &*USART1::ptr() };
+    let hz = mode.sysclk_hz();
+    let bit_cycles = board::sysclk_hz() / SHELL_BAUD;
+    let mut bits = DRAIN_BITS;
+    while usart.isr.read().tc().bit_is_clear() && bits > 0 {
+        bits -= 1;
+        cortex_m::asm::delay(bit_cycles);
+    }
+
+    cortex_m::interrupt::free(|_| {
+        match mode {
+            ClockMode::Performance => {
+                // a wait state is needed above 24MHz
+                flash.acr.modify(|_, w| w.latency().ws1());
+                rcc.cr.modify(|_, w| w.pllon().on());
+                while rcc.cr.read().pllrdy().is_not_ready() {}
+                rcc.cfgr.modify(|_, w| w.sw().pll());
+                while !rcc.cfgr.read().sws().is_pll() {}
+            },
+            ClockMode::LowPower => {
+                rcc.cfgr.modify(|_, w| w.sw().hsi());
+                while !rcc.cfgr.read().sws().is_hsi() {}
+                rcc.cr.modify(|_, w| w.pllon().off());
+                flash.acr.modify(|_, w| w.latency().ws0());
+            },
+        }
+
+        // SAFETY: SysTick's reload, for the monotonic's millisecond ticks
+        unsafe { (*SYST::PTR).rvr.write(hz / TICK_HZ - 1) };
+
+        // the baud rate can only be set while the USART's disabled
+        usart.cr1.modify(|_, w| w.ue().disabled());
+        usart.brr.write(|w| unsafe { w.bits(hz / SHELL_BAUD) });
+        usart.cr1.modify(|_, w| w.ue().enabled());
+    });
+
+    DMAi2c::set_kernel_clock(hz);
+}
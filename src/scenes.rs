@@ -0,0 +1,309 @@
+//! The programs of scenes: the demo, played in turn while nothing else
+//! (the accelerometer, or gravity tuned by hand) takes over gravity, and
+//! the clock's, the maze's, Pong's, the lava lamp's, the hourglass's, the
+//! rain's, the painting's, the split screen's, the attract mode's and
+//! the parameter sweep's. Each frame is 1/30s.
+
+use fluid_core::Layout;
+use fluid_core::keyframe::{Easing::{EaseInOut, Linear}, Keyframe};
+use fluid_core::scene::Scene;
+use crate::artwork;
+
+
+// The diagonal components of a unit vector
+const D: f32 = core::f32::consts::FRAC_1_SQRT_2;
+
+// The quadratic viscosities of the attract mode's scenes: the tuner's
+// default, as water's, none, and four times the default
+#[cfg(feature = "attract")]
+const WATER: Option<(f32, f32)> = Some((0.0, 0.1));
+#[cfg(feature = "attract")]
+const INVISCID: Option<(f32, f32)> = Some((0.0, 0.0));
+#[cfg(feature = "attract")]
+const THICK: Option<(f32, f32)> = Some((0.0, 0.4));
+
+
+pub const DEMO: &[Scene] = &[
+    // The logo, floating, then sloshing around the display, with gravity
+    // turning over a second between directions
+    Scene {
+        name: "logo",
+        layout: Some(Layout::Logo),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(285, 0.0, 0.0),
+            Keyframe::eased(315, 0.0, 1.0, EaseInOut),
+            Keyframe::new(385, 0.0, 1.0),
+            Keyframe::eased(415, 1.0, 0.0, EaseInOut),
+            Keyframe::new(585, 1.0, 0.0),
+            Keyframe::eased(615, -1.0, 0.0, EaseInOut),
+            Keyframe::new(885, -1.0, 0.0),
+            Keyframe::eased(915, 0.0, -1.0, EaseInOut),
+            Keyframe::new(985, 0.0, -1.0),
+            Keyframe::eased(1015, 0.0, 0.0, EaseInOut),
+        ],
+        frames: 1300,
+    },
+    // A column along the left wall, collapsing like a breaking dam, and
+    // flowing back
+    Scene {
+        name: "dam",
+        layout: Some(Layout::Block { x: 0, y: 1, columns: 6 }),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(0, 0.0, 1.0),
+            Keyframe::new(300, -1.0, 0.0),
+            Keyframe::new(360, 0.0, 1.0),
+        ],
+        frames: 600,
+    },
+    // A slab dropped from the top, splashing on the floor
+    Scene {
+        name: "drop",
+        layout: Some(Layout::Block { x: 32, y: 0, columns: 10 }),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(0, 0.0, 1.0),
+        ],
+        frames: 300,
+    },
+    // The puddle from the drop, swirled by gravity turning clockwise
+    // twice, then settling
+    Scene {
+        name: "swirl",
+        layout: None,
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(0, 0.0, 1.0),
+            Keyframe::eased(20, -D, D, Linear),
+            Keyframe::eased(40, -1.0, 0.0, Linear),
+            Keyframe::eased(60, -D, -D, Linear),
+            Keyframe::eased(80, 0.0, -1.0, Linear),
+            Keyframe::eased(100, D, -D, Linear),
+            Keyframe::eased(120, 1.0, 0.0, Linear),
+            Keyframe::eased(140, D, D, Linear),
+            Keyframe::eased(160, 0.0, 1.0, Linear),
+            Keyframe::eased(180, -D, D, Linear),
+            Keyframe::eased(200, -1.0, 0.0, Linear),
+            Keyframe::eased(220, -D, -D, Linear),
+            Keyframe::eased(240, 0.0, -1.0, Linear),
+            Keyframe::eased(260, D, -D, Linear),
+            Keyframe::eased(280, 1.0, 0.0, Linear),
+            Keyframe::eased(300, D, D, Linear),
+            Keyframe::eased(320, 0.0, 1.0, Linear),
+        ],
+        frames: 400,
+    },
+    // Gravity tracing a figure-eight, (sin t, sin 2t), twice, sloshing
+    // the water from corner to corner, then settling
+    Scene {
+        name: "eight",
+        layout: None,
+        viscosity: None,
+        gravity: &[
+            Keyframe::eased(24, D, 1.0, Linear),
+            Keyframe::eased(48, 1.0, 0.0, Linear),
+            Keyframe::eased(72, D, -1.0, Linear),
+            Keyframe::eased(96, 0.0, 0.0, Linear),
+            Keyframe::eased(120, -D, 1.0, Linear),
+            Keyframe::eased(144, -1.0, 0.0, Linear),
+            Keyframe::eased(168, -D, -1.0, Linear),
+            Keyframe::eased(192, 0.0, 0.0, Linear),
+            Keyframe::eased(216, D, 1.0, Linear),
+            Keyframe::eased(240, 1.0, 0.0, Linear),
+            Keyframe::eased(264, D, -1.0, Linear),
+            Keyframe::eased(288, 0.0, 0.0, Linear),
+            Keyframe::eased(312, -D, 1.0, Linear),
+            Keyframe::eased(336, -1.0, 0.0, Linear),
+            Keyframe::eased(360, -D, -1.0, Linear),
+            Keyframe::eased(384, 0.0, 0.0, Linear),
+            Keyframe::eased(414, 0.0, 1.0, EaseInOut),
+        ],
+        frames: 480,
+    },
+    // A heart, drawn in art/heart_layout.xbm, floating, then breaking
+    // as gravity eases in
+    Scene {
+        name: "heart",
+        layout: Some(Layout::Positions(artwork::HEART_LAYOUT)),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(120, 0.0, 0.0),
+            Keyframe::eased(150, 0.0, 1.0, EaseInOut),
+        ],
+        frames: 360,
+    },
+];
+
+
+/// The clock's scene (see clock.rs), started over as the time reforms
+/// each minute: the time floats for 40 seconds, then gravity eases in
+/// over 2 seconds and collapses it to the floor
+#[cfg(feature = "clock")]
+pub const CLOCK: &[Scene] = &[
+    Scene {
+        name: "clock",
+        layout: None,
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(1200, 0.0, 0.0),
+            Keyframe::eased(1260, 0.0, 1.0, EaseInOut),
+        ],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The maze's scene (see maze.rs): the water starts in the top left, and
+/// falls until the player tilts it
+#[cfg(feature = "maze")]
+pub const MAZE: &[Scene] = &[
+    Scene {
+        name: "maze",
+        layout: Some(Layout::Block { x: 0, y: 0, columns: 20 }),
+        viscosity: None,
+        gravity: &[Keyframe::new(0, 0.0, 1.0)],
+        frames: u16::MAX,
+    },
+];
+
+
+/// Pong's scene (see pong.rs): the water floats without gravity, as the
+/// game serves it from the middle
+#[cfg(feature = "pong")]
+pub const PONG: &[Scene] = &[
+    Scene {
+        name: "pong",
+        layout: None,
+        viscosity: None,
+        gravity: &[],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The lava lamp's scene (see lava.rs): the water and the wax settle in
+/// layers, and gravity sways gently from side to side, over 2 minutes
+#[cfg(feature = "lava")]
+pub const LAVA: &[Scene] = &[
+    Scene {
+        name: "lava",
+        layout: Some(Layout::Block { x: 2, y: 31, columns: 20 }),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(0, 0.0, 1.0),
+            Keyframe::eased(900, 0.2, 1.0, EaseInOut),
+            Keyframe::eased(2700, -0.2, 1.0, EaseInOut),
+            Keyframe::eased(3600, 0.0, 1.0, EaseInOut),
+        ],
+        frames: 3600,
+    },
+];
+
+
+/// The hourglass's scenes (see hourglass.rs): upright, then flipped, with
+/// gravity inverted, in turn as the time's up
+#[cfg(feature = "hourglass")]
+pub const HOURGLASS: &[Scene] = &[
+    Scene {
+        name: "hourglass",
+        layout: None,
+        viscosity: None,
+        gravity: &[Keyframe::new(0, 0.0, 1.0)],
+        frames: u16::MAX,
+    },
+    Scene {
+        name: "hourglass flipped",
+        layout: None,
+        viscosity: None,
+        gravity: &[Keyframe::new(0, 0.0, -1.0)],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The rain's scene (see rain.rs): the pool, in the bottom of the display
+#[cfg(feature = "rain")]
+pub const RAIN: &[Scene] = &[
+    Scene {
+        name: "rain",
+        layout: Some(Layout::Block { x: 0, y: 43, columns: 21 }),
+        viscosity: None,
+        gravity: &[Keyframe::new(0, 0.0, 1.0)],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The painting's scene (see paint.rs): the water to pour from starts in
+/// a pool along the bottom, and there's no gravity
+#[cfg(feature = "paint")]
+pub const PAINT: &[Scene] = &[
+    Scene {
+        name: "paint",
+        layout: Some(Layout::Block { x: 0, y: 49, columns: 21 }),
+        viscosity: None,
+        gravity: &[],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The split screen's scene (see split.rs), in each half: a column
+/// along the left wall, collapsing like a breaking dam, then sloshed
+/// right and left, for the halves' viscosities to tell apart
+#[cfg(feature = "split")]
+pub const SPLIT: &[Scene] = &[
+    Scene {
+        name: "split",
+        layout: Some(Layout::Block { x: 0, y: 1, columns: 4 }),
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(150, 0.0, 1.0),
+            Keyframe::eased(180, 1.0, 0.0, EaseInOut),
+            Keyframe::new(270, 1.0, 0.0),
+            Keyframe::eased(330, -1.0, 0.0, EaseInOut),
+            Keyframe::new(420, -1.0, 0.0),
+            Keyframe::eased(450, 0.0, 1.0, EaseInOut),
+        ],
+        frames: u16::MAX,
+    },
+];
+
+
+/// The attract mode's scenes (see attract.rs): the demo's, some played
+/// at more than one viscosity to tell them apart, each named by the
+/// caption scrolled across it
+#[cfg(feature = "attract")]
+pub const ATTRACT: &[Scene] = &[
+    Scene { name: "a fluid of particles, viscosity x1", viscosity: WATER, ..DEMO[0] },
+    Scene { name: "breaking dam, viscosity x1", viscosity: WATER, ..DEMO[1] },
+    Scene { name: "breaking dam, viscosity x4", viscosity: THICK, ..DEMO[1] },
+    Scene { name: "drop, no viscosity", viscosity: INVISCID, ..DEMO[2] },
+    Scene { name: "drop, viscosity x4", viscosity: THICK, ..DEMO[2] },
+    Scene { name: "swirl, viscosity x4", ..DEMO[3] },
+    Scene { name: "figure eight, viscosity x1", viscosity: WATER, ..DEMO[4] },
+    Scene { name: "heart, viscosity x1", ..DEMO[5] },
+];
+
+
+/// The parameter sweep's scene (see sweep.rs): the water carried on from
+/// where it was, sloshed into one bottom corner, then the other, and
+/// poured back onto the floor, over and over as the viscosity sweeps
+#[cfg(feature = "sweep")]
+pub const SWEEP: &[Scene] = &[
+    Scene {
+        name: "sweep",
+        layout: None,
+        viscosity: None,
+        gravity: &[
+            Keyframe::new(0, 0.0, 1.0),
+            Keyframe::eased(30, D, D, EaseInOut),
+            Keyframe::new(120, D, D),
+            Keyframe::eased(180, -D, D, EaseInOut),
+            Keyframe::new(270, -D, D),
+            Keyframe::eased(300, 0.0, 1.0, EaseInOut),
+        ],
+        frames: 300,
+    },
+];
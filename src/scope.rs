@@ -0,0 +1,66 @@
+//! Probe outputs for a logic analyzer, so frame timing and the display
+//! bus's occupancy are measured on the real hardware rather than
+//! estimated: PA12 toggles as each frame starts, so each edge is a frame
+//! and the time between them its period, and PF0 toggles as each I2C
+//! transmission ends (see DMAi2c::set_completion_hook), so its edges
+//! against the frame's mark how long the bus is kept busy. Which toggle
+//! is enabled is configured by AppConfig's probes (see config.rs).
+
+use stm32f0xx_hal::pac::{GPIOA, GPIOF, RCC};
+use crate::config::AppConfig;
+use crate::oled::DMAi2c;
+
+
+/// The probe outputs enabled
+#[derive(Copy, Clone)]
+pub struct Probes {
+    /// Toggle PA12 as each frame starts
+    pub frame_start: bool,
+    /// Toggle PF0 as each I2C transmission ends
+    pub tx_complete: bool,
+}
+
+impl Probes {
+    /// Both outputs
+    pub const ALL: Self = Self { frame_start: true, tx_complete: true };
+}
+
+
+/// Configure the enabled probes' pins, as fast push-pull outputs, low,
+/// and toggle PF0 as each transmission ends
+pub fn init(gpioa: &GPIOA, gpiof: &GPIOF, rcc: &RCC) {
+    let probes = AppConfig::DEFAULT.probes;
+    if probes.frame_start {
+        rcc.ahbenr.modify(|_, w| w.iopaen().enabled());
+        gpioa.ospeedr.modify(|_, w| w.ospeedr12().high_speed());
+        gpioa.moder.modify(|_, w| w.moder12().output());
+    }
+    if probes.tx_complete {
+        rcc.ahbenr.modify(|_, w| w.iopfen().enabled());
+        gpiof.ospeedr.modify(|_, w| w.ospeedr0().high_speed());
+        gpiof.moder.modify(|_, w| w.moder0().output());
+        DMAi2c::set_completion_hook(Some(tx_complete));
+    }
+}
+
+/// Toggle PA12, as a frame starts
+pub fn frame_start() {
+    if AppConfig::DEFAULT.probes.frame_start {
+        // SAFETY: an atomic set or reset of PA12, which only this module drives
+        let gpioa = unsafe { &*GPIOA::ptr() };
+        match gpioa.odr.read().odr12().bit_is_set() {
+            true => gpioa.bsrr.write(|w| w.br12().set_bit()),
+            false => gpioa.bsrr.write(|w| w.bs12().set_bit()),
+        }
+    }
+}
+
+// Toggle PF0, as a transmission ends, from the DMA interrupt
+fn tx_complete(_address: u8) {
+    // SAFETY: an atomic set or reset of PF0, which only this module drives
+    let gpiof = unsafe { &*GPIOF::ptr() };
+    match gpiof.odr.read().odr0().bit_is_set() {
+        true => gpiof.bsrr.write(|w| w.br0().set_bit()),
+        false => gpiof.bsrr.write(|w| w.bs0().set_bit()),
+    }
+}
@@ -0,0 +1,395 @@
+//! A log of the simulation on an SD card, for long unattended runs to
+//! be analyzed afterwards, e.g. for the fluid blowing up or settling
+//! into a stuck state, or the frame rate or stack headroom regressing.
+//! The card is on SPI1 (SCK on PB3, MISO on PB4, MOSI on PB5, alternate
+//! function 0), selected by PA15, and is written raw, without a
+//! filesystem: the log is a run of 512 byte blocks from the card's
+//! first, read back with e.g. `dd if=/dev/sdX of=fluid.log bs=512`. Any
+//! filesystem on the card is overwritten, and the card must be blank
+//! (e.g. zeroed over its first blocks) before it's first logged to.
+//!
+//! Each boot appends to the log, finding its end by a binary search for
+//! the first block without the log's header, and numbers its blocks
+//! with the next session. The blocks are written in one multiple block
+//! write, each streamed to the card as its records are made, so the log
+//! needs no block buffer in RAM; a block is programmed once it's full,
+//! and the records due while the card is busy wait for it. Each block is:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 2     | the header, "FL"                                            |
+//! | 2     | the session, little-endian, counting the boots logged       |
+//! | 508   | records, each whole, then padding of zeros                  |
+//!
+//! The records are the stats, once a second, and a snapshot of the
+//! particles, every 10 seconds, each starting with its tag:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 1     | the tag, 0x01 for the stats                                 |
+//! | 4     | the uptime, in seconds, little-endian (as are the rest)     |
+//! | 2     | the frames dropped, saturated                               |
+//! | 1     | the CPU load, in percent                                    |
+//! | 2     | the stack headroom, in bytes                                |
+//! | 2     | the fluid's kinetic energy, in 16ths, saturated             |
+//!
+//! | bytes     | content                                                 |
+//! |-----------|---------------------------------------------------------|
+//! | 1         | the tag, 0x02 for a snapshot                            |
+//! | 1         | the number of particles, n                              |
+//! | (13n+7)/8 | each particle's display position, compressed to 7 bits  |
+//! |           | of x then 6 of y, packed from each byte's lowest bit    |
+
+use core::ptr;
+use fluid_core::{fixed::FixedPt, Fluid};
+use stm32f0xx_hal::pac::{GPIOA, GPIOB, RCC, SPI1};
+use crate::{load, log, stack};
+
+
+// The log's extent, from the card's first block, within a card of 1GB
+const LOG_BLOCKS: u32 = 1 << 21;
+
+// The block's size, and its header
+const BLOCK_SIZE: u16 = 512;
+const HEADER: [u8; 2] = *b"FL";
+const HEADER_SIZE: u16 = 4;
+
+// The frames between the stats records, and between the snapshots
+const STATS_PERIOD: u16 = 30;
+const SNAPSHOT_PERIOD: u16 = 300;
+
+// The records' tags, and their sizes besides a snapshot's particles
+const TAG_STATS: u8 = 0x01;
+const TAG_SNAPSHOT: u8 = 0x02;
+const STATS_SIZE: u16 = 12;
+const SNAPSHOT_HEADER_SIZE: u16 = 2;
+
+// The bits of a particle's x and y in a snapshot, and the largest of
+// each they hold
+const X_BITS: u8 = 7;
+const Y_BITS: u8 = 6;
+const X_MAX: i8 = ((1u16 << X_BITS) - 1) as i8;
+const Y_MAX: i8 = ((1u16 << Y_BITS) - 1) as i8;
+
+// The kinetic energy's fractional bits logged
+const ENERGY_FRACTION_BITS: u8 = 4;
+
+// The commands, the tokens, and the responses used, in SPI mode
+const GO_IDLE_STATE: u8 = 0;
+const SEND_IF_COND: u8 = 8;
+const READ_SINGLE_BLOCK: u8 = 17;
+const WRITE_MULTIPLE_BLOCK: u8 = 25;
+const SD_SEND_OP_COND: u8 = 41;
+const APP_CMD: u8 = 55;
+const READ_OCR: u8 = 58;
+const START_BLOCK: u8 = 0xFE;
+const START_MULTIPLE_BLOCK: u8 = 0xFC;
+const STOP_TRANSMISSION: u8 = 0xFD;
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+const DATA_ACCEPTED: u8 = 0x05;
+
+// The OCR's card capacity status bit: set if the card is addressed by
+// block rather than by byte
+const OCR_CCS: u32 = 1 << 30;
+
+// The bytes polled for a response, and for a block to be read, 100ms
+// or so at most, and the times the card's asked to initialize, for a
+// second or so
+const RESPONSE_POLLS: u16 = 16;
+const TOKEN_POLLS: u16 = 50_000;
+const INIT_POLLS: u16 = 2_000;
+
+
+/// Configure SPI1 and its pins for the card, at 375kHz to initialize
+/// it, deselected
+pub fn init(spi: &SPI1, gpioa: &GPIOA, gpiob: &GPIOB, rcc: &RCC) {
+    rcc.ahbenr.modify(|_, w| w.iopaen().enabled().iopben().enabled());
+    rcc.apb2enr.modify(|_, w| w.spi1en().enabled());
+    gpioa.bsrr.write(|w| w.bs15().set_bit());
+    gpioa.moder.modify(|_, w| w.moder15().output());
+    gpiob.afrl.modify(|_, w| w.afrl3().af0().afrl4().af0().afrl5().af0());
+    gpiob.moder.modify(|_, w| w.moder3().alternate().moder4().alternate().moder5().alternate());
+    // MISO is pulled up, so a missing card doesn't respond
+    gpiob.pupdr.modify(|_, w| w.pupdr4().pull_up());
+
+    spi.cr2.write(|w| w.ds().eight_bit().frxth().quarter());
+    spi.cr1.write(|w| w.mstr().master()
+                       .br().div128()
+                       .ssm().enabled()
+                       .ssi().slave_not_selected()
+                       .spe().enabled());
+}
+
+
+/// Logs the simulation to the card, if one's found
+pub struct SdLog {
+    card: Option<Card>,
+    // The frames until the next stats record and snapshot, which are
+    // due at 0 until they're written
+    stats_wait: u16,
+    snapshot_wait: u16,
+}
+
+// The log being written to the card
+struct Card {
+    session: u16,
+    // The blocks left in the log, and the bytes written of the block
+    // being written
+    blocks_left: u32,
+    written: u16,
+}
+
+impl SdLog {
+    /// Create a log, not logging. SPI1 is configured by init.
+    pub const fn new() -> Self {
+        Self { card: None, stats_wait: 0, snapshot_wait: 0 }
+    }
+
+    /// Initialize the card, if there's one, and start appending to its
+    /// log. This takes up to a second or so.
+    pub fn start(&mut self) {
+        self.card = Card::open();
+        match &self.card {
+            Some(card) => log::info!("sdlog: session {=u16}, {=u32} blocks left", card.session, card.blocks_left),
+            None => log::info!("sdlog: no card"),
+        }
+    }
+
+    /// Write the records due, unless the card's busy, when they wait.
+    /// Called once a frame, with the uptime in seconds and the frames
+    /// dropped.
+    pub fn update<const N: usize>(&mut self, uptime: u32, dropped: u32, fluid: &Fluid<N>) {
+        let Some(card) = &mut self.card else {
+            return;
+        };
+        self.stats_wait = self.stats_wait.saturating_sub(1);
+        self.snapshot_wait = self.snapshot_wait.saturating_sub(1);
+
+        let result = if self.stats_wait == 0 {
+            card.record(STATS_SIZE, || write_stats(uptime, dropped, fluid))
+                .map(|written| if written { self.stats_wait = STATS_PERIOD })
+        } else if self.snapshot_wait == 0 {
+            card.record(snapshot_size(N), || write_snapshot(fluid))
+                .map(|written| if written { self.snapshot_wait = SNAPSHOT_PERIOD })
+        } else {
+            Ok(())
+        };
+        if result.is_err() {
+            log::warn!("sdlog: write failed or log full, logging stopped");
+            self.card = None;
+        }
+    }
+}
+
+impl Card {
+    // Initialize the card, if it responds, and open its log, after its
+    // last block
+    fn open() -> Option<Self> {
+        deselect();
+        for _ in 0..10 {
+            transfer(0xFF);
+        }
+        select();
+        let card = Self::initialize();
+        if card.is_none() {
+            deselect();
+        }
+        card
+    }
+
+    fn initialize() -> Option<Self> {
+        if command(GO_IDLE_STATE, 0) != R1_IDLE {
+            return None;
+        }
+        // cards of version 2 and later check the supply, echoing the
+        // pattern, and may be of high capacity
+        let version_2 = match command(SEND_IF_COND, 0x1AA) {
+            R1_IDLE => read_u32() & 0xFFF == 0x1AA,
+            r1 if r1 & R1_ILLEGAL_COMMAND != 0 => false,
+            _ => return None,
+        };
+        let argument = if version_2 { OCR_CCS } else { 0 };
+        (0..INIT_POLLS).find(|_| command(APP_CMD, 0) <= R1_IDLE && command(SD_SEND_OP_COND, argument) == 0)?;
+        let block_addressing = version_2 && command(READ_OCR, 0) == 0 && read_u32() & OCR_CCS != 0;
+
+        // SAFETY: only SPI1's registers are modified, which only this module uses
+        let spi = unsafe { &*SPI1::ptr() };
+        spi.cr1.modify(|_, w| w.spe().disabled());
+        spi.cr1.modify(|_, w| w.br().div4().spe().enabled());
+
+        // The log is the blocks with the header from the first, as it's
+        // only appended to
+        let (mut start, mut end) = (0, LOG_BLOCKS);
+        while start < end {
+            let middle = start + (end - start) / 2;
+            match read_header(middle, block_addressing)? {
+                Some(_) => start = middle + 1,
+                None => end = middle,
+            }
+        }
+        if start == LOG_BLOCKS {
+            return None;
+        }
+        let session = match start {
+            0 => 0,
+            _ => read_header(start - 1, block_addressing)?.unwrap_or(0).wrapping_add(1),
+        };
+        let address = if block_addressing { start } else { start * BLOCK_SIZE as u32 };
+        if command(WRITE_MULTIPLE_BLOCK, address) != 0 {
+            return None;
+        }
+        Some(Self { session, blocks_left: LOG_BLOCKS - start, written: BLOCK_SIZE })
+    }
+
+    // Write a record of the given size with the given closure, which
+    // sends its bytes, unless the card's busy programming the last block,
+    // ending the block if the record doesn't fit. Returns true if it's
+    // written, or an error if the card fails.
+    fn record(&mut self, size: u16, write: impl FnOnce()) -> Result<bool, ()> {
+        if transfer(0xFF) != 0xFF {
+            return Ok(false);
+        }
+        if self.written + size > BLOCK_SIZE {
+            if self.written < BLOCK_SIZE {
+                self.end_block()?;
+                return Ok(false);
+            }
+            if self.blocks_left == 0 {
+                transfer(STOP_TRANSMISSION);
+                return Err(());
+            }
+            self.blocks_left -= 1;
+            let [s0, s1] = self.session.to_le_bytes();
+            for byte in [START_MULTIPLE_BLOCK, HEADER[0], HEADER[1], s0, s1] {
+                transfer(byte);
+            }
+            self.written = HEADER_SIZE;
+        }
+        write();
+        self.written += size;
+        Ok(true)
+    }
+
+    // Pad the block being written, and send it to be programmed
+    fn end_block(&mut self) -> Result<(), ()> {
+        for _ in self.written..BLOCK_SIZE {
+            transfer(0);
+        }
+        self.written = BLOCK_SIZE;
+        // the CRC, which is ignored in SPI mode, and the data response
+        transfer(0xFF);
+        transfer(0xFF);
+        match transfer(0xFF) & 0x1F == DATA_ACCEPTED {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+}
+
+
+// Send a stats record
+fn write_stats<const N: usize>(uptime: u32, dropped: u32, fluid: &Fluid<N>) {
+    let energy = fluid.kinetic_energy().value >> (FixedPt::BASE - ENERGY_FRACTION_BITS);
+    let [u0, u1, u2, u3] = uptime.to_le_bytes();
+    let [d0, d1] = (dropped.min(u16::MAX as u32) as u16).to_le_bytes();
+    let [h0, h1] = (stack::headroom().min(u16::MAX as usize) as u16).to_le_bytes();
+    let [e0, e1] = (energy.clamp(0, u16::MAX as i32) as u16).to_le_bytes();
+    for byte in [TAG_STATS, u0, u1, u2, u3, d0, d1, load::busy_percent(), h0, h1, e0, e1] {
+        transfer(byte);
+    }
+}
+
+// The size of a snapshot of N particles
+const fn snapshot_size(particles: usize) -> u16 {
+    let count = if particles < u8::MAX as usize { particles } else { u8::MAX as usize };
+    SNAPSHOT_HEADER_SIZE + (count as u16 * (X_BITS + Y_BITS) as u16).div_ceil(8)
+}
+
+// Send a snapshot of the particles, up to 255 of them, their positions'
+// bits gathered and sent a byte at a time
+fn write_snapshot<const N: usize>(fluid: &Fluid<N>) {
+    let count = N.min(u8::MAX as usize);
+    transfer(TAG_SNAPSHOT);
+    transfer(count as u8);
+    let (mut bits, mut pending) = (0u32, 0u8);
+    for particle in &fluid.get_particles()[..count] {
+        let (x, y) = particle.get_display_position();
+        let x = x.clamp(0, X_MAX) as u32;
+        let y = y.clamp(0, Y_MAX) as u32;
+        bits |= (x | y << X_BITS) << pending;
+        pending += X_BITS + Y_BITS;
+        while pending >= 8 {
+            transfer(bits as u8);
+            bits >>= 8;
+            pending -= 8;
+        }
+    }
+    if pending > 0 {
+        transfer(bits as u8);
+    }
+}
+
+// Send a command, returning its R1 response, or 0xFF if there's none
+fn command(index: u8, argument: u32) -> u8 {
+    // only the first commands are checked for their CRC, in SPI mode
+    let crc = match index {
+        GO_IDLE_STATE => 0x95,
+        SEND_IF_COND => 0x87,
+        _ => 0x01,
+    };
+    transfer(0xFF);
+    let [a0, a1, a2, a3] = argument.to_be_bytes();
+    for byte in [0x40 | index, a0, a1, a2, a3, crc] {
+        transfer(byte);
+    }
+    (0..RESPONSE_POLLS).map(|_| transfer(0xFF)).find(|r1| r1 & 0x80 == 0).unwrap_or(0xFF)
+}
+
+// Read the rest of an R3 or R7 response, after its R1
+fn read_u32() -> u32 {
+    u32::from_be_bytes([transfer(0xFF), transfer(0xFF), transfer(0xFF), transfer(0xFF)])
+}
+
+// Read a block's session, if it has the log's header, or None if the
+// block can't be read
+fn read_header(block: u32, block_addressing: bool) -> Option<Option<u16>> {
+    let address = if block_addressing { block } else { block * BLOCK_SIZE as u32 };
+    if command(READ_SINGLE_BLOCK, address) != 0 {
+        return None;
+    }
+    (0..TOKEN_POLLS).find(|_| transfer(0xFF) == START_BLOCK)?;
+    let header = [transfer(0xFF), transfer(0xFF), transfer(0xFF), transfer(0xFF)];
+    // the rest of the block, and its CRC
+    for _ in HEADER_SIZE..BLOCK_SIZE + 2 {
+        transfer(0xFF);
+    }
+    let [h0, h1, s0, s1] = header;
+    Some(([h0, h1] == HEADER).then_some(u16::from_le_bytes([s0, s1])))
+}
+
+// Exchange a byte with the card
+fn transfer(byte: u8) -> u8 {
+    // SAFETY: only SPI1's registers are accessed, which only this module
+    //         uses. The data register is accessed by byte, as a 16 bit
+    //         write sends two frames.
+    let spi = unsafe { &*SPI1::ptr() };
+    let dr = spi.dr.as_ptr() as *mut u8;
+    while spi.sr.read().txe().bit_is_clear() {}
+    unsafe { ptr::write_volatile(dr, byte) };
+    while spi.sr.read().rxne().bit_is_clear() {}
+    unsafe { ptr::read_volatile(dr) }
+}
+
+// Select or deselect the card, with its chip select, PA15
+fn select() {
+    // SAFETY: an atomic write of PA15's bit, which only this module uses
+    let gpioa = unsafe { &*GPIOA::ptr() };
+    gpioa.bsrr.write(|w| w.br15().set_bit());
+}
+
+fn deselect() {
+    // SAFETY: an atomic write of PA15's bit, which only this module uses
+    let gpioa = unsafe { &*GPIOA::ptr() };
+    gpioa.bsrr.write(|w| w.bs15().set_bit());
+}
@@ -0,0 +1,139 @@
+//! The tuned settings, kept across power cycles: the viscosity, gravity
+//! (once it's tuned), the contrast, the HUD's visibility and how the
+//! accelerometer's mounted (see calibrate.rs) are restored at boot, and
+//! saved
+//! once they've been left alone for a couple of seconds after changing,
+//! so turning the encoder through many steps writes them once. They're
+//! kept by a backend (see Backend), e.g. an EEPROM (see eeprom.rs), as:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 1     | the marker, 0xF7, as a blank EEPROM reads 0xFF              |
+//! | 1     | the viscosity's step                                        |
+//! | 1     | gravity's step, or 0xFF while the demo steers it            |
+//! | 1     | the contrast's step                                         |
+//! | 1     | 1 if the HUD's shown, else 0                                |
+//! | 3     | the accelerometer's axes along the display's (see Mounting) |
+//! | 3     | the accelerometer's offsets, in 1/128ths of a g             |
+//! | 1     | the checksum: the wrapping sum of the bytes before it       |
+
+use crate::accel::Mounting;
+use crate::log;
+use crate::tuning::Parameter;
+
+
+// The bytes kept, and the marker starting them
+const SIZE: usize = 12;
+const MARKER: u8 = 0xF7;
+
+// The frames the settings must be left alone for to be saved
+const SAVE_DELAY_FRAMES: u16 = 60;
+
+
+/// Somewhere to keep the settings' bytes
+pub trait Backend {
+    /// Read the bytes kept, returning false if they can't be read
+    fn load(&mut self, bytes: &mut [u8]) -> bool;
+    /// Keep the bytes, returning false if they can't be written
+    fn save(&mut self, bytes: &[u8]) -> bool;
+}
+
+
+/// The settings kept
+#[derive(Copy, Clone, PartialEq)]
+pub struct Settings {
+    pub viscosity: u8,
+    pub gravity: Option<u8>,
+    pub contrast: u8,
+    pub hud: bool,
+    pub mounting: Mounting,
+}
+
+impl Settings {
+    fn encode(&self) -> [u8; SIZE] {
+        let [ax, ay, az] = self.mounting.axes.map(|axis| axis as u8);
+        let [ox, oy, oz] = self.mounting.offsets.map(|offset| offset as u8);
+        let mut bytes = [MARKER, self.viscosity, self.gravity.unwrap_or(u8::MAX), self.contrast, self.hud as u8, ax, ay, az, ox, oy, oz, 0];
+        bytes[SIZE - 1] = checksum(&bytes[..SIZE - 1]);
+        bytes
+    }
+
+    // The settings kept in the bytes, if they're valid
+    fn decode(bytes: &[u8; SIZE]) -> Option<Self> {
+        let [marker, viscosity, gravity, contrast, hud, ax, ay, az, ox, oy, oz, sum] = *bytes;
+        let mounting = Mounting { axes: [ax, ay, az].map(|axis| axis as i8), offsets: [ox, oy, oz].map(|offset| offset as i8) };
+        let in_range = |parameter: Parameter, step: u8| step as i16 <= parameter.max_step();
+        let gravity = (gravity != u8::MAX).then_some(gravity);
+        let valid = marker == MARKER && sum == checksum(&bytes[..SIZE - 1])
+            && in_range(Parameter::Viscosity, viscosity)
+            && gravity.is_none_or(|gravity| in_range(Parameter::GravityAngle, gravity))
+            && in_range(Parameter::Contrast, contrast)
+            && hud <= 1
+            && mounting.is_valid();
+        valid.then_some(Self { viscosity, gravity, contrast, hud: hud == 1, mounting })
+    }
+}
+
+
+/// Keeps the settings in a backend, once it's opened
+pub struct SettingsKeeper<B> {
+    backend: Option<B>,
+    // The settings as last kept, and as changed since, and the frames
+    // until they're saved, if they're left alone
+    saved: Option<Settings>,
+    changed: Option<Settings>,
+    wait: u16,
+}
+
+impl<B: Backend> SettingsKeeper<B> {
+    /// Create a keeper, without a backend
+    pub const fn new() -> Self {
+        Self { backend: None, saved: None, changed: None, wait: 0 }
+    }
+
+    /// Keep the settings in the given backend, returning those it
+    /// kept, if it has any
+    pub fn open(&mut self, mut backend: B) -> Option<Settings> {
+        let mut bytes = [0; SIZE];
+        self.saved = match backend.load(&mut bytes) {
+            true => Settings::decode(&bytes),
+            false => None,
+        };
+        self.backend = Some(backend);
+        if self.saved.is_none() {
+            log::info!("settings: none kept");
+        }
+        self.saved
+    }
+
+    /// Save the settings, once they've been left alone for long enough
+    /// since changing. Called once a frame.
+    pub fn update(&mut self, settings: Settings) {
+        let Some(backend) = &mut self.backend else {
+            return;
+        };
+        if self.saved == Some(settings) {
+            return;
+        }
+        if self.changed != Some(settings) {
+            self.changed = Some(settings);
+            self.wait = SAVE_DELAY_FRAMES;
+            return;
+        }
+        self.wait -= 1;
+        if self.wait > 0 {
+            return;
+        }
+        match backend.save(&settings.encode()) {
+            true => log::info!("settings: saved"),
+            false => log::warn!("settings: not saved"),
+        }
+        self.saved = Some(settings);
+    }
+}
+
+
+// The wrapping sum of the bytes
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
@@ -0,0 +1,350 @@
+//! A command shell on USART1 (115200 baud, 8N1; TX on PA9, RX on PA10),
+//! so the simulation can be driven and tuned from a laptop through a
+//! USB-serial adapter. Each line is a command:
+//!
+//! - `gravity <0-15>` points gravity in one of 16 directions, clockwise
+//!   from down, and `gravity cycle` returns gravity to the demo's scenes
+//! - `viscosity <0-25>` sets the viscosity
+//! - `contrast <0-15>` sets the display's contrast
+//! - `scene <n>` starts the program's nth scene (from 0, see config.rs)
+//! - `clock` shows the time (see clock.rs), until a scene is started,
+//!   and `time <HH:MM>` sets it
+//! - `maze` starts the tilt maze game (see maze.rs), `pong` a game of
+//!   Pong (see pong.rs), `lava` the lava lamp (see lava.rs),
+//!   `hourglass [<1-600>]` the hourglass, for the given seconds or a
+//!   minute (see hourglass.rs), `rain` the rain (see rain.rs),
+//!   `paint` painting with water (see paint.rs) and `split <0-25>` the
+//!   split screen, with the right half's viscosity (see split.rs), until
+//!   a scene is started
+//! - `calibrate` finds how the accelerometer's mounted, from the board
+//!   laid flat and stood upright as the display asks (see calibrate.rs)
+//! - `attract` starts the attract mode, the demo's scenes captioned, for
+//!   leaving the board running unattended (see attract.rs)
+//! - `sweep` starts the parameter sweep, the viscosity swept from water's
+//!   to honey's and back, shown on the HUD (see sweep.rs)
+//! - `hud` shows or hides the performance HUD (see hud.rs)
+//! - `telemetry <0-60>` streams the telemetry, every given seconds, or
+//!   stops it (see remote.rs)
+//! - `stats` reports the uptime, dropped frames, CPU load, stack headroom
+//!   and I2C counters
+//! - `speed fast|slow|auto` fixes the system clock at 48MHz or 8MHz, or
+//!   has it follow how lively the water is (see scaling.rs)
+//! - `histogram` reports the frame time histograms, and `histogram reset`
+//!   restarts them (see histogram.rs)
+//! - `dfu` resets the board into the STM32's bootloader, to update the
+//!   firmware over the USART (see dfu.rs)
+//! - `help` lists the commands
+//!
+//! The shell assembles lines and parses them into commands; the
+//! application carries them out. With the `remote` feature, it also
+//! takes commands in the remote protocol's frames, for a phone app
+//! through a Bluetooth serial module (see remote.rs).
+
+use core::fmt::{self, Write};
+use crate::config::AppConfig;
+use crate::oled::NumberText;
+#[cfg(feature = "remote")]
+use crate::remote::{Decoder, Received, MAX_TELEMETRY_SECONDS};
+#[cfg(feature = "clock")]
+use crate::rtc::Time;
+#[cfg(feature = "scaling")]
+use crate::scaling::ClockMode;
+use crate::tuning::Parameter;
+
+
+// The longest command line
+const LINE_CAPACITY: usize = 32;
+
+// The hourglass's time, in seconds, unless given, and the longest, within
+// its scenes' length
+#[cfg(feature = "hourglass")]
+const HOURGLASS_SECONDS: u16 = 60;
+#[cfg(feature = "hourglass")]
+const HOURGLASS_MAX_SECONDS: u16 = 600;
+
+
+/// A command parsed from a line
+#[derive(Copy, Clone, defmt::Format)]
+pub enum Command {
+    /// Set a parameter to the given step
+    Set(Parameter, i16),
+    /// Return gravity to the demo's scenes
+    GravityCycle,
+    /// Start a scene of the demo
+    Scene(u8),
+    /// Show the time
+    #[cfg(feature = "clock")]
+    Clock,
+    /// Set the time
+    #[cfg(feature = "clock")]
+    Time(Time),
+    /// Start the maze game
+    #[cfg(feature = "maze")]
+    Maze,
+    /// Start a game of Pong
+    #[cfg(feature = "pong")]
+    Pong,
+    /// Switch on the lava lamp
+    #[cfg(feature = "lava")]
+    Lava,
+    /// Start the hourglass, for the given seconds
+    #[cfg(feature = "hourglass")]
+    Hourglass(u16),
+    /// Start the rain
+    #[cfg(feature = "rain")]
+    Rain,
+    /// Start painting
+    #[cfg(feature = "paint")]
+    Paint,
+    /// Split the screen, with the right half's viscosity step
+    #[cfg(feature = "split")]
+    Split(u8),
+    /// Calibrate the accelerometer's mounting
+    #[cfg(feature = "calibrate")]
+    Calibrate,
+    /// Start the attract mode
+    #[cfg(feature = "attract")]
+    Attract,
+    /// Start the parameter sweep
+    #[cfg(feature = "sweep")]
+    Sweep,
+    /// Stream the telemetry, every given seconds, or stop it (0)
+    #[cfg(feature = "remote")]
+    Telemetry(u8),
+    /// Fix the clock mode, or have it follow the fluid (None)
+    #[cfg(feature = "scaling")]
+    Speed(Option<ClockMode>),
+    /// Show or hide the performance HUD
+    Hud,
+    /// Report the statistics
+    Stats,
+    /// Report the frame time histograms, or restart them (true)
+    #[cfg(feature = "histogram")]
+    Histogram(bool),
+    /// Reset into the bootloader
+    #[cfg(feature = "dfu")]
+    Dfu,
+    /// List the commands
+    Help,
+}
+
+impl Command {
+    /// Determine if the command starts a mode, e.g. a scene or a game,
+    /// ending any other
+    pub fn starts_mode(&self) -> bool {
+        match self {
+            Command::Scene(_) => true,
+            #[cfg(feature = "clock")]
+            Command::Clock => true,
+            #[cfg(feature = "maze")]
+            Command::Maze => true,
+            #[cfg(feature = "pong")]
+            Command::Pong => true,
+            #[cfg(feature = "lava")]
+            Command::Lava => true,
+            #[cfg(feature = "hourglass")]
+            Command::Hourglass(_) => true,
+            #[cfg(feature = "rain")]
+            Command::Rain => true,
+            #[cfg(feature = "paint")]
+            Command::Paint => true,
+            #[cfg(feature = "split")]
+            Command::Split(_) => true,
+            #[cfg(feature = "calibrate")]
+            Command::Calibrate => true,
+            #[cfg(feature = "attract")]
+            Command::Attract => true,
+            #[cfg(feature = "sweep")]
+            Command::Sweep => true,
+            _ => false,
+        }
+    }
+}
+
+/// The commands, for the help command
+pub const HELP: &str = "gravity <0-15>|cycle, viscosity <0-25>, contrast <0-15>, scene <n>, clock, time <HH:MM>, maze, pong, lava, hourglass [<1-600>], rain, paint, split <0-25>, calibrate, attract, sweep, hud, telemetry <0-60>, speed fast|slow|auto, stats, histogram [reset], dfu, help\r\n";
+
+
+/// Assembles received bytes into lines, and lines into commands
+pub struct Shell {
+    line: [u8; LINE_CAPACITY],
+    len: u8,
+    #[cfg(feature = "remote")]
+    frames: Decoder,
+}
+
+impl Shell {
+    pub const fn new() -> Self {
+        Self {
+            line: [0; LINE_CAPACITY],
+            len: 0,
+            #[cfg(feature = "remote")]
+            frames: Decoder::new(),
+        }
+    }
+
+    /// Take a received byte if it's part of a remote protocol frame
+    /// (see remote.rs), rather than text to receive
+    #[cfg(feature = "remote")]
+    pub fn receive_frame(&mut self, byte: u8) -> Received {
+        self.frames.receive(byte)
+    }
+
+    /// Take a received byte, returning the command (or an error) once
+    /// a line ends. A backspace removes the previous byte.
+    pub fn receive(&mut self, byte: u8) -> Option<Result<Command, &'static str>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len) as usize;
+                if len > LINE_CAPACITY {
+                    return Some(Err("line too long"));
+                }
+                let line = &self.line[..len];
+                // SAFETY: the line's checked to be ASCII
+                let line = line.is_ascii().then(|| unsafe { core::str::from_utf8_unchecked(line) }).ok_or("invalid text");
+                match line.map(str::trim_ascii) {
+                    Ok("") => None,
+                    line => Some(line.and_then(parse)),
+                }
+            },
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                None
+            },
+            _ if (self.len as usize) < LINE_CAPACITY => {
+                self.line[self.len as usize] = byte;
+                self.len += 1;
+                None
+            },
+            // the rest of a line too long to hold is dropped
+            _ => {
+                self.len = LINE_CAPACITY as u8 + 1;
+                None
+            },
+        }
+    }
+}
+
+
+/// Write a line of labelled counts, e.g. "uptime 12, dropped 0",
+/// without the formatting machinery
+pub fn write_counts(out: &mut impl Write, counts: &[(&str, u32)]) -> fmt::Result {
+    for (i, (label, count)) in counts.iter().enumerate() {
+        if i > 0 {
+            out.write_str(", ")?;
+        }
+        out.write_str(label)?;
+        out.write_str(" ")?;
+        out.write_str(NumberText::from_i32(*count as i32).as_str())?;
+    }
+    out.write_str("\r\n")
+}
+
+
+/// Parse a command line
+fn parse(line: &str) -> Result<Command, &'static str> {
+    let mut words = line.split_ascii_whitespace();
+    let command = words.next().unwrap_or("");
+    let argument = words.next();
+    if words.next().is_some() {
+        return Err("too many arguments");
+    }
+
+    let set = |parameter: Parameter| match argument.map(str::parse::<i16>) {
+        Some(Ok(step)) if (0..=parameter.max_step()).contains(&step) => Ok(Command::Set(parameter, step)),
+        _ => Err("expected a step in range"),
+    };
+    match (command, argument) {
+        ("gravity", Some("cycle")) => Ok(Command::GravityCycle),
+        ("gravity", _) => set(Parameter::GravityAngle),
+        ("viscosity", _) => set(Parameter::Viscosity),
+        ("contrast", _) => set(Parameter::Contrast),
+        ("scene", _) => match argument.map(str::parse::<i16>) {
+            Some(Ok(index)) if (0..AppConfig::DEFAULT.program.len() as i16).contains(&index) => Ok(Command::Scene(index as u8)),
+            _ => Err("expected a scene in range"),
+        },
+        #[cfg(not(feature = "clock"))]
+        ("clock" | "time", _) => Err("no clock, see the clock feature"),
+        #[cfg(feature = "clock")]
+        ("clock", None) => Ok(Command::Clock),
+        #[cfg(feature = "clock")]
+        ("time", _) => argument.and_then(Time::parse).map(Command::Time).ok_or("expected a time, HH:MM"),
+        #[cfg(not(feature = "maze"))]
+        ("maze", _) => Err("no maze, see the maze feature"),
+        #[cfg(feature = "maze")]
+        ("maze", None) => Ok(Command::Maze),
+        #[cfg(not(feature = "pong"))]
+        ("pong", _) => Err("no pong, see the pong feature"),
+        #[cfg(feature = "pong")]
+        ("pong", None) => Ok(Command::Pong),
+        #[cfg(not(feature = "lava"))]
+        ("lava", _) => Err("no lava lamp, see the lava feature"),
+        #[cfg(feature = "lava")]
+        ("lava", None) => Ok(Command::Lava),
+        #[cfg(not(feature = "hourglass"))]
+        ("hourglass", _) => Err("no hourglass, see the hourglass feature"),
+        #[cfg(feature = "hourglass")]
+        ("hourglass", _) => match argument.map(str::parse::<u16>) {
+            None => Ok(Command::Hourglass(HOURGLASS_SECONDS)),
+            Some(Ok(seconds)) if (1..=HOURGLASS_MAX_SECONDS).contains(&seconds) => Ok(Command::Hourglass(seconds)),
+            _ => Err("expected seconds, 1-600"),
+        },
+        #[cfg(not(feature = "rain"))]
+        ("rain", _) => Err("no rain, see the rain feature"),
+        #[cfg(feature = "rain")]
+        ("rain", None) => Ok(Command::Rain),
+        #[cfg(not(feature = "paint"))]
+        ("paint", _) => Err("no painting, see the paint feature"),
+        #[cfg(feature = "paint")]
+        ("paint", None) => Ok(Command::Paint),
+        #[cfg(not(feature = "split"))]
+        ("split", _) => Err("no split screen, see the split feature"),
+        #[cfg(feature = "split")]
+        ("split", _) => match argument.map(str::parse::<i16>) {
+            Some(Ok(step)) if (0..=Parameter::Viscosity.max_step()).contains(&step) => Ok(Command::Split(step as u8)),
+            _ => Err("expected a viscosity step in range"),
+        },
+        #[cfg(not(feature = "calibrate"))]
+        ("calibrate", _) => Err("no calibration, see the calibrate feature"),
+        #[cfg(feature = "calibrate")]
+        ("calibrate", None) => Ok(Command::Calibrate),
+        #[cfg(not(feature = "attract"))]
+        ("attract", _) => Err("no attract mode, see the attract feature"),
+        #[cfg(feature = "attract")]
+        ("attract", None) => Ok(Command::Attract),
+        #[cfg(not(feature = "sweep"))]
+        ("sweep", _) => Err("no parameter sweep, see the sweep feature"),
+        #[cfg(feature = "sweep")]
+        ("sweep", None) => Ok(Command::Sweep),
+        #[cfg(not(feature = "remote"))]
+        ("telemetry", _) => Err("no telemetry, see the remote feature"),
+        #[cfg(feature = "remote")]
+        ("telemetry", _) => match argument.map(str::parse::<u8>) {
+            Some(Ok(seconds)) if seconds <= MAX_TELEMETRY_SECONDS => Ok(Command::Telemetry(seconds)),
+            _ => Err("expected seconds, 0-60"),
+        },
+        #[cfg(not(feature = "scaling"))]
+        ("speed", _) => Err("no clock scaling, see the scaling feature"),
+        #[cfg(feature = "scaling")]
+        ("speed", _) => match argument {
+            Some("fast") => Ok(Command::Speed(Some(ClockMode::Performance))),
+            Some("slow") => Ok(Command::Speed(Some(ClockMode::LowPower))),
+            Some("auto") => Ok(Command::Speed(None)),
+            _ => Err("expected fast, slow or auto"),
+        },
+        ("hud", None) => Ok(Command::Hud),
+        ("stats", None) => Ok(Command::Stats),
+        #[cfg(not(feature = "histogram"))]
+        ("histogram", _) => Err("no histogram, see the histogram feature"),
+        #[cfg(feature = "histogram")]
+        ("histogram", None) => Ok(Command::Histogram(false)),
+        #[cfg(feature = "histogram")]
+        ("histogram", Some("reset")) => Ok(Command::Histogram(true)),
+        #[cfg(not(feature = "dfu"))]
+        ("dfu", _) => Err("no bootloader, see the dfu feature"),
+        #[cfg(feature = "dfu")]
+        ("dfu", None) => Ok(Command::Dfu),
+        ("help", None) => Ok(Command::Help),
+        _ => Err("unknown command, try help"),
+    }
+}
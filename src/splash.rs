@@ -0,0 +1,69 @@
+//! The boot splash: the logo's particles drop into place one by one,
+//! over the firmware's version and the particle capacity, and once the
+//! logo has been shown for a while the text is cleared and the logo
+//! melts, as the simulation starts from it.
+
+use fluid_core::Fluid;
+use crate::draw_particle;
+use crate::oled::{NumberText, OLEDDriver, FONT_ADVANCE, OLED_PXLS_X};
+
+
+// The particles start dropping this many to a frame, from this height,
+// and each takes this many frames to fall into place
+const PARTICLES_PER_FRAME: usize = 2;
+const DROP_HEIGHT: i32 = 64;
+const FALL_FRAMES: i32 = 8;
+
+// The text's lines, beneath the logo
+const VERSION_Y: i32 = 46;
+const CAPACITY_Y: i32 = 55;
+
+
+pub struct Splash {
+    frame: usize,
+    // The frames the whole logo is held for, before it melts
+    hold_frames: usize,
+}
+
+impl Splash {
+    /// Create a splash, from its first frame, holding the whole logo for
+    /// the given frames
+    pub const fn new(hold_frames: u16) -> Self {
+        Self { frame: 0, hold_frames: hold_frames as usize }
+    }
+
+    /// Draw the splash's next frame, for the fluid in its initial layout.
+    /// Returns false once the splash has ended, drawing nothing.
+    pub fn draw<const N: usize>(&mut self, display: &mut OLEDDriver, fluid: &Fluid<N>) -> bool {
+        let frames = N.div_ceil(PARTICLES_PER_FRAME) + FALL_FRAMES as usize + self.hold_frames;
+        if self.frame == frames {
+            return false;
+        }
+
+        display.clear();
+        for (i, particle) in fluid.get_particles().iter().enumerate() {
+            let falling = self.frame as i32 - (i / PARTICLES_PER_FRAME) as i32;
+            if falling < 0 {
+                break;
+            }
+            let (x, y) = particle.get_display_position();
+            let y = y as i32 + (DROP_HEIGHT - y as i32) * (FALL_FRAMES - falling).max(0) / FALL_FRAMES;
+            draw_particle(display, x as usize, y as usize);
+        }
+
+        let version = concat!("v", env!("CARGO_PKG_VERSION"));
+        display.draw_text(centred(version.len()), VERSION_Y, version);
+        let capacity = NumberText::from_i32(N as i32);
+        let x = display.draw_text(centred(capacity.as_str().len() + 10), CAPACITY_Y, capacity.as_str());
+        display.draw_text(x, CAPACITY_Y, " particles");
+
+        self.frame += 1;
+        true
+    }
+}
+
+
+// The x position centring a line of the given number of characters
+fn centred(characters: usize) -> i32 {
+    (OLED_PXLS_X as i32 - characters as i32 * FONT_ADVANCE) / 2
+}
@@ -0,0 +1,79 @@
+//! The split screen, for comparing solver settings live: the display is
+//! split down the middle between two fluids, the simulation's own on the
+//! left and a second on the right, each 60x61, started from the same
+//! layout and under the same gravity, but with the right's viscosity
+//! set apart from the left's, which is tuned as usual. There's no RAM
+//! for a second fluid as large as the simulation's, so split builds
+//! simulate half as many particles in each.
+
+use fluid_core::{scene::SceneManager, Fluid};
+use crate::oled::OLEDDriver;
+use crate::{draw_particle, scenes, tuning};
+
+
+/// The particles simulated in each fluid
+pub const PARTICLES: usize = 30;
+
+// The display's area, and each half's width, which leaves room between
+// the halves for the particles' pixels and the divider
+const WIDTH: i8 = 125;
+const HEIGHT: i8 = 61;
+const HALF_WIDTH: i8 = 60;
+
+// The divider between the halves, and the right half's left edge
+const DIVIDER_X: i32 = 63;
+const RIGHT_X: usize = 64;
+
+
+pub struct SplitScreen {
+    on: bool,
+    // The right half's fluid
+    fluid: Fluid<PARTICLES>,
+}
+
+impl SplitScreen {
+    /// Create the split screen, off
+    pub const fn new() -> Self {
+        Self { on: false, fluid: Fluid::empty() }
+    }
+
+    /// Split the screen, the right half's fluid at the given viscosity
+    /// step (see tuning.rs)
+    pub fn start<const N: usize>(&mut self, viscosity: u8, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        fluid.set_area(HALF_WIDTH, HEIGHT);
+        scenes.play(scenes::SPLIT, 0, fluid);
+        self.fluid.reset(HALF_WIDTH, HEIGHT);
+        self.fluid.arrange_at(fluid.get_particles().iter().map(|particle| particle.get_display_position()));
+        self.fluid.set_viscosity(0.0, tuning::viscosity_at(viscosity));
+        self.on = true;
+    }
+
+    /// Join the screen back up, e.g. to return to the demo
+    pub fn stop<const N: usize>(&mut self, fluid: &mut Fluid<N>) {
+        if self.on {
+            fluid.set_area(WIDTH, HEIGHT);
+            self.on = false;
+        }
+    }
+
+    /// Step the right half's fluid under the left's gravity, while the
+    /// screen's split
+    pub fn update<const N: usize>(&mut self, fluid: &Fluid<N>) {
+        if self.on {
+            self.fluid.copy_gravity(fluid);
+            self.fluid.step();
+        }
+    }
+
+    /// Draw the divider and the right half's fluid, while the screen's
+    /// split, beside the left's
+    pub fn draw(&self, display: &mut OLEDDriver) {
+        if self.on {
+            display.draw_line((DIVIDER_X, 0), (DIVIDER_X, HEIGHT as i32 + 2), 1);
+            for particle in self.fluid.get_particles() {
+                let (x, y) = particle.get_display_position();
+                draw_particle(display, RIGHT_X + x as usize, y as usize);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Stack watermarking: the F030's 4K of SRAM is mostly taken by the
+//! frame buffer, the fluid and the DMA queue, and the stack grows down
+//! into them unchecked, so an overflow shows up as a corrupted display
+//! rather than a fault. The free stack is painted with a pattern at boot
+//! (see paint), and the depth the pattern survives to is the stack's
+//! headroom: the bytes it has never used. The headroom is logged as it
+//! shrinks (see StackMonitor), and reported by the shell's `stats`.
+
+use core::ptr;
+use crate::log;
+
+
+// The word the free stack is painted with
+const PAINT: u32 = 0xCCCC_CCCC;
+
+// The stack just below the painter is left alone, for its own calls
+const PAINT_MARGIN: usize = 64;
+
+// The headroom worth a warning, in bytes
+const LOW_HEADROOM: usize = 128;
+
+extern "C" {
+    // The bottom of the stack, at the end of the static data, as placed
+    // by cortex-m-rt's linker script
+    static mut _stack_end: u32;
+}
+
+
+/// Paint the free stack, below the caller's frame. Called once at boot,
+/// with interrupts disabled, e.g. first thing in init.
+pub fn paint() {
+    let top = cortex_m::register::msp::read() as usize - PAINT_MARGIN;
+    let mut word = bottom();
+    while (word as usize) < top {
+        // SAFETY: the words between the end of the static data and the
+        //         stack pointer are unused, as nothing runs underneath
+        unsafe { ptr::write_volatile(word, PAINT) };
+        word = word.wrapping_add(1);
+    }
+}
+
+/// The stack's headroom: the bytes from its bottom it has never used
+pub fn headroom() -> usize {
+    let mut word = bottom();
+    // SAFETY: the words read are within RAM, below the stack pointer,
+    //         and painted at boot, so the scan ends at the first used word
+    while unsafe { ptr::read_volatile(word) } == PAINT {
+        word = word.wrapping_add(1);
+    }
+    word as usize - bottom() as usize
+}
+
+// The lowest word of the stack
+fn bottom() -> *mut u32 {
+    ptr::addr_of_mut!(_stack_end)
+}
+
+
+/// Logs the stack's headroom each time it shrinks
+pub struct StackMonitor {
+    headroom: usize,
+}
+
+impl StackMonitor {
+    /// Create a monitor, logging the headroom from its first check
+    pub const fn new() -> Self {
+        Self { headroom: usize::MAX }
+    }
+
+    /// Measure the headroom, logging it if it's shrunk. Called once a
+    /// frame, its scan is short as long as the headroom is.
+    pub fn check(&mut self) {
+        let headroom = headroom();
+        if headroom >= self.headroom {
+            return;
+        }
+        self.headroom = headroom;
+        match headroom < LOW_HEADROOM {
+            true => log::warn!("stack: {=usize} bytes of headroom", headroom),
+            false => log::info!("stack: {=usize} bytes of headroom", headroom),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Streaming of the particles' state to a host over USART1, so a host
+//! tool can record it, visualize it at a higher resolution, or diff it
+//! against the desktop simulator. Each frame is sent by DMA (channel 4,
+//! with USART1's transmit requests remapped from channel 2, which the
+//! display's I2C interface uses), so it costs the frame loop only the
+//! encoding. The shell's replies share the USART, and are written
+//! between frames.
+//!
+//! Each frame is:
+//!
+//! | bytes | content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 2     | the sync bytes, 0xF1 0x1D                                   |
+//! | 1     | the sequence number, which wraps, to detect dropped frames  |
+//! | 1     | flags: bit 0 is set if densities follow the positions       |
+//! | 1     | the number of particles, n                                  |
+//! | 2n    | each particle's display position, x then y, as i8           |
+//! | n     | each particle's density, in 16ths, saturated (if flagged)   |
+//! | 1     | the checksum: the wrapping sum of every byte but the sync   |
+//!
+//! At 115200 baud, a frame of 60 particles takes 11ms to send, or 16ms
+//! with their densities, within the 33ms frame period.
+
+use fluid_core::{fixed::FixedPt, Fluid};
+use stm32f0xx_hal::pac::{DMA1, SYSCFG, USART1};
+
+
+/// The bytes starting each frame
+pub const SYNC: [u8; 2] = [0xF1, 0x1D];
+
+// The frame's flags
+const FLAG_DENSITIES: u8 = 1 << 0;
+
+// The bytes of a frame besides the particles': the sync bytes, the
+// sequence number, flags and count, and the checksum
+const OVERHEAD: usize = SYNC.len() + 3 + 1;
+
+// The density's fractional bits sent
+const DENSITY_FRACTION_BITS: u8 = 4;
+
+
+/// The size of a frame of the given number of particles,
+/// with or without their densities
+pub const fn frame_size(particles: usize, densities: bool) -> usize {
+    OVERHEAD + particles * if densities { 3 } else { 2 }
+}
+
+
+/// Encodes frames into a buffer of CAPACITY bytes, and sends them
+pub struct Streamer<const CAPACITY: usize> {
+    buffer: [u8; CAPACITY],
+    densities: bool,
+    sequence: u8,
+    skipped: u32,
+}
+
+#[allow(dead_code)]
+impl<const CAPACITY: usize> Streamer<CAPACITY> {
+    /// Create a streamer, sending the particles' densities or not.
+    /// The USART and DMA channel are configured by init.
+    pub const fn new(densities: bool) -> Self {
+        Self {
+            buffer: [0; CAPACITY],
+            densities,
+            sequence: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Send the fluid's state, unless the previous frame is still being
+    /// sent, when this frame is skipped. Particles beyond the capacity
+    /// of the buffer aren't sent.
+    pub fn send<const N: usize>(&mut self, fluid: &Fluid<N>) {
+        if is_busy() {
+            self.skipped = self.skipped.wrapping_add(1);
+            return;
+        }
+
+        let per_particle = if self.densities { 3 } else { 2 };
+        let count = (fluid.particle_count()).min((CAPACITY - OVERHEAD) / per_particle).min(u8::MAX as usize);
+        let particles = &fluid.get_particles()[..count];
+
+        let (header, body) = self.buffer.split_at_mut(OVERHEAD - 1);
+        header[..SYNC.len()].copy_from_slice(&SYNC);
+        header[SYNC.len()..].copy_from_slice(&[
+            self.sequence,
+            if self.densities { FLAG_DENSITIES } else { 0 },
+            count as u8,
+        ]);
+        let (positions, rest) = body.split_at_mut(2 * count);
+        for (bytes, particle) in positions.chunks_exact_mut(2).zip(particles) {
+            let (x, y) = particle.get_display_position();
+            bytes.copy_from_slice(&[x as u8, y as u8]);
+        }
+        let mut length = OVERHEAD - 1 + 2 * count;
+        if self.densities {
+            for (byte, particle) in rest.iter_mut().zip(particles) {
+                let density = particle.density.far.value >> (FixedPt::BASE - DENSITY_FRACTION_BITS);
+                *byte = density.clamp(0, u8::MAX as i32) as u8;
+            }
+            length += count;
+        }
+        self.buffer[length] = self.buffer[SYNC.len()..length].iter().fold(0, |sum, byte| sum.wrapping_add(*byte));
+        length += 1;
+
+        self.sequence = self.sequence.wrapping_add(1);
+        start(&self.buffer[..length]);
+    }
+
+    /// The number of frames skipped while the previous was being sent
+    pub fn skipped(&self) -> u32 {
+        self.skipped
+    }
+}
+
+
+/// Remap USART1's transmit requests to DMA channel 4, and configure the
+/// channel to feed the USART, which must be configured beforehand (as
+/// configuring it resets its DMA requests)
+pub fn init(syscfg: &SYSCFG, dma: &DMA1) {
+    syscfg.cfgr1.modify(|_, w| w.usart1_tx_dma_rmp().remapped());
+
+    // SAFETY: only the USART's transmit DMA requests are enabled, and 
+    //         its data register's address taken
+    let usart = unsafe { &*USART1::ptr() };
+
+    let txdr = &usart.tdr as *const _ as u32;
+    dma.ch4.par.write(|w| unsafe { w.bits(txdr) });
+    dma.ch4.cr.write(|w| w.mem2mem().disabled()
+                          .pl().low()
+                          .msize().bits8()
+                          .psize().bits8()
+                          .minc().enabled()
+                          .pinc().disabled()
+                          .circ().disabled()
+                          .dir().from_memory());
+    usart.cr3.modify(|_, w| w.dmat().enabled());
+}
+
+/// Determine if a frame is being sent
+pub fn is_busy() -> bool {
+    // SAFETY: a read of channel 4's registers, which only this module uses
+    let dma = unsafe { &*DMA1::ptr() };
+    dma.ch4.cr.read().en().is_enabled() && dma.ch4.ndtr.read().ndt().bits() != 0
+}
+
+/// Wait for the frame being sent, if any, e.g. before writing to the USART
+pub fn wait_idle() {
+    while is_busy() {}
+}
+
+// Have DMA channel 4 send the given bytes, which must not be modified
+// until they are sent
+fn start(bytes: &[u8]) {
+    // SAFETY: only channel 4's registers are modified, which only this module uses
+    let dma = unsafe { &*DMA1::ptr() };
+    dma.ch4.cr.modify(|_, w| w.en().disabled());
+    dma.ch4.mar.write(|w| unsafe { w.bits(bytes.as_ptr() as u32) });
+    dma.ch4.ndtr.write(|w| unsafe { w.bits(bytes.len() as u32) });
+    dma.ch4.cr.modify(|_, w| w.en().enabled());
+}
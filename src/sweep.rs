@@ -0,0 +1,65 @@
+//! The parameter sweep, a demo of the solver's parameter space: the
+//! viscosity sweeps continuously from water's, the tuner's default, up to
+//! honey's, the most it tunes to, and back, while gravity sloshes the
+//! water from side to side, so the same flow is seen at each viscosity
+//! in turn. The viscosity's shown on the HUD as it sweeps, as a multiple
+//! of water's (see Hud::show_reading).
+
+use fluid_core::{fixed::FixedPt, scene::SceneManager, Fluid};
+use crate::scenes;
+
+
+// The frames a sweep takes, thickening over the first half and thinning
+// over the second
+const CYCLE_FRAMES: u16 = 900;
+
+// Water's quadratic viscosity, and the most the viscosity's multiplied
+// by, honey's
+const WATER: f32 = 0.1;
+const HONEY: f32 = 5.0;
+
+// The reading's label, before the multiple of water's viscosity
+const LABEL: &str = "viscosity x";
+
+// The sweep's full scale
+const THICKEST: u16 = CYCLE_FRAMES / 2;
+
+
+pub struct Sweep {
+    // The frame of the sweep's cycle, while it's running
+    frame: Option<u16>,
+}
+
+impl Sweep {
+    /// Create the sweep, not running
+    pub const fn new() -> Self {
+        Self { frame: None }
+    }
+
+    /// Start sweeping, from water's viscosity
+    pub fn start<const N: usize>(&mut self, scenes: &mut SceneManager, fluid: &mut Fluid<N>) {
+        scenes.play(scenes::SWEEP, 0, fluid);
+        self.frame = Some(0);
+    }
+
+    /// Stop sweeping, e.g. to return to the demo. Returns true if it was
+    /// running, and has set the viscosity.
+    pub fn stop(&mut self) -> bool {
+        self.frame.take().is_some()
+    }
+
+    /// Set the viscosity for the sweep's cycle, while it's running.
+    /// Returns the reading for the HUD, the label and the multiple of
+    /// water's viscosity, or None while it's not running.
+    pub fn update<const N: usize>(&mut self, fluid: &mut Fluid<N>) -> Option<(&'static str, FixedPt)> {
+        let frame = self.frame?;
+        let thickness = match frame < THICKEST {
+            true => frame,
+            false => CYCLE_FRAMES - frame,
+        };
+        let multiple = 1.0 + (HONEY - 1.0) * thickness as f32 / THICKEST as f32;
+        fluid.set_viscosity(0.0, multiple * WATER);
+        self.frame = Some((frame + 1) % CYCLE_FRAMES);
+        Some((LABEL, FixedPt::from_f32(multiple)))
+    }
+}
@@ -0,0 +1,66 @@
+//! The MCU's internal temperature sensor (ADC_IN16), coupling the fluid
+//! to the room: as with water, it's runnier when warm and thicker when
+//! cold (see Tuner::set_temperature). The sensor measures the die, which
+//! runs a little warmer than the air, and is only accurate to a few
+//! degrees, which is plenty for a subtle effect.
+
+use stm32f0xx_hal::pac::{ADC, RCC};
+use crate::adc;
+
+
+// The sensor's ADC channel
+const CHANNEL: u8 = 16;
+
+// The address of the factory calibration: the sensor's sample at 30C,
+// with VDDA at 3.3V (as on the board)
+const TS_CAL1_ADDRESS: usize = 0x1FFF_F7B8;
+const TS_CAL1_CELSIUS: i32 = 30;
+
+// The sensor's slope, 4.3mV per degree, in samples per 100 degrees with
+// VDDA at 3.3V; its output falls as the die warms
+const SAMPLES_PER_100_CELSIUS: i32 = 534;
+
+// The samples averaged into each reading, one a frame
+const SAMPLES_AVERAGED: u16 = 32;
+
+
+/// Turn the sensor on, and calibrate and enable the ADC (which the
+/// knobs then share)
+pub fn init(adc: &ADC, rcc: &RCC) {
+    adc.ccr.modify(|_, w| w.tsen().set_bit());
+    adc::enable(adc, rcc);
+}
+
+
+/// Averages the sensor's samples into readings
+pub struct Thermometer {
+    sum: u32,
+    count: u16,
+}
+
+impl Thermometer {
+    /// Create a thermometer. The sensor is turned on by init.
+    pub const fn new() -> Self {
+        Self { sum: 0, count: 0 }
+    }
+
+    /// Sample the sensor, once a frame, returning the temperature in
+    /// degrees Celsius once enough samples have been averaged
+    pub fn sample(&mut self) -> Option<i8> {
+        // SAFETY: the ADC converts only in the simulation task, where
+        //         the knobs' conversions are finished by this one's
+        let adc = unsafe { &*ADC::ptr() };
+        self.sum += adc::convert(adc, CHANNEL) as u32;
+        self.count += 1;
+        if self.count < SAMPLES_AVERAGED {
+            return None;
+        }
+
+        let average = (core::mem::take(&mut self.sum) / SAMPLES_AVERAGED as u32) as i32;
+        self.count = 0;
+        // SAFETY: the calibration is read-only, and always present
+        let calibration = unsafe { core::ptr::read_volatile(TS_CAL1_ADDRESS as *const u16) } as i32;
+        let celsius = TS_CAL1_CELSIUS + (calibration - average) * 100 / SAMPLES_PER_100_CELSIUS;
+        Some(celsius.clamp(i8::MIN as i32, i8::MAX as i32) as i8)
+    }
+}
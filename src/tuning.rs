@@ -0,0 +1,212 @@
+//! Live tuning of the simulation and display with the rotary encoder.
+//! The wake button selects the parameter to tune, and each detent of
+//! the encoder steps it, so the effect is seen immediately. Parameters
+//! can also be set outright, e.g. by the analog knobs.
+
+use fluid_core::Fluid;
+use crate::log;
+use crate::oled::OLEDDriver;
+#[cfg(feature = "eeprom")]
+use crate::accel::Mounting;
+#[cfg(feature = "eeprom")]
+use crate::settings::Settings;
+
+
+// The quadratic viscosity (beta) per step, and the steps available
+const VISCOSITY_STEP: f32 = 0.02;
+const VISCOSITY_STEPS: i16 = 25;
+
+// The viscosity at the ambient temperature, when it's measured, relative
+// to that tuned: it falls by 2% per degree above 25C, as water's does
+// (roughly), and rises below, within half to one and a half times
+const REFERENCE_CELSIUS: i8 = 25;
+const VISCOSITY_PER_DEGREE: f32 = -0.02;
+const VISCOSITY_SCALE_RANGE: (f32, f32) = (0.5, 1.5);
+
+// The directions gravity can be tuned to, as (x, y) with +y down,
+// clockwise from straight down in steps of 22.5 degrees
+const GRAVITY_DIRECTIONS: [(f32, f32); 16] = {
+    const S: f32 = 0.382_683_43; // sin 22.5
+    const C: f32 = 0.923_879_5;  // cos 22.5
+    const D: f32 = core::f32::consts::FRAC_1_SQRT_2;
+    [
+        (0.0, 1.0), (-S, C), (-D, D), (-C, S),
+        (-1.0, 0.0), (-C, -S), (-D, -D), (-S, -C),
+        (0.0, -1.0), (S, -C), (D, -D), (C, -S),
+        (1.0, 0.0), (C, S), (D, D), (S, C),
+    ]
+};
+
+// The display's contrast levels (see OLEDDriver::set_contrast)
+const CONTRAST_LEVELS: i16 = 16;
+
+
+/// The parameters that can be tuned
+#[derive(Copy, Clone, defmt::Format)]
+pub enum Parameter {
+    Viscosity,
+    GravityAngle,
+    Contrast,
+}
+
+impl Parameter {
+    /// The parameter's largest step, from 0
+    pub const fn max_step(self) -> i16 {
+        match self {
+            Parameter::Viscosity => VISCOSITY_STEPS,
+            Parameter::GravityAngle => GRAVITY_DIRECTIONS.len() as i16 - 1,
+            Parameter::Contrast => CONTRAST_LEVELS - 1,
+        }
+    }
+}
+
+
+pub struct Tuner {
+    selected: Parameter,
+    viscosity: u8,
+    gravity: Option<u8>,
+    contrast: u8,
+    celsius: Option<i8>,
+}
+
+impl Tuner {
+    /// Start from the simulation's and display's initial settings,
+    /// with the demo's scenes steering gravity until it's tuned
+    pub const fn new() -> Self {
+        Self {
+            selected: Parameter::Viscosity,
+            viscosity: 5,
+            gravity: None,
+            contrast: 7,
+            celsius: None,
+        }
+    }
+
+    /// Select the next parameter to tune
+    pub fn select_next(&mut self) {
+        self.selected = match self.selected {
+            Parameter::Viscosity => Parameter::GravityAngle,
+            Parameter::GravityAngle => Parameter::Contrast,
+            Parameter::Contrast => Parameter::Viscosity,
+        };
+        log::info!("tuning: {}", self.selected);
+    }
+
+    /// Step the selected parameter by the given number of detents,
+    /// applying it to the simulation or display
+    pub fn adjust<const N: usize>(&mut self, detents: i16, fluid_sim: &mut Fluid<N>, display: &mut OLEDDriver) {
+        self.step(self.selected, detents, fluid_sim, display);
+    }
+
+    /// Set a parameter to the given level of a knob, scaled from
+    /// 0 to full_scale over the parameter's range
+    pub fn set<const N: usize>(&mut self, parameter: Parameter, level: u16, full_scale: u16, fluid_sim: &mut Fluid<N>, display: &mut OLEDDriver) {
+        let steps = parameter.max_step() as i32 + 1;
+        let step = (level as i32 * steps / (full_scale as i32 + 1)) as i16;
+        self.set_step(parameter, step, fluid_sim, display);
+    }
+
+    /// Set a parameter to the given step, from 0 to its max_step
+    pub fn set_step<const N: usize>(&mut self, parameter: Parameter, step: i16, fluid_sim: &mut Fluid<N>, display: &mut OLEDDriver) {
+        let current = match parameter {
+            Parameter::Viscosity => self.viscosity,
+            Parameter::GravityAngle => self.gravity.unwrap_or(0),
+            Parameter::Contrast => self.contrast,
+        };
+        self.step(parameter, step - current as i16, fluid_sim, display);
+    }
+
+    /// Return gravity to the demo's scenes, untuning it
+    pub fn release_gravity(&mut self) {
+        self.gravity = None;
+        log::info!("tuning: gravity cycle");
+    }
+
+    /// Scale the tuned viscosity for the ambient temperature, in degrees
+    /// Celsius, as it's measured
+    pub fn set_temperature<const N: usize>(&mut self, celsius: i8, fluid_sim: &mut Fluid<N>) {
+        if self.celsius != Some(celsius) {
+            self.celsius = Some(celsius);
+            self.apply_viscosity(fluid_sim);
+            log::info!("tuning: temperature {=i8}C", celsius);
+        }
+    }
+
+    /// Set the fluid's viscosity to the tuned one again, e.g. once the
+    /// attract mode's scenes, or the parameter sweep, have set their own
+    #[cfg(any(feature = "attract", feature = "sweep"))]
+    pub fn restore_viscosity<const N: usize>(&self, fluid_sim: &mut Fluid<N>) {
+        self.apply_viscosity(fluid_sim);
+    }
+
+    /// Step a parameter by the given number of detents
+    fn step<const N: usize>(&mut self, parameter: Parameter, detents: i16, fluid_sim: &mut Fluid<N>, display: &mut OLEDDriver) {
+        match parameter {
+            Parameter::Viscosity => {
+                self.viscosity = (self.viscosity as i16 + detents).clamp(0, VISCOSITY_STEPS) as u8;
+                self.apply_viscosity(fluid_sim);
+                log::info!("tuning: viscosity step {}", self.viscosity);
+            },
+            Parameter::GravityAngle => {
+                let direction = self.gravity.unwrap_or(0) as i16 + detents;
+                let direction = direction.rem_euclid(GRAVITY_DIRECTIONS.len() as i16) as u8;
+                self.gravity = Some(direction);
+                log::info!("tuning: gravity direction {}", direction);
+            },
+            Parameter::Contrast => {
+                self.contrast = (self.contrast as i16 + detents).clamp(0, CONTRAST_LEVELS - 1) as u8;
+                display.set_contrast(self.contrast);
+                log::info!("tuning: contrast {}", self.contrast);
+            },
+        }
+    }
+
+    // Set the fluid's viscosity from its step, scaled for the temperature
+    fn apply_viscosity<const N: usize>(&self, fluid_sim: &mut Fluid<N>) {
+        let (min, max) = VISCOSITY_SCALE_RANGE;
+        let scale = match self.celsius {
+            Some(celsius) => (1.0 + (celsius - REFERENCE_CELSIUS) as f32 * VISCOSITY_PER_DEGREE).clamp(min, max),
+            None => 1.0,
+        };
+        fluid_sim.set_viscosity(0.0, self.viscosity as f32 * VISCOSITY_STEP * scale);
+    }
+
+    /// The parameters' steps: the viscosity's, gravity's, if it has been
+    /// tuned, and the contrast's
+    #[cfg(any(feature = "remote", feature = "brownout"))]
+    pub fn steps(&self) -> (u8, Option<u8>, u8) {
+        (self.viscosity, self.gravity, self.contrast)
+    }
+
+    /// The settings to keep (see settings.rs), with the HUD's visibility
+    /// and the accelerometer's mounting
+    #[cfg(feature = "eeprom")]
+    pub fn settings(&self, hud: bool, mounting: Mounting) -> Settings {
+        Settings { viscosity: self.viscosity, gravity: self.gravity, contrast: self.contrast, hud, mounting }
+    }
+
+    /// Restore the settings kept, applying them to the simulation and
+    /// display
+    #[cfg(feature = "eeprom")]
+    pub fn restore<const N: usize>(&mut self, settings: Settings, fluid_sim: &mut Fluid<N>, display: &mut OLEDDriver) {
+        self.set_step(Parameter::Viscosity, settings.viscosity as i16, fluid_sim, display);
+        self.set_step(Parameter::Contrast, settings.contrast as i16, fluid_sim, display);
+        if let Some(gravity) = settings.gravity {
+            self.set_step(Parameter::GravityAngle, gravity as i16, fluid_sim, display);
+        }
+    }
+
+    /// The gravity tuned with the encoder or knobs, if it has been tuned,
+    /// as (x, y). This replaces the demo's gravity.
+    pub fn gravity(&self) -> Option<(f32, f32)> {
+        self.gravity.map(|direction| GRAVITY_DIRECTIONS[direction as usize])
+    }
+}
+
+
+/// The quadratic viscosity at the given viscosity step, before it's
+/// scaled for the temperature
+#[cfg(feature = "split")]
+pub fn viscosity_at(step: u8) -> f32 {
+    step as f32 * VISCOSITY_STEP
+}
@@ -0,0 +1,147 @@
+//! The command shell on a USB serial port (CDC ACM), for boards with a
+//! USB capable MCU, e.g. the STM32F072 (see board/fluid_f072.rs), so the
+//! fluid can be tuned with only a USB cable. The USB peripheral runs from
+//! the HSI48 oscillator, trimmed by the clock recovery system from the
+//! host's start of frame packets, so the board needs no crystal.
+//!
+//! The port is serviced from the USB interrupt, which passes it to the
+//! same shell as the USART's (serve_shell in main.rs). Replies are
+//! dropped while no terminal has the port open, and the core sleeps
+//! rather than stopping while a host has the device configured, as the
+//! USB peripheral's clock stops in Stop mode.
+
+use core::{cell::RefCell, fmt};
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::Mutex;
+use embedded_hal::serial::{Read, Write};
+use stm32f0xx_hal::gpio::{gpioa::{PA11, PA12}, Floating, Input};
+use stm32f0xx_hal::pac::{rcc, CRS, RCC, USB};
+use stm32f0xx_hal::usb::{Peripheral, UsbBus, UsbBusType};
+use usb_device::{bus::UsbBusAllocator, prelude::*};
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+
+// The device's IDs: pid.codes' shared VID and PID for CDC ACM devices
+const VID_PID: UsbVidPid = UsbVidPid(0x16C0, 0x27DD);
+
+// The polls of the device a reply waits through for the host to take
+// what's queued, before the rest is dropped
+const MAX_STALLED_POLLS: u32 = 10_000;
+
+// Whether a host has configured the device, so Stop mode would drop it
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+
+// The device, from its bring-up until the USB interrupt takes it
+static DEVICE: Mutex<RefCell<Option<UsbSerial>>> = Mutex::new(RefCell::new(None));
+
+
+/// Start the USB peripheral's clock, the HSI48 oscillator, trimmed by the
+/// clock recovery system. Like the other drivers configuring their own
+/// clocks, this must be called before the RCC is frozen.
+pub fn init_clock(rcc: &RCC, crs: CRS) {
+    restart_clock(rcc);
+    rcc.apb1enr.modify(|_, w| w.crsen().set_bit());
+    crs.cr.modify(|_, w| w.autotrimen().set_bit().cen().set_bit());
+}
+
+/// Restart the HSI48 oscillator, e.g. after Stop mode turned it off
+pub fn restart_clock(rcc: &rcc::RegisterBlock) {
+    rcc.cr2.modify(|_, w| w.hsi48on().set_bit());
+    while rcc.cr2.read().hsi48rdy().bit_is_clear() {}
+}
+
+/// Whether a host has configured the device, as of its last poll
+pub fn is_configured() -> bool {
+    CONFIGURED.load(Ordering::Relaxed)
+}
+
+
+/// Bring up the USB device on its pins, PA11 (D-) and PA12 (D+), for the
+/// host to enumerate, once the USB interrupt's task takes it
+pub fn init(usb: USB, pin_dm: PA11<Input<Floating>>, pin_dp: PA12<Input<Floating>>) {
+    let bus: &'static UsbBusAllocator<UsbBusType> = cortex_m::singleton!(
+        : UsbBusAllocator<UsbBusType> = UsbBus::new(Peripheral { usb, pin_dm, pin_dp })
+    ).unwrap();
+    let port = SerialPort::new(bus);
+    let device = UsbDeviceBuilder::new(bus, VID_PID)
+        .manufacturer("Imaginary Garage")
+        .product("Fluid")
+        .serial_number(env!("CARGO_PKG_VERSION"))
+        .device_class(USB_CLASS_CDC)
+        .build();
+    cortex_m::interrupt::free(|cs| DEVICE.borrow(cs).replace(Some(UsbSerial { device, port })));
+}
+
+
+/// The USB device, and its serial port
+pub struct UsbSerial {
+    device: UsbDevice<'static, UsbBusType>,
+    port: SerialPort<'static, UsbBusType>,
+}
+
+impl UsbSerial {
+    /// Service the device, from the USB interrupt, bound as an RTIC
+    /// hardware task along with a local device slot, initially None,
+    /// which takes the device on the first interrupt. Returns the port if
+    /// it may have received bytes.
+    pub fn on_interrupt(slot: &mut Option<UsbSerial>) -> Option<&mut UsbSerial> {
+        if slot.is_none() {
+            *slot = cortex_m::interrupt::free(|cs| DEVICE.borrow(cs).take());
+        }
+        let usb = slot.as_mut()?;
+        let received = usb.device.poll(&mut [&mut usb.port]);
+        CONFIGURED.store(usb.device.state() == UsbDeviceState::Configured, Ordering::Relaxed);
+        received.then_some(usb)
+    }
+
+    // Queue the bytes for the host, polling the device as the port's
+    // buffer fills so the host takes them. They're dropped if no terminal
+    // has the port open, or the host stops taking them.
+    fn write_all(&mut self, mut bytes: &[u8]) {
+        if !self.port.dtr() {
+            return;
+        }
+        let mut stalled = 0;
+        while !bytes.is_empty() && stalled < MAX_STALLED_POLLS {
+            match self.port.write(bytes) {
+                Ok(written) => {
+                    bytes = &bytes[written..];
+                    stalled = 0;
+                },
+                Err(UsbError::WouldBlock) => {
+                    self.device.poll(&mut [&mut self.port]);
+                    stalled += 1;
+                },
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+impl Read<u8> for UsbSerial {
+    type Error = UsbError;
+
+    fn read(&mut self) -> nb::Result<u8, UsbError> {
+        Read::read(&mut self.port)
+    }
+}
+
+impl Write<u8> for UsbSerial {
+    type Error = UsbError;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), UsbError> {
+        self.write_all(&[byte]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), UsbError> {
+        Write::flush(&mut self.port)
+    }
+}
+
+impl fmt::Write for UsbSerial {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.write_all(text.as_bytes());
+        Ok(())
+    }
+}